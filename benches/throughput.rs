@@ -0,0 +1,74 @@
+//! Throughput of the create, filter and bulk paths on a SQLite database, run with
+//! `cargo bench`. The tuning variables (`DATABASE_MAX_CONNECTIONS`,
+//! `DATABASE_LOG_STATEMENTS`, ...) are read from the environment as usual.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rusql_alchemy::prelude::*;
+use tokio::runtime::Runtime;
+
+#[derive(FromRow, Clone, Debug, Default, Model)]
+struct Item {
+    #[model(primary_key = true, auto = true, null = false)]
+    id: Integer,
+    name: Text,
+    price: Float,
+}
+
+/// The operations of an iteration, the throughput being reported per operation.
+const ROWS: usize = 1_000;
+
+fn throughput(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let path = std::env::temp_dir().join("rusql-alchemy-bench.sqlite");
+    let _ = std::fs::remove_file(&path);
+    std::env::set_var(
+        "DATABASE_URL",
+        format!("sqlite://{}?mode=rwc", path.display()),
+    );
+    let conn = runtime.block_on(async {
+        let conn = Database::new().await.conn;
+        migrate!([Item], &conn);
+        conn
+    });
+
+    let mut group = c.benchmark_group("throughput");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    group.sample_size(10);
+
+    group.bench_function("create", |b| {
+        b.to_async(&runtime).iter(|| async {
+            for index in 0..ROWS {
+                Item::create(
+                    kwargs!(name = format!("item {index}"), price = index as f64),
+                    &conn,
+                )
+                .await
+                .unwrap();
+            }
+        })
+    });
+
+    group.bench_function("filter", |b| {
+        b.to_async(&runtime).iter(|| async {
+            for index in 0..ROWS {
+                Item::filter(kwargs!(price == index as f64), &conn).await;
+            }
+        })
+    });
+
+    group.bench_function("copy_in", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let records =
+                (0..ROWS).map(|index| kwargs!(name = format!("bulk {index}"), price = 1.0));
+            Item::copy_in(futures_util::stream::iter(records), &conn)
+                .await
+                .unwrap();
+        })
+    });
+
+    group.finish();
+    let _ = std::fs::remove_file(&path);
+}
+
+criterion_group!(benches, throughput);
+criterion_main!(benches);
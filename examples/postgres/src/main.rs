@@ -2,7 +2,7 @@ use rusql_alchemy::prelude::*;
 use sqlx::FromRow;
 
 #[derive(FromRow, Clone, Debug, Default, Model)]
-struct User_ {
+struct User {
     #[model(primary_key = true)]
     id: Serial, // in postgresql, serial is auto increment
     #[model(size = 50, unique = true, null = false)]
@@ -29,7 +29,7 @@ struct Product {
     description: Text,
     #[model(default = true)]
     is_sel: Boolean,
-    #[model(null = false, foreign_key = "User_.id")]
+    #[model(null = false, foreign_key = "User.id")]
     owner: Integer,
     #[model(default = "now")]
     at: DateTime,
@@ -39,9 +39,9 @@ struct Product {
 async fn main() {
     let conn = Database::new().await.conn;
 
-    migrate!([User_, Product], &conn);
+    migrate!([User, Product], &conn);
 
-    User_ {
+    User {
         name: "johnDoe@gmail.com".to_string(),
         email: "21john@gmail.com".to_string(),
         password: "p455w0rd".to_string(),
@@ -50,12 +50,13 @@ async fn main() {
         ..Default::default()
     }
     .save(&conn)
-    .await;
+    .await
+    .unwrap();
 
-    let users = User_::all(&conn).await;
+    let users = User::all(&conn).await;
     println!("{:#?}", users);
 
-    User_::create(
+    User::create(
         kwargs!(
             name = "joe",
             email = "24nomeniavo@gmail.com",
@@ -65,21 +66,22 @@ async fn main() {
         ),
         &conn,
     )
-    .await;
+    .await
+    .unwrap();
 
-    let users = User_::all(&conn).await;
+    let users = User::all(&conn).await;
     println!("1: {:#?}", users);
 
-    if let Some(mut user) = User_::get(
+    if let Some(mut user) = User::get(
         kwargs!(email == "24nomeniavo@gmail.com").and(kwargs!(password == "strongpassword")),
         &conn,
     )
     .await
     {
         user.role = "admin".into();
-        user.update(&conn).await;
+        user.update(&conn).await.unwrap();
     }
-    let user = User_::get(
+    let user = User::get(
         kwargs!(email == "24nomeniavo@gmail.com").and(kwargs!(password == "strongpassword")),
         &conn,
     )
@@ -96,7 +98,8 @@ async fn main() {
         ),
         &conn,
     )
-    .await;
+    .await
+    .unwrap();
 
     let products = Product::all(&conn).await;
     println!("3: {:#?}", products);
@@ -106,8 +109,8 @@ async fn main() {
 
     let products = Product::all(&conn).await;
     println!("5: {:#?}", products);
-    products.delete(&conn).await;
+    products.delete(&conn).await.unwrap();
 
-    let users = User_::filter(kwargs!(age <= 18), &conn).await;
+    let users = User::filter(kwargs!(age <= 18), &conn).await;
     println!("6: {:#?}", users);
 }
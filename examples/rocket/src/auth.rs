@@ -0,0 +1,34 @@
+//! A request guard authenticating the user of the `Authorization: Bearer <token>`
+//! header with the `auth` module.
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rusql_alchemy::auth::{self, Token, User};
+use rusql_alchemy::Connection;
+
+/// The user authenticated by the token of the request, `401` if there is none.
+pub struct AuthUser(pub User);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthUser {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let conn = request
+            .rocket()
+            .state::<Connection>()
+            .expect("the Connection is managed");
+        let token = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(auth::bearer_token);
+        let user = match token {
+            Some(token) => Token::verify(token, conn).await,
+            None => None,
+        };
+        match user {
+            Some(user) => Outcome::Success(AuthUser(user)),
+            None => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
@@ -1,18 +1,26 @@
 #[macro_use]
 extern crate rocket;
 
-use rocket::serde::json::{json, Value};
+mod auth;
+
+use std::time::Duration;
+
+use rocket::http::CookieJar;
+use rocket::serde::json::{json, Json, Value};
 use rocket::State;
 use rusql_alchemy::prelude::*;
+use rusql_alchemy::session::{rocket::CurrentSession, SessionStore};
 use serde::Serialize;
 
+use auth::AuthUser;
+
 #[derive(Clone)]
 struct AppState {
     conn: Connection,
 }
 
 #[derive(Model, FromRow, Clone, Serialize)]
-struct User_ {
+struct User {
     #[model(primary_key = true)]
     id: Serial,
     #[model(unique = true, null = false, size = 50)]
@@ -22,17 +30,64 @@ struct User_ {
 #[get("/users")]
 async fn list_user(app_state: &State<AppState>) -> Value {
     let conn = app_state.conn.clone();
-    let users = User_::all(&conn).await;
+    let users = User::all(&conn).await;
     json!(users)
 }
 
+#[post("/login/<username>")]
+async fn login(
+    username: &str,
+    mut session: CurrentSession,
+    store: &State<SessionStore>,
+    cookies: &CookieJar<'_>,
+) -> Value {
+    session.values.insert("username".to_string(), json!(username));
+    let saved = session.save(store, cookies).await.is_ok();
+    json!({ "saved": saved })
+}
+
+#[get("/me")]
+fn me(session: CurrentSession) -> Value {
+    json!(session.values.get("username"))
+}
+
+#[post("/logout")]
+async fn logout(session: CurrentSession, store: &State<SessionStore>, cookies: &CookieJar<'_>) -> Value {
+    let deleted = session.destroy(store, cookies).await.is_ok();
+    json!({ "deleted": deleted })
+}
+
+#[derive(serde::Deserialize)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+#[post("/token", data = "<credentials>")]
+async fn token(credentials: Json<Credentials>, conn: &State<Connection>) -> Option<Value> {
+    let user = rusql_alchemy::auth::User::authenticate(&credentials.username, &credentials.password, conn).await?;
+    let token = user.issue_token(Duration::from_secs(3600), conn).await.ok()?;
+    Some(json!({ "token": token }))
+}
+
+#[get("/profile")]
+fn profile(user: AuthUser) -> Value {
+    json!({ "id": user.0.id, "username": user.0.username })
+}
+
 #[main]
 async fn main() {
     let conn = Database::new().await.conn;
-    // migrate!([User_], &conn);
+    // migrate!([User], &conn);
+    // the tables of `rusql_alchemy::session::Session`, `rusql_alchemy::auth::User` and
+    // `rusql_alchemy::auth::Token` are created by their `migrate` method
+    let sessions = SessionStore::new(conn.clone(), Duration::from_secs(14 * 24 * 3600));
+    rocket::tokio::spawn(sessions.clone().run_cleanup(Duration::from_secs(3600)));
     rocket::build()
-        .mount("/", routes![list_user])
-        .manage(AppState { conn })
+        .mount("/", routes![list_user, login, me, logout, token, profile])
+        .manage(AppState { conn: conn.clone() })
+        .manage(conn)
+        .manage(sessions)
         .launch()
         .await
         .expect("failed to launch rocket instance");
@@ -50,7 +50,8 @@ async fn main() {
         ..Default::default()
     }
     .save(&conn)
-    .await;
+    .await
+    .unwrap();
 
     let users = User::all(&conn).await;
     println!("{:#?}", users);
@@ -65,7 +66,8 @@ async fn main() {
         ),
         &conn,
     )
-    .await;
+    .await
+    .unwrap();
 
     let users = User::all(&conn).await;
     println!("1: {:#?}", users);
@@ -77,7 +79,7 @@ async fn main() {
     .await
     {
         user.role = "admin".into();
-        user.update(&conn).await;
+        user.update(&conn).await.unwrap();
     }
     let user = User::get(
         kwargs!(email == "24nomeniavo@gmail.com").and(kwargs!(password == "strongpassword")),
@@ -96,7 +98,8 @@ async fn main() {
         ),
         &conn,
     )
-    .await;
+    .await
+    .unwrap();
 
     let products = Product::all(&conn).await;
     println!("3: {:#?}", products);
@@ -106,7 +109,7 @@ async fn main() {
 
     let products = Product::all(&conn).await;
     println!("5: {:#?}", products);
-    products.delete(&conn).await;
+    products.delete(&conn).await.unwrap();
 
     let users = User::filter(kwargs!(age <= 18), &conn).await;
     println!("6: {:#?}", users);
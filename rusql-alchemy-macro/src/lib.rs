@@ -0,0 +1,968 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Lit};
+
+const ANONYMIZE_STRATEGIES: [&str; 6] = [
+    "fake_email",
+    "fake_name",
+    "fake_phone",
+    "mask",
+    "redact",
+    "null",
+];
+
+#[proc_macro_derive(Model, attributes(model))]
+pub fn model_derive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
+
+/// Expands the `Model` derive of `input`, or the error of an invalid `#[model(...)]`
+/// attribute, reported on its span.
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = input.ident;
+    let vis = input.vis;
+
+    let mut materialized_view = quote! {};
+    let mut partition_by = quote! {};
+    let mut table_comment = quote! {};
+    let mut table_charset = quote! {};
+    let mut table_database = quote! {};
+    let mut retention = quote! {};
+    let mut eq_on_pk = false;
+    let mut state_machines = Vec::new();
+    let mut indexed_columns = Vec::new();
+    let mut table_name = table_name(&name.to_string());
+
+    for attr in &input.attrs {
+        if attr.path.is_ident("model") {
+            let meta = attr.parse_meta()?;
+            if let syn::Meta::List(ref list) = meta {
+                for nested in &list.nested {
+                    if let syn::NestedMeta::Meta(syn::Meta::List(ref list)) = nested {
+                        if list.path.is_ident("partition_by") {
+                            if let Some(syn::NestedMeta::Meta(syn::Meta::NameValue(ref nv))) =
+                                list.nested.first()
+                            {
+                                let strategy = nv
+                                    .path
+                                    .get_ident()
+                                    .map(|ident| ident.to_string())
+                                    .unwrap_or_default();
+                                if !["range", "list", "hash"].contains(&strategy.as_str()) {
+                                    return Err(syn::Error::new_spanned(
+                                        &nv.path,
+                                        "partition_by supports range, list or hash",
+                                    ));
+                                }
+                                if let Lit::Str(ref lit) = nv.lit {
+                                    let column = lit.value();
+                                    partition_by = quote! {
+                                        const PARTITION_BY: Option<(&'static str, &'static str)> =
+                                            Some((#strategy, #column));
+                                    };
+                                }
+                            }
+                        } else if list.path.is_ident("state_machine") {
+                            state_machines.push(state_machine(list)?);
+                        } else if list.path.is_ident("retention") {
+                            retention = retention_policy(list)?;
+                        } else if list.path.is_ident("index") {
+                            for column in &list.nested {
+                                match column {
+                                    syn::NestedMeta::Meta(syn::Meta::Path(path))
+                                        if path.get_ident().is_some() =>
+                                    {
+                                        indexed_columns.extend(path.get_ident().cloned());
+                                    }
+                                    _ => return Err(syn::Error::new_spanned(
+                                        column,
+                                        "index lists the columns of the model, e.g. index(Email)",
+                                    )),
+                                }
+                            }
+                        }
+                    }
+                    if let syn::NestedMeta::Meta(syn::Meta::NameValue(ref nv)) = nested {
+                        if nv.path.is_ident("materialized_view") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                let query = lit.value();
+                                let query = query.trim().trim_end_matches(';');
+                                materialized_view = quote! {
+                                    const MATERIALIZED_VIEW: Option<&'static str> = Some(#query);
+                                };
+                            }
+                        } else if nv.path.is_ident("comment") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                let comment = lit.value();
+                                table_comment = quote! {
+                                    const COMMENT: Option<&'static str> = Some(#comment);
+                                };
+                            }
+                        } else if nv.path.is_ident("database") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                let database = lit.value();
+                                table_database = quote! {
+                                    const DATABASE: Option<&'static str> = Some(#database);
+                                };
+                            }
+                        } else if nv.path.is_ident("eq_on") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                if lit.value() != "pk" {
+                                    return Err(syn::Error::new_spanned(
+                                        lit,
+                                        "eq_on only supports \"pk\"",
+                                    ));
+                                }
+                                eq_on_pk = true;
+                            }
+                        } else if nv.path.is_ident("table") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                table_name = lit.value();
+                            }
+                        } else if nv.path.is_ident("charset") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                let charset = lit.value();
+                                table_charset = quote! {
+                                    const CHARSET: Option<&'static str> = Some(#charset);
+                                };
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &name,
+                    "Model derive macro only supports structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &name,
+                "Model derive macro only supports structs",
+            ))
+        }
+    };
+
+    let mut schema_fields = Vec::new();
+    let mut create_args = Vec::new();
+    let mut update_args = Vec::new();
+    let mut anonymize = Vec::new();
+    let mut columns = Vec::new();
+    let mut column_comments = Vec::new();
+    let mut indexes = Vec::new();
+    let mut field_names = Vec::new();
+    let mut sensitive = Vec::new();
+    let mut generated = Vec::new();
+    let mut ranges = Vec::new();
+    let mut nullable_foreign_keys = Vec::new();
+    let mut foreign_keys = Vec::new();
+
+    let mut pk_fields = Vec::new();
+    let composite_pk = fields.iter().filter(|field| is_primary_key(field)).count() > 1;
+
+    for field in fields {
+        let Some(field_name) = field.ident.as_ref() else {
+            return Err(syn::Error::new_spanned(
+                field,
+                "Model derive macro needs named fields",
+            ));
+        };
+        field_names.push(field_name);
+        let field_type = match &field.ty {
+            syn::Type::Path(type_path) if !type_path.path.segments.is_empty() => {
+                type_path.path.segments[type_path.path.segments.len() - 1]
+                    .ident
+                    .to_string()
+            }
+            ty => return Err(syn::Error::new_spanned(ty, "Unsupported field type")),
+        };
+        if field_type == "Int4Range" || field_type == "TsRange" {
+            ranges.push(field_name.to_string());
+        }
+
+        let mut is_nullable = true;
+        let mut is_primary_key = false;
+        let mut is_auto = false;
+        let mut is_unique = false;
+        let mut is_default = false;
+        let mut size = None;
+        let mut default = quote! {};
+        let mut foreign_key = quote! {};
+        let mut references = None;
+        let mut collation = None;
+
+        for attr in &field.attrs {
+            if attr.path.is_ident("model") {
+                let meta = attr.parse_meta()?;
+                if let syn::Meta::List(ref list) = meta {
+                    for nested in &list.nested {
+                        if let syn::NestedMeta::Meta(syn::Meta::NameValue(ref nv)) = nested {
+                            if nv.path.is_ident("primary_key") {
+                                if let Lit::Bool(ref lit) = nv.lit {
+                                    if lit.value {
+                                        pk_fields.push((field_name.clone(), field.ty.clone()));
+                                    }
+                                    is_primary_key = lit.value;
+                                }
+                            } else if nv.path.is_ident("auto") {
+                                if let Lit::Bool(ref lit) = nv.lit {
+                                    is_auto = lit.value;
+                                }
+                            } else if nv.path.is_ident("size") {
+                                if let Lit::Int(ref lit) = nv.lit {
+                                    size = Some(lit.clone());
+                                }
+                            } else if nv.path.is_ident("index") {
+                                if let Lit::Bool(ref lit) = nv.lit {
+                                    if lit.value {
+                                        indexes.push(field_name.to_string());
+                                    }
+                                }
+                            } else if nv.path.is_ident("sensitive") {
+                                if let Lit::Bool(ref lit) = nv.lit {
+                                    if lit.value {
+                                        sensitive.push(field_name.to_string());
+                                    }
+                                }
+                            } else if nv.path.is_ident("unique") {
+                                if let Lit::Bool(ref lit) = nv.lit {
+                                    is_unique = lit.value;
+                                }
+                            } else if nv.path.is_ident("null") {
+                                if let Lit::Bool(ref lit) = nv.lit {
+                                    is_nullable = lit.value;
+                                }
+                            } else if nv.path.is_ident("default") {
+                                is_default = true;
+                                if let Lit::Str(ref str) = nv.lit {
+                                    default = if str.value() == "now" {
+                                        if field_type == "Date" {
+                                            quote! { default current_date}
+                                        } else if field_type == "DateTime" {
+                                            quote! { default current_timestamp}
+                                        } else {
+                                            return Err(syn::Error::new_spanned(
+                                                str,
+                                                "'now' is work only with Date or DateTime",
+                                            ));
+                                        }
+                                    } else {
+                                        let str = format!("'{str}'", str = str.value());
+                                        quote! { default #str }
+                                    }
+                                } else if let Lit::Bool(ref bool) = nv.lit {
+                                    default = if bool.value {
+                                        quote! {default 1}
+                                    } else {
+                                        quote! {default 0}
+                                    };
+                                } else if let Lit::Int(ref int) = nv.lit {
+                                    default = quote! { default #int }
+                                }
+                            } else if nv.path.is_ident("collation") {
+                                if let Lit::Str(ref lit) = nv.lit {
+                                    collation = Some(lit.value());
+                                }
+                            } else if nv.path.is_ident("comment") {
+                                if let Lit::Str(ref lit) = nv.lit {
+                                    let field = field_name.to_string();
+                                    let comment = lit.value();
+                                    column_comments.push(quote! { (#field, #comment) });
+                                }
+                            } else if nv.path.is_ident("anonymize") {
+                                if let Lit::Str(ref lit) = nv.lit {
+                                    let strategy = lit.value();
+                                    if !ANONYMIZE_STRATEGIES.contains(&strategy.as_str()) {
+                                        return Err(syn::Error::new_spanned(
+                                            lit,
+                                            format!("Unknown anonymize strategy '{strategy}'"),
+                                        ));
+                                    }
+                                    let field = field_name.to_string();
+                                    anonymize.push(quote! { (#field, #strategy) });
+                                }
+                            } else if nv.path.is_ident("foreign_key") {
+                                if let Lit::Str(ref lit) = nv.lit {
+                                    let fk = lit.value();
+                                    let foreign_key_parts: Vec<&str> = fk.split('.').collect();
+                                    if foreign_key_parts.len() != 2 {
+                                        return Err(syn::Error::new_spanned(
+                                            lit,
+                                            "Invalid foreign key, expected \"Model.column\"",
+                                        ));
+                                    }
+                                    let foreign_key_table = foreign_key_parts[0];
+                                    let foreign_key_field = foreign_key_parts[1];
+
+                                    foreign_key = quote! {
+                                         references #foreign_key_table(#foreign_key_field)
+                                    };
+                                    references = Some((
+                                        foreign_key_table.to_string(),
+                                        foreign_key_field.to_string(),
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let field_schema = {
+            let base_type = match field_type.as_str() {
+                "Serial" => quote! { serial },
+                "Integer" => quote! { integer },
+                "String" => {
+                    if let Some(size) = size {
+                        quote! {varchar(#size)}
+                    } else {
+                        quote! {varchar(255)}
+                    }
+                }
+                "Float" => quote! { float },
+                "Text" | "Translated" => quote! { text },
+                "CiText" => quote! { citext },
+                "Int4Range" => quote! { int4range },
+                "TsRange" => quote! { tsrange },
+                "Uuid" => quote! { varchar(36) },
+                "Date" => quote! { varchar(10) },
+                "Boolean" | "bool" => quote! { integer },
+                "DateTime" => quote! { varchar(40) },
+                p_type => {
+                    return Err(syn::Error::new_spanned(
+                        &field.ty,
+                        format!("Unsupported field type '{p_type}'"),
+                    ))
+                }
+            };
+
+            let primary_key = if is_primary_key {
+                let auto = if is_auto {
+                    generated.push(field_name.to_string());
+                    quote! { autoincrement }
+                } else if field_type.as_str() == "Serial" {
+                    generated.push(field_name.to_string());
+                    quote! {}
+                } else {
+                    create_args.push(quote! { #field_name });
+                    quote! {}
+                };
+                // a composite key is declared after the columns
+                if composite_pk {
+                    quote! { #auto }
+                } else {
+                    quote! { primary key #auto}
+                }
+            } else {
+                create_args.push(quote! { #field_name });
+                update_args.push(quote! { #field_name });
+                quote! {}
+            };
+
+            if is_default {
+                create_args.pop();
+                generated.push(field_name.to_string());
+            }
+
+            let nullable = if is_nullable {
+                quote! {}
+            } else {
+                quote! {not null}
+            };
+            let unique = if is_unique {
+                quote! { unique }
+            } else {
+                quote! {}
+            };
+
+            quote! { #field_name #base_type #primary_key #unique #default #nullable #foreign_key }
+        };
+
+        if let Some((table, column)) = &references {
+            let field = field_name.to_string();
+            let parent = syn::Ident::new(table, field_name.span());
+            foreign_keys.push(quote! {
+                rusql_alchemy::db::models::ForeignKey {
+                    field: #field,
+                    table: <#parent as Model>::NAME,
+                    column: #column,
+                    foreign_keys: rusql_alchemy::db::models::foreign_keys::<#parent>,
+                    columns: rusql_alchemy::db::models::columns::<#parent>,
+                }
+            });
+        }
+        if let Some(references) = references.filter(|_| is_nullable && !is_primary_key) {
+            nullable_foreign_keys.push((field_name, references));
+        }
+
+        let field = field_name.to_string();
+        let mut definition = field_schema.to_string().replace('"', "");
+        if let Some(collation) = collation {
+            definition = format!("{definition} collate \"{collation}\"");
+        }
+        columns.push(quote! { (#field, #definition) });
+        schema_fields.push(definition);
+    }
+
+    if pk_fields.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &name,
+            "Model derive macro needs a primary key",
+        ));
+    }
+    let pk_names = pk_fields.iter().map(|(name, _)| name).collect::<Vec<_>>();
+    let pk_types = pk_fields.iter().map(|(_, ty)| ty).collect::<Vec<_>>();
+
+    let primary_key = {
+        let pk = pk_names[0].to_string();
+        let (pk_type, pk_value, pk_columns) = if composite_pk {
+            let columns = pk_names.iter().map(|name| name.to_string());
+            (
+                quote! { (#(#pk_types),*) },
+                quote! { (#(self.#pk_names.clone()),*) },
+                quote! { const PK_COLUMNS: &'static [&'static str] = &[#(#columns),*]; },
+            )
+        } else {
+            let (pk_name, pk_type) = (pk_names[0], pk_types[0]);
+            (
+                quote! { #pk_type },
+                quote! { self.#pk_name.clone() },
+                quote! {},
+            )
+        };
+        quote! {
+            const PK: &'static str = #pk;
+            #pk_columns
+
+            type Pk = #pk_type;
+
+            fn pk(&self) -> Self::Pk {
+                #pk_value
+            }
+        }
+    };
+
+    let schema = {
+        let mut fields = schema_fields.join(", ");
+        if composite_pk {
+            let columns = pk_names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>();
+            fields.push_str(&format!(", primary key ({})", columns.join(", ")));
+        }
+
+        let schema = format!("create table if not exists {table_name} ({fields});");
+
+        quote! {
+            const SCHEMA: &'static str = #schema;
+        }
+    };
+
+    let create = quote! {
+        async fn save(&self, conn: &Connection) -> Result<(), rusql_alchemy::Error> {
+            Self::create(
+                kwargs!(
+                    #(#create_args = self.#create_args),*
+                ),
+                conn,
+            )
+            .await
+        }
+    };
+
+    let update = quote! {
+        async fn update(&self, conn: &Connection) -> Result<u64, rusql_alchemy::Error> {
+            self.update_fields(
+                kwargs!(
+                    #(#update_args = self.#update_args),*
+                ),
+                conn,
+            )
+            .await
+        }
+    };
+
+    let upsert = {
+        // the generated keys are set by the instance, which is updated when they exist
+        let created = create_args
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>();
+        let generated_pk = pk_names
+            .iter()
+            .filter(|name| !created.contains(&name.to_string()))
+            .map(|name| quote! { #name });
+        let upsert_args = generated_pk
+            .chain(create_args.iter().cloned())
+            .collect::<Vec<_>>();
+        quote! {
+            async fn upsert(
+                &self,
+                conn: &Connection,
+            ) -> Result<rusql_alchemy::db::models::Upserted<Self>, rusql_alchemy::Error> {
+                Self::create_or_update(
+                    kwargs!(
+                        #(#upsert_args = self.#upsert_args),*
+                    ),
+                    conn,
+                )
+                .await
+            }
+        }
+    };
+
+    let diff = quote! {
+        fn diff(&self, other: &Self) -> Vec<FieldChange> {
+            let mut changes = Vec::new();
+            #(
+                if self.#field_names != other.#field_names {
+                    changes.push(FieldChange {
+                        field: stringify!(#field_names),
+                        old: self.#field_names.clone().into(),
+                        new: other.#field_names.clone().into(),
+                    });
+                }
+            )*
+            changes
+        }
+
+        fn fields(&self) -> Vec<(&'static str, rusql_alchemy::serde_json::Value)> {
+            vec![#((stringify!(#field_names), self.#field_names.clone().into())),*]
+        }
+    };
+
+    let (column_type, column) = {
+        let column_name = syn::Ident::new(&format!("{name}Column"), name.span());
+        let variants = field_names
+            .iter()
+            .map(|field| column_variant(field))
+            .collect::<Vec<_>>();
+        // the columns of `#[model(index(...))]`, an unknown one failing to compile on
+        // the variant it names
+        for column in &indexed_columns {
+            let field = field_names
+                .iter()
+                .find(|field| column_variant(field) == *column)
+                .map(|field| field.to_string());
+            if let Some(field) = field.filter(|field| !indexes.contains(field)) {
+                indexes.push(field);
+            }
+        }
+        let doc = format!("The columns of `{name}`.");
+        (
+            quote! { type Column = #column_name; },
+            quote! {
+                #[doc = #doc]
+                #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+                #[allow(dead_code)]
+                #vis enum #column_name {
+                    #(#variants),*
+                }
+
+                impl rusql_alchemy::db::models::Column for #column_name {
+                    fn name(&self) -> &'static str {
+                        match self {
+                            #(Self::#variants => stringify!(#field_names)),*
+                        }
+                    }
+                }
+
+                const _: &[#column_name] = &[#(#column_name::#indexed_columns),*];
+            },
+        )
+    };
+
+    let detach = nullable_foreign_keys.iter().map(|(field, (table, column))| {
+        let parent = syn::Ident::new(table, name.span());
+        let column = syn::Ident::new(column, name.span());
+        let link = field.to_string();
+        let link = link.strip_suffix("_id").unwrap_or(&link);
+        let detach = syn::Ident::new(&format!("detach_{link}"), field.span());
+        // `orphan_{children}`, suffixed by the field when several of them reference `table`
+        let children = crate::table_name(&name.to_string());
+        let orphan = if nullable_foreign_keys.iter().filter(|(_, (other, _))| other == table).count() > 1 {
+            format!("orphan_{children}_by_{link}")
+        } else {
+            format!("orphan_{children}")
+        };
+        let orphan = syn::Ident::new(&orphan, field.span());
+        let detach_doc = format!("Sets `{field}` to null, dropping the link to its `{table}`.");
+        let orphan_doc = format!(
+            "Sets to null the `{field}` of the `{name}` rows linked to this `{table}`, \
+             returning their count."
+        );
+        quote! {
+            impl #name {
+                #[doc = #detach_doc]
+                #[allow(dead_code)]
+                pub async fn #detach(&self, conn: &Connection) -> Result<(), rusql_alchemy::Error> {
+                    self.update_fields(kwargs!(#field = rusql_alchemy::serde_json::Value::Null), conn)
+                        .await
+                        .map(|_| ())
+                }
+            }
+
+            impl #parent {
+                #[doc = #orphan_doc]
+                #[allow(dead_code)]
+                pub async fn #orphan(&self, conn: &Connection) -> Result<u64, rusql_alchemy::Error> {
+                    #name::update_where(
+                        kwargs!(#field == self.#column),
+                        kwargs!(#field = rusql_alchemy::serde_json::Value::Null),
+                        conn,
+                    )
+                    .await
+                }
+            }
+        }
+    });
+
+    let transitions = state_machines.iter().map(|(field, transitions)| {
+        let column = syn::Ident::new(field, name.span());
+        let mut targets: Vec<(&str, Vec<&str>)> = Vec::new();
+        for (from, to) in transitions {
+            match targets.iter_mut().find(|(target, _)| target == to) {
+                Some((_, sources)) => sources.push(from),
+                None => targets.push((to, vec![from])),
+            }
+        }
+        let methods = targets.iter().map(|(to, from)| {
+            let state = to
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+                .collect::<String>();
+            let mark = syn::Ident::new(&format!("mark_{state}"), name.span());
+            let can_mark = syn::Ident::new(&format!("can_mark_{state}"), name.span());
+            let mark_doc = format!(
+                "Moves `{field}` to `{to}` from {}, if it was not changed in the database since \
+                 the instance was read.",
+                from.iter().map(|from| format!("`{from}`")).collect::<Vec<_>>().join(" or ")
+            );
+            let can_mark_doc = format!("Returns `true` if `{field}` can move to `{to}`.");
+            quote! {
+                #[doc = #mark_doc]
+                #[allow(dead_code)]
+                pub async fn #mark(&mut self, conn: &Connection) -> Result<(), rusql_alchemy::Error> {
+                    self.transition(#field, &self.#column, &[#(#from),*], #to, conn).await?;
+                    self.#column = #to.into();
+                    Ok(())
+                }
+
+                #[doc = #can_mark_doc]
+                #[allow(dead_code)]
+                pub fn #can_mark(&self) -> bool {
+                    [#(#from),*].contains(&self.#column.as_str())
+                }
+            }
+        });
+        quote! {
+            impl #name {
+                #(#methods)*
+            }
+        }
+    });
+
+    let eq = if eq_on_pk {
+        quote! {
+            impl PartialEq for #name {
+                fn eq(&self, other: &Self) -> bool {
+                    self.pk() == other.pk()
+                }
+            }
+
+            impl Eq for #name {}
+
+            impl std::hash::Hash for #name {
+                fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                    self.pk().hash(state);
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let expanded = quote! {
+        #[async_trait]
+        impl Model for #name {
+            const NAME: &'static str = #table_name;
+            const MODEL_NAME: &'static str = stringify!(#name);
+            #schema
+            #primary_key
+            #column_type
+            const ANONYMIZE: &'static [(&'static str, &'static str)] = &[#(#anonymize),*];
+            #materialized_view
+            #partition_by
+            #table_comment
+            #table_charset
+            #table_database
+            #retention
+            const COLUMNS: &'static [(&'static str, &'static str)] = &[#(#columns),*];
+            const COLUMN_COMMENTS: &'static [(&'static str, &'static str)] = &[#(#column_comments),*];
+            const INDEXES: &'static [&'static str] = &[#(#indexes),*];
+            const SENSITIVE: &'static [&'static str] = &[#(#sensitive),*];
+            const GENERATED: &'static [&'static str] = &[#(#generated),*];
+            const RANGES: &'static [&'static str] = &[#(#ranges),*];
+            const FOREIGN_KEYS: &'static [rusql_alchemy::db::models::ForeignKey] = &[#(#foreign_keys),*];
+            #create
+            #update
+            #upsert
+            #diff
+        }
+
+        #eq
+
+        #column
+
+        #(#detach)*
+
+        #(#transitions)*
+    };
+
+    Ok(expanded)
+}
+
+/// Parses `state_machine(field = "status", transitions("pending -> paid", ...))` into the
+/// field and its `(from, to)` transitions.
+fn state_machine(list: &syn::MetaList) -> syn::Result<(String, Vec<(String, String)>)> {
+    let mut field = None;
+    let mut transitions = Vec::new();
+    for nested in &list.nested {
+        match nested {
+            syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) if nv.path.is_ident("field") => {
+                if let Lit::Str(ref lit) = nv.lit {
+                    field = Some(lit.value());
+                }
+            }
+            syn::NestedMeta::Meta(syn::Meta::List(list)) if list.path.is_ident("transitions") => {
+                for transition in &list.nested {
+                    let syn::NestedMeta::Lit(Lit::Str(lit)) = transition else {
+                        return Err(syn::Error::new_spanned(
+                            transition,
+                            "a transition is written \"from -> to\"",
+                        ));
+                    };
+                    let transition = lit.value();
+                    let Some((from, to)) = transition.split_once("->") else {
+                        return Err(syn::Error::new_spanned(
+                            lit,
+                            format!("invalid transition '{transition}', expected \"from -> to\""),
+                        ));
+                    };
+                    transitions.push((from.trim().to_string(), to.trim().to_string()));
+                }
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    nested,
+                    "state_machine supports field and transitions",
+                ))
+            }
+        }
+    }
+    let Some(field) = field else {
+        return Err(syn::Error::new_spanned(
+            list,
+            "state_machine requires the field holding the state",
+        ));
+    };
+    Ok((field, transitions))
+}
+
+/// Parses `retention(days = 90, on = "created_at")`, with an optional `archive = "table"`
+/// or `soft_delete = "column"`, into the `RETENTION` constant.
+fn retention_policy(list: &syn::MetaList) -> syn::Result<proc_macro2::TokenStream> {
+    let mut days = None;
+    let mut column = None;
+    let mut action = quote! { rusql_alchemy::db::models::RetentionAction::Delete };
+    let mut actions = 0;
+    for nested in &list.nested {
+        let syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) = nested else {
+            return Err(syn::Error::new_spanned(
+                nested,
+                "retention supports days, on, archive and soft_delete",
+            ));
+        };
+        match (nv.path.get_ident().map(|ident| ident.to_string()).as_deref(), &nv.lit) {
+            (Some("days"), Lit::Int(lit)) => {
+                days = Some(lit.base10_parse::<u32>()?);
+            }
+            (Some("on"), Lit::Str(lit)) => column = Some(lit.value()),
+            (Some("archive"), Lit::Str(lit)) => {
+                let table = lit.value();
+                action = quote! { rusql_alchemy::db::models::RetentionAction::Archive(#table) };
+                actions += 1;
+            }
+            (Some("soft_delete"), Lit::Str(lit)) => {
+                let column = lit.value();
+                action = quote! { rusql_alchemy::db::models::RetentionAction::SoftDelete(#column) };
+                actions += 1;
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    nv,
+                    "retention supports days = 90, on = \"column\", archive = \"table\" and soft_delete = \"column\"",
+                ))
+            }
+        }
+    }
+    if actions > 1 {
+        return Err(syn::Error::new_spanned(
+            list,
+            "retention supports either archive or soft_delete",
+        ));
+    }
+    let Some(days) = days else {
+        return Err(syn::Error::new_spanned(
+            list,
+            "retention requires the days the rows are kept",
+        ));
+    };
+    let Some(column) = column else {
+        return Err(syn::Error::new_spanned(
+            list,
+            "retention requires the column the age of the rows is read from",
+        ));
+    };
+    Ok(quote! {
+        const RETENTION: Option<rusql_alchemy::db::models::Retention> =
+            Some(rusql_alchemy::db::models::Retention {
+                days: #days,
+                column: #column,
+                action: #action,
+            });
+    })
+}
+
+/// The variant of the generated column enum naming `field` (`created_at` → `CreatedAt`).
+fn column_variant(field: &syn::Ident) -> syn::Ident {
+    let variant = field
+        .to_string()
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect::<String>();
+    syn::Ident::new(&variant, field.span())
+}
+
+/// Converts a `CamelCase` model name to `snake_case`.
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (index, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if index > 0 && !snake.ends_with('_') {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// The nouns ending in `f` whose plural only appends `s` (`Roof` → `roofs`).
+const PLURAL_F_EXCEPTIONS: &[&str] = &["roof", "proof", "chief", "belief", "chef", "brief", "reef"];
+
+/// The default table of the model `name`, its plural in `snake_case` (`User` → `users`,
+/// `Category` → `categories`, `Shelf` → `shelves`), without the trailing underscore of the
+/// names avoiding a keyword. A model whose plural is irregular names its table with
+/// `#[model(table = "...")]`.
+fn table_name(name: &str) -> String {
+    let name = snake_case(name.trim_end_matches('_'));
+    let consonant_y = name
+        .strip_suffix('y')
+        .filter(|stem| stem.ends_with(|c: char| c.is_ascii_alphabetic() && !"aeiou".contains(c)));
+    let last_word = name.rsplit('_').next().unwrap_or(&name);
+    let f_stem = name.strip_suffix("fe").or_else(|| {
+        name.strip_suffix('f')
+            .filter(|stem| !stem.ends_with('f') && !PLURAL_F_EXCEPTIONS.contains(&last_word))
+    });
+    if let Some(stem) = consonant_y {
+        format!("{stem}ies")
+    } else if let Some(stem) = f_stem {
+        format!("{stem}ves")
+    } else if ["s", "x", "z", "ch", "sh"]
+        .iter()
+        .any(|suffix| name.ends_with(suffix))
+    {
+        format!("{name}es")
+    } else {
+        format!("{name}s")
+    }
+}
+
+/// Whether `field` has the `primary_key = true` attribute.
+fn is_primary_key(field: &syn::Field) -> bool {
+    field.attrs.iter().any(|attr| {
+        let Ok(syn::Meta::List(list)) = attr.parse_meta() else {
+            return false;
+        };
+        list.path.is_ident("model")
+            && list.nested.iter().any(|nested| {
+                matches!(
+                    nested,
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(nv))
+                        if nv.path.is_ident("primary_key")
+                            && matches!(nv.lit, Lit::Bool(ref lit) if lit.value)
+                )
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::table_name;
+
+    #[test]
+    fn pluralizes_regular_names() {
+        assert_eq!(table_name("User"), "users");
+        assert_eq!(table_name("OrderItem"), "order_items");
+        assert_eq!(table_name("Type_"), "types");
+    }
+
+    #[test]
+    fn pluralizes_consonant_y() {
+        assert_eq!(table_name("Category"), "categories");
+        assert_eq!(table_name("Key"), "keys");
+        assert_eq!(table_name("Day"), "days");
+    }
+
+    #[test]
+    fn pluralizes_sibilants() {
+        assert_eq!(table_name("Status"), "statuses");
+        assert_eq!(table_name("Box"), "boxes");
+        assert_eq!(table_name("Waltz"), "waltzes");
+        assert_eq!(table_name("Batch"), "batches");
+        assert_eq!(table_name("Wish"), "wishes");
+    }
+
+    #[test]
+    fn pluralizes_f_and_fe() {
+        assert_eq!(table_name("Shelf"), "shelves");
+        assert_eq!(table_name("BookShelf"), "book_shelves");
+        assert_eq!(table_name("Knife"), "knives");
+        assert_eq!(table_name("Roof"), "roofs");
+        assert_eq!(table_name("Chief"), "chiefs");
+        assert_eq!(table_name("Cliff"), "cliffs");
+    }
+}
@@ -0,0 +1,333 @@
+//! Files attached to the instances of any model, with the `attachments` feature.
+//!
+//! The content of a file is stored once in a `BlobStore` under its SHA-256 digest, so
+//! the same file attached twice takes the space of one, and an `Attachment` row holds
+//! its metadata (name, mime type, size) and the model instance it is attached to.
+//!
+//! # Example
+//! ```ignore
+//! use rusql_alchemy::attachment::{Attachment, FileSystemBlobStore};
+//!
+//! Attachment::migrate(&conn).await?;
+//! let blobs = FileSystemBlobStore::new("./uploads");
+//!
+//! let avatar = Attachment::attach(&user, "avatar.png", "image/png", &bytes, &blobs, &conn).await?;
+//! for attachment in Attachment::of(&user, &conn).await {
+//!     let bytes = attachment.read(&blobs).await?;
+//! }
+//! avatar.remove(&blobs, &conn).await?;
+//! ```
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use ring::digest;
+
+use crate::cache;
+use crate::prelude::*;
+use crate::Error;
+
+/// Where the content of the files is stored, by key.
+///
+/// The crate provides the `FileSystemBlobStore`, the `MemoryBlobStore` and, with the
+/// `s3` feature, the `S3BlobStore`; another storage implements it in the application.
+#[async_trait::async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Stores `bytes` under `key`, replacing the previous content.
+    async fn put(&self, key: &str, bytes: &[u8]) -> io::Result<()>;
+
+    /// The content stored under `key`, `io::ErrorKind::NotFound` if there is none.
+    async fn get(&self, key: &str) -> io::Result<Vec<u8>>;
+
+    /// Deletes the content stored under `key`, if any.
+    async fn delete(&self, key: &str) -> io::Result<()>;
+
+    async fn exists(&self, key: &str) -> io::Result<bool>;
+}
+
+/// Stores the content of the files in a directory, `ab/cdef...` for the key `abcdef...`
+/// so a directory does not hold too many files.
+#[derive(Debug, Clone)]
+pub struct FileSystemBlobStore {
+    root: PathBuf,
+}
+
+impl FileSystemBlobStore {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        if key.len() < 3 || !key.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid blob key '{key}'"),
+            ));
+        }
+        Ok(self.root.join(&key[..2]).join(&key[2..]))
+    }
+}
+
+#[async_trait::async_trait]
+impl BlobStore for FileSystemBlobStore {
+    async fn put(&self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // written aside then renamed, so a reader never sees a partial file
+        let partial = path.with_extension("partial");
+        tokio::fs::write(&partial, bytes).await?;
+        tokio::fs::rename(&partial, &path).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        tokio::fs::read(self.path(key)?).await
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        tokio::fs::try_exists(self.path(key)?).await
+    }
+}
+
+/// Stores the content of the files in memory, for the unit tests.
+#[derive(Debug, Default)]
+pub struct MemoryBlobStore {
+    blobs: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryBlobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl BlobStore for MemoryBlobStore {
+    async fn put(&self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        self.blobs
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), bytes.to_vec());
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        self.blobs
+            .lock()
+            .unwrap()
+            .get(key)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no blob '{key}'")))
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        self.blobs.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        Ok(self.blobs.lock().unwrap().contains_key(key))
+    }
+}
+
+/// Stores the content of the files as the objects of an S3 bucket (or of a compatible
+/// storage), with the `s3` feature.
+///
+/// # Example
+/// ```ignore
+/// let config = aws_config::load_from_env().await;
+/// let blobs = S3BlobStore::new(aws_sdk_s3::Client::new(&config), "uploads");
+/// ```
+#[cfg(feature = "s3")]
+#[derive(Debug, Clone)]
+pub struct S3BlobStore {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+}
+
+#[cfg(feature = "s3")]
+impl S3BlobStore {
+    pub fn new(client: aws_sdk_s3::Client, bucket: impl Into<String>) -> Self {
+        Self {
+            client,
+            bucket: bucket.into(),
+        }
+    }
+}
+
+#[cfg(feature = "s3")]
+#[async_trait::async_trait]
+impl BlobStore for S3BlobStore {
+    async fn put(&self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .body(bytes.to_vec().into())
+            .send()
+            .await
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|err| match err.as_service_error() {
+                Some(service) if service.is_no_such_key() => {
+                    io::Error::new(io::ErrorKind::NotFound, format!("no blob '{key}'"))
+                }
+                _ => io::Error::other(err),
+            })?;
+        let body = object.body.collect().await.map_err(io::Error::other)?;
+        Ok(body.into_bytes().to_vec())
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        // deleting a missing object succeeds
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        let head = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await;
+        match head {
+            Ok(_) => Ok(true),
+            Err(err) if err.as_service_error().is_some_and(|err| err.is_not_found()) => Ok(false),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+}
+
+/// A file attached to an instance of a model.
+#[derive(FromRow, Clone, Debug, Default, Model)]
+#[model(table = "attachments")]
+pub struct Attachment {
+    #[model(primary_key = true, auto = true, null = false)]
+    pub id: Integer,
+    /// The table of the model the file is attached to.
+    #[model(size = 100, null = false, index = true)]
+    pub owner_type: String,
+    /// The primary key of the instance the file is attached to, its values joined by `,`.
+    #[model(size = 255, null = false, index = true)]
+    pub owner_id: String,
+    #[model(size = 255, null = false)]
+    pub name: String,
+    #[model(size = 255, null = false)]
+    pub mime: String,
+    /// The size of the file, in bytes.
+    #[model(null = false)]
+    pub size: Integer,
+    /// The SHA-256 digest of the content, in hexadecimal.
+    #[model(size = 64, null = false, index = true)]
+    pub hash: String,
+    /// The key of the content in the `BlobStore`.
+    #[model(size = 255, null = false)]
+    pub storage_key: String,
+    #[model(default = "now")]
+    pub created_at: DateTime,
+}
+
+impl Attachment {
+    /// Attaches the file `name` of content `bytes` to `owner`, storing the content
+    /// unless the same content is already stored.
+    pub async fn attach<T: Model + Sync>(
+        owner: &T,
+        name: &str,
+        mime: &str,
+        bytes: &[u8],
+        blobs: &dyn BlobStore,
+        conn: &Connection,
+    ) -> Result<Attachment, Error> {
+        let size = Integer::try_from(bytes.len())
+            .map_err(|_| Error::Invalid(format!("the file '{name}' is too large")))?;
+        let hash = digest::digest(&digest::SHA256, bytes)
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        if !blobs.exists(&hash).await? {
+            blobs.put(&hash, bytes).await?;
+        }
+
+        let owner_type = T::NAME;
+        let owner_id = cache::key(&owner.pk().args());
+        Attachment::create(
+            kwargs!(
+                owner_type = owner_type,
+                owner_id = owner_id.clone(),
+                name = name,
+                mime = mime,
+                size = size,
+                hash = hash.clone(),
+                storage_key = hash.clone()
+            ),
+            conn,
+        )
+        .await?;
+        Attachment::objects()
+            .filter(kwargs!(owner_type == owner_type).and(kwargs!(owner_id == owner_id)))
+            .filter(kwargs!(hash == hash))
+            .order_by(&["-id"])
+            .first(conn)
+            .await
+            .ok_or(Error::Sqlx(sqlx::Error::RowNotFound))
+    }
+
+    /// The files attached to `owner`, in the order they were attached.
+    pub async fn of<T: Model>(owner: &T, conn: &Connection) -> Vec<Attachment> {
+        let owner_type = T::NAME;
+        let owner_id = cache::key(&owner.pk().args());
+        Attachment::objects()
+            .filter(kwargs!(owner_type == owner_type).and(kwargs!(owner_id == owner_id)))
+            .order_by(&["id"])
+            .all(conn)
+            .await
+    }
+
+    /// The content of the file.
+    pub async fn read(&self, blobs: &dyn BlobStore) -> Result<Vec<u8>, Error> {
+        Ok(blobs.get(&self.storage_key).await?)
+    }
+
+    /// Detaches the file, deleting its content unless it is attached elsewhere.
+    pub async fn remove(&self, blobs: &dyn BlobStore, conn: &Connection) -> Result<(), Error> {
+        self.delete(conn).await?;
+        let storage_key = self.storage_key.clone();
+        if !Attachment::exists(kwargs!(storage_key == storage_key), conn).await? {
+            blobs.delete(&self.storage_key).await?;
+        }
+        Ok(())
+    }
+}
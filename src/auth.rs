@@ -0,0 +1,253 @@
+//! Users with hashed passwords and the tokens authenticating them, with the `auth`
+//! feature.
+//!
+//! The passwords are hashed with PBKDF2-HMAC-SHA256 in the format of Django
+//! (`pbkdf2_sha256$iterations$salt$hash`), so the users of a Django application can be
+//! shared. The tokens are random and only their SHA-256 digest is stored, so a leak of
+//! the table does not leak usable tokens.
+//!
+//! # Example
+//! ```ignore
+//! use rusql_alchemy::auth::{self, Token, User};
+//!
+//! migrate!([User, Token], &conn);
+//! let user = User::create_user("joe", "correct horse battery staple", &conn).await?;
+//!
+//! // login, returning the token to the client
+//! if let Some(user) = User::authenticate("joe", "correct horse battery staple", &conn).await {
+//!     let token = user.issue_token(Duration::from_secs(3600), &conn).await?;
+//! }
+//!
+//! // the following requests
+//! let user = match auth::bearer_token(authorization_header) {
+//!     Some(token) => Token::verify(token, &conn).await,
+//!     None => None,
+//! };
+//! ```
+
+use std::num::NonZeroU32;
+use std::time::{Duration, SystemTime};
+
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use ring::{digest, pbkdf2};
+
+use crate::naming::quote_ident;
+use crate::prelude::*;
+use crate::session::timestamp;
+use crate::{Error, PLACEHOLDER};
+
+/// The PBKDF2 iterations of the new password hashes, the ones of the existing hashes
+/// being read from them.
+pub const PBKDF2_ITERATIONS: u32 = 600_000;
+
+const ALGORITHM: &str = "pbkdf2_sha256";
+
+lazy_static::lazy_static! {
+    /// A hash checked against when the user does not exist, so the time of a failed
+    /// login does not tell whether the username exists.
+    static ref DUMMY_HASH: String = make_password("");
+}
+
+/// A user authenticated by a password.
+#[derive(FromRow, Clone, Debug, Default, Model)]
+#[model(table = "auth_users")]
+pub struct User {
+    #[model(primary_key = true, auto = true, null = false)]
+    pub id: Integer,
+    #[model(size = 150, unique = true, null = false)]
+    pub username: String,
+    /// The hash of the password, see `make_password`.
+    #[model(size = 255, null = false, sensitive = true)]
+    pub password: String,
+    /// The inactive users can neither log in nor use their tokens.
+    #[model(default = true)]
+    pub is_active: Boolean,
+    #[model(default = "now")]
+    pub created_at: DateTime,
+}
+
+/// A token authenticating a user until it expires.
+#[derive(FromRow, Clone, Debug, Default, Model)]
+#[model(table = "auth_tokens")]
+pub struct Token {
+    /// The SHA-256 digest of the token, in hexadecimal.
+    #[model(primary_key = true, size = 64, null = false)]
+    pub digest: String,
+    #[model(null = false, foreign_key = "User.id", index = true)]
+    pub user_id: Integer,
+    /// The UTC time the token expires at, as `YYYY-MM-DD HH:MM:SS`.
+    #[model(null = false)]
+    pub expires_at: DateTime,
+    #[model(default = "now")]
+    pub created_at: DateTime,
+}
+
+impl User {
+    /// Creates an active user, hashing its password.
+    pub async fn create_user(
+        username: &str,
+        password: &str,
+        conn: &Connection,
+    ) -> Result<User, Error> {
+        User::create(
+            kwargs!(username = username, password = make_password(password)),
+            conn,
+        )
+        .await?;
+        User::get(kwargs!(username == username), conn)
+            .await
+            .ok_or(Error::Sqlx(sqlx::Error::RowNotFound))
+    }
+
+    /// The active user `username`, if `password` is its password.
+    pub async fn authenticate(username: &str, password: &str, conn: &Connection) -> Option<User> {
+        match User::get(kwargs!(username == username), conn).await {
+            Some(user) if user.check_password(password) && user.is_active != 0 => Some(user),
+            Some(_) => None,
+            None => {
+                check_password(password, &DUMMY_HASH);
+                None
+            }
+        }
+    }
+
+    /// Returns `true` if `password` is the password of the user.
+    pub fn check_password(&self, password: &str) -> bool {
+        check_password(password, &self.password)
+    }
+
+    /// Changes the password of the user, revoking its tokens.
+    pub async fn set_password(&mut self, password: &str, conn: &Connection) -> Result<(), Error> {
+        let hash = make_password(password);
+        self.update_fields(kwargs!(password = hash.clone()), conn)
+            .await?;
+        self.password = hash;
+        self.revoke_tokens(conn).await.map(|_| ())
+    }
+
+    /// Issues a token authenticating the user for `ttl`, returned to the client (only
+    /// its digest is stored).
+    pub async fn issue_token(&self, ttl: Duration, conn: &Connection) -> Result<String, Error> {
+        let token = URL_SAFE_NO_PAD.encode(random_bytes::<32>()?);
+        let expires_at = timestamp(SystemTime::now() + ttl);
+        Token::create(
+            kwargs!(
+                digest = token_digest(&token),
+                user_id = self.id,
+                expires_at = expires_at
+            ),
+            conn,
+        )
+        .await?;
+        Ok(token)
+    }
+
+    /// Revokes every token of the user, e.g. on logout from all the devices, returning
+    /// their count.
+    pub async fn revoke_tokens(&self, conn: &Connection) -> Result<u64, Error> {
+        let placeholder = PLACEHOLDER.to_string();
+        let query = format!(
+            "delete from {table_name} where user_id={placeholder}1;",
+            table_name = quote_ident(Token::NAME)
+        );
+        let stream = sqlx::query(&query).bind(self.id);
+        let result = on_conn!(conn, stream, execute).await?;
+        Ok(result.rows_affected())
+    }
+}
+
+impl Token {
+    /// The active user authenticated by `token`, if it exists and has not expired.
+    pub async fn verify(token: &str, conn: &Connection) -> Option<User> {
+        let digest = token_digest(token);
+        let now = timestamp(SystemTime::now());
+        let token = Token::get(
+            kwargs!(digest == digest).and(kwargs!(expires_at > now)),
+            conn,
+        )
+        .await?;
+        let user_id = token.user_id;
+        User::get(kwargs!(id == user_id).and(kwargs!(is_active == true)), conn).await
+    }
+
+    /// Revokes `token`, e.g. on logout.
+    pub async fn revoke(token: &str, conn: &Connection) -> Result<(), Error> {
+        let token = Token {
+            digest: token_digest(token),
+            ..Default::default()
+        };
+        token.delete(conn).await
+    }
+}
+
+/// Hashes `password` with a random salt, as `pbkdf2_sha256$iterations$salt$hash`.
+pub fn make_password(password: &str) -> String {
+    let salt = match random_bytes::<16>() {
+        Ok(bytes) => URL_SAFE_NO_PAD.encode(bytes),
+        // the salt only needs to be unique
+        Err(_) => format!("{:032x}", fastrand::u128(..)),
+    };
+    let mut hash = [0u8; digest::SHA256_OUTPUT_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+        salt.as_bytes(),
+        password.as_bytes(),
+        &mut hash,
+    );
+    format!(
+        "{ALGORITHM}${PBKDF2_ITERATIONS}${salt}${}",
+        STANDARD.encode(hash)
+    )
+}
+
+/// Returns `true` if `password` matches the `encoded` hash of `make_password`, in
+/// constant time.
+pub fn check_password(password: &str, encoded: &str) -> bool {
+    let mut parts = encoded.splitn(4, '$');
+    let (Some(ALGORITHM), Some(iterations), Some(salt), Some(hash)) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return false;
+    };
+    let (Some(iterations), Ok(hash)) = (
+        iterations.parse().ok().and_then(NonZeroU32::new),
+        STANDARD.decode(hash),
+    ) else {
+        return false;
+    };
+    pbkdf2::verify(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt.as_bytes(),
+        password.as_bytes(),
+        &hash,
+    )
+    .is_ok()
+}
+
+/// The token of an `Authorization: Bearer <token>` header.
+pub fn bearer_token(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.trim().split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
+/// The SHA-256 digest of `token` in hexadecimal, as stored.
+fn token_digest(token: &str) -> String {
+    digest::digest(&digest::SHA256, token.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N], Error> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|err| Error::Invalid(format!("no random bytes generated: {err}")))?;
+    Ok(bytes)
+}
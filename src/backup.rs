@@ -0,0 +1,202 @@
+use std::{fs::File, process::Command};
+
+use percent_encoding::percent_decode_str;
+use sqlx::{Executor, Row};
+use url::Url;
+
+use crate::{get_database_url, Backend, Database, Error, BACKEND};
+
+fn run(mut command: Command) -> Result<(), Error> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .status()
+        .map_err(|err| Error::Io(std::io::Error::new(err.kind(), format!("{program}: {err}"))))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::Io(std::io::Error::other(format!(
+            "{program} exited with {status}"
+        ))))
+    }
+}
+
+/// Parses the database url, for the credentials of the command line tools.
+fn parse_url(database_url: &str) -> Result<Url, Error> {
+    Url::parse(database_url).map_err(|err| Error::Invalid(format!("invalid DATABASE_URL: {err}")))
+}
+
+/// Decodes a percent-encoded part of the url, e.g. a password with a `@`.
+fn decode(part: &str) -> String {
+    percent_decode_str(part).decode_utf8_lossy().into_owned()
+}
+
+/// Builds the `pg_dump`/`psql` command on the database url, its password being passed
+/// by the `PGPASSWORD` variable rather than on the command line, where any process
+/// listing could read it.
+fn postgres_command(program: &str, url: &Url) -> Command {
+    let mut command = Command::new(program);
+    let mut url = url.clone();
+    if let Some(password) = url.password() {
+        command.env("PGPASSWORD", decode(password));
+    }
+    let _ = url.set_password(None);
+    command.arg(format!("--dbname={url}"));
+    command
+}
+
+/// Builds the `mysqldump`/`mysql` connection arguments from the database url, the
+/// password being passed by the `MYSQL_PWD` variable rather than on the command line.
+fn mysql_command(program: &str, url: &Url) -> Command {
+    let mut command = Command::new(program);
+    command
+        .arg(format!("--host={}", url.host_str().unwrap_or("localhost")))
+        .arg(format!("--port={}", url.port().unwrap_or(3306)))
+        .arg(format!("--user={}", decode(url.username())));
+    if let Some(password) = url.password() {
+        command.env("MYSQL_PWD", decode(password));
+    }
+    command
+}
+
+async fn spawn(command: Command) -> Result<(), Error> {
+    tokio::task::spawn_blocking(move || run(command))
+        .await
+        .map_err(|err| Error::Io(std::io::Error::other(err)))?
+}
+
+impl Database {
+    /// Writes a backup of the whole database to `path`.
+    ///
+    /// SQLite databases are copied with `VACUUM INTO`, Postgres and MySQL databases
+    /// are dumped with the `pg_dump` and `mysqldump` tools, which must be installed.
+    ///
+    /// # Arguments
+    /// * `path` - The file the backup is written to.
+    ///
+    /// # Returns
+    /// `Ok` if the backup is successful, the error otherwise (`Error::Io` if the tool
+    /// could not be run or failed).
+    ///
+    /// # Example
+    /// ```ignore
+    /// let database = Database::new().await;
+    /// if let Err(err) = database.backup("backup.sql").await {
+    ///     eprintln!("Backup failed: {err}");
+    /// }
+    /// ```
+    pub async fn backup(&self, path: &str) -> Result<(), Error> {
+        let database_url = get_database_url()?;
+        match *BACKEND {
+            Backend::Sqlite => {
+                let query = format!("vacuum into '{}';", path.replace('\'', "''"));
+                self.conn.execute(query.as_str()).await?;
+                Ok(())
+            }
+            Backend::Postgres => {
+                let mut command = postgres_command("pg_dump", &parse_url(&database_url)?);
+                command
+                    .arg("--clean")
+                    .arg("--if-exists")
+                    .arg(format!("--file={path}"));
+                spawn(command).await
+            }
+            Backend::MySql => {
+                let url = parse_url(&database_url)?;
+                let mut command = mysql_command("mysqldump", &url);
+                command
+                    .arg(format!("--result-file={path}"))
+                    .arg(url.path().trim_start_matches('/'));
+                spawn(command).await
+            }
+        }
+    }
+
+    /// Restores the database from a backup written by `backup`.
+    ///
+    /// Every table of the backup replaces the content of the table with the same name,
+    /// tables which are not in the backup are left untouched.
+    ///
+    /// # Arguments
+    /// * `path` - The backup file.
+    ///
+    /// # Returns
+    /// `Ok` if the restore is successful, the error otherwise.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let database = Database::new().await;
+    /// if let Err(err) = database.restore("backup.sql").await {
+    ///     eprintln!("Restore failed: {err}");
+    /// }
+    /// ```
+    pub async fn restore(&self, path: &str) -> Result<(), Error> {
+        let database_url = get_database_url()?;
+        match *BACKEND {
+            Backend::Sqlite => self.restore_sqlite(path).await,
+            Backend::Postgres => {
+                let mut command = postgres_command("psql", &parse_url(&database_url)?);
+                command.arg("--quiet").arg(format!("--file={path}"));
+                spawn(command).await
+            }
+            Backend::MySql => {
+                let url = parse_url(&database_url)?;
+                let file = File::open(path)?;
+                let mut command = mysql_command("mysql", &url);
+                command.arg(url.path().trim_start_matches('/')).stdin(file);
+                spawn(command).await
+            }
+        }
+    }
+
+    /// Restores the SQLite database from the backup `path`, attached to a connection of
+    /// the pool for the copy and detached whether it succeeds or not, so the connection
+    /// goes back to the pool without it.
+    async fn restore_sqlite(&self, path: &str) -> Result<(), Error> {
+        let mut conn = self.conn.acquire().await?;
+        let attach = format!("attach database '{}' as backup;", path.replace('\'', "''"));
+        conn.execute(attach.as_str()).await?;
+
+        let copied = async {
+            let tables = sqlx::query(
+                "select name, sql from backup.sqlite_master \
+                 where type = 'table' and name not like 'sqlite_%';",
+            )
+            .fetch_all(&mut *conn)
+            .await?;
+
+            let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+            for table in &tables {
+                let name: String = table.get(0);
+                let sql: String = table.get(1);
+                let create = sql.replacen("CREATE TABLE", "CREATE TABLE IF NOT EXISTS", 1);
+                tx.execute(create.as_str()).await?;
+                tx.execute(format!("delete from main.\"{name}\";").as_str())
+                    .await?;
+                // the generated columns (hidden from `table_info`) are not copied
+                let columns: String = sqlx::query_scalar(
+                    "select group_concat('\"' || replace(name, '\"', '\"\"') || '\"', ', ') \
+                     from pragma_table_info(?1, 'backup');",
+                )
+                .bind(&name)
+                .fetch_one(&mut *tx)
+                .await?;
+                tx.execute(
+                    format!(
+                        "insert into main.\"{name}\" ({columns}) \
+                         select {columns} from backup.\"{name}\";"
+                    )
+                    .as_str(),
+                )
+                .await?;
+            }
+            tx.commit().await
+        }
+        .await;
+
+        // the transaction is rolled back when dropped, the database can be detached
+        let detached = conn.execute("detach database backup;").await;
+        copied?;
+        detached?;
+        Ok(())
+    }
+}
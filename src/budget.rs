@@ -0,0 +1,119 @@
+use std::{
+    cell::RefCell,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use crate::{consistency, snapshot, throttle};
+
+/// The limits of a query budget, see `with_query_budget`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueryBudget {
+    /// The maximum number of queries.
+    pub max_queries: Option<usize>,
+    /// The maximum total time spent in queries.
+    pub max_duration: Option<Duration>,
+    /// Refuse the queries once the budget is exceeded instead of only logging it.
+    pub strict: bool,
+}
+
+/// What was spent by the queries run within `with_query_budget`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BudgetReport {
+    pub queries: usize,
+    pub duration: Duration,
+    pub exceeded: bool,
+}
+
+struct BudgetState {
+    budget: QueryBudget,
+    report: BudgetReport,
+}
+
+impl BudgetState {
+    fn is_exceeded(&self) -> bool {
+        self.budget
+            .max_queries
+            .is_some_and(|max_queries| self.report.queries > max_queries)
+            || self
+                .budget
+                .max_duration
+                .is_some_and(|max_duration| self.report.duration > max_duration)
+    }
+}
+
+tokio::task_local! {
+    static BUDGET: RefCell<BudgetState>;
+}
+
+/// Runs `future` with a query budget, typically one request of a web handler.
+///
+/// Every query run by the model methods within `future` counts against the budget.
+/// When it is exceeded the offending query is logged, and with `strict` the following
+/// queries are refused (the methods return `false`/empty results), which helps catching
+/// N+1 queries and runaway handlers in staging.
+///
+/// # Example
+/// ```ignore
+/// let budget = QueryBudget {
+///     max_queries: Some(20),
+///     max_duration: Some(Duration::from_millis(200)),
+///     strict: false,
+/// };
+/// let (users, report) = with_query_budget(budget, User::all(&conn)).await;
+/// println!("{} queries in {:?}", report.queries, report.duration);
+/// ```
+pub async fn with_query_budget<F: Future>(
+    budget: QueryBudget,
+    future: F,
+) -> (F::Output, BudgetReport) {
+    let state = RefCell::new(BudgetState {
+        budget,
+        report: BudgetReport::default(),
+    });
+    BUDGET
+        .scope(state, async {
+            let output = future.await;
+            let report = BUDGET.with(|state| state.borrow().report);
+            (output, report)
+        })
+        .await
+}
+
+/// Runs the query `future` against the current query budget, if any.
+///
+/// # Returns
+/// The output of `future`, or `None` if the query was refused by a strict budget.
+pub async fn track_query<F: Future>(query: &str, future: F) -> Option<F::Output> {
+    let refused = BUDGET
+        .try_with(|state| {
+            let state = state.borrow();
+            state.budget.strict && state.report.exceeded
+        })
+        .unwrap_or(false);
+    if refused {
+        eprintln!("Refused by the query budget\n->{query}");
+        return None;
+    }
+
+    let _permit = throttle::wait().await;
+    let start = Instant::now();
+    let output = future.await;
+    let elapsed = start.elapsed();
+    consistency::record(query);
+    snapshot::record(query);
+
+    let _ = BUDGET.try_with(|state| {
+        let mut state = state.borrow_mut();
+        state.report.queries += 1;
+        state.report.duration += elapsed;
+        if !state.report.exceeded && state.is_exceeded() {
+            state.report.exceeded = true;
+            eprintln!(
+                "Query budget exceeded ({} queries in {:?})\n->{query}",
+                state.report.queries, state.report.duration
+            );
+        }
+    });
+    Some(output)
+}
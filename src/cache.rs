@@ -0,0 +1,130 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    transaction::{self, Shared},
+    types::SqlValue,
+    Connection,
+};
+
+struct Entry {
+    instance: Arc<dyn Any + Send + Sync>,
+    expires: Instant,
+}
+
+/// The table of an invalidation, and the primary key invalidated (`None` for all).
+type Invalidation = (&'static str, Option<String>);
+
+lazy_static::lazy_static! {
+    /// The instances read by `Model::get_cached`, keyed by their table and primary key.
+    static ref CACHE: Mutex<HashMap<(&'static str, String), Entry>> = Mutex::new(HashMap::new());
+    /// The invalidations made within the transactions (keyed by their address), made
+    /// again once committed: a concurrent `get_cached` may cache the committed row
+    /// again until then.
+    static ref DEFERRED: Mutex<HashMap<usize, Vec<Invalidation>>> = Mutex::new(HashMap::new());
+}
+
+/// The key of the primary key `args` in the cache.
+pub(crate) fn key(args: &[SqlValue]) -> String {
+    args.iter()
+        .map(SqlValue::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The cached instance of `table` with the primary key `key`, unless it expired.
+pub(crate) fn get<T: Clone + 'static>(table: &'static str, key: &str) -> Option<T> {
+    let mut cache = CACHE.lock().unwrap();
+    let cache_key = (table, key.to_string());
+    match cache.get(&cache_key) {
+        Some(entry) if entry.expires > Instant::now() => entry.instance.downcast_ref().cloned(),
+        Some(_) => {
+            cache.remove(&cache_key);
+            None
+        }
+        None => None,
+    }
+}
+
+pub(crate) fn insert<T: Send + Sync + 'static>(
+    table: &'static str,
+    key: String,
+    instance: T,
+    ttl: Duration,
+) {
+    let entry = Entry {
+        instance: Arc::new(instance),
+        expires: Instant::now() + ttl,
+    };
+    CACHE.lock().unwrap().insert((table, key), entry);
+}
+
+/// Drops the cached instance of `table` with the primary key `key`, after a write on
+/// `conn`, and again once its transaction is committed.
+pub(crate) fn invalidate(table: &'static str, key: &str, conn: &Connection) {
+    remove(table, Some(key));
+    defer(table, Some(key.to_string()), conn);
+}
+
+/// Drops every cached instance of `table`, after a write to rows not known by key on
+/// `conn`, and again once its transaction is committed.
+pub(crate) fn invalidate_table(table: &'static str, conn: &Connection) {
+    remove(table, None);
+    defer(table, None, conn);
+}
+
+/// Drops the cached instance of `table` with the primary key `key`, or all of them.
+fn remove(table: &'static str, key: Option<&str>) {
+    let mut cache = CACHE.lock().unwrap();
+    if cache.is_empty() {
+        return;
+    }
+    match key {
+        Some(key) => {
+            cache.remove(&(table, key.to_string()));
+        }
+        None => cache.retain(|(cached, _), _| *cached != table),
+    }
+}
+
+fn defer(table: &'static str, key: Option<String>, conn: &Connection) {
+    if let Some(transaction) = transaction::current(conn) {
+        let id = Arc::as_ptr(&transaction) as usize;
+        DEFERRED
+            .lock()
+            .unwrap()
+            .entry(id)
+            .or_default()
+            .push((table, key));
+    }
+}
+
+/// Makes the invalidations deferred by `transaction`, once it is committed (or drops
+/// them once it is rolled back, the cache being cleared anyway).
+pub(crate) fn finish(transaction: &Shared, committed: bool) {
+    let id = Arc::as_ptr(transaction) as usize;
+    let Some(invalidations) = DEFERRED.lock().unwrap().remove(&id) else {
+        return;
+    };
+    if committed {
+        for (table, key) in invalidations {
+            remove(table, key.as_deref());
+        }
+    }
+}
+
+/// Empties the cache of `Model::get_cached`, e.g. after the rows were changed by
+/// another process or by raw SQL.
+///
+/// # Example
+/// ```ignore
+/// sqlx::query("update flags set enabled = 0").execute(&conn).await?;
+/// rusql_alchemy::clear_object_cache();
+/// ```
+pub fn clear_object_cache() {
+    CACHE.lock().unwrap().clear();
+}
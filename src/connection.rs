@@ -0,0 +1,152 @@
+use std::{fmt, ops::Deref};
+
+use futures_util::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    FutureExt, StreamExt,
+};
+use sqlx::{
+    any::{AnyQueryResult, AnyRow, AnyStatement, AnyTypeInfo},
+    Any, Describe, Either, Execute, Executor, Pool,
+};
+
+use crate::transaction::{finished, Shared};
+
+/// A connection to a database: its pool, or a transaction started on the pool by
+/// `Database::transaction`.
+///
+/// The model methods called with a `Transaction` run in the transaction, as do the
+/// queries run on it with `sqlx` (`sqlx::query(...).execute(&tx)`). Once the
+/// transaction is committed or rolled back, they fail.
+#[derive(Clone)]
+pub enum Connection {
+    Pool(Pool<Any>),
+    Transaction(Pool<Any>, Shared),
+}
+
+impl Connection {
+    /// The pool of the connection, the one its transaction was started on.
+    pub fn pool(&self) -> &Pool<Any> {
+        match self {
+            Connection::Pool(pool) | Connection::Transaction(pool, _) => pool,
+        }
+    }
+
+    /// The transaction of the connection, `None` for a pool.
+    pub(crate) fn transaction(&self) -> Option<&Shared> {
+        match self {
+            Connection::Pool(_) => None,
+            Connection::Transaction(_, transaction) => Some(transaction),
+        }
+    }
+}
+
+impl From<Pool<Any>> for Connection {
+    fn from(pool: Pool<Any>) -> Self {
+        Connection::Pool(pool)
+    }
+}
+
+impl Deref for Connection {
+    type Target = Pool<Any>;
+
+    fn deref(&self) -> &Pool<Any> {
+        self.pool()
+    }
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Connection::Pool(pool) => f.debug_tuple("Pool").field(pool).finish(),
+            Connection::Transaction(pool, _) => f.debug_tuple("Transaction").field(pool).finish(),
+        }
+    }
+}
+
+impl<'c> Executor<'c> for &'c Connection {
+    type Database = Any;
+
+    fn fetch_many<'e, 'q: 'e, E>(
+        self,
+        query: E,
+    ) -> BoxStream<'e, Result<Either<AnyQueryResult, AnyRow>, sqlx::Error>>
+    where
+        'c: 'e,
+        E: 'q + Execute<'q, Any>,
+    {
+        match self {
+            Connection::Pool(pool) => pool.fetch_many(query),
+            // the rows are read while the transaction is locked
+            Connection::Transaction(_, transaction) => stream::once(async move {
+                match transaction.lock().await.as_mut() {
+                    Some(transaction) => {
+                        (&mut **transaction)
+                            .fetch_many(query)
+                            .collect::<Vec<_>>()
+                            .await
+                    }
+                    None => vec![Err(finished())],
+                }
+            })
+            .flat_map(stream::iter)
+            .boxed(),
+        }
+    }
+
+    fn fetch_optional<'e, 'q: 'e, E>(
+        self,
+        query: E,
+    ) -> BoxFuture<'e, Result<Option<AnyRow>, sqlx::Error>>
+    where
+        'c: 'e,
+        E: 'q + Execute<'q, Any>,
+    {
+        match self {
+            Connection::Pool(pool) => pool.fetch_optional(query),
+            Connection::Transaction(_, transaction) => async move {
+                match transaction.lock().await.as_mut() {
+                    Some(transaction) => (&mut **transaction).fetch_optional(query).await,
+                    None => Err(finished()),
+                }
+            }
+            .boxed(),
+        }
+    }
+
+    fn prepare_with<'e, 'q: 'e>(
+        self,
+        sql: &'q str,
+        parameters: &'e [AnyTypeInfo],
+    ) -> BoxFuture<'e, Result<AnyStatement<'q>, sqlx::Error>>
+    where
+        'c: 'e,
+    {
+        match self {
+            Connection::Pool(pool) => pool.prepare_with(sql, parameters),
+            Connection::Transaction(_, transaction) => async move {
+                match transaction.lock().await.as_mut() {
+                    Some(transaction) => (&mut **transaction).prepare_with(sql, parameters).await,
+                    None => Err(finished()),
+                }
+            }
+            .boxed(),
+        }
+    }
+
+    fn describe<'e, 'q: 'e>(self, sql: &'q str) -> BoxFuture<'e, Result<Describe<Any>, sqlx::Error>>
+    where
+        'c: 'e,
+    {
+        match self {
+            Connection::Pool(pool) => pool.describe(sql),
+            Connection::Transaction(_, transaction) => async move {
+                match transaction.lock().await.as_mut() {
+                    Some(transaction) => (&mut **transaction).describe(sql).await,
+                    None => Err(finished()),
+                }
+            }
+            .boxed(),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::{
+    cell::Cell,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+tokio::task_local! {
+    static LAST_WRITE: Cell<Option<Instant>>;
+}
+
+/// Runs `future` as one read-your-writes context, typically one request.
+///
+/// The writes run within `future` are remembered, and `Database::reader` returns the
+/// primary database instead of a replica for the reads following them (during the
+/// stickiness window), so they never observe data older than their own writes.
+///
+/// # Example
+/// ```ignore
+/// read_your_writes(async {
+///     User::create(kwargs!(name = "joe"), database.writer()).await;
+///     // goes to the primary, the replicas may not have the new user yet
+///     let users = User::all(database.reader()).await;
+/// })
+/// .await;
+/// ```
+pub async fn read_your_writes<F: Future>(future: F) -> F::Output {
+    LAST_WRITE.scope(Cell::new(None), future).await
+}
+
+/// Remembers that `query` was run, if it is a write.
+pub(crate) fn record(query: &str) {
+    let statement = query.trim_start().to_lowercase();
+    let is_write = ["insert", "update", "delete"]
+        .iter()
+        .any(|write| statement.starts_with(write));
+    if is_write {
+        let _ = LAST_WRITE.try_with(|last_write| last_write.set(Some(Instant::now())));
+    }
+}
+
+/// Returns `true` if the current context wrote during the last `window`.
+pub(crate) fn wrote_within(window: Duration) -> bool {
+    LAST_WRITE
+        .try_with(|last_write| last_write.get())
+        .ok()
+        .flatten()
+        .is_some_and(|last_write| last_write.elapsed() < window)
+}
@@ -0,0 +1,351 @@
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use super::export::row_to_json;
+use super::expression::ToSql;
+use super::models::{
+    comparison_lookup, number_placeholders, select_conditions, selected_columns, Condition,
+    ForeignKey, Model,
+};
+use crate::naming::quote_ident;
+use crate::telemetry::track_operation;
+use crate::types::SqlValue;
+use crate::{Connection, Error};
+
+/// The arguments bound to the `?` of an expression.
+type Args = Vec<SqlValue>;
+
+/// An aggregation query over a model, built by `Model::aggregate`.
+///
+/// # Example
+/// ```ignore
+/// // the owners having more than 3 products of more than 10
+/// let owners = Product::aggregate(&conn)
+///     .group_by(&["owner"])
+///     .count("total")
+///     .filter(kwargs!(price > 10.0))
+///     .having(kwargs!(total > 3))
+///     .run()
+///     .await;
+/// for owner in owners {
+///     println!("{} has {} products", owner["owner"], owner["total"]);
+/// }
+/// ```
+pub struct Aggregate<'c, T> {
+    conn: &'c Connection,
+    group_by: Vec<String>,
+    // (expression, arguments, alias)
+    annotations: Vec<(String, Args, String)>,
+    aggregates: Vec<(String, String)>,
+    filter: Vec<Condition>,
+    having: Vec<Condition>,
+    order_by: Vec<(String, Args)>,
+    _model: PhantomData<T>,
+}
+
+impl<'c, T: Model> Aggregate<'c, T> {
+    pub(crate) fn new(conn: &'c Connection) -> Self {
+        Self {
+            conn,
+            group_by: Vec::new(),
+            annotations: Vec::new(),
+            aggregates: Vec::new(),
+            filter: Vec::new(),
+            having: Vec::new(),
+            order_by: Vec::new(),
+            _model: PhantomData,
+        }
+    }
+
+    /// Groups the rows by `columns`, which are returned with the aggregates.
+    pub fn group_by(mut self, columns: &[&str]) -> Self {
+        self.group_by
+            .extend(columns.iter().map(|column| column.to_string()));
+        self
+    }
+
+    /// Computes `expression` as `alias`, grouping by it when there are aggregates.
+    pub fn annotate(mut self, alias: &str, expression: impl ToSql) -> Self {
+        self.annotations
+            .push((expression.to_sql(), expression.args(), alias.to_string()));
+        self
+    }
+
+    /// Counts the rows (of each group) as `alias`.
+    pub fn count(mut self, alias: &str) -> Self {
+        self.aggregates
+            .push(("count(*)".to_string(), alias.to_string()));
+        self
+    }
+
+    /// Sums `column` (of each group) as `sum_{column}`.
+    pub fn sum(self, column: &str) -> Self {
+        self.aggregate("sum", column)
+    }
+
+    /// Averages `column` (of each group) as `avg_{column}`.
+    pub fn avg(self, column: &str) -> Self {
+        self.aggregate("avg", column)
+    }
+
+    /// Computes the minimum of `column` (of each group) as `min_{column}`.
+    pub fn min(self, column: &str) -> Self {
+        self.aggregate("min", column)
+    }
+
+    /// Computes the maximum of `column` (of each group) as `max_{column}`.
+    pub fn max(self, column: &str) -> Self {
+        self.aggregate("max", column)
+    }
+
+    fn aggregate(mut self, function: &str, column: &str) -> Self {
+        self.aggregates.push((
+            format!("{function}({column})"),
+            format!("{function}_{column}"),
+        ));
+        self
+    }
+
+    /// Filters the rows before the aggregation (the `where` clause).
+    pub fn filter(mut self, kw: Vec<Condition>) -> Self {
+        self.filter = kw;
+        self
+    }
+
+    /// Filters the groups on their aggregates (the `having` clause), referenced by
+    /// their alias.
+    pub fn having(mut self, kw: Vec<Condition>) -> Self {
+        self.having = kw;
+        self
+    }
+
+    /// Orders the result by `expression`, a column (e.g. `UserColumn::Name`), an alias
+    /// or a function.
+    pub fn order_by(mut self, expression: impl ToSql) -> Self {
+        self.order_by.push((expression.to_sql(), expression.args()));
+        self
+    }
+
+    /// Orders the result by `expression`, descending.
+    pub fn order_by_desc(mut self, expression: impl ToSql) -> Self {
+        self.order_by
+            .push((format!("{} desc", expression.to_sql()), expression.args()));
+        self
+    }
+
+    /// Runs the aggregation, deserializing each group into `R` whose fields are named
+    /// after the grouped columns and the aggregate aliases. The aggregates of no rows
+    /// are `null`, so they are read as an `Option`.
+    ///
+    /// # Returns
+    /// One `R` per group, or the error of the first group not matching `R`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[derive(serde::Deserialize)]
+    /// struct Prices {
+    ///     sum_price: Option<f64>,
+    ///     min_price: Option<f64>,
+    /// }
+    ///
+    /// let prices = Product::aggregate(&conn)
+    ///     .sum("price")
+    ///     .min("price")
+    ///     .run_as::<Prices>()
+    ///     .await?;
+    /// ```
+    pub async fn run_as<R: DeserializeOwned>(self) -> Result<Vec<R>, serde_json::Error> {
+        self.run()
+            .await
+            .into_iter()
+            .map(|group| serde_json::from_value(Value::Object(group)))
+            .collect()
+    }
+
+    /// Runs the aggregation.
+    ///
+    /// # Returns
+    /// One JSON object per group, keyed by the grouped columns and the aggregate
+    /// aliases.
+    pub async fn run(self) -> Vec<Map<String, Value>> {
+        let mut args = Vec::new();
+        let mut columns = self.group_by.clone();
+        for (expression, values, alias) in &self.annotations {
+            let expression = number_placeholders(expression, values, &mut args);
+            columns.push(format!("{expression} as {alias}"));
+        }
+        columns.extend(
+            self.aggregates
+                .iter()
+                .map(|(expression, alias)| format!("{expression} as {alias}")),
+        );
+
+        let mut query = format!(
+            "select {columns} from {table_name}",
+            columns = columns.join(", "),
+            table_name = quote_ident(T::NAME)
+        );
+        if !self.filter.is_empty() {
+            let conditions = select_conditions(&self.filter, T::RANGES, &mut args);
+            query.push_str(&format!(" where {conditions}"));
+        }
+        let mut group_by = self.group_by.clone();
+        if !self.aggregates.is_empty() {
+            // by position, the bound arguments of an expression being numbered anew
+            let first = self.group_by.len() + 1;
+            group_by.extend((first..first + self.annotations.len()).map(|index| index.to_string()));
+        }
+        if !group_by.is_empty() {
+            query.push_str(&format!(" group by {}", group_by.join(", ")));
+        }
+        if !self.having.is_empty() {
+            // the aliases of the select can not be used in the `having` clause
+            let having = self
+                .having
+                .iter()
+                .map(|condition| match condition {
+                    Condition::FieldCondition {
+                        field,
+                        value,
+                        comparaison_operator,
+                    } => {
+                        let (field, comparaison_operator) =
+                            comparison_lookup(field).unwrap_or((field, comparaison_operator));
+                        match self.aliased(field) {
+                            (expression, values) if values.is_empty() => {
+                                Condition::FieldCondition {
+                                    field: expression,
+                                    value: value.clone(),
+                                    comparaison_operator: comparaison_operator.to_string(),
+                                }
+                            }
+                            (expression, mut values) => {
+                                values.push(value.clone());
+                                Condition::Raw {
+                                    sql: format!("{expression}{comparaison_operator}?"),
+                                    args: values,
+                                }
+                            }
+                        }
+                    }
+                    Condition::Expression {
+                        field,
+                        sql,
+                        args,
+                        comparaison_operator,
+                    } => {
+                        let (field, comparaison_operator) =
+                            comparison_lookup(field).unwrap_or((field, comparaison_operator));
+                        let (expression, mut values) = self.aliased(field);
+                        values.extend(args.iter().cloned());
+                        Condition::Raw {
+                            sql: format!("{expression}{comparaison_operator}{sql}"),
+                            args: values,
+                        }
+                    }
+                    condition => condition.clone(),
+                })
+                .collect::<Vec<_>>();
+            let conditions = select_conditions(&having, T::RANGES, &mut args);
+            query.push_str(&format!(" having {conditions}"));
+        }
+        if !self.order_by.is_empty() {
+            let order_by = self
+                .order_by
+                .iter()
+                .map(|(expression, values)| number_placeholders(expression, values, &mut args))
+                .collect::<Vec<_>>();
+            query.push_str(&format!(" order by {}", order_by.join(", ")));
+        }
+        query.push(';');
+
+        let mut stream = sqlx::query(&query);
+        binds!(args, stream, Vec::new());
+        match track_operation(
+            T::NAME,
+            "aggregate",
+            &query,
+            on_conn!(self.conn, stream, fetch_all),
+        )
+        .await
+        {
+            Some(Ok(rows)) => rows.iter().map(row_to_json).collect(),
+            Some(Err(err)) => {
+                eprintln!("Error during the aggregation\n->{err}");
+                Vec::new()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// The expression (and its arguments) of the annotation or aggregate named `field`,
+    /// `field` itself if it is a column.
+    fn aliased(&self, field: &str) -> (String, Args) {
+        if let Some((expression, args, _)) =
+            self.annotations.iter().find(|(_, _, alias)| alias == field)
+        {
+            return (expression.clone(), args.clone());
+        }
+        let expression = self
+            .aggregates
+            .iter()
+            .find(|(_, alias)| alias == field)
+            .map_or(field, |(expression, _)| expression);
+        (expression.to_string(), Vec::new())
+    }
+}
+
+/// The count of the rows of a related model referencing each instance through a
+/// foreign key, computed as `alias` and filtered on by `Model::filter_annotated`.
+#[derive(Debug, Clone)]
+pub struct RelatedAggregate {
+    alias: String,
+    table: &'static str,
+    foreign_keys: &'static [ForeignKey],
+}
+
+/// Counts the rows of `M` referencing each instance as `alias`, see
+/// `Model::filter_annotated`.
+pub fn count_related<M: Model>(alias: &str) -> RelatedAggregate {
+    RelatedAggregate {
+        alias: alias.to_string(),
+        table: M::NAME,
+        foreign_keys: M::FOREIGN_KEYS,
+    }
+}
+
+impl RelatedAggregate {
+    /// Renders the rows of `T` annotated with the aggregate, as a derived table named
+    /// after `T` (joining the related rows and grouping them by the primary key of `T`),
+    /// or `Error::Invalid` if no foreign key of the related model references `T`.
+    pub(crate) fn annotated_table<T: Model>(&self) -> Result<String, Error> {
+        let Some(key) = self.foreign_keys.iter().find(|key| key.table == T::NAME) else {
+            return Err(Error::Invalid(format!(
+                "No foreign key of {} references {}",
+                self.table,
+                T::NAME
+            )));
+        };
+        let (model, related, alias) = (quote_ident(T::NAME), quote_ident(self.table), &self.alias);
+        let group_by = if T::PK_COLUMNS.is_empty() {
+            &[T::PK][..]
+        } else {
+            T::PK_COLUMNS
+        }
+        .iter()
+        .map(|column| format!("{model}.{column}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+        // the joined table is aliased as well, in case `T` references itself
+        Ok(format!(
+            "(select {columns}, count({alias}.{field}) as {alias} from {model} \
+             left join {related} as {alias} on {alias}.{field}={model}.{column} \
+             group by {group_by}) as {model}",
+            columns = selected_columns::<T>(Some(&model)),
+            field = key.field,
+            column = key.column,
+        ))
+    }
+}
@@ -0,0 +1,90 @@
+use serde_json::{Map, Value};
+use sqlx::{any::AnyRow, Column, Row};
+
+/// Converts a row into a JSON object keyed by column name.
+pub fn row_to_json(row: &AnyRow) -> Map<String, Value> {
+    let mut object = Map::new();
+    for column in row.columns() {
+        let index = column.ordinal();
+        let value = if let Ok(value) = row.try_get::<Option<i64>, _>(index) {
+            value.map_or(Value::Null, Value::from)
+        } else if let Ok(value) = row.try_get::<Option<f64>, _>(index) {
+            value.map_or(Value::Null, Value::from)
+        } else if let Ok(value) = row.try_get::<Option<String>, _>(index) {
+            value.map_or(Value::Null, Value::from)
+        } else if let Ok(value) = row.try_get::<Option<bool>, _>(index) {
+            value.map_or(Value::Null, Value::from)
+        } else {
+            Value::Null
+        };
+        object.insert(column.name().to_string(), value);
+    }
+    object
+}
+
+/// Renders rows of cells as an ASCII table.
+pub fn render_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let widths = header
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            rows.iter()
+                .map(|row| row[index].chars().count())
+                .chain([field.chars().count()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    let separator = widths
+        .iter()
+        .map(|width| format!("+{}", "-".repeat(width + 2)))
+        .collect::<String>()
+        + "+\n";
+    let line = |values: Vec<&str>| {
+        values
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("| {value:<width$} "))
+            .collect::<String>()
+            + "|\n"
+    };
+
+    let mut table = separator.clone();
+    table.push_str(&line(header.to_vec()));
+    table.push_str(&separator);
+    for row in rows {
+        table.push_str(&line(row.iter().map(String::as_str).collect()));
+    }
+    if !rows.is_empty() {
+        table.push_str(&separator);
+    }
+    table
+}
+
+/// Replaces `value` according to the anonymization `strategy`.
+///
+/// `index` is the position of the row in the dump, used to keep the fake values
+/// unique so they still satisfy unique constraints.
+pub fn anonymize(strategy: &str, value: &Value, index: usize) -> Value {
+    if value.is_null() {
+        return Value::Null;
+    }
+    match strategy {
+        "fake_email" => format!("user{index}@example.com").into(),
+        "fake_name" => format!("User {index}").into(),
+        "fake_phone" => format!("+1555{index:07}").into(),
+        "mask" => {
+            let text = value
+                .as_str()
+                .map_or_else(|| value.to_string(), String::from);
+            let mut chars = text.chars();
+            chars
+                .next()
+                .map(|first| format!("{first}{}", "*".repeat(chars.count())))
+                .unwrap_or_default()
+                .into()
+        }
+        "redact" => "REDACTED".into(),
+        _ => Value::Null,
+    }
+}
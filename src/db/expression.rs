@@ -0,0 +1,572 @@
+use std::ops::{Add, Sub};
+
+use super::models::{marked_conditions, Column, Condition};
+use crate::{types::SqlValue, Backend, BACKEND};
+
+/// A value rendered as SQL instead of being bound, usable in annotations.
+pub trait ToSql {
+    /// The SQL of the value, a `?` marking each of its arguments.
+    fn to_sql(&self) -> String;
+
+    /// The arguments bound to the `?` of `to_sql`, in order.
+    fn args(&self) -> Vec<SqlValue> {
+        Vec::new()
+    }
+}
+
+/// A value of the `kwargs!` conditions, bound as an argument, or rendered as SQL for
+/// the expressions (`F("age") + 1`, a `Case` or a function).
+pub trait Kwarg {
+    /// The condition comparing `field` with (or setting it to) the value.
+    fn condition(self, field: &str, operator: &str) -> Condition;
+}
+
+impl<T: Into<SqlValue>> Kwarg for T {
+    fn condition(self, field: &str, operator: &str) -> Condition {
+        Condition::FieldCondition {
+            field: field.to_string(),
+            value: self.into(),
+            comparaison_operator: operator.to_string(),
+        }
+    }
+}
+
+macro_rules! expressions {
+    ($($type:ty),*) => {
+        $(
+            impl Kwarg for $type {
+                fn condition(self, field: &str, operator: &str) -> Condition {
+                    Condition::Expression {
+                        field: field.to_string(),
+                        sql: self.to_sql(),
+                        args: self.args(),
+                        comparaison_operator: operator.to_string(),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+expressions!(F<'_>, Expression, Case, Func);
+
+/// A reference to a column, used to compute a new value from the current one in SQL.
+///
+/// # Example
+/// ```ignore
+/// user.update_fields(kwargs!(age = F("age") + 1), &conn).await;
+/// subscription.update_fields(kwargs!(expires_at = F("expires_at") + days(30)), &conn).await;
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct F<'a>(pub &'a str);
+
+impl<C: Column> ToSql for C {
+    fn to_sql(&self) -> String {
+        self.name().to_string()
+    }
+}
+
+impl ToSql for F<'_> {
+    fn to_sql(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntervalUnit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Month,
+    Year,
+}
+
+impl IntervalUnit {
+    fn name(self) -> &'static str {
+        match self {
+            IntervalUnit::Second => "second",
+            IntervalUnit::Minute => "minute",
+            IntervalUnit::Hour => "hour",
+            IntervalUnit::Day => "day",
+            IntervalUnit::Month => "month",
+            IntervalUnit::Year => "year",
+        }
+    }
+}
+
+/// A duration added to or subtracted from a `Date`/`DateTime` column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub amount: i64,
+    pub unit: IntervalUnit,
+}
+
+pub fn seconds(amount: i64) -> Interval {
+    Interval {
+        amount,
+        unit: IntervalUnit::Second,
+    }
+}
+
+pub fn minutes(amount: i64) -> Interval {
+    Interval {
+        amount,
+        unit: IntervalUnit::Minute,
+    }
+}
+
+pub fn hours(amount: i64) -> Interval {
+    Interval {
+        amount,
+        unit: IntervalUnit::Hour,
+    }
+}
+
+pub fn days(amount: i64) -> Interval {
+    Interval {
+        amount,
+        unit: IntervalUnit::Day,
+    }
+}
+
+pub fn months(amount: i64) -> Interval {
+    Interval {
+        amount,
+        unit: IntervalUnit::Month,
+    }
+}
+
+pub fn years(amount: i64) -> Interval {
+    Interval {
+        amount,
+        unit: IntervalUnit::Year,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Number(f64),
+    Interval(Interval),
+}
+
+/// An arithmetic expression on a column, rendered as SQL for the current backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    column: String,
+    negative: bool,
+    operand: Operand,
+}
+
+impl Expression {
+    /// Renders the expression for the current backend.
+    pub fn to_sql(&self) -> String {
+        let column = &self.column;
+        let sign = if self.negative { "-" } else { "+" };
+        match self.operand {
+            Operand::Number(number) => format!("{column} {sign} {number}"),
+            Operand::Interval(Interval { amount, unit }) => {
+                let unit = unit.name();
+                match *BACKEND {
+                    Backend::Postgres => format!(
+                        "cast(cast({column} as timestamp) {sign} interval '{amount} {unit}' as varchar)"
+                    ),
+                    Backend::MySql => {
+                        let function = if self.negative { "date_sub" } else { "date_add" };
+                        format!("{function}({column}, interval {amount} {unit})")
+                    }
+                    Backend::Sqlite => {
+                        format!("datetime({column}, '{sign}{amount} {unit}s')")
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl ToSql for Expression {
+    fn to_sql(&self) -> String {
+        Expression::to_sql(self)
+    }
+}
+
+/// A conditional expression, the value of the first matching branch or the
+/// `otherwise` one (`null` if not given).
+///
+/// # Example
+/// ```ignore
+/// // categorize all the products in one statement
+/// Product::update_where(
+///     kwargs!(active == true),
+///     kwargs!(category = Case::when(kwargs!(price > 100.0), "premium").otherwise("standard")),
+///     &conn,
+/// ).await;
+///
+/// // or compute the category in an annotation
+/// let categories = Product::aggregate(&conn)
+///     .annotate("category", Case::when(kwargs!(price > 100.0), "premium").otherwise("standard"))
+///     .count("total")
+///     .run()
+///     .await;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    branches: Vec<(String, Vec<SqlValue>)>,
+    otherwise: Option<SqlValue>,
+}
+
+impl Case {
+    /// Starts the expression with a first branch.
+    pub fn when(condition: Vec<Condition>, then: impl Into<SqlValue>) -> Self {
+        Self {
+            branches: Vec::new(),
+            otherwise: None,
+        }
+        .or_when(condition, then)
+    }
+
+    /// Adds a branch, tested after the previous ones.
+    pub fn or_when<T: Into<SqlValue>>(mut self, condition: Vec<Condition>, then: T) -> Self {
+        let mut args = Vec::new();
+        let condition = marked_conditions(&condition, &[], &mut args);
+        args.push(then.into());
+        self.branches
+            .push((format!(" when {condition} then ?"), args));
+        self
+    }
+
+    /// Sets the value when no branch matches.
+    pub fn otherwise<T: Into<SqlValue>>(mut self, value: T) -> Self {
+        self.otherwise = Some(value.into());
+        self
+    }
+}
+
+impl ToSql for Case {
+    fn to_sql(&self) -> String {
+        let mut sql = "case".to_string();
+        for (branch, _) in &self.branches {
+            sql.push_str(branch);
+        }
+        if self.otherwise.is_some() {
+            sql.push_str(" else ?");
+        }
+        sql.push_str(" end");
+        sql
+    }
+
+    fn args(&self) -> Vec<SqlValue> {
+        self.branches
+            .iter()
+            .flat_map(|(_, args)| args.iter().cloned())
+            .chain(self.otherwise.clone())
+            .collect()
+    }
+}
+
+/// A SQL function call, built by `coalesce`, `nullif`, `lower`, `length`, `abs` and
+/// `now`.
+///
+/// # Example
+/// ```ignore
+/// // as a filter value
+/// let expired = Session::filter(kwargs!(expires_at < now()), &conn).await;
+///
+/// // as a filtered column
+/// let users = User::filter(lower(F("email")).compare("=", "john@example.com"), &conn).await;
+///
+/// // in annotations and ordering
+/// let names = User::aggregate(&conn)
+///     .annotate("name", coalesce(F("nickname"), "anonymous"))
+///     .order_by_desc(length(F("name")))
+///     .run()
+///     .await;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Func {
+    sql: String,
+    args: Vec<SqlValue>,
+}
+
+impl Func {
+    /// Compares the result of the function with `value`, as kwargs conditions.
+    pub fn compare<T: Into<SqlValue>>(&self, operator: &str, value: T) -> Vec<Condition> {
+        let mut args = self.args.clone();
+        args.push(value.into());
+        vec![Condition::Raw {
+            sql: format!("{}{operator}?", self.sql),
+            args,
+        }]
+    }
+}
+
+impl ToSql for Func {
+    fn to_sql(&self) -> String {
+        self.sql.clone()
+    }
+
+    fn args(&self) -> Vec<SqlValue> {
+        self.args.clone()
+    }
+}
+
+/// Calls the SQL function `name` with `values`.
+fn call(name: &str, values: &[&dyn ToSql]) -> Func {
+    Func {
+        sql: format!(
+            "{name}({})",
+            values
+                .iter()
+                .map(|value| value.to_sql())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        args: values.iter().flat_map(|value| value.args()).collect(),
+    }
+}
+
+/// The first of the two values which is not `null`.
+pub fn coalesce(value: impl ToSql, default: impl ToSql) -> Func {
+    call("coalesce", &[&value, &default])
+}
+
+/// `null` if the two values are equal, the first one otherwise.
+pub fn nullif(value: impl ToSql, other: impl ToSql) -> Func {
+    call("nullif", &[&value, &other])
+}
+
+pub fn lower(value: impl ToSql) -> Func {
+    call("lower", &[&value])
+}
+
+/// The number of characters of the value.
+pub fn length(value: impl ToSql) -> Func {
+    let function = if *BACKEND == Backend::MySql {
+        "char_length"
+    } else {
+        "length"
+    };
+    call(function, &[&value])
+}
+
+pub fn abs(value: impl ToSql) -> Func {
+    call("abs", &[&value])
+}
+
+/// The current date and time, as stored by the `default = "now"` columns.
+pub fn now() -> Func {
+    Func {
+        sql: "current_timestamp".to_string(),
+        args: Vec::new(),
+    }
+}
+
+/// The value of `locale` in a `Translated` column, `null` if it has none.
+pub fn localized(value: impl ToSql, locale: &str) -> Func {
+    let (sql, key) = match *BACKEND {
+        Backend::Postgres => ("(cast({value} as jsonb) ->> ?)", locale.to_string()),
+        Backend::MySql => (
+            "json_unquote(json_extract({value}, ?))",
+            format!("$.\"{}\"", locale.replace('"', "")),
+        ),
+        _ => (
+            "json_extract({value}, ?)",
+            format!("$.\"{}\"", locale.replace('"', "")),
+        ),
+    };
+    let mut args = value.args();
+    args.push(key.into());
+    Func {
+        sql: sql.replace("{value}", &value.to_sql()),
+        args,
+    }
+}
+
+/// The position of the `null` values in an ordering, see `OrderBy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nulls {
+    First,
+    Last,
+}
+
+impl Nulls {
+    /// Parses the ` nulls first` or ` nulls last` suffix of an ordering path, returning
+    /// the path without it.
+    pub(crate) fn strip(path: &str) -> (&str, Option<Nulls>) {
+        if let Some(path) = path.strip_suffix(" nulls first") {
+            (path, Some(Nulls::First))
+        } else if let Some(path) = path.strip_suffix(" nulls last") {
+            (path, Some(Nulls::Last))
+        } else {
+            (path, None)
+        }
+    }
+}
+
+/// An ordering of an aggregation, placing the `null` values first or last.
+///
+/// # Example
+/// ```ignore
+/// // the most expensive products first, the ones without a price at the end
+/// let products = Product::aggregate(&conn)
+///     .annotate("name", F("name"))
+///     .order_by(desc(ProductColumn::Price).nulls_last())
+///     .run()
+///     .await;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
+    expression: String,
+    args: Vec<SqlValue>,
+    descending: bool,
+    nulls: Option<Nulls>,
+}
+
+/// Orders by `expression`, ascending.
+pub fn asc(expression: impl ToSql) -> OrderBy {
+    OrderBy {
+        expression: expression.to_sql(),
+        args: expression.args(),
+        descending: false,
+        nulls: None,
+    }
+}
+
+/// Orders by `expression`, descending.
+pub fn desc(expression: impl ToSql) -> OrderBy {
+    OrderBy {
+        descending: true,
+        ..asc(expression)
+    }
+}
+
+impl OrderBy {
+    pub fn nulls_first(mut self) -> Self {
+        self.nulls = Some(Nulls::First);
+        self
+    }
+
+    pub fn nulls_last(mut self) -> Self {
+        self.nulls = Some(Nulls::Last);
+        self
+    }
+
+    /// The ordering in the reverse direction, the `null` values moving to the other end.
+    pub(crate) fn reversed(&self) -> Self {
+        Self {
+            descending: !self.descending,
+            nulls: self.nulls.map(|nulls| match nulls {
+                Nulls::First => Nulls::Last,
+                Nulls::Last => Nulls::First,
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// The `-column nulls last` path of the ordering by a bare column, `None` for an
+    /// expression.
+    pub(crate) fn path(&self) -> Option<String> {
+        let column = !self.expression.is_empty()
+            && self.args.is_empty()
+            && self
+                .expression
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !column {
+            return None;
+        }
+        let direction = if self.descending { "-" } else { "" };
+        let nulls = match self.nulls {
+            Some(Nulls::First) => " nulls first",
+            Some(Nulls::Last) => " nulls last",
+            None => "",
+        };
+        Some(format!("{direction}{}{nulls}", self.expression))
+    }
+}
+
+impl ToSql for OrderBy {
+    fn to_sql(&self) -> String {
+        order_sql(&self.expression, self.descending, self.nulls)
+    }
+
+    fn args(&self) -> Vec<SqlValue> {
+        // the expression is rendered twice where `nulls first`/`nulls last` is emulated
+        if self.nulls.is_some() && *BACKEND != Backend::Postgres {
+            [self.args.clone(), self.args.clone()].concat()
+        } else {
+            self.args.clone()
+        }
+    }
+}
+
+/// Renders the ordering by `expression`, with `nulls first`/`nulls last` on Postgres
+/// and sorting by `expression is null` first on the backends lacking them.
+pub(crate) fn order_sql(expression: &str, descending: bool, nulls: Option<Nulls>) -> String {
+    let direction = if descending { " desc" } else { "" };
+    match nulls {
+        None => format!("{expression}{direction}"),
+        Some(Nulls::First) if *BACKEND == Backend::Postgres => {
+            format!("{expression}{direction} nulls first")
+        }
+        Some(Nulls::Last) if *BACKEND == Backend::Postgres => {
+            format!("{expression}{direction} nulls last")
+        }
+        Some(Nulls::First) => format!("{expression} is not null, {expression}{direction}"),
+        Some(Nulls::Last) => format!("{expression} is null, {expression}{direction}"),
+    }
+}
+
+macro_rules! literals {
+    ($($type:ty),*) => {
+        $(
+            impl ToSql for $type {
+                fn to_sql(&self) -> String {
+                    "?".to_string()
+                }
+
+                fn args(&self) -> Vec<SqlValue> {
+                    vec![self.clone().into()]
+                }
+            }
+        )*
+    };
+}
+
+literals!(&str, String, i32, i64, f64, bool);
+
+macro_rules! arithmetic {
+    ($($operand:ty => $variant:expr),*) => {
+        $(
+            impl Add<$operand> for F<'_> {
+                type Output = Expression;
+
+                fn add(self, operand: $operand) -> Expression {
+                    Expression {
+                        column: self.0.to_string(),
+                        negative: false,
+                        operand: $variant(operand),
+                    }
+                }
+            }
+
+            impl Sub<$operand> for F<'_> {
+                type Output = Expression;
+
+                fn sub(self, operand: $operand) -> Expression {
+                    Expression {
+                        column: self.0.to_string(),
+                        negative: true,
+                        operand: $variant(operand),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+arithmetic!(
+    Interval => Operand::Interval,
+    i32 => |n: i32| Operand::Number(n.into()),
+    f64 => Operand::Number
+);
@@ -1 +1,6 @@
-pub mod models;
\ No newline at end of file
+pub mod aggregate;
+pub mod export;
+pub mod expression;
+pub mod models;
+pub mod queryset;
+pub mod store;
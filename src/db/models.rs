@@ -1,23 +1,135 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    marker::PhantomData,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use futures_util::{Stream, StreamExt, TryStreamExt};
 use lazy_static::lazy_static;
-use sqlx::{any::AnyRow, FromRow, Row};
+use serde::de::DeserializeOwned;
+use sqlx::{any::AnyRow, Any, Connection as _, FromRow, Row, Transaction};
 
-use crate::{get_placeholder, get_type_name, Connection};
+use super::aggregate::{Aggregate, RelatedAggregate};
+use super::export::{anonymize, render_table, row_to_json};
+use super::expression::{order_sql, Nulls};
+use super::queryset::{keyset_condition, OrderField, Page, QuerySet};
+use crate::cache;
+use crate::guardrail::{check_rows, check_statement, row_limit, soft_max_rows};
+use crate::migration::{column_type, live_columns};
+use crate::naming::{index_name, named_constraints, quote_ident, resolve_references};
+use crate::session::timestamp;
+use crate::telemetry::track_operation;
+use crate::track_query;
+use crate::transaction;
+use crate::types::SqlValue;
+use crate::{get_backend, get_placeholder, Backend, Connection, Error};
 
 lazy_static! {
     pub static ref PLACEHOLDER: &'static str = get_placeholder().unwrap_or("?");
+    pub static ref BACKEND: Backend = get_backend().unwrap_or(Backend::Sqlite);
 }
 
-#[derive(Debug)]
+/// The maximum number of expired rows processed in one transaction by
+/// `Model::run_retention`.
+pub const RETENTION_CHUNK_SIZE: usize = 500;
+
+/// The maximum number of bound parameters in one `copy_in` statement (SQLite's
+/// historical limit, well below the Postgres and MySQL ones).
+pub const COPY_IN_MAX_PARAMS: usize = 999;
+
+/// The maximum number of `select` composed by one `union` (SQLite's default limit).
+const COMPOUND_SELECT_MAX: usize = 500;
+
+/// The Postgres cursors of `Model::cursor` are numbered, so the cursors open at once in
+/// a same transaction have unique names.
+static CURSORS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone)]
 pub enum Condition {
     FieldCondition {
         field: String,
-        value: String,
-        value_type: String,
+        value: SqlValue,
         comparaison_operator: String,
     },
     LogicalOperator {
         operator: String,
     },
+    /// A field compared with (or set to) a SQL expression, e.g. `F("age") + 1`, rendered
+    /// instead of being bound, its `?` being bound to the `args`, see
+    /// `Kwarg`.
+    Expression {
+        field: String,
+        sql: String,
+        args: Vec<SqlValue>,
+        comparaison_operator: String,
+    },
+    /// A raw SQL predicate, its `?` being bound to the `args`, see `raw!`.
+    Raw {
+        sql: String,
+        args: Vec<SqlValue>,
+    },
+}
+
+/// A field whose value differs between two instances, see `Model::diff`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old: serde_json::Value,
+    pub new: serde_json::Value,
+}
+
+/// Whether `Model::upsert` inserted the row or updated the existing one, holding the
+/// row as stored.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Upserted<T> {
+    Created(T),
+    Updated(T),
+}
+
+impl<T> Upserted<T> {
+    /// Returns the row, whether it was created or updated.
+    pub fn into_inner(self) -> T {
+        match self {
+            Upserted::Created(row) | Upserted::Updated(row) => row,
+        }
+    }
+
+    pub fn is_created(&self) -> bool {
+        matches!(self, Upserted::Created(_))
+    }
+}
+
+/// A model instance printed with its sensitive fields redacted, see
+/// `Model::redacted`.
+pub struct Redacted<'a, T>(&'a T);
+
+impl<T: Model> std::fmt::Display for Redacted<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fields = self
+            .0
+            .fields()
+            .into_iter()
+            .map(|(field, value)| format!("{field}: {}", redact::<T>(field, &value, true)))
+            .collect::<Vec<_>>();
+        write!(f, "{} {{ {} }}", T::NAME, fields.join(", "))
+    }
+}
+
+impl<T: Model> std::fmt::Debug for Redacted<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(self, f)
+    }
+}
+
+/// Renders the value of `field`, `***` if it is sensitive.
+fn redact<T: Model>(field: &str, value: &serde_json::Value, quoted: bool) -> String {
+    match value {
+        _ if T::SENSITIVE.contains(&field) => "***".to_string(),
+        serde_json::Value::String(value) if !quoted => value.clone(),
+        value => value.to_string(),
+    }
 }
 
 pub trait Or {
@@ -48,99 +160,875 @@ impl And for Vec<Condition> {
     }
 }
 
+/// A group of conditions combined with `&`, `|` and `!`, built by `Q!` and passed to
+/// the queries with `.into()`.
+///
+/// # Example
+/// ```ignore
+/// let users = User::filter(
+///     ((Q!(role__eq: "admin") | Q!(age__gte: 18)) & !Q!(name__startswith: "test")).into(),
+///     &conn,
+/// ).await;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Q(pub Vec<Condition>);
+
+impl Q {
+    /// The conditions of `self` in parentheses, preceded by `operator`.
+    fn grouped(self, operator: Option<&str>) -> Vec<Condition> {
+        let logical = |operator: &str| Condition::LogicalOperator {
+            operator: operator.to_string(),
+        };
+        let mut conditions = Vec::new();
+        conditions.extend(operator.map(logical));
+        conditions.push(logical("("));
+        conditions.extend(self.0);
+        conditions.push(logical(")"));
+        conditions
+    }
+}
+
+macro_rules! combinators {
+    ($($trait:ident::$method:ident => $operator:literal),*) => {
+        $(
+            impl std::ops::$trait for Q {
+                type Output = Q;
+
+                fn $method(self, other: Q) -> Q {
+                    let mut conditions = self.grouped(None);
+                    conditions.extend(other.grouped(Some($operator)));
+                    Q(conditions)
+                }
+            }
+        )*
+    };
+}
+
+combinators!(BitAnd::bitand => "and", BitOr::bitor => "or");
+
+impl std::ops::Not for Q {
+    type Output = Q;
+
+    fn not(self) -> Q {
+        Q(self.grouped(Some("not")))
+    }
+}
+
+impl From<Q> for Vec<Condition> {
+    fn from(q: Q) -> Self {
+        q.0
+    }
+}
+
 pub trait Query {
-    fn to_update_query(&self) -> (String, Vec<(String, String)>);
-    fn to_select_query(&self) -> (String, Vec<(String, String)>);
-    fn to_insert_query(&self) -> (String, String, Vec<(String, String)>);
+    fn to_update_query(&self) -> (String, Vec<SqlValue>);
+    fn to_select_query(&self) -> (String, Vec<SqlValue>);
+    fn to_model_select_query<T: Model>(&self) -> (String, Vec<SqlValue>);
+    fn to_insert_query(&self) -> (String, String, Vec<SqlValue>);
 }
 
 impl Query for Vec<Condition> {
-    //                              sql_query, [(value, type)]
-    fn to_insert_query(&self) -> (String, String, Vec<(String, String)>) {
+    //                              sql_query, [value]
+    fn to_insert_query(&self) -> (String, String, Vec<SqlValue>) {
+        let mut args = Vec::new();
+        let fields =
+            self.iter()
+                .filter_map(|condition| match condition {
+                    Condition::FieldCondition { field, .. }
+                    | Condition::Expression { field, .. } => Some(field.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+        let placeholders = insert_values(self, &mut args);
+        (fields.join(", "), placeholders, args)
+    }
+
+    //                               (placeholders, args)
+    fn to_update_query(&self) -> (String, Vec<SqlValue>) {
         let mut args = Vec::new();
-        let mut fields = Vec::new();
         let mut placeholders = Vec::new();
         let mut index = 0;
         for condition in self {
-            if let Condition::FieldCondition {
+            if let Condition::FieldCondition { field, value, .. } = condition {
+                index += 1;
+                args.push(value.clone());
+                // (field + = + placeholder + index)
+                let placeholder = PLACEHOLDER.to_string();
+                placeholders.push(format!("{field}={placeholder}{index}",));
+            } else if let Condition::Expression {
                 field,
-                value,
-                value_type,
-                #[allow(unused_variables)]
-                comparaison_operator,
+                sql,
+                args: values,
+                ..
             } = condition
             {
-                index += 1;
-                args.push((value.clone(), value_type.clone()));
-                fields.push(field.clone());
-                let placeholder = PLACEHOLDER.to_string();
-                placeholders.push(format!("{placeholder}{index}",));
+                // expressions are rendered as SQL instead of being bound
+                let sql = number_placeholders(sql, values, &mut args);
+                placeholders.push(format!("{field}={sql}"));
+                index = args.len();
             }
         }
-        (fields.join(", "), placeholders.join(", "), args)
+        (placeholders.join(", "), args)
     }
 
     //                               (placeholders, args)
-    fn to_update_query(&self) -> (String, Vec<(String, String)>) {
+    fn to_select_query(&self) -> (String, Vec<SqlValue>) {
         let mut args = Vec::new();
-        let mut placeholders = Vec::new();
-        let mut index = 0;
-        for condition in self {
-            if let Condition::FieldCondition {
+        let placeholders = select_conditions(self, &[], &mut args);
+        (placeholders, args)
+    }
+
+    //                               (placeholders, args), on the range fields of `T`
+    fn to_model_select_query<T: Model>(&self) -> (String, Vec<SqlValue>) {
+        let mut args = Vec::new();
+        let placeholders = select_conditions(self, T::RANGES, &mut args);
+        (placeholders, args)
+    }
+}
+
+/// Renders the values inserted by `conditions`, pushing their arguments after `args`
+/// (so the placeholders are numbered after the ones already bound).
+fn insert_values(conditions: &[Condition], args: &mut Vec<SqlValue>) -> String {
+    conditions
+        .iter()
+        .filter_map(|condition| match condition {
+            Condition::FieldCondition { value, .. } => {
+                Some(number_placeholders("?", std::slice::from_ref(value), args))
+            }
+            // expressions are rendered as SQL instead of being bound
+            Condition::Expression {
+                sql, args: values, ..
+            } => Some(number_placeholders(sql, values, args)),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Renders `conditions` as a `where` clause, pushing their arguments after `args` (so
+/// the placeholders are numbered after the ones already bound), `ranges` being the
+/// range fields of the model.
+pub(crate) fn select_conditions(
+    conditions: &[Condition],
+    ranges: &[&str],
+    args: &mut Vec<SqlValue>,
+) -> String {
+    let mut values = Vec::new();
+    let conditions = marked_conditions(conditions, ranges, &mut values);
+    number_placeholders(&conditions, &values, args)
+}
+
+/// Renders `conditions` with a `?` marking each of their arguments, pushed on `args`
+/// in the same order, as `raw!` does, so they can be embedded in an expression.
+pub(crate) fn marked_conditions(
+    conditions: &[Condition],
+    ranges: &[&str],
+    args: &mut Vec<SqlValue>,
+) -> String {
+    let mut placeholders = Vec::new();
+    for (index, condition) in conditions.iter().enumerate() {
+        if index > 0 && needs_and(&conditions[index - 1], condition) {
+            placeholders.push("and".to_string());
+        }
+        match condition {
+            Condition::FieldCondition {
                 field,
                 value,
-                value_type,
-                #[allow(unused_variables)]
                 comparaison_operator,
-            } = condition
-            {
-                index += 1;
-                args.push((value.clone(), value_type.clone()));
-                // (field + = + placeholder + index)
-                let placeholder = PLACEHOLDER.to_string();
-                placeholders.push(format!("{field}={placeholder}{index}",));
+            } => {
+                let (field, comparaison_operator) = match comparison_lookup(field) {
+                    Some(lookup) => lookup,
+                    None => (field.as_str(), comparaison_operator.as_str()),
+                };
+                let condition = match field.split_once("__") {
+                    Some((column, "isnull")) => null_lookup(column, is_true(value)),
+                    // `null` is never equal to a value
+                    None if *value == SqlValue::Null
+                        && ["=", "!="].contains(&comparaison_operator) =>
+                    {
+                        null_lookup(field, comparaison_operator == "=")
+                    }
+                    Some((column, "in")) => in_lookup(column, value, args),
+                    Some((column, lookup @ ("contains" | "overlaps")))
+                        if ranges.contains(&column) =>
+                    {
+                        range_lookup(column, lookup, value, args)
+                    }
+                    Some((column, lookup @ ("contains" | "startswith" | "endswith"))) => {
+                        match value {
+                            SqlValue::Text(text) => {
+                                args.push(SqlValue::Text(like_pattern(lookup, text)));
+                                format!("{column} like ? escape '!'")
+                            }
+                            _ => unsupported_lookup(column, lookup, args),
+                        }
+                    }
+                    Some((column, lookup)) => unsupported_lookup(column, lookup, args),
+                    None => {
+                        args.push(value.clone());
+                        format!("{field}{comparaison_operator}?")
+                    }
+                };
+                placeholders.push(condition);
+            }
+            Condition::Expression {
+                field,
+                sql,
+                args: values,
+                comparaison_operator,
+            } => {
+                let (field, comparaison_operator) = comparison_lookup(field)
+                    .unwrap_or((field.as_str(), comparaison_operator.as_str()));
+                args.extend(values.iter().cloned());
+                placeholders.push(format!("{field}{comparaison_operator}{sql}"));
+            }
+            Condition::LogicalOperator { operator } => {
+                placeholders.push(operator.to_owned());
+            }
+            Condition::Raw { sql, args: values } => {
+                args.extend(values.iter().cloned());
+                placeholders.push(format!("({sql})"));
             }
         }
-        (placeholders.join(", "), args)
     }
+    placeholders.join(" ")
+}
 
-    //                               (placeholders, args)
-    fn to_select_query(&self) -> (String, Vec<(String, String)>) {
-        let mut args = Vec::new();
-        let mut placeholders = Vec::new();
-        let mut index = 0;
-        for condition in self {
-            match condition {
-                Condition::FieldCondition {
-                    field,
-                    value,
-                    value_type,
-                    comparaison_operator,
-                } => {
-                    index += 1;
-                    args.push((value.clone(), value_type.clone()));
-                    // (field + = + placeholder + index)
-                    let placeholder = PLACEHOLDER.to_string();
-                    placeholders
-                        .push(format!("{field}{comparaison_operator}{placeholder}{index}",));
-                }
-                Condition::LogicalOperator { operator } => {
-                    placeholders.push(operator.to_owned());
+/// Splits a comparison lookup `field`, e.g. `price__gt` into (`price`, `>`), `None`
+/// for a plain field or another lookup.
+///
+/// * `__gt`, `__gte` - greater than, greater than or equal to the value.
+/// * `__lt`, `__lte` - less than, less than or equal to the value.
+/// * `__eq`, `__ne` - equal to, different from the value.
+pub(crate) fn comparison_lookup(field: &str) -> Option<(&str, &'static str)> {
+    let (column, lookup) = field.split_once("__")?;
+    let operator = match lookup {
+        "eq" => "=",
+        "gt" => ">",
+        "gte" => ">=",
+        "lt" => "<",
+        "lte" => "<=",
+        "ne" => "!=",
+        _ => return None,
+    };
+    Some((column, operator))
+}
+
+/// Renders the `column in (...)` of an `__in` lookup, binding each element of the list
+/// `value` after `args` (marked by a `?`).
+fn in_lookup(column: &str, value: &SqlValue, args: &mut Vec<SqlValue>) -> String {
+    let SqlValue::List(elements) = value else {
+        return unsupported_lookup(column, "in", args);
+    };
+    // `in ()` is not valid SQL, an empty list matches nothing
+    if elements.is_empty() {
+        return "1=0".to_string();
+    }
+    let placeholders = elements
+        .iter()
+        .map(|element| {
+            args.push(element.clone());
+            "?"
+        })
+        .collect::<Vec<_>>();
+    format!("{column} in ({})", placeholders.join(", "))
+}
+
+/// Renders the `column is null` of an `__isnull` lookup, `is not null` if not `null`.
+pub(crate) fn null_lookup(column: &str, null: bool) -> String {
+    if null {
+        format!("{column} is null")
+    } else {
+        format!("{column} is not null")
+    }
+}
+
+/// Whether `value` is `true` (or `1`, as the `Boolean` columns store it).
+pub(crate) fn is_true(value: &SqlValue) -> bool {
+    matches!(
+        value,
+        SqlValue::Bool(true) | SqlValue::Int(1) | SqlValue::BigInt(1)
+    )
+}
+
+/// The `like` pattern of a `__contains`, `__startswith` or `__endswith` lookup on
+/// `text`, its `%` and `_` being escaped by `!`.
+pub(crate) fn like_pattern(lookup: &str, text: &str) -> String {
+    let text = text
+        .replace('!', "!!")
+        .replace('%', "!%")
+        .replace('_', "!_");
+    match lookup {
+        "startswith" => format!("{text}%"),
+        "endswith" => format!("%{text}"),
+        _ => format!("%{text}%"),
+    }
+}
+
+/// Whether two successive conditions have no logical operator between them, the
+/// conditions of a same `kwargs!` being joined by `and`.
+pub(crate) fn needs_and(previous: &Condition, condition: &Condition) -> bool {
+    let is_operator = |condition| matches!(condition, &Condition::LogicalOperator { .. });
+    !is_operator(previous) && !is_operator(condition)
+}
+
+/// The offsets of the `?` of a raw `sql`, the ones of its quoted literals and
+/// identifiers left out.
+pub(crate) fn placeholder_offsets(sql: &str) -> Vec<usize> {
+    let mut quote = None;
+    let mut offsets = Vec::new();
+    for (offset, c) in sql.char_indices() {
+        match quote {
+            // a doubled quote closes the quoted part and opens it again
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if matches!(c, '\'' | '"' | '`') => quote = Some(c),
+            None if c == '?' => offsets.push(offset),
+            None => {}
+        }
+    }
+    offsets
+}
+
+/// Replaces the `?` of a raw `sql` (outside its quoted parts) by the numbered
+/// placeholders of `values`, pushed after `args`.
+pub(crate) fn number_placeholders(
+    sql: &str,
+    values: &[SqlValue],
+    args: &mut Vec<SqlValue>,
+) -> String {
+    let placeholder = PLACEHOLDER.to_string();
+    let mut numbered = String::new();
+    let mut start = 0;
+    for (offset, value) in placeholder_offsets(sql).into_iter().zip(values) {
+        numbered.push_str(&sql[start..offset]);
+        args.push(value.clone());
+        numbered.push_str(&format!("{placeholder}{}", args.len()));
+        start = offset + 1;
+    }
+    numbered.push_str(&sql[start..]);
+    numbered
+}
+
+/// A primary key value, a scalar or a tuple of scalars for a composite key.
+pub trait PrimaryKey {
+    /// The arguments bound for each column of the key.
+    fn args(&self) -> Vec<SqlValue>;
+}
+
+macro_rules! primary_key {
+    ($($type:ty),*) => {
+        $(
+            impl PrimaryKey for $type {
+                fn args(&self) -> Vec<SqlValue> {
+                    vec![self.clone().into()]
                 }
             }
+        )*
+    };
+}
+
+primary_key!(i32, i64, String);
+
+impl<A: PrimaryKey, B: PrimaryKey> PrimaryKey for (A, B) {
+    fn args(&self) -> Vec<SqlValue> {
+        let mut args = self.0.args();
+        args.extend(self.1.args());
+        args
+    }
+}
+
+impl<A: PrimaryKey, B: PrimaryKey, C: PrimaryKey> PrimaryKey for (A, B, C) {
+    fn args(&self) -> Vec<SqlValue> {
+        let mut args = self.0.args();
+        args.extend(self.1.args());
+        args.extend(self.2.args());
+        args
+    }
+}
+
+/// A column of a model, generated by the derive as the `{Model}Column` enum so the
+/// column references are checked at compile time.
+///
+/// # Example
+/// ```ignore
+/// let emails = User::pluck::<String>(UserColumn::Email, kwargs!(role == "admin"), &conn).await;
+/// ```
+pub trait Column: Copy {
+    /// The name of the column.
+    fn name(&self) -> &'static str;
+}
+
+/// The retention policy of a model, generated by the derive for
+/// `#[model(retention(days = 90, on = "created_at"))]` and applied by
+/// `Model::run_retention`.
+#[derive(Debug, Clone, Copy)]
+pub struct Retention {
+    /// The number of days the rows are kept.
+    pub days: u32,
+    /// The column the age of the rows is read from, a `DateTime` in UTC.
+    pub column: &'static str,
+    /// What becomes of the expired rows.
+    pub action: RetentionAction,
+}
+
+/// What becomes of the expired rows of a `Retention` policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionAction {
+    /// The rows are deleted.
+    Delete,
+    /// The rows are copied to the table, then deleted.
+    Archive(&'static str),
+    /// The column is set to the current time, the rows where it is set being skipped.
+    SoftDelete(&'static str),
+}
+
+/// A foreign key of a model, generated by the derive for the `foreign_key` fields and
+/// followed by the `field__column` orderings of `Model::order_by`.
+#[derive(Debug, Clone, Copy)]
+pub struct ForeignKey {
+    /// The field holding the key.
+    pub field: &'static str,
+    /// The referenced table.
+    pub table: &'static str,
+    /// The referenced column.
+    pub column: &'static str,
+    /// The foreign keys of the referenced model.
+    pub foreign_keys: fn() -> &'static [ForeignKey],
+    /// The (column, definition) pairs of the referenced model.
+    pub columns: fn() -> &'static [(&'static str, &'static str)],
+}
+
+/// Returns the foreign keys of `M`, as referenced by a `ForeignKey` pointing to `M`.
+pub fn foreign_keys<M: Model>() -> &'static [ForeignKey] {
+    M::FOREIGN_KEYS
+}
+
+/// Returns the columns of `M`, as referenced by a `ForeignKey` pointing to `M`.
+pub fn columns<M: Model>() -> &'static [(&'static str, &'static str)] {
+    M::COLUMNS
+}
+
+/// Renders the (joins, `order by` terms) sorting `T` by `ordering`, a left join being
+/// added for each foreign key followed by a `field__column` path, a `-` prefix sorting
+/// in descending order and a ` nulls first` or ` nulls last` suffix placing the `null`
+/// values.
+///
+/// # Errors
+/// `Error::Invalid` if a field of `ordering` is not a column of its model, so that it
+/// is never rendered into the SQL.
+pub(crate) fn related_ordering<T: Model>(
+    ordering: &[&str],
+) -> Result<(String, Vec<String>), Error> {
+    let mut joined = Vec::new();
+    let mut joins = String::new();
+    let mut order = Vec::new();
+    for path in ordering {
+        let (path, nulls) = Nulls::strip(path);
+        let (path, descending) = match path.strip_prefix('-') {
+            Some(path) => (path, true),
+            None => (path, false),
+        };
+        let mut fields = path.split("__").collect::<Vec<_>>();
+        let column = fields.pop().unwrap_or_default();
+
+        let mut alias = quote_ident(T::NAME);
+        let mut keys = T::FOREIGN_KEYS;
+        let mut columns = T::COLUMNS;
+        for (depth, field) in fields.iter().enumerate() {
+            let Some(key) = keys.iter().find(|key| key.field == *field) else {
+                return Err(Error::Invalid(format!(
+                    "Unknown foreign key '{field}' in the ordering '{path}'"
+                )));
+            };
+            // the joined tables are aliased by their path, e.g. `owner__company`
+            let target = fields[..=depth].join("__");
+            if !joined.contains(&target) {
+                joins.push_str(&format!(
+                    " left join {} as {target} on {target}.{}={alias}.{field}",
+                    quote_ident(key.table),
+                    key.column
+                ));
+                joined.push(target.clone());
+            }
+            alias = target;
+            keys = (key.foreign_keys)();
+            columns = (key.columns)();
+        }
+        if !columns.iter().any(|(name, _)| *name == column) {
+            return Err(Error::Invalid(format!(
+                "Unknown column '{column}' in the ordering '{path}'"
+            )));
+        }
+        order.push(order_sql(&format!("{alias}.{column}"), descending, nulls));
+    }
+    Ok((joins, order))
+}
+
+/// Checks the arguments can be bound, as `binds!` does.
+fn check_args(args: &[SqlValue]) -> Result<(), Error> {
+    args.iter().try_for_each(SqlValue::check)
+}
+
+/// Executes the DDL statement `query` of a migration, recorded by `capture_queries` so
+/// the schema of each backend can be snapshotted.
+async fn execute_ddl(query: &str, conn: &Connection) -> Result<(), sqlx::Error> {
+    crate::snapshot::record(query);
+    sqlx::query(query).execute(conn).await.map(|_| ())
+}
+
+/// Creates the archive table `archive` of `T` with the columns of its table if it does
+/// not exist, and adds the columns the model gained since it was created.
+async fn create_archive<T: Model>(archive: &str, conn: &Connection) -> Result<(), sqlx::Error> {
+    let create = format!(
+        "create table if not exists {archive} as select * from {table_name} where 1=0;",
+        archive = quote_ident(archive),
+        table_name = quote_ident(T::NAME),
+    );
+    on_conn!(conn, sqlx::query(&create), execute).await?;
+
+    let live = live_columns(archive, conn).await;
+    for (column, definition) in T::COLUMNS {
+        if live
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case(column))
+        {
+            continue;
+        }
+        // the archive keeps the values without the constraints of the table
+        let kind = match column_type(&column_definition(column, definition)).as_str() {
+            "serial" => "integer".to_string(),
+            kind => kind.to_string(),
+        };
+        let alter = format!(
+            "alter table {} add column {column} {kind};",
+            quote_ident(archive)
+        );
+        on_conn!(conn, sqlx::query(&alter), execute).await?;
+    }
+    Ok(())
+}
+
+/// The (`insert into` columns, selected columns) copying the rows of `T` to its archive
+/// column by column, so the archive may have more columns or in another order.
+fn archived_columns<T: Model>() -> (String, String) {
+    if T::COLUMNS.is_empty() {
+        return (String::new(), "*".to_string());
+    }
+    let columns = T::COLUMNS
+        .iter()
+        .map(|(column, _)| *column)
+        .collect::<Vec<_>>()
+        .join(", ");
+    (format!(" ({columns})"), columns)
+}
+
+/// Renders the condition selecting the `rows` (read by `select {pk}`) by their primary
+/// key `pk`, pushing the values of the keys after `args`.
+fn keys_condition(rows: &[AnyRow], pk: &[&str], args: &mut Vec<SqlValue>) -> String {
+    let placeholder = PLACEHOLDER.to_string();
+    // the keys are read as JSON, the rows of a soft delete not decoding as the model
+    rows.iter()
+        .map(|row| {
+            let values = row_to_json(row);
+            let key = pk
+                .iter()
+                .map(|column| {
+                    args.push(values.get(*column).cloned().unwrap_or_default().into());
+                    format!("{column}={placeholder}{}", args.len())
+                })
+                .collect::<Vec<_>>()
+                .join(" and ");
+            format!("({key})")
+        })
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
+/// The size of the data sent at once by `copy_postgres`.
+#[cfg(feature = "postgres")]
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Streams the `records` to a `copy` into `table_name` on a native Postgres connection,
+/// see `Model::copy_in`.
+#[cfg(feature = "postgres")]
+async fn copy_postgres<S>(
+    table_name: &'static str,
+    records: S,
+    conn: &Connection,
+) -> Result<(), Error>
+where
+    S: Stream<Item = Vec<Condition>> + Send,
+{
+    use sqlx::postgres::{PgConnectOptions, PgConnection};
+
+    let mut records = Box::pin(records);
+    let Some(first) = records.next().await else {
+        return Ok(());
+    };
+    let (fields, _, _) = first.to_insert_query();
+    let copy = format!(
+        "copy {table_name} ({fields}) from stdin with (format csv);",
+        table_name = quote_ident(table_name)
+    );
+    let options = PgConnectOptions::try_from(&*conn.connect_options())?;
+    let mut connection = PgConnection::connect_with(&options).await?;
+
+    let copied = async {
+        let mut writer = connection.copy_in_raw(&copy).await?;
+        let mut buffer = String::new();
+        let mut record = Some(first);
+        while let Some(kw) = record {
+            let (record_fields, _, args) = kw.to_insert_query();
+            if record_fields != fields {
+                return Err(Error::Invalid(
+                    "the records must set the same fields".to_string(),
+                ));
+            }
+            let values = args
+                .iter()
+                .map(|value| value.check().map(|_| csv_field(value)))
+                .collect::<Result<Vec<_>, _>>()?;
+            buffer.push_str(&values.join(","));
+            buffer.push('\n');
+            if buffer.len() >= COPY_BUFFER_SIZE {
+                writer
+                    .send(std::mem::take(&mut buffer).into_bytes())
+                    .await?;
+            }
+            record = records.next().await;
+        }
+        if !buffer.is_empty() {
+            writer.send(buffer.into_bytes()).await?;
         }
-        (placeholders.join(" "), args)
+        let rows = writer.finish().await?;
+        Ok(((), rows))
+    };
+    track_operation(table_name, "copy", &copy, copied)
+        .await
+        .unwrap_or_else(|| {
+            Err(Error::BudgetExceeded {
+                query: copy.clone(),
+            })
+        })
+        .map(|_| ())
+}
+
+/// Renders `value` as a field of the CSV format of `copy`, an unquoted empty field
+/// being `null`.
+#[cfg(feature = "postgres")]
+fn csv_field(value: &SqlValue) -> String {
+    let quoted = |text: &str| format!("\"{}\"", text.replace('"', "\"\""));
+    match value {
+        SqlValue::Null => String::new(),
+        SqlValue::Bool(value) => (*value as i32).to_string(),
+        SqlValue::Int(value) => value.to_string(),
+        SqlValue::BigInt(value) => value.to_string(),
+        SqlValue::Float(value) => value.to_string(),
+        SqlValue::Text(value) => quoted(value),
+        SqlValue::Bytes(bytes) => {
+            let hex = bytes
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>();
+            quoted(&format!("\\x{hex}"))
+        }
+        // rejected by `SqlValue::check` before being written
+        SqlValue::List(_) | SqlValue::Unsupported(_) => String::new(),
+    }
+}
+
+/// Converts the output of `track_query` for the query `query`.
+pub(crate) fn tracked<T>(query: &str, output: Option<Result<T, sqlx::Error>>) -> Result<T, Error> {
+    let output = output.ok_or_else(|| Error::BudgetExceeded {
+        query: query.to_string(),
+    })?;
+    Ok(output?)
+}
+
+/// Renders the condition selecting the row of `key`, the (column, argument) pairs of
+/// its primary key.
+fn key_condition(key: &[(&str, SqlValue)], args: &mut Vec<SqlValue>) -> String {
+    let placeholder = PLACEHOLDER.to_string();
+    key.iter()
+        .map(|(column, arg)| {
+            args.push(arg.clone());
+            format!("{column}={placeholder}{}", args.len())
+        })
+        .collect::<Vec<_>>()
+        .join(" and ")
+}
+
+/// Whether a generated column of the primary key of `instance` still holds its default
+/// value, the database not having set it yet.
+fn has_unset_pk<T: Model>(instance: &T) -> bool {
+    pk_columns::<T>()
+        .iter()
+        .zip(instance.pk().args())
+        .any(|(column, value)| {
+            T::GENERATED.contains(column) && matches!(value, SqlValue::Int(0) | SqlValue::BigInt(0))
+        })
+}
+
+/// The columns of the primary key of `T`.
+pub(crate) fn pk_columns<T: Model>() -> &'static [&'static str] {
+    if T::PK_COLUMNS.is_empty() {
+        std::slice::from_ref(&T::PK)
+    } else {
+        T::PK_COLUMNS
     }
 }
 
+/// Renders the condition selecting the instance of `T` with the primary key `pk`,
+/// pushing its arguments after `args`.
+fn pk_condition<T: Model>(pk: &T::Pk, args: &mut Vec<SqlValue>) -> String {
+    let columns = pk_columns::<T>();
+    let placeholder = PLACEHOLDER.to_string();
+    columns
+        .iter()
+        .zip(pk.args())
+        .map(|(column, arg)| {
+            args.push(arg);
+            format!("{column}={placeholder}{}", args.len())
+        })
+        .collect::<Vec<_>>()
+        .join(" and ")
+}
+
 #[async_trait::async_trait]
 pub trait Model {
     // The SQL schema of the model
     const SCHEMA: &'static str;
     // The Table name of the model
     const NAME: &'static str;
+    // The name of the Rust type of the model, e.g. `User` for the `users` table
+    const MODEL_NAME: &'static str;
     // The Primary Key of the model
     const PK: &'static str;
+    // The (field, strategy) pairs applied by `dump` when anonymizing
+    const ANONYMIZE: &'static [(&'static str, &'static str)] = &[];
+    // The query backing the model when it is a materialized view
+    const MATERIALIZED_VIEW: Option<&'static str> = None;
+    // The (strategy, column) the table is partitioned by on Postgres
+    const PARTITION_BY: Option<(&'static str, &'static str)> = None;
+    // The comment of the table
+    const COMMENT: Option<&'static str> = None;
+    // The name of the secondary database the model is routed to
+    const DATABASE: Option<&'static str> = None;
+    // The default character set of the table on MySQL
+    const CHARSET: Option<&'static str> = None;
+    // The (column, definition) pairs making up the schema
+    const COLUMNS: &'static [(&'static str, &'static str)] = &[];
+    // The (column, comment) pairs of the commented columns
+    const COLUMN_COMMENTS: &'static [(&'static str, &'static str)] = &[];
+    // The indexed columns
+    const INDEXES: &'static [&'static str] = &[];
+    // The fields redacted by `redacted` and `to_table`
+    const SENSITIVE: &'static [&'static str] = &[];
+    // The fields set by the database on `save`, the auto primary keys and the defaults
+    const GENERATED: &'static [&'static str] = &[];
+    // The `Int4Range` and `TsRange` fields, whose `__contains` lookup is a range one
+    const RANGES: &'static [&'static str] = &[];
+    // The columns of a composite primary key (`PK` being the first one)
+    const PK_COLUMNS: &'static [&'static str] = &[];
+    // The foreign keys, followed by the `field__column` orderings of `order_by`
+    const FOREIGN_KEYS: &'static [ForeignKey] = &[];
+    // The retention policy applied by `run_retention`
+    const RETENTION: Option<Retention> = None;
+
+    // The type of the primary key, a tuple for a composite one
+    type Pk: PrimaryKey + Clone + Send + Sync;
+
+    /// Returns the primary key of the instance.
+    fn pk(&self) -> Self::Pk;
+
+    // The columns of the model, the generated `{Model}Column` enum
+    type Column: Column + Send + Sync;
+
+    /// Builds the `create` statement of the model for the current backend.
+    ///
+    /// # Returns
+    /// The SQL statement creating the table (or materialized view) of the model.
+    ///
+    /// # Example
+    /// ```ignore
+    /// println!("{}", User::schema());
+    /// ```
+    fn schema() -> String
+    where
+        Self: Sized,
+    {
+        if let Some(view) = Self::MATERIALIZED_VIEW {
+            let kind = if *BACKEND == Backend::Postgres {
+                "materialized view"
+            } else {
+                "table"
+            };
+            return format!(
+                "create {kind} if not exists {table_name} as {view};",
+                table_name = quote_ident(Self::NAME)
+            );
+        }
+
+        let mut constraints = Vec::new();
+        let columns = if Self::COLUMNS.is_empty() {
+            let schema = Self::SCHEMA.trim_end_matches(';');
+            let start = schema.find('(').map_or(0, |index| index + 1);
+            schema[start..schema.len() - 1].to_string()
+        } else {
+            Self::COLUMNS
+                .iter()
+                .map(|(column, definition)| {
+                    let bounds = range_bounds(column, definition);
+                    let definition = resolve_references::<Self>(column, definition);
+                    let definition = column_definition(column, &definition);
+                    // the constraints are named, to be referenced by later migrations
+                    let (definition, named) = named_constraints(Self::NAME, column, &definition);
+                    constraints.extend(named);
+                    let definition = match Self::COLUMN_COMMENTS.iter().find(|(c, _)| c == column) {
+                        Some((_, comment)) if *BACKEND == Backend::MySql => {
+                            format!("{definition} comment {}", quote(comment))
+                        }
+                        _ => definition,
+                    };
+                    std::iter::once(definition)
+                        .chain(bounds.into_iter().map(|(_, bound)| bound))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let mut options = String::new();
+        match *BACKEND {
+            Backend::MySql => {
+                if let Some(charset) = Self::CHARSET {
+                    options.push_str(&format!(" default charset={charset}"));
+                }
+                if let Some(comment) = Self::COMMENT {
+                    options.push_str(&format!(" comment={}", quote(comment)));
+                }
+            }
+            Backend::Postgres => {
+                if let Some((strategy, column)) = Self::PARTITION_BY {
+                    options.push_str(&format!(" partition by {strategy} ({column})"));
+                }
+            }
+            Backend::Sqlite => {}
+        }
+        let mut columns = columns;
+        if Self::PK_COLUMNS.len() > 1 {
+            columns.push_str(&format!(", primary key ({})", Self::PK_COLUMNS.join(", ")));
+        }
+        for constraint in constraints {
+            columns.push_str(&format!(", {constraint}"));
+        }
+        format!(
+            "create table if not exists {table_name} ({columns}){options};",
+            table_name = quote_ident(Self::NAME)
+        )
+    }
 
     /// Migrates the model schema to the database
     ///
@@ -148,281 +1036,3008 @@ pub trait Model {
     /// * `conn` - The database connection
     ///
     /// # Returns
-    /// `true` if the migration was successful, `false` otherwise
+    /// `Ok` if the migration was successful, the error otherwise
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// if let Err(err) = User::migrate(&conn).await {
+    ///     eprintln!("Migration failed: {err}");
+    /// }
+    /// ```
+    async fn migrate(conn: &Connection) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        let schema = Self::schema();
+        let uses_citext = Self::COLUMNS
+            .iter()
+            .any(|(column, definition)| definition.starts_with(&format!("{column} citext")));
+        if uses_citext && *BACKEND == Backend::Postgres {
+            let extension = "create extension if not exists citext;";
+            execute_ddl(extension, conn).await?;
+        }
+        execute_ddl(&schema, conn).await?;
+
+        if *BACKEND == Backend::Postgres {
+            let table_comment = Self::COMMENT.map(|comment| {
+                format!(
+                    "comment on table {table_name} is {comment};",
+                    table_name = quote_ident(Self::NAME),
+                    comment = quote(comment)
+                )
+            });
+            let column_comments = Self::COLUMN_COMMENTS.iter().map(|(column, comment)| {
+                format!(
+                    "comment on column {table_name}.{column} is {comment};",
+                    table_name = quote_ident(Self::NAME),
+                    comment = quote(comment)
+                )
+            });
+            for query in table_comment.into_iter().chain(column_comments) {
+                execute_ddl(&query, conn).await?;
+            }
+        }
+
+        for column in Self::INDEXES {
+            let table_name = Self::NAME;
+            let name = index_name(table_name, column);
+            let query = if *BACKEND == Backend::MySql {
+                // MySQL has no `create index if not exists`
+                let placeholder = PLACEHOLDER.to_string();
+                let exists = format!(
+                    "select count(*) from information_schema.statistics \
+                     where table_schema = database() and table_name = {placeholder}1 \
+                     and index_name = {placeholder}2;"
+                );
+                let count = sqlx::query_scalar::<_, i64>(&exists)
+                    .bind(table_name)
+                    .bind(name.clone())
+                    .fetch_one(conn)
+                    .await
+                    .unwrap_or(0);
+                if count > 0 {
+                    continue;
+                }
+                format!(
+                    "create index {name} on {} ({column});",
+                    quote_ident(table_name)
+                )
+            } else {
+                format!(
+                    "create index if not exists {name} on {} ({column});",
+                    quote_ident(table_name)
+                )
+            };
+            execute_ddl(&query, conn).await?;
+        }
+        Ok(())
+    }
+
+    /// Refreshes the content of a materialized view model.
+    ///
+    /// On Postgres this runs `refresh materialized view`, other backends emulate the
+    /// view with a table which is repopulated from the view query in a transaction.
+    ///
+    /// # Arguments
+    /// * `concurrently` - Refresh without locking out readers (Postgres only, the view
+    ///   needs a unique index).
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// `Ok(())` if the refresh is successful, `Error::Invalid` if the model is not a
+    /// materialized view, or the error of the query.
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[derive(FromRow, Clone, Debug, Model)]
+    /// #[model(materialized_view = "select owner, count(*) as total from products group by owner")]
+    /// struct ProductStats {
+    ///     #[model(primary_key = true)]
+    ///     owner: Integer,
+    ///     total: Integer,
+    /// }
+    ///
+    /// ProductStats::refresh(false, &conn).await?;
+    /// ```
+    async fn refresh(concurrently: bool, conn: &Connection) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        let Some(view) = Self::MATERIALIZED_VIEW else {
+            return Err(Error::Invalid(format!(
+                "{} is not a materialized view",
+                Self::NAME
+            )));
+        };
+        let table_name = quote_ident(Self::NAME);
+        if *BACKEND == Backend::Postgres {
+            let concurrently = if concurrently { "concurrently " } else { "" };
+            let query = format!("refresh materialized view {concurrently}{table_name};");
+            on_conn!(conn, sqlx::query(&query), execute).await?;
+        } else {
+            let mut connection = transaction::acquire(conn).await?;
+            let mut tx = connection.begin().await?;
+            sqlx::query(&format!("delete from {table_name};"))
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(&format!("insert into {table_name} {view};"))
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+        }
+        // the cached instances are stale once the view is repopulated
+        cache::invalidate_table(Self::NAME, conn);
+        Ok(())
+    }
+
+    /// Creates the range partition holding the rows where `from <= column < to`.
+    ///
+    /// The partition is named after the table and the lower bound, e.g.
+    /// `events_2024_01_01` for `from = "2024-01-01"`. Declarative partitioning only
+    /// exists on Postgres, other backends keep every row in the main table and this is
+    /// a no-op.
+    ///
+    /// # Arguments
+    /// * `from` - The inclusive lower bound.
+    /// * `to` - The exclusive upper bound.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// `Ok(())` if the partition exists after the call, `Error::Invalid` if the model is
+    /// not partitioned or if a bound is not made of letters, digits, spaces and `-:.+_`
+    /// (the bounds of a partition can not be bound parameters), or the error of the
+    /// query.
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[derive(FromRow, Clone, Debug, Model)]
+    /// #[model(partition_by(range = "at"))]
+    /// struct Event {
+    ///     #[model(primary_key = true)]
+    ///     id: Serial,
+    ///     #[model(default = "now")]
+    ///     at: DateTime,
+    /// }
+    ///
+    /// Event::create_partition("2024-01-01", "2024-02-01", &conn).await?;
+    /// ```
+    async fn create_partition(from: &str, to: &str, conn: &Connection) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        if Self::PARTITION_BY.is_none() {
+            return Err(Error::Invalid(format!("{} is not partitioned", Self::NAME)));
+        }
+        check_bound(from)?;
+        check_bound(to)?;
+        if *BACKEND != Backend::Postgres {
+            return Ok(());
+        }
+        let query = format!(
+            "create table if not exists {partition} partition of {table_name} \
+             for values from ('{from}') to ('{to}');",
+            partition = partition_name(Self::NAME, from),
+            table_name = quote_ident(Self::NAME),
+        );
+        on_conn!(conn, sqlx::query(&query), execute).await?;
+        Ok(())
+    }
+
+    /// Drops the range partition created by `create_partition` for `from`, with
+    /// every row it holds.
+    ///
+    /// Backends without declarative partitioning delete the rows of the range from
+    /// the main table instead.
+    ///
+    /// # Arguments
+    /// * `from` - The inclusive lower bound.
+    /// * `to` - The exclusive upper bound.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// `Ok(())` if the drop is successful, `Error::Invalid` if the model is not
+    /// partitioned, or the error of the query.
+    ///
+    /// # Example
+    /// ```ignore
+    /// Event::drop_partition("2024-01-01", "2024-02-01", &conn).await?;
+    /// ```
+    async fn drop_partition(from: &str, to: &str, conn: &Connection) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        let Some((_, column)) = Self::PARTITION_BY else {
+            return Err(Error::Invalid(format!("{} is not partitioned", Self::NAME)));
+        };
+        let dropped = if *BACKEND == Backend::Postgres {
+            let query = format!(
+                "drop table if exists {partition};",
+                partition = partition_name(Self::NAME, from)
+            );
+            on_conn!(conn, sqlx::query(&query), execute).await
+        } else {
+            let placeholder = PLACEHOLDER.to_string();
+            let query = format!(
+                "delete from {table_name} where {column}>={placeholder}1 and {column}<{placeholder}2;",
+                table_name = quote_ident(Self::NAME),
+            );
+            let stream = sqlx::query(&query)
+                .bind(from.to_string())
+                .bind(to.to_string());
+            on_conn!(conn, stream, execute).await
+        };
+        // the rows of the range are gone, or some of them if the query failed
+        cache::invalidate_table(Self::NAME, conn);
+        dropped?;
+        Ok(())
+    }
+
+    /// Applies the retention policy declared with
+    /// `#[model(retention(days = 90, on = "created_at"))]`: the rows whose `on` column is
+    /// older than `days` are deleted, or copied to the `archive = "table"` table before
+    /// being deleted, or marked in the `soft_delete = "column"` column.
+    ///
+    /// The expired rows are processed in chunks of at most `RETENTION_CHUNK_SIZE`, each
+    /// in its own transaction, so the locks of a large purge stay short. The archive
+    /// table is created with the columns of the model if it does not exist, and gains
+    /// the columns added to the model since.
+    ///
+    /// # Arguments
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The number of expired rows, or the error (`Error::Invalid` if the model has no
+    /// retention policy).
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[derive(FromRow, Clone, Debug, Default, Model)]
+    /// #[model(retention(days = 90, on = "created_at"))]
+    /// struct Event {
+    ///     #[model(primary_key = true, auto = true, null = false)]
+    ///     id: Integer,
+    ///     #[model(default = "now", index = true)]
+    ///     created_at: DateTime,
+    /// }
+    ///
+    /// // e.g. in a daily job
+    /// let purged = Event::run_retention(&conn).await?;
+    /// ```
+    async fn run_retention(conn: &Connection) -> Result<u64, Error>
+    where
+        Self: Sized,
+    {
+        let Some(retention) = Self::RETENTION else {
+            return Err(Error::Invalid(format!(
+                "the model {} has no retention policy",
+                Self::NAME
+            )));
+        };
+        let table_name = quote_ident(Self::NAME);
+        let column = retention.column;
+        let now = SystemTime::now();
+        let cutoff = timestamp(now - Duration::from_secs(u64::from(retention.days) * 86_400));
+        let placeholder = PLACEHOLDER.to_string();
+        let mut condition = format!("{column}<{placeholder}1");
+        match retention.action {
+            RetentionAction::Delete => {}
+            RetentionAction::Archive(archive) => create_archive::<Self>(archive, conn).await?,
+            RetentionAction::SoftDelete(deleted) => {
+                condition.push_str(&format!(" and {deleted} is null"));
+            }
+        }
+        let pk = pk_columns::<Self>();
+        let size = (COPY_IN_MAX_PARAMS / pk.len()).min(RETENTION_CHUNK_SIZE);
+        let select = format!(
+            "select {pk} from {table_name} where {condition} order by {column} limit {size};",
+            pk = pk.join(", ")
+        );
+
+        let mut expired_rows = 0;
+        loop {
+            let mut connection = transaction::acquire(conn).await?;
+            let mut tx = connection.begin().await?;
+            let stream = sqlx::query(&select).bind(cutoff.clone());
+            let output =
+                track_operation(Self::NAME, "select", &select, stream.fetch_all(&mut *tx)).await;
+            let expired = tracked(&select, output)?;
+            if expired.is_empty() {
+                break;
+            }
+
+            let mut args = Vec::new();
+            if let RetentionAction::SoftDelete(_) = retention.action {
+                let deleted_at = timestamp(now);
+                args.push(SqlValue::Text(deleted_at));
+            }
+            let keys = keys_condition(&expired, pk, &mut args);
+            if let RetentionAction::Archive(archive) = retention.action {
+                let (into, columns) = archived_columns::<Self>();
+                let insert = format!(
+                    "insert into {archive}{into} select {columns} from {table_name} where {keys};",
+                    archive = quote_ident(archive)
+                );
+                let mut stream = sqlx::query(&insert);
+                binds!(args.clone(), stream)?;
+                let output =
+                    track_operation(Self::NAME, "insert", &insert, stream.execute(&mut *tx)).await;
+                tracked(&insert, output)?;
+            }
+            let (operation, query) = match retention.action {
+                RetentionAction::SoftDelete(deleted) => (
+                    "update",
+                    format!("update {table_name} set {deleted}={placeholder}1 where {keys};"),
+                ),
+                _ => ("delete", format!("delete from {table_name} where {keys};")),
+            };
+            let mut stream = sqlx::query(&query);
+            binds!(args, stream)?;
+            let output =
+                track_operation(Self::NAME, operation, &query, stream.execute(&mut *tx)).await;
+            expired_rows += tracked(&query, output)?.rows_affected();
+            tx.commit().await?;
+            cache::invalidate_table(Self::NAME, conn);
+            if expired.len() < size {
+                break;
+            }
+        }
+        Ok(expired_rows)
+    }
+
+    /// Moves the instances matching the conditions to the `<table>_archive` table,
+    /// created with the columns of the model if it does not exist, so the table stays
+    /// small without losing the old rows.
+    ///
+    /// The rows are moved in one transaction, by a single statement on Postgres and
+    /// elsewhere by chunks of primary keys, each chunk being copied then deleted by its
+    /// keys, so a row is either moved or left in the table. The archive gains the
+    /// columns added to the model since it was created.
+    ///
+    /// # Arguments
+    /// * `kw` - The conditions selecting the instances to archive.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The number of archived instances, or the error.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let archived = User::archive_where(kwargs!(role == "guest"), &conn).await?;
+    /// println!("Archived: {archived}");
+    /// ```
+    async fn archive_where(kw: Vec<Condition>, conn: &Connection) -> Result<u64, Error>
+    where
+        Self: Sized,
+    {
+        let table_name = quote_ident(Self::NAME);
+        let archive = format!("{}_archive", Self::NAME);
+        let mut args = Vec::new();
+        let conditions = if kw.is_empty() {
+            String::new()
+        } else {
+            format!(" where {}", select_conditions(&kw, Self::RANGES, &mut args))
+        };
+        let delete = format!("delete from {table_name}{conditions}");
+        if !check_statement(&format!("{delete};")) {
+            return Err(Error::Rejected {
+                query: format!("{delete};"),
+            });
+        }
+        check_args(&args)?;
+        create_archive::<Self>(&archive, conn).await?;
+        let archive = quote_ident(&archive);
+        let (into, columns) = archived_columns::<Self>();
+
+        let mut connection = transaction::acquire(conn).await?;
+        let mut tx = connection.begin().await?;
+        let archived = if *BACKEND == Backend::Postgres {
+            let query = format!(
+                "with moved as ({delete} returning *) insert into {archive}{into} select {columns} from moved;"
+            );
+            let mut stream = sqlx::query(&query);
+            binds!(args, stream)?;
+            let output =
+                track_operation(Self::NAME, "delete", &query, stream.execute(&mut *tx)).await;
+            tracked(&query, output)?.rows_affected()
+        } else {
+            // the rows are deleted by the keys copied, so a row matching the conditions
+            // after the copy is left in the table
+            let pk = pk_columns::<Self>();
+            let size = COPY_IN_MAX_PARAMS / pk.len();
+            let lock = if *BACKEND == Backend::MySql {
+                " for update"
+            } else {
+                ""
+            };
+            let select = format!(
+                "select {pk} from {table_name}{conditions} limit {size}{lock};",
+                pk = pk.join(", ")
+            );
+            let mut archived = 0;
+            loop {
+                let mut stream = sqlx::query(&select);
+                binds!(args.clone(), stream)?;
+                let output =
+                    track_operation(Self::NAME, "select", &select, stream.fetch_all(&mut *tx))
+                        .await;
+                let moved = tracked(&select, output)?;
+                if moved.is_empty() {
+                    break;
+                }
+
+                let mut keys_args = Vec::new();
+                let keys = keys_condition(&moved, pk, &mut keys_args);
+                let insert = format!(
+                    "insert into {archive}{into} select {columns} from {table_name} where {keys};"
+                );
+                let mut stream = sqlx::query(&insert);
+                binds!(keys_args.clone(), stream)?;
+                let output =
+                    track_operation(Self::NAME, "insert", &insert, stream.execute(&mut *tx)).await;
+                tracked(&insert, output)?;
+
+                let query = format!("delete from {table_name} where {keys};");
+                let mut stream = sqlx::query(&query);
+                binds!(keys_args, stream)?;
+                let output =
+                    track_operation(Self::NAME, "delete", &query, stream.execute(&mut *tx)).await;
+                let deleted = tracked(&query, output)?.rows_affected();
+                archived += deleted;
+                if deleted == 0 || moved.len() < size {
+                    break;
+                }
+            }
+            archived
+        };
+        tx.commit().await?;
+        cache::invalidate_table(Self::NAME, conn);
+        Ok(archived)
+    }
+
+    /// Saves the current model instance to the database.
+    ///
+    /// # Arguments
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// `Ok` if save is successful, the error otherwise.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let user = User {
+    ///     name: "johnDoe@gmail.com".to_string(),
+    ///     email: "21john@gmail.com".to_string(),
+    ///     password: "p455w0rd".to_string(),
+    ///     age: 18,
+    ///     weight: 60.0,
+    ///     ..Default::default()
+    /// };
+    /// if let Err(err) = user.save(&conn).await {
+    ///     eprintln!("Save failed: {err}");
+    /// }
+    /// ```
+    async fn save(&self, conn: &Connection) -> Result<(), Error>
+    where
+        Self: Sized;
+
+    /// Creates a new model instance with the specified parameters.
+    ///
+    /// # Arguments
+    /// * `kw` - The key-value arguments for the new instance.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// `Ok` if creation is successful, the error otherwise (`Error::Invalid` for a
+    /// value that cannot be bound).
+    ///
+    /// # Example
+    /// ```ignore
+    /// let success = User::create(
+    ///     kwargs!(
+    ///         name = "joe",
+    ///         email = "24nomeniavo@gmail.com",
+    ///         password = "strongpassword",
+    ///         age = 19,
+    ///         weight = 80.1
+    ///     ),
+    ///     &conn,
+    /// ).await;
+    /// println!("Create success: {}", success.is_ok());
+    /// ```
+    async fn create(kw: Vec<Condition>, conn: &Connection) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        let (fields, placeholders, args) = kw.to_insert_query();
+        check_args(&args)?;
+
+        let query = format!(
+            "insert into {table_name} ({fields}) values ({placeholders});",
+            table_name = quote_ident(Self::NAME)
+        );
+        let mut stream = sqlx::query(&query);
+        binds!(args, stream)?;
+        let output = track_operation(
+            Self::NAME,
+            "create",
+            &query,
+            on_conn!(conn, stream, execute),
+        )
+        .await;
+        tracked(&query, output).map(|_| ())
+    }
+
+    /// Inserts the current model instance, or updates the instance with the same
+    /// primary key.
+    ///
+    /// # Arguments
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The row as stored, `Upserted::Created` or `Upserted::Updated`, or
+    /// `Error::MissingPrimaryKey` if the generated primary key of the instance is not set.
+    ///
+    /// # Example
+    /// ```ignore
+    /// match user.upsert(&conn).await? {
+    ///     Upserted::Created(user) => println!("Welcome {}", user.name),
+    ///     Upserted::Updated(user) => println!("Welcome back {}", user.name),
+    /// }
+    /// ```
+    async fn upsert(&self, conn: &Connection) -> Result<Upserted<Self>, Error>
+    where
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone;
+
+    /// Inserts a new instance of the model, or updates the instance with the same
+    /// primary key, which `kw` must set.
+    ///
+    /// Postgres tells the created rows from their `xmax`, MySQL and SQLite check the row
+    /// exists in the same transaction.
+    ///
+    /// # Arguments
+    /// * `kw` - The key-value arguments of the instance, its primary key included.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The row as stored, `Upserted::Created` or `Upserted::Updated`, or
+    /// `Error::MissingPrimaryKey` if `kw` does not set the primary key.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let upserted = User::create_or_update(
+    ///     kwargs!(id = 1, name = "joe", email = "joe@gmail.com"),
+    ///     &conn,
+    /// ).await?;
+    /// println!("Created: {}", upserted.is_created());
+    /// ```
+    async fn create_or_update(
+        kw: Vec<Condition>,
+        conn: &Connection,
+    ) -> Result<Upserted<Self>, Error>
+    where
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        let (fields, placeholders, args) = kw.to_insert_query();
+        check_args(&args)?;
+        let columns = fields.split(", ").collect::<Vec<_>>();
+        let pk = pk_columns::<Self>();
+        // the key identifying the row, with the generated columns set
+        let key = pk
+            .iter()
+            .map(|column| {
+                let index = columns.iter().position(|field| field == column)?;
+                let value = &args[index];
+                let unset = Self::GENERATED.contains(column)
+                    && matches!(value, SqlValue::Int(0) | SqlValue::BigInt(0));
+                (!unset).then(|| (*column, value.clone()))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(Error::MissingPrimaryKey { model: Self::NAME })?;
+
+        let mut set = columns
+            .iter()
+            .filter(|column| !pk.contains(column))
+            .map(|column| match *BACKEND {
+                Backend::MySql => format!("{column}=values({column})"),
+                _ => format!("{column}=excluded.{column}"),
+            })
+            .collect::<Vec<_>>();
+        if set.is_empty() {
+            // an update of the key alone, so the row is returned
+            set = pk
+                .iter()
+                .map(|column| match *BACKEND {
+                    Backend::MySql => format!("{column}={column}"),
+                    _ => format!("{column}=excluded.{column}"),
+                })
+                .collect();
+        }
+        let set = set.join(", ");
+        let table_name = quote_ident(Self::NAME);
+        let insert = format!("insert into {table_name} ({fields}) values ({placeholders})");
+
+        let (row, created) = match *BACKEND {
+            Backend::Postgres => {
+                let query = format!(
+                    "{insert} on conflict ({pk}) do update set {set} returning {columns}, (xmax = 0)::integer as created__;",
+                    pk = pk.join(", "),
+                    columns = selected_columns::<Self>(None),
+                );
+                let mut stream = sqlx::query(&query);
+                binds!(args, stream)?;
+                let output = track_operation(
+                    Self::NAME,
+                    "upsert",
+                    &query,
+                    on_conn!(conn, stream, fetch_one),
+                )
+                .await;
+                let row = tracked(&query, output)?;
+                // the Any driver does not decode the Postgres booleans
+                let created = row.try_get::<i32, _>("created__")? == 1;
+                (Self::from_row(&row)?, created)
+            }
+            _ => {
+                let mut connection = transaction::acquire(conn).await?;
+                let mut tx = connection.begin().await?;
+                let mut key_args = Vec::new();
+                let condition = key_condition(&key, &mut key_args);
+                let lock = if *BACKEND == Backend::MySql {
+                    " for update"
+                } else {
+                    ""
+                };
+                let select = format!("select count(*) from {table_name} where {condition}{lock};");
+                let mut stream = sqlx::query(&select);
+                binds!(key_args.clone(), stream)?;
+                let output =
+                    track_operation(Self::NAME, "select", &select, stream.fetch_one(&mut *tx))
+                        .await;
+                let existing = tracked(&select, output)?.try_get::<i64, _>(0)?;
+
+                let row = if *BACKEND == Backend::MySql {
+                    let query = format!("{insert} on duplicate key update {set};");
+                    let mut stream = sqlx::query(&query);
+                    binds!(args, stream)?;
+                    let output =
+                        track_operation(Self::NAME, "upsert", &query, stream.execute(&mut *tx))
+                            .await;
+                    tracked(&query, output)?;
+                    let query = format!("select * from {table_name} where {condition};");
+                    let mut stream = sqlx::query_as::<_, Self>(&query);
+                    binds!(key_args, stream)?;
+                    let output = track_operation(
+                        Self::NAME,
+                        "select",
+                        &query,
+                        stream.fetch_optional(&mut *tx),
+                    )
+                    .await;
+                    tracked(&query, output)?.ok_or(sqlx::Error::RowNotFound)?
+                } else {
+                    let query = format!(
+                        "{insert} on conflict ({pk}) do update set {set} returning *;",
+                        pk = pk.join(", "),
+                    );
+                    let mut stream = sqlx::query(&query);
+                    binds!(args, stream)?;
+                    let output =
+                        track_operation(Self::NAME, "upsert", &query, stream.fetch_one(&mut *tx))
+                            .await;
+                    Self::from_row(&tracked(&query, output)?)?
+                };
+                tx.commit().await?;
+                (row, existing == 0)
+            }
+        };
+        let key_args = key.into_iter().map(|(_, arg)| arg).collect::<Vec<_>>();
+        cache::invalidate(Self::NAME, &cache::key(&key_args), conn);
+        Ok(if created {
+            Upserted::Created(row)
+        } else {
+            Upserted::Updated(row)
+        })
+    }
+
+    /// Inserts a large number of records in bulk.
+    ///
+    /// On Postgres the records are streamed to a `copy ... from stdin` run on a native
+    /// connection opened with the options of the pool. Elsewhere, and within an
+    /// `atomic` block (whose transaction the native connection cannot join), they are
+    /// grouped into multi-row `insert` statements (bounded by `COPY_IN_MAX_PARAMS`
+    /// bound parameters each) executed in a single transaction. Either way, every
+    /// record is inserted or none is. Every record must set the same fields, in the
+    /// same order, as the first one.
+    ///
+    /// Note: MySQL uses multi-row inserts as well, the MySQL driver of sqlx not
+    /// implementing the `LOCAL INFILE` requests of `LOAD DATA LOCAL`.
+    ///
+    /// # Arguments
+    /// * `records` - A stream of key-value arguments, one per record.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// `Ok` if every record was inserted, the error otherwise, no record being inserted
+    /// then.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let records = futures_util::stream::iter(
+    ///     (0..100_000).map(|i| kwargs!(name = format!("user{i}"), age = 18)),
+    /// );
+    /// let success = User::copy_in(records, &conn).await;
+    /// println!("Copy success: {}", success.is_ok());
+    /// ```
+    async fn copy_in<S>(records: S, conn: &Connection) -> Result<(), Error>
+    where
+        Self: Sized,
+        S: Stream<Item = Vec<Condition>> + Send,
+    {
+        #[cfg(feature = "postgres")]
+        if *BACKEND == Backend::Postgres && transaction::current(conn).is_none() {
+            return copy_postgres(Self::NAME, records, conn).await;
+        }
+
+        let mut records = Box::pin(records);
+        let mut connection = transaction::acquire(conn).await?;
+        let mut tx = connection.begin().await?;
+
+        let mut fields = String::new();
+        let mut rows = Vec::new();
+        let mut args = Vec::new();
+        let mut done = false;
+        while !done {
+            let record = records.next().await;
+            if let Some(record) = record {
+                let (record_fields, _, record_args) = record.to_insert_query();
+                if fields.is_empty() {
+                    fields = record_fields;
+                } else if fields != record_fields {
+                    return Err(Error::Invalid(
+                        "the records must set the same fields".to_string(),
+                    ));
+                }
+                check_args(&record_args)?;
+                rows.push(format!("({})", insert_values(&record, &mut args)));
+            } else {
+                done = true;
+            }
+
+            let full = args.len() + fields.split(", ").count() > COPY_IN_MAX_PARAMS;
+            if !rows.is_empty() && (done || full) {
+                let query = format!(
+                    "insert into {table_name} ({fields}) values {rows};",
+                    table_name = quote_ident(Self::NAME),
+                    rows = rows.join(", "),
+                );
+                let mut stream = sqlx::query(&query);
+                binds!(std::mem::take(&mut args), stream)?;
+                let output =
+                    track_operation(Self::NAME, "insert", &query, stream.execute(&mut *tx)).await;
+                tracked(&query, output)?;
+                rows.clear();
+            }
+        }
+        Ok(tx.commit().await?)
+    }
+
+    /// Compares the fields of the instance with `other`.
+    ///
+    /// # Arguments
+    /// * `other` - The instance to compare with.
+    ///
+    /// # Returns
+    /// The changes from the instance to `other`, in the order of the fields.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let before = User::get(kwargs!(id == 1), &conn).await.unwrap();
+    /// let mut after = before.clone();
+    /// after.role = "admin".to_string();
+    /// for change in before.diff(&after) {
+    ///     println!("{}: {} -> {}", change.field, change.old, change.new);
+    /// }
+    /// if !before.diff(&after).is_empty() {
+    ///     after.update(&conn).await;
+    /// }
+    /// ```
+    fn diff(&self, other: &Self) -> Vec<FieldChange>
+    where
+        Self: Sized;
+
+    /// Returns the (field, value) pairs of the instance, in the order of the fields.
+    fn fields(&self) -> Vec<(&'static str, serde_json::Value)>
+    where
+        Self: Sized;
+
+    /// Wraps the instance to print it with the `#[model(sensitive = true)]` fields
+    /// redacted, with `{}` or `{:?}`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// println!("{}", user.redacted()); // User { id: 1, email: "joe@gmail.com", password: *** }
+    /// ```
+    fn redacted(&self) -> Redacted<'_, Self>
+    where
+        Self: Sized,
+    {
+        Redacted(self)
+    }
+
+    /// Renders instances as an ASCII table, for debugging query results from a REPL
+    /// or a command line tool. The `#[model(sensitive = true)]` fields are redacted.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let users = User::all(&conn).await;
+    /// println!("{}", User::to_table(&users));
+    /// // +----+---------------+----------+
+    /// // | id | email         | password |
+    /// // +----+---------------+----------+
+    /// // | 1  | joe@gmail.com | ***      |
+    /// // +----+---------------+----------+
+    /// ```
+    fn to_table(rows: &[Self]) -> String
+    where
+        Self: Sized,
+    {
+        let header = match rows.first() {
+            Some(row) => row.fields().into_iter().map(|(field, _)| field).collect(),
+            None => Self::COLUMNS
+                .iter()
+                .map(|(column, _)| *column)
+                .collect::<Vec<_>>(),
+        };
+        let cells = rows
+            .iter()
+            .map(|row| {
+                row.fields()
+                    .into_iter()
+                    .map(|(field, value)| redact::<Self>(field, &value, false))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        render_table(&header, &cells)
+    }
+
+    /// Updates the current model instance in the database.
+    ///
+    /// # Arguments
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The number of rows matched, `0` if the instance was deleted meanwhile, or
+    /// `Error::MissingPrimaryKey` if its generated primary key is not set.
+    ///
+    /// # Example
+    /// ```ignore
+    /// if let Some(mut user) = User::get(
+    ///     kwargs!(email = "24nomeniavo@gmail.com", password = "strongpassword"),
+    ///     &conn,
+    /// ).await {
+    ///     user.role = "admin".to_string();
+    ///     if user.update(&conn).await? == 0 {
+    ///         println!("The user was deleted");
+    ///     }
+    /// }
+    /// ```
+    async fn update(&self, conn: &Connection) -> Result<u64, Error>
+    where
+        Self: Sized;
+
+    /// Updates some fields of the current model instance, identified by its primary key.
+    ///
+    /// # Arguments
+    /// * `kw` - The key-value arguments for the update, which may be expressions.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The number of rows matched, or `Error::MissingPrimaryKey` if the generated
+    /// primary key of the instance is not set.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let success = user.update_fields(kwargs!(role = "admin"), &conn).await;
+    /// println!("Update success: {}", success.is_ok_and(|rows| rows == 1));
+    /// ```
+    async fn update_fields(&self, kw: Vec<Condition>, conn: &Connection) -> Result<u64, Error>
+    where
+        Self: Sized,
+    {
+        if has_unset_pk(self) {
+            return Err(Error::MissingPrimaryKey { model: Self::NAME });
+        }
+        let (placeholders, mut args) = kw.to_update_query();
+        let pk = self.pk();
+        let condition = pk_condition::<Self>(&pk, &mut args);
+        check_args(&args)?;
+        let query = format!(
+            "update {table_name} set {placeholders} where {condition};",
+            table_name = quote_ident(Self::NAME),
+        );
+
+        let mut stream = sqlx::query(&query);
+        binds!(args, stream)?;
+        let output = track_operation(
+            Self::NAME,
+            "update",
+            &query,
+            on_conn!(conn, stream, execute),
+        )
+        .await;
+        cache::invalidate(Self::NAME, &cache::key(&pk.args()), conn);
+        tracked(&query, output).map(|result| result.rows_affected())
+    }
+
+    /// Moves the state held by `field` from `current` to `to`, as the `mark_{to}` methods
+    /// generated by the `state_machine` attribute do.
+    ///
+    /// The update only applies if `field` still holds `current` in the database, so of
+    /// two concurrent transitions from the same state only one succeeds.
+    ///
+    /// # Arguments
+    /// * `field` - The column holding the state.
+    /// * `current` - The state of the instance.
+    /// * `from` - The states `to` can be reached from.
+    /// * `to` - The new state.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// `Error::InvalidTransition` if `to` can not be reached from `current`,
+    /// `Error::StaleState` if the state was changed in the database in the meantime.
+    ///
+    /// # Example
+    /// ```ignore
+    /// order.transition("status", &order.status, &["paid"], "shipped", &conn).await?;
+    /// ```
+    async fn transition(
+        &self,
+        field: &'static str,
+        current: &str,
+        from: &[&str],
+        to: &str,
+        conn: &Connection,
+    ) -> Result<(), Error>
+    where
+        Self: Sized + Sync,
+    {
+        if !from.contains(&current) {
+            return Err(Error::InvalidTransition {
+                model: Self::NAME,
+                field,
+                from: current.to_string(),
+                to: to.to_string(),
+            });
+        }
+        if has_unset_pk(self) {
+            return Err(Error::MissingPrimaryKey { model: Self::NAME });
+        }
+        let placeholder = PLACEHOLDER.to_string();
+        let mut args = vec![SqlValue::from(to)];
+        let pk = self.pk();
+        let condition = pk_condition::<Self>(&pk, &mut args);
+        args.push(SqlValue::from(current));
+        let query = format!(
+            "update {table_name} set {field}={placeholder}1 where {condition} and {field}={placeholder}{index};",
+            table_name = quote_ident(Self::NAME),
+            index = args.len(),
+        );
+
+        let mut stream = sqlx::query(&query);
+        binds!(args, stream)?;
+        let output = track_operation(
+            Self::NAME,
+            "update",
+            &query,
+            on_conn!(conn, stream, execute),
+        )
+        .await;
+        cache::invalidate(Self::NAME, &cache::key(&pk.args()), conn);
+        match tracked(&query, output)?.rows_affected() {
+            0 => Err(Error::StaleState {
+                model: Self::NAME,
+                field,
+                state: current.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Updates all the instances matching the conditions in one statement.
+    ///
+    /// # Arguments
+    /// * `kw` - The conditions selecting the instances to update.
+    /// * `values` - The key-value arguments for the update, which may be expressions.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The number of updated instances, or the error.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let updated = Product::update_where(
+    ///     kwargs!(active == true),
+    ///     kwargs!(category = Case::when(kwargs!(price > 100.0), "premium").otherwise("standard")),
+    ///     &conn,
+    /// ).await;
+    /// println!("Updated: {:?}", updated);
+    /// ```
+    async fn update_where(
+        kw: Vec<Condition>,
+        values: Vec<Condition>,
+        conn: &Connection,
+    ) -> Result<u64, Error> {
+        let (placeholders, mut args) = values.to_update_query();
+        let mut query = format!(
+            "update {table_name} set {placeholders}",
+            table_name = quote_ident(Self::NAME),
+        );
+        if !kw.is_empty() {
+            let conditions = select_conditions(&kw, Self::RANGES, &mut args);
+            query.push_str(&format!(" where {conditions}"));
+        }
+        query.push(';');
+        if !check_statement(&query) {
+            return Err(Error::Rejected { query });
+        }
+        check_args(&args)?;
+
+        let mut stream = sqlx::query(&query);
+        binds!(args, stream)?;
+        let output = track_operation(
+            Self::NAME,
+            "update",
+            &query,
+            on_conn!(conn, stream, execute),
+        )
+        .await;
+        cache::invalidate_table(Self::NAME, conn);
+        tracked(&query, output).map(|result| result.rows_affected())
+    }
+
+    /// Deletes the current model instance from the database.
+    ///
+    /// # Arguments
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// `Ok` if delete is successful, the error otherwise.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let success = user.delete(&conn).await;
+    /// println!("Delete success: {}", success.is_ok());
+    /// ```
+    async fn delete(&self, conn: &Connection) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        let mut args = Vec::new();
+        let pk = self.pk();
+        let condition = pk_condition::<Self>(&pk, &mut args);
+        check_args(&args)?;
+        let query = format!(
+            "delete from {table_name} where {condition};",
+            table_name = quote_ident(Self::NAME),
+        );
+
+        let mut stream = sqlx::query(&query);
+        binds!(args, stream)?;
+        let output = track_operation(
+            Self::NAME,
+            "delete",
+            &query,
+            on_conn!(conn, stream, execute),
+        )
+        .await;
+        cache::invalidate(Self::NAME, &cache::key(&pk.args()), conn);
+        tracked(&query, output).map(|_| ())
+    }
+
+    /// Retrieves all instances of the model from the database.
+    ///
+    /// # Arguments
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// A vector of all instances of the model.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let users = User::all(&conn).await;
+    /// println!("{:#?}", users);
+    /// ```
+    async fn all(conn: &Connection) -> Vec<Self>
+    where
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        let limit = row_limit()
+            .map(|limit| format!(" limit {limit}"))
+            .unwrap_or_default();
+        let table_name = quote_ident(Self::NAME);
+        let query = format!(
+            "select {columns} from {table_name}{limit}",
+            columns = selected_columns::<Self>(None)
+        );
+        let rows = track_operation(
+            Self::NAME,
+            "select",
+            &query,
+            on_conn!(conn, sqlx::query_as::<_, Self>(&query), fetch_all),
+        )
+        .await
+        .and_then(Result::ok)
+        .unwrap_or_default();
+        if check_rows(&query, rows.len()) {
+            rows
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Retrieves all instances of the model sorted by the given fields, see `order_by`.
+    ///
+    /// # Arguments
+    /// * `conn` - The database connection.
+    /// * `ordering` - The fields to sort by, see `OrderField`.
+    ///
+    /// # Returns
+    /// All the instances of the model, in order, or `Error::Invalid` if a field of
+    /// `ordering` is not a column of its model.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let users = User::all_ordered(&conn, &[desc(UserColumn::CreatedAt)]).await?;
+    /// ```
+    async fn all_ordered<F>(conn: &Connection, ordering: &[F]) -> Result<Vec<Self>, Error>
+    where
+        F: Into<OrderField> + Clone + Sync,
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        Self::objects().order_by(ordering).try_all(conn).await
+    }
+
+    /// Iterates over all instances of the model in batches of `size` rows.
+    ///
+    /// The batches are fetched lazily with keyset pagination on the columns of the
+    /// primary key (`where pk > last_pk order by pk limit size`), so memory stays
+    /// bounded and no long-running cursor is held open between batches.
+    ///
+    /// # Arguments
+    /// * `size` - The number of instances per batch.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// A `Chunks` iterator yielding the batches, or the error that ended the iteration.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut chunks = User::iter_chunks(1000, &conn);
+    /// while let Some(users) = chunks.next().await {
+    ///     println!("{} users", users?.len());
+    /// }
+    /// ```
+    fn iter_chunks(size: usize, conn: &Connection) -> Chunks<'_, Self>
+    where
+        Self: Sized,
+    {
+        Chunks {
+            conn,
+            size,
+            last: None,
+            done: size == 0,
+            _model: PhantomData,
+        }
+    }
+
+    /// Opens a server-side cursor over all instances of the model.
+    ///
+    /// `fetch_size` is the number of rows fetched per round-trip: on Postgres the rows
+    /// are read with `declare ... cursor` / `fetch forward {fetch_size}` inside a
+    /// transaction. The server-side cursor is Postgres only: MySQL and SQLite are read
+    /// in keyset batches of `fetch_size` rows instead (see `iter_chunks`), each batch
+    /// being a query of its own. Bigger batches mean fewer round-trips but more memory.
+    ///
+    /// # Arguments
+    /// * `fetch_size` - The number of rows fetched at once.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// A `Cursor` yielding the batches, or the error that ended the iteration.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut cursor = User::cursor(500, &conn);
+    /// while let Some(users) = cursor.next().await {
+    ///     println!("{} users", users?.len());
+    /// }
+    /// ```
+    fn cursor(fetch_size: usize, conn: &Connection) -> Cursor<'_, Self>
+    where
+        Self: Sized,
+    {
+        if *BACKEND == Backend::Postgres {
+            Cursor::Postgres {
+                conn,
+                name: format!("rusql_cursor_{}", CURSORS.fetch_add(1, Ordering::Relaxed)),
+                fetch_size,
+                tx: None,
+                declared: false,
+                done: fetch_size == 0,
+                _model: PhantomData,
+            }
+        } else {
+            Cursor::Chunks(Self::iter_chunks(fetch_size, conn))
+        }
+    }
+
+    /// Polls the model every `interval` for the instances created or updated since the
+    /// previous poll, yielding them in the order of `column`, a change feed for the
+    /// backends without `LISTEN`/`NOTIFY`.
+    ///
+    /// `column` must be set on every write to a value ordered as the writes, e.g. an
+    /// `updated_at` timestamp, and not be `null`. The feed starts after the instance
+    /// with the latest `column` at the first poll, and resumes after the last instance
+    /// it yielded (by `column` then primary key), so an instance written with a value
+    /// older than the latest one yielded (e.g. by a long transaction) is not seen.
+    ///
+    /// Each poll loads at most `batch_size` instances; while a poll returns a full batch
+    /// the next one runs without waiting, so a backlog is caught up in batches.
+    ///
+    /// # Arguments
+    /// * `column` - The column ordering the writes.
+    /// * `interval` - The time between two polls.
+    /// * `batch_size` - The maximum number of instances loaded by a poll.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// A `Stream` of the created or updated instances, which never ends, or of the
+    /// error of a poll (the next poll being retried after `interval`). It yields
+    /// `Error::Invalid` then ends if the model has no `column`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let interval = Duration::from_secs(5);
+    /// let mut changes = Product::poll_changes("updated_at", interval, 500, &conn);
+    /// while let Some(product) = changes.next().await {
+    ///     search_index.upsert(&product?).await;
+    /// }
+    /// ```
+    fn poll_changes<'c>(
+        column: &str,
+        interval: Duration,
+        batch_size: usize,
+        conn: &'c Connection,
+    ) -> Changes<'c, Self>
+    where
+        Self: Sized + Send + Unpin + for<'r> FromRow<'r, AnyRow> + 'c,
+    {
+        if !Self::COLUMNS.iter().any(|(name, _)| *name == column) {
+            let err = Error::Invalid(format!("{} has no column {column}", Self::NAME));
+            return Box::pin(futures_util::stream::once(async move { Err(err) }));
+        }
+        let mut ordering = vec![column.to_string()];
+        for pk in pk_columns::<Self>() {
+            if pk != &column {
+                ordering.push(pk.to_string());
+            }
+        }
+        let batch_size = batch_size.max(1);
+        // the keyset of the last instance yielded, `None` until the first poll, and
+        // whether to wait before the next poll (not after a full batch)
+        let state = (
+            None::<Option<Vec<serde_json::Value>>>,
+            VecDeque::new(),
+            false,
+        );
+        Box::pin(futures_util::stream::unfold(
+            state,
+            move |(mut last, mut pending, mut wait)| {
+                let ordering = ordering.clone();
+                async move {
+                    loop {
+                        if let Some(instance) = pending.pop_front() {
+                            return Some((Ok(instance), (last, pending, wait)));
+                        }
+                        if wait {
+                            tokio::time::sleep(interval).await;
+                        }
+                        wait = true;
+                        let fields = ordering.iter().map(String::as_str).collect::<Vec<_>>();
+                        let Some(after) = last.clone() else {
+                            let latest = ordering
+                                .iter()
+                                .map(|column| format!("-{column}"))
+                                .collect::<Vec<_>>();
+                            match Self::objects()
+                                .order_by(&latest)
+                                .limit(1)
+                                .try_all(conn)
+                                .await
+                            {
+                                Ok(mut latest) => {
+                                    last = Some(
+                                        latest
+                                            .pop()
+                                            .map(|latest| keyset_values(&latest, &ordering)),
+                                    );
+                                    continue;
+                                }
+                                Err(err) => return Some((Err(err), (last, pending, wait))),
+                            }
+                        };
+
+                        let mut query = Self::objects().order_by(&fields).limit(batch_size);
+                        if let Some(values) = after {
+                            let keyset = ordering
+                                .iter()
+                                .map(|column| (column.clone(), false))
+                                .collect::<Vec<_>>();
+                            query = query.filter(keyset_condition(&keyset, values).0);
+                        }
+                        match query.try_all(conn).await {
+                            Ok(rows) => {
+                                wait = rows.len() < batch_size;
+                                pending = rows.into();
+                            }
+                            Err(err) => return Some((Err(err), (last, pending, wait))),
+                        }
+                        if let Some(latest) = pending.back() {
+                            last = Some(Some(keyset_values(latest, &ordering)));
+                        }
+                    }
+                }
+            },
+        ))
+    }
+
+    /// Dumps every row of the model as JSON objects, optionally anonymized.
+    ///
+    /// When `anonymized` is `true`, each field declared with
+    /// `#[model(anonymize = "...")]` is replaced according to its strategy, so
+    /// production data can be copied to staging safely. The available strategies are
+    /// `fake_email`, `fake_name`, `fake_phone`, `mask`, `redact` and `null`.
+    ///
+    /// # Arguments
+    /// * `anonymized` - Whether to apply the anonymization strategies.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// One JSON object per row, keyed by column name.
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[derive(FromRow, Clone, Debug, Default, Model)]
+    /// struct User {
+    ///     #[model(primary_key = true, auto = true, null = false)]
+    ///     id: Integer,
+    ///     #[model(anonymize = "fake_email")]
+    ///     email: String,
+    /// }
+    ///
+    /// let users = User::dump(true, &conn).await;
+    /// println!("{}", serde_json::to_string_pretty(&users).unwrap());
+    /// ```
+    async fn dump(anonymized: bool, conn: &Connection) -> Vec<serde_json::Value>
+    where
+        Self: Sized,
+    {
+        let table_name = quote_ident(Self::NAME);
+        let query = format!(
+            "select {columns} from {table_name}",
+            columns = selected_columns::<Self>(None)
+        );
+        let rows = track_query(&query, on_conn!(conn, sqlx::query(&query), fetch_all))
+            .await
+            .and_then(Result::ok)
+            .unwrap_or_default();
+
+        rows.iter()
+            .enumerate()
+            .map(|(index, row)| {
+                let mut object = row_to_json(row);
+                if anonymized {
+                    for (field, strategy) in Self::ANONYMIZE {
+                        if let Some(value) = object.get_mut(*field) {
+                            *value = anonymize(strategy, value, index + 1);
+                        }
+                    }
+                }
+                serde_json::Value::Object(object)
+            })
+            .collect()
+    }
+
+    /// Computes a SHA-256 checksum of the instances matching the conditions, to compare
+    /// the data of two environments or of a table before and after a migration.
+    ///
+    /// The rows are hashed in the order of their primary key, each as its (column, value)
+    /// pairs sorted by column, so the checksum doesn't depend on the physical order of the
+    /// rows or of the columns. They are hashed as they are streamed, so a large table is
+    /// never held in memory. The values are read as the backend returns them (e.g. the
+    /// booleans are integers on SQLite), so only the checksums of a same backend compare.
+    ///
+    /// # Arguments
+    /// * `kw` - The conditions selecting the instances, all of them if empty.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The hexadecimal digest, or the error.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let before = User::checksum(kwargs!(role == "admin"), &conn).await?;
+    /// // ... the migration
+    /// assert_eq!(before, User::checksum(kwargs!(role == "admin"), &conn).await?);
+    /// ```
+    async fn checksum(kw: Vec<Condition>, conn: &Connection) -> Result<String, Error>
+    where
+        Self: Sized,
+    {
+        let columns = if Self::COLUMNS.is_empty() {
+            "*".to_string()
+        } else {
+            Self::COLUMNS
+                .iter()
+                .map(|(column, _)| *column)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut args = Vec::new();
+        let mut query = format!(
+            "select {columns} from {table_name}",
+            table_name = quote_ident(Self::NAME)
+        );
+        if !kw.is_empty() {
+            let conditions = select_conditions(&kw, Self::RANGES, &mut args);
+            query.push_str(&format!(" where {conditions}"));
+        }
+        query.push_str(&format!(" order by {};", pk_columns::<Self>().join(", ")));
+        check_args(&args)?;
+
+        let mut stream = sqlx::query(&query);
+        binds!(args, stream)?;
+        let mut connection = transaction::acquire(conn).await?;
+        let hashed = async {
+            let mut rows = stream.fetch(&mut *connection);
+            let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+            let mut count = 0;
+            while let Some(row) = rows.try_next().await? {
+                count += 1;
+                let mut values = row_to_json(&row).into_iter().collect::<Vec<_>>();
+                values.sort_by(|(a, _), (b, _)| a.cmp(b));
+                context.update(
+                    serde_json::to_string(&values)
+                        .unwrap_or_default()
+                        .as_bytes(),
+                );
+                context.update(b"\n");
+            }
+            Ok((context.finish(), count))
+        };
+        let output = track_operation(Self::NAME, "select", &query, hashed).await;
+        let (digest, _) = tracked(&query, output)?;
+        Ok(digest
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect())
+    }
+
+    /// Filters instances of the model based on the provided parameters.
+    ///
+    /// # Arguments
+    /// * `kw` - The key-value arguments for filtering.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// A vector of instances matching the filter criteria.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let users = User::filter(
+    ///     kwargs!(age__gte = 18, weight__lte = 80.0),
+    ///     &conn,
+    /// ).await;
+    /// println!("{:#?}", users);
+    /// ```
+    async fn filter(kw: Vec<Condition>, conn: &Connection) -> Vec<Self>
+    where
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        let (fields, args) = kw.to_model_select_query::<Self>();
+
+        let limit = row_limit()
+            .map(|limit| format!(" LIMIT {limit}"))
+            .unwrap_or_default();
+        let table_name = quote_ident(Self::NAME);
+        let query = format!(
+            "SELECT {columns} FROM {table_name} WHERE {fields}{limit};",
+            columns = selected_columns::<Self>(None)
+        );
+
+        let mut stream = sqlx::query_as::<_, Self>(&query);
+        binds!(args, stream, Vec::new());
+        let rows = track_operation(
+            Self::NAME,
+            "select",
+            &query,
+            on_conn!(conn, stream, fetch_all),
+        )
+        .await
+        .and_then(Result::ok)
+        .unwrap_or_default();
+        if check_rows(&query, rows.len()) {
+            rows
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Filters instances of the model sorted by the given fields, the columns of a
+    /// related model being reached through its foreign key with `field__column`.
+    ///
+    /// # Arguments
+    /// * `kw` - The key-value arguments for filtering, all the instances if empty.
+    /// * `ordering` - The fields to sort by, see `OrderField`.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The instances matching the filter criteria, in order, or `Error::Invalid` if a
+    /// field of `ordering` is not a column of its model (or a foreign key of the path),
+    /// the ordering being checked before being rendered into the SQL.
+    ///
+    /// # Example
+    /// ```ignore
+    /// // the products sorted by the name of the company of their owner
+    /// let products = Product::order_by(
+    ///     kwargs!(price__gt = 10.0),
+    ///     &["owner__company__name", "-price"],
+    ///     &conn,
+    /// ).await?;
+    /// ```
+    async fn order_by<F>(
+        kw: Vec<Condition>,
+        ordering: &[F],
+        conn: &Connection,
+    ) -> Result<Vec<Self>, Error>
+    where
+        F: Into<OrderField> + Clone + Sync,
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        Self::objects()
+            .filter(kw)
+            .order_by(ordering)
+            .try_all(conn)
+            .await
+    }
+
+    /// Filters a slice of instances of the model, e.g. to list a large table. The
+    /// instances are sorted by primary key so that the slices do not overlap, see
+    /// `objects` to sort them otherwise.
+    ///
+    /// # Arguments
+    /// * `kw` - The key-value arguments for filtering, all the instances if empty.
+    /// * `limit` - The maximum number of instances returned.
+    /// * `offset` - The number of instances skipped.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// At most `limit` instances matching the filter criteria, after the first `offset`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// // the 20 adults after the first 40
+    /// let users = User::slice(kwargs!(age__gte = 18), 20, 40, &conn).await;
+    /// ```
+    async fn slice(kw: Vec<Condition>, limit: usize, offset: usize, conn: &Connection) -> Vec<Self>
+    where
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        Self::objects()
+            .filter(kw)
+            .order_by(pk_columns::<Self>())
+            .limit(limit)
+            .offset(offset)
+            .all(conn)
+            .await
+    }
+
+    /// Filters a page of instances of the model, with the totals of the pages, see
+    /// `QuerySet::paginate`.
+    ///
+    /// # Arguments
+    /// * `kw` - The key-value arguments for filtering, all the instances if empty.
+    /// * `page` - The number of the page, from 1.
+    /// * `per_page` - The number of instances per page.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The page, serializable to be returned by a web handler.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let page = User::paginate(kwargs!(age__gte = 18), 3, 20, &conn).await;
+    /// println!("{} of {} pages", page.page, page.page_count);
+    /// ```
+    async fn paginate(
+        kw: Vec<Condition>,
+        page: usize,
+        per_page: usize,
+        conn: &Connection,
+    ) -> Page<Self>
+    where
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        Self::objects()
+            .filter(kw)
+            .paginate(page, per_page, conn)
+            .await
+    }
+
+    /// Filters the first instance of each group of `columns` in the order of `ordering`,
+    /// e.g. the latest product of each owner.
+    ///
+    /// Postgres uses `distinct on`, the other backends number the rows of each group
+    /// with the `row_number` window function.
+    ///
+    /// # Arguments
+    /// * `columns` - The columns of the groups.
+    /// * `ordering` - The fields sorting each group, descending when prefixed by `-`.
+    /// * `kw` - The key-value arguments for filtering, all the instances if empty.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The first instance of each group, ordered by `columns`, or `Error::Invalid` if a
+    /// field of `ordering` is not a column of its model.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let latest = Product::distinct_on(&[ProductColumn::Owner], &["-at"], vec![], &conn).await?;
+    /// ```
+    async fn distinct_on(
+        columns: &[Self::Column],
+        ordering: &[&str],
+        kw: Vec<Condition>,
+        conn: &Connection,
+    ) -> Result<Vec<Self>, Error>
+    where
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        let (fields, args) = kw.to_model_select_query::<Self>();
+        let condition = if fields.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {fields}")
+        };
+        let table_name = quote_ident(Self::NAME);
+        let (joins, order) = related_ordering::<Self>(ordering)?;
+        let columns = columns
+            .iter()
+            .map(|column| format!("{table_name}.{}", column.name()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if columns.is_empty() {
+            return Err(Error::Invalid(
+                "distinct_on needs at least one column".to_string(),
+            ));
+        }
+        let order = if order.is_empty() {
+            columns.clone()
+        } else {
+            format!("{columns}, {}", order.join(", "))
+        };
+        let limit = row_limit()
+            .map(|limit| format!(" LIMIT {limit}"))
+            .unwrap_or_default();
+        let rows = format!(
+            "(SELECT {columns} FROM {table_name}{condition}) AS {table_name}{joins}",
+            columns = selected_columns::<Self>(None)
+        );
+        let query = if *BACKEND == Backend::Postgres {
+            format!("SELECT DISTINCT ON ({columns}) {table_name}.* FROM {rows} ORDER BY {order}{limit};")
+        } else {
+            format!(
+                "SELECT * FROM (SELECT {table_name}.*, row_number() OVER (PARTITION BY {columns} ORDER BY {order}) AS row_number__ FROM {rows}) AS {table_name} WHERE row_number__=1 ORDER BY {columns}{limit};"
+            )
+        };
+
+        let mut stream = sqlx::query_as::<_, Self>(&query);
+        binds!(args, stream)?;
+        let output = track_operation(
+            Self::NAME,
+            "select",
+            &query,
+            on_conn!(conn, stream, fetch_all),
+        )
+        .await;
+        let rows = tracked(&query, output)?;
+        if check_rows(&query, rows.len()) {
+            Ok(rows)
+        } else {
+            Err(Error::Rejected { query })
+        }
+    }
+
+    /// Filters instances of the model on an aggregate of their related rows, e.g. the
+    /// users having at least 3 products.
+    ///
+    /// # Arguments
+    /// * `annotation` - The aggregate, referenced by its alias in `kw`.
+    /// * `kw` - The key-value arguments for filtering.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The instances matching the filter criteria, `Error::Invalid` if no foreign key of
+    /// the related model references this one, or the error of the query.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let users = User::filter_annotated(
+    ///     count_related::<Product>("products"),
+    ///     kwargs!(products__gte = 3).and(kwargs!(role == "admin")),
+    ///     &conn,
+    /// ).await?;
+    /// ```
+    async fn filter_annotated(
+        annotation: RelatedAggregate,
+        kw: Vec<Condition>,
+        conn: &Connection,
+    ) -> Result<Vec<Self>, Error>
+    where
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        let (fields, args) = kw.to_model_select_query::<Self>();
+        let condition = if fields.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {fields}")
+        };
+        let limit = row_limit()
+            .map(|limit| format!(" LIMIT {limit}"))
+            .unwrap_or_default();
+        let query = format!(
+            "SELECT * FROM {table}{condition}{limit};",
+            table = annotation.annotated_table::<Self>()?
+        );
+
+        let mut stream = sqlx::query_as::<_, Self>(&query);
+        binds!(args, stream)?;
+        let output = track_operation(
+            Self::NAME,
+            "select",
+            &query,
+            on_conn!(conn, stream, fetch_all),
+        )
+        .await;
+        let rows = tracked(&query, output)?;
+        if check_rows(&query, rows.len()) {
+            Ok(rows)
+        } else {
+            Err(Error::Rejected { query })
+        }
+    }
+
+    /// Retrieves all instances of the model, within the soft limit of `set_max_rows`.
+    ///
+    /// # Arguments
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// All the instances, or `Error::ResultTooLarge` if there are more than the limit.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let users = User::try_all(&conn).await?;
+    /// ```
+    async fn try_all(conn: &Connection) -> Result<Vec<Self>, Error>
+    where
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        Self::try_filter(Vec::new(), conn).await
+    }
+
+    /// Retrieves the instances matching the filter criteria, within the soft limit of
+    /// `set_max_rows`.
+    ///
+    /// # Arguments
+    /// * `kw` - The key-value arguments for filtering, all the instances if empty.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The matching instances, or `Error::ResultTooLarge` if there are more than the
+    /// limit.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let users = User::try_filter(kwargs!(age <= 18), &conn).await?;
+    /// // a larger result for this call
+    /// let users = with_max_rows(Some(100_000), User::try_filter(kwargs!(age > 18), &conn)).await?;
+    /// ```
+    async fn try_filter(kw: Vec<Condition>, conn: &Connection) -> Result<Vec<Self>, Error>
+    where
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        let (fields, args) = kw.to_model_select_query::<Self>();
+        let condition = if fields.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {fields}")
+        };
+        let max_rows = soft_max_rows();
+        // one more row than the limit to detect it is exceeded
+        let limit = max_rows
+            .map(|max_rows| format!(" LIMIT {}", max_rows + 1))
+            .unwrap_or_default();
+        let table_name = quote_ident(Self::NAME);
+        let query = format!(
+            "SELECT {columns} FROM {table_name}{condition}{limit};",
+            columns = selected_columns::<Self>(None)
+        );
+
+        let mut stream = sqlx::query_as::<_, Self>(&query);
+        binds!(args, stream)?;
+        let output = track_operation(
+            Self::NAME,
+            "select",
+            &query,
+            on_conn!(conn, stream, fetch_all),
+        )
+        .await;
+        let rows = tracked(&query, output)?;
+        match max_rows {
+            Some(max_rows) if rows.len() > max_rows => {
+                Err(Error::ResultTooLarge { query, max_rows })
+            }
+            _ => Ok(rows),
+        }
+    }
+
+    /// Runs a raw SQL query, read into instances of the model, for the queries the other
+    /// methods can not express (window functions, vendor-specific SQL).
+    ///
+    /// # Arguments
+    /// * `sql` - The query, selecting every column of the model, its `?` being bound to
+    ///   the `params` in order. The `?` of the quoted literals and identifiers are kept,
+    ///   but not the Postgres `?` operators of `jsonb` (use `jsonb_exists` instead).
+    /// * `params` - The values bound to the query.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The instances read from the rows, `Error::Invalid` if the number of `params`
+    /// does not match the `?` of the query or a param can not be bound (a list).
+    ///
+    /// # Example
+    /// ```ignore
+    /// let ranked = User::raw(
+    ///     "select * from (select *, rank() over (partition by role order by age desc) as rank
+    ///      from users) as ranked where rank <= ? and role != ?",
+    ///     &[3.into(), "guest".into()],
+    ///     &conn,
+    /// )
+    /// .await?;
+    /// ```
+    async fn raw(sql: &str, params: &[SqlValue], conn: &Connection) -> Result<Vec<Self>, Error>
+    where
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow>,
+    {
+        let expected = placeholder_offsets(sql).len();
+        if expected != params.len() {
+            return Err(Error::Invalid(format!(
+                "the query binds {expected} values, {} given",
+                params.len()
+            )));
+        }
+        let mut args = Vec::new();
+        let query = number_placeholders(sql, params, &mut args);
+        if !check_statement(&query) {
+            return Err(Error::Rejected { query });
+        }
+
+        let mut stream = sqlx::query_as::<_, Self>(&query);
+        binds!(args, stream)?;
+        let output = track_operation(
+            Self::NAME,
+            "select",
+            &query,
+            on_conn!(conn, stream, fetch_all),
+        )
+        .await;
+        let rows = tracked(&query, output)?;
+        if check_rows(&query, rows.len()) {
+            Ok(rows)
+        } else {
+            Err(Error::Rejected { query })
+        }
+    }
+
+    /// Retrieves only some columns of the instances matching the filter criteria.
+    ///
+    /// # Arguments
+    /// * `columns` - The columns to select.
+    /// * `kw` - The key-value arguments for filtering, all the instances if empty.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// One JSON object per instance, keyed by the selected columns.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let users = User::only(&[UserColumn::Id, UserColumn::Email], kwargs!(age >= 18), &conn).await;
+    /// ```
+    async fn only(
+        columns: &[Self::Column],
+        kw: Vec<Condition>,
+        conn: &Connection,
+    ) -> Vec<serde_json::Map<String, serde_json::Value>>
+    where
+        Self: Sized,
+    {
+        let columns = columns
+            .iter()
+            .map(|column| column.name())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let (fields, args) = kw.to_model_select_query::<Self>();
+        let condition = if fields.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {fields}")
+        };
+        let limit = row_limit()
+            .map(|limit| format!(" LIMIT {limit}"))
+            .unwrap_or_default();
+        let query = format!(
+            "SELECT {columns} FROM {table_name}{condition}{limit};",
+            table_name = quote_ident(Self::NAME)
+        );
+
+        let mut stream = sqlx::query(&query);
+        binds!(args, stream, Vec::new());
+        let rows = track_operation(
+            Self::NAME,
+            "select",
+            &query,
+            on_conn!(conn, stream, fetch_all),
+        )
+        .await
+        .and_then(Result::ok)
+        .unwrap_or_default();
+        if check_rows(&query, rows.len()) {
+            rows.iter().map(row_to_json).collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Retrieves the given columns of the instances matching the filter criteria into
+    /// another `FromRow` struct, so a wide table is not fully read for a listing.
+    ///
+    /// # Arguments
+    /// * `columns` - The columns to select, read by name into the fields of `D`.
+    /// * `kw` - The key-value arguments for filtering, all the instances if empty.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// One `D` per instance, none if the rows can not be read as `D`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[derive(FromRow)]
+    /// struct UserSummary {
+    ///     id: Integer,
+    ///     name: String,
+    /// }
+    ///
+    /// let summaries = User::select_as::<UserSummary>(
+    ///     &[UserColumn::Id, UserColumn::Name],
+    ///     kwargs!(age >= 18),
+    ///     &conn,
+    /// )
+    /// .await;
+    /// ```
+    async fn select_as<D>(columns: &[Self::Column], kw: Vec<Condition>, conn: &Connection) -> Vec<D>
+    where
+        Self: Sized,
+        D: Send + Unpin + for<'r> FromRow<'r, AnyRow>,
+    {
+        let columns = columns
+            .iter()
+            .map(|column| column.name())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let (fields, args) = kw.to_model_select_query::<Self>();
+        let condition = if fields.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {fields}")
+        };
+        let limit = row_limit()
+            .map(|limit| format!(" LIMIT {limit}"))
+            .unwrap_or_default();
+        let query = format!(
+            "SELECT {columns} FROM {table_name}{condition}{limit};",
+            table_name = quote_ident(Self::NAME)
+        );
+
+        let mut stream = sqlx::query_as::<_, D>(&query);
+        binds!(args, stream, Vec::new());
+        let rows = track_operation(
+            Self::NAME,
+            "select",
+            &query,
+            on_conn!(conn, stream, fetch_all),
+        )
+        .await
+        .and_then(Result::ok)
+        .unwrap_or_default();
+        if check_rows(&query, rows.len()) {
+            rows
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Retrieves the values of one column of the instances matching the filter criteria.
+    ///
+    /// # Arguments
+    /// * `column` - The column to select.
+    /// * `kw` - The key-value arguments for filtering, all the instances if empty.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The values of the column, `null` being read as `None` by an `Option`. The values
+    /// which can not be read as `V` are left out, as by `pluck_distinct`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let emails = User::pluck::<String>(UserColumn::Email, kwargs!(role == "admin"), &conn).await;
+    /// let ids = User::pluck::<i32>(UserColumn::Id, vec![], &conn).await;
+    /// ```
+    async fn pluck<V: DeserializeOwned>(
+        column: Self::Column,
+        kw: Vec<Condition>,
+        conn: &Connection,
+    ) -> Vec<V>
+    where
+        Self: Sized,
+    {
+        Self::only(&[column], kw, conn)
+            .await
+            .into_iter()
+            .filter_map(|mut row| row.remove(column.name()))
+            .filter_map(|value| serde_json::from_value(value).ok())
+            .collect()
+    }
+
+    /// Retrieves the distinct values of one column of the instances matching the filter
+    /// criteria, sorted.
+    ///
+    /// # Arguments
+    /// * `column` - The column to select.
+    /// * `kw` - The key-value arguments for filtering, all the instances if empty.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// The distinct values of the column, `null` being read as `None` by an `Option`.
+    /// The values which can not be read as `V` are left out, e.g. the booleans stored
+    /// as integers are read as `i32`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let roles = User::pluck_distinct::<String>(UserColumn::Role, kwargs!(age >= 18), &conn).await;
+    /// ```
+    async fn pluck_distinct<V: DeserializeOwned>(
+        column: Self::Column,
+        kw: Vec<Condition>,
+        conn: &Connection,
+    ) -> Vec<V>
+    where
+        Self: Sized,
+    {
+        let column = column.name();
+        let (fields, args) = kw.to_model_select_query::<Self>();
+        let condition = if fields.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {fields}")
+        };
+        let limit = row_limit()
+            .map(|limit| format!(" LIMIT {limit}"))
+            .unwrap_or_default();
+        let query = format!(
+            "SELECT DISTINCT {column} FROM {table_name}{condition} ORDER BY {column}{limit};",
+            table_name = quote_ident(Self::NAME)
+        );
+
+        let mut stream = sqlx::query(&query);
+        binds!(args, stream, Vec::new());
+        let rows = track_operation(
+            Self::NAME,
+            "select",
+            &query,
+            on_conn!(conn, stream, fetch_all),
+        )
+        .await
+        .and_then(Result::ok)
+        .unwrap_or_default();
+        if !check_rows(&query, rows.len()) {
+            return Vec::new();
+        }
+        rows.iter()
+            .filter_map(|row| row_to_json(row).remove(column))
+            .filter_map(|value| serde_json::from_value(value).ok())
+            .collect()
+    }
+
+    /// Retrieves the first instance of the model matching the filter criteria.
+    ///
+    /// # Arguments
+    /// * `kw` - The key-value arguments for filtering.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// An optional instance matching the filter criteria.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let user = User::get(
+    ///     kwargs!(email = "24nomeniavo@gmail.com", password = "strongpassword"),
+    ///     &conn,
+    /// ).await;
+    /// println!("{:#?}", user);
+    /// ```
+    async fn get(kw: Vec<Condition>, conn: &Connection) -> Option<Self>
+    where
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        Self::objects().filter(kw).first(conn).await
+    }
+
+    /// Retrieves the instance of the model with the lowest primary key.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let oldest = User::first(&conn).await;
+    /// ```
+    async fn first(conn: &Connection) -> Option<Self>
+    where
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        Self::objects().first(conn).await
+    }
+
+    /// Retrieves the instance of the model with the highest primary key.
     ///
     /// # Example
-    /// ```rust
-    /// let success = User::migrate(&conn).await;
-    /// println!("Migration success: {}", success);
+    /// ```ignore
+    /// let newest = User::last(&conn).await;
     /// ```
-    async fn migrate(conn: &Connection) -> bool
+    async fn last(conn: &Connection) -> Option<Self>
     where
-        Self: Sized,
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
     {
-        println!("{:?}", Self::SCHEMA);
-        if let Err(err) = sqlx::query(Self::SCHEMA).execute(conn).await {
-            eprintln!("Error during the migration\n->{err}");
-            false
-        } else {
-            true
-        }
+        Self::objects().last(conn).await
     }
 
-    /// Saves the current model instance to the database.
-    ///
-    /// # Arguments
-    /// * `conn` - The database connection.
-    ///
-    /// # Returns
-    /// `true` if save is successful, `false` otherwise.
+    /// Retrieves the instance matching the filter criteria with the lowest primary key.
     ///
     /// # Example
+    /// ```ignore
+    /// let first_admin = User::first_where(kwargs!(role = "admin"), &conn).await;
     /// ```
-    /// let user = User {
-    ///     name: "johnDoe@gmail.com".to_string(),
-    ///     email: "21john@gmail.com".to_string(),
-    ///     password: "p455w0rd".to_string(),
-    ///     age: 18,
-    ///     weight: 60.0,
-    ///     ..Default::default()
-    /// };
-    /// let success = user.save(&conn).await;
-    /// println!("Save success: {}", success);
+    async fn first_where(kw: Vec<Condition>, conn: &Connection) -> Option<Self>
+    where
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        Self::objects().filter(kw).first(conn).await
+    }
+
+    /// Retrieves the instance matching the filter criteria with the highest primary key.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let last_admin = User::last_where(kwargs!(role = "admin"), &conn).await;
     /// ```
-    async fn save(&self, conn: &Connection) -> bool
+    async fn last_where(kw: Vec<Condition>, conn: &Connection) -> Option<Self>
     where
-        Self: Sized;
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+    {
+        Self::objects().filter(kw).last(conn).await
+    }
 
-    /// Creates a new model instance with the specified parameters.
+    /// Retrieves the instance of the model with the given primary key.
     ///
     /// # Arguments
-    /// * `kw` - The key-value arguments for the new instance.
+    /// * `pk` - The value of the primary key, a tuple for a composite one.
     /// * `conn` - The database connection.
     ///
     /// # Returns
-    /// `true` if creation is successful, `false` otherwise.
+    /// The instance if it exists.
     ///
     /// # Example
+    /// ```ignore
+    /// let user = User::get_by_pk(user_id, &conn).await;
+    /// let membership = Membership::get_by_pk((team_id, "joe"), &conn).await;
     /// ```
-    /// let success = User::create(
-    ///     kwargs!(
-    ///         name = "joe",
-    ///         email = "24nomeniavo@gmail.com",
-    ///         password = "strongpassword",
-    ///         age = 19,
-    ///         weight = 80.1
-    ///     ),
-    ///     &conn,
-    /// ).await;
-    /// println!("Create success: {}", success);
-    /// ```
-    async fn create(kw: Vec<Condition>, conn: &Connection) -> bool
+    async fn get_by_pk<K: Into<Self::Pk> + Send>(pk: K, conn: &Connection) -> Option<Self>
     where
-        Self: Sized,
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
     {
-        let (fields, placeholders, args) = kw.to_insert_query();
-
+        let mut args = Vec::new();
+        let condition = pk_condition::<Self>(&pk.into(), &mut args);
+        let table_name = quote_ident(Self::NAME);
         let query = format!(
-            "insert into {table_name} ({fields}) values ({placeholders});",
-            table_name = Self::NAME
+            "select {columns} from {table_name} where {condition};",
+            columns = selected_columns::<Self>(None),
         );
-        let mut stream = sqlx::query(&query);
-        binds!(args, stream);
-        stream.execute(conn).await.is_ok()
+
+        let mut stream = sqlx::query_as::<_, Self>(&query);
+        binds!(args, stream, None);
+        match track_operation(
+            Self::NAME,
+            "select",
+            &query,
+            on_conn!(conn, stream, fetch_optional),
+        )
+        .await
+        {
+            Some(Ok(row)) => row,
+            Some(Err(err)) => {
+                eprintln!("Error during the get by primary key\n->{err}");
+                None
+            }
+            None => None,
+        }
     }
 
-    /// Updates the current model instance in the database.
+    /// Retrieves an instance by its primary key through an in-memory cache, for the hot
+    /// lookups of single rows such as sessions and feature flags.
+    ///
+    /// The instance is read once and kept for `ttl`, or until it is written through the
+    /// model methods (`update`, `update_fields`, `delete`, ...). The writes made by raw
+    /// SQL or by other processes are seen once the `ttl` expires, or after
+    /// `clear_object_cache`. Within an `atomic` block the instance read is not cached,
+    /// and the writes are invalidated again once the transaction is committed.
     ///
     /// # Arguments
-    /// * `conn` - The database connection.
+    /// * `pk` - The value of the primary key, a tuple for a composite one.
+    /// * `ttl` - How long the instance is kept.
+    /// * `conn` - The database connection, for a cache miss.
     ///
     /// # Returns
-    /// `true` if update is successful, `false` otherwise.
+    /// The instance, `None` if there is none (which is not cached).
     ///
     /// # Example
+    /// ```ignore
+    /// let flag = Flag::get_cached("new_checkout", Duration::from_secs(30), &conn).await;
     /// ```
-    /// if let Some(mut user) = User::get(
-    ///     kwargs!(email = "24nomeniavo@gmail.com", password = "strongpassword"),
-    ///     &conn,
-    /// ).await {
-    ///     user.role = "admin".to_string();
-    ///     let success = user.update(&conn).await;
-    ///     println!("Update success: {}", success);
-    /// }
-    /// ```
-    async fn update(&self, conn: &Connection) -> bool
+    async fn get_cached<K: Into<Self::Pk> + Send>(
+        pk: K,
+        ttl: Duration,
+        conn: &Connection,
+    ) -> Option<Self>
     where
-        Self: Sized;
+        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone + Send + Sync + 'static,
+        Self::Pk: Send,
+    {
+        let pk = pk.into();
+        let key = cache::key(&pk.args());
+        if let Some(instance) = cache::get::<Self>(Self::NAME, &key) {
+            return Some(instance);
+        }
+        let instance = Self::get_by_pk(pk, conn).await?;
+        // the rows read in a transaction may be rolled back, and are not seen by the
+        // other connections before the commit
+        if transaction::current(conn).is_none() {
+            cache::insert(Self::NAME, key, instance.clone(), ttl);
+        }
+        Some(instance)
+    }
 
-    /// Updates a specific model instance identified by its primary key with the given parameters.
+    /// Returns which of the primary keys `pks` have no instance, in one query per 500
+    /// keys (or `COPY_IN_MAX_PARAMS` bound parameters), e.g. to check the references of an import
+    /// before inserting it. The keys are compared by the database, e.g. `"Joe"` is found
+    /// by `"joe"` under a case-insensitive collation.
     ///
     /// # Arguments
-    /// * `id_value` - The value of the primary key.
-    /// * `kw` - The key-value arguments for the update.
+    /// * `pks` - The values of the primary key, tuples for a composite one.
     /// * `conn` - The database connection.
     ///
     /// # Returns
-    /// `true` if update is successful, `false` otherwise.
+    /// The missing keys in the order of `pks`, or the error of the query.
     ///
     /// # Example
+    /// ```ignore
+    /// let owners = rows.iter().map(|row| row.owner).collect::<Vec<_>>();
+    /// let missing = User::missing_pks(&owners, &conn).await?;
+    /// if !missing.is_empty() {
+    ///     return Err(Error::Invalid(format!("unknown owners {missing:?}")));
+    /// }
     /// ```
-    /// let success = User::set(
-    ///     user_id,
-    ///     kwargs!(role = "admin"),
-    ///     &conn,
-    /// ).await;
-    /// println!("Set success: {}", success);
-    /// ```
-    async fn set<T: ToString + Clone + Send + Sync>(
-        id_value: T,
-        kw: Vec<Condition>,
-        conn: &Connection,
-    ) -> bool {
-        let (placeholders, mut args) = kw.to_update_query();
-
-        args.push((
-            id_value.clone().to_string(),
-            get_type_name(id_value.clone()).to_string(),
-        ));
-        let index_id = args.len();
-        let placeholder = PLACEHOLDER.to_string();
-        let query = format!(
-            "update {table_name} set {placeholders} where {id}={placeholder}{index_id};",
-            id = Self::PK,
-            table_name = Self::NAME,
-        );
+    async fn missing_pks(pks: &[Self::Pk], conn: &Connection) -> Result<Vec<Self::Pk>, Error>
+    where
+        Self: Sized,
+        Self::Pk: Clone + Sync,
+    {
+        if pks.is_empty() {
+            return Ok(Vec::new());
+        }
+        let columns = pk_columns::<Self>();
+        let size = (COPY_IN_MAX_PARAMS / columns.len()).min(COMPOUND_SELECT_MAX);
+        let mut found = HashSet::new();
+        for (chunk, keys) in pks.chunks(size).enumerate() {
+            // every key selects its position back, so the keys are compared by the
+            // database, under the collation of their columns
+            let mut args = Vec::new();
+            let query = keys
+                .iter()
+                .enumerate()
+                .map(|(index, pk)| {
+                    format!(
+                        "select '{index}' as position from {table_name} where {condition}",
+                        table_name = quote_ident(Self::NAME),
+                        condition = pk_condition::<Self>(pk, &mut args),
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" union all ");
+            let query = format!("{query};");
+            check_args(&args)?;
 
-        let mut stream = sqlx::query(&query);
-        binds!(args, stream);
-        stream.execute(conn).await.is_ok()
+            let mut stream = sqlx::query(&query);
+            binds!(args, stream)?;
+            let output = track_operation(
+                Self::NAME,
+                "select",
+                &query,
+                on_conn!(conn, stream, fetch_all),
+            )
+            .await;
+            for row in tracked(&query, output)? {
+                let position = row.try_get::<String, _>("position")?;
+                if let Ok(position) = position.parse::<usize>() {
+                    found.insert(chunk * size + position);
+                }
+            }
+        }
+        Ok(pks
+            .iter()
+            .enumerate()
+            .filter(|(position, _)| !found.contains(position))
+            .map(|(_, pk)| pk.clone())
+            .collect())
     }
 
-    /// Deletes the current model instance from the database.
+    /// Counts the number of instances of the model in the database.
     ///
     /// # Arguments
     /// * `conn` - The database connection.
     ///
     /// # Returns
-    /// `true` if delete is successful, `false` otherwise.
+    /// The count of instances.
     ///
     /// # Example
+    /// ```ignore
+    /// let count = User::count(&conn).await;
+    /// println!("User count: {}", count);
     /// ```
-    /// let success = user.delete(&conn).await;
-    /// println!("Delete success: {}", success);
-    /// ```
-    async fn delete(&self, conn: &Connection) -> bool
+    async fn count(conn: &Connection) -> i64
     where
-        Self: Sized;
+        Self: Sized,
+    {
+        Self::count_where(Vec::new(), conn).await
+    }
 
-    /// Retrieves all instances of the model from the database.
+    /// Counts the number of instances matching the filter criteria.
     ///
     /// # Arguments
+    /// * `kw` - The key-value arguments for filtering, all the instances if empty.
     /// * `conn` - The database connection.
     ///
     /// # Returns
-    /// A vector of all instances of the model.
+    /// The count of matching instances.
     ///
     /// # Example
+    /// ```ignore
+    /// let adults = User::count_where(kwargs!(age >= 18), &conn).await;
+    /// println!("Adult count: {}", adults);
     /// ```
-    /// let users = User::all(&conn).await;
-    /// println!("{:#?}", users);
-    /// ```
-    async fn all(conn: &Connection) -> Vec<Self>
+    async fn count_where(kw: Vec<Condition>, conn: &Connection) -> i64
     where
-        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+        Self: Sized,
     {
-        let query = format!("select * from {table_name}", table_name = Self::NAME);
-        sqlx::query_as::<_, Self>(&query)
-            .fetch_all(conn)
-            .await
-            .unwrap_or_default()
+        let (fields, args) = kw.to_model_select_query::<Self>();
+        let condition = if fields.is_empty() {
+            String::new()
+        } else {
+            format!(" where {fields}")
+        };
+        let query = format!(
+            "select count(*) from {table_name}{condition}",
+            table_name = quote_ident(Self::NAME)
+        );
+
+        let mut stream = sqlx::query(&query);
+        binds!(args, stream, 0);
+        track_operation(
+            Self::NAME,
+            "count",
+            &query,
+            on_conn!(conn, stream, fetch_one),
+        )
+        .await
+        .and_then(Result::ok)
+        .map_or(0, |r| r.get(0))
     }
 
-    /// Filters instances of the model based on the provided parameters.
+    /// Checks if an instance matches the filter criteria, without fetching it.
     ///
     /// # Arguments
-    /// * `kw` - The key-value arguments for filtering.
+    /// * `kw` - The key-value arguments for filtering, any instance if empty.
     /// * `conn` - The database connection.
     ///
     /// # Returns
-    /// A vector of instances matching the filter criteria.
+    /// `true` if an instance matches, the error of the query otherwise.
     ///
     /// # Example
+    /// ```ignore
+    /// if User::exists(kwargs!(email == "joe@gmail.com"), &conn).await? {
+    ///     return Err(Error::Invalid("the email is taken".to_string()));
+    /// }
     /// ```
-    /// let users = User::filter(
-    ///     kwargs!(age__gte = 18, weight__lte = 80.0),
-    ///     &conn,
-    /// ).await;
-    /// println!("{:#?}", users);
-    /// ```
-    async fn filter(kw: Vec<Condition>, conn: &Connection) -> Vec<Self>
+    async fn exists(kw: Vec<Condition>, conn: &Connection) -> Result<bool, Error>
     where
-        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+        Self: Sized,
     {
-        let (fields, args) = kw.to_select_query();
-
+        let (fields, args) = kw.to_model_select_query::<Self>();
+        let condition = if fields.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {fields}")
+        };
         let query = format!(
-            "SELECT * FROM {table_name} WHERE {fields};",
-            table_name = Self::NAME
+            "SELECT EXISTS(SELECT 1 FROM {table_name}{condition});",
+            table_name = quote_ident(Self::NAME)
         );
 
-        let mut stream = sqlx::query_as::<_, Self>(&query);
-        binds!(args, stream);
-        stream.fetch_all(conn).await.unwrap_or_default()
+        let mut stream = sqlx::query(&query);
+        binds!(args, stream)?;
+        let output = track_operation(
+            Self::NAME,
+            "exists",
+            &query,
+            on_conn!(conn, stream, fetch_one),
+        )
+        .await;
+        let row = tracked(&query, output)?;
+        // only Postgres has a boolean type, the others return 0 or 1
+        if *BACKEND == Backend::Postgres {
+            Ok(row.try_get::<bool, _>(0)?)
+        } else {
+            Ok(row.try_get::<i64, _>(0)? != 0)
+        }
     }
 
-    /// Retrieves the first instance of the model matching the filter criteria.
-    ///
-    /// # Arguments
-    /// * `kw` - The key-value arguments for filtering.
-    /// * `conn` - The database connection.
-    ///
-    /// # Returns
-    /// An optional instance matching the filter criteria.
+    /// Starts an aggregation query over the model, see `Aggregate`.
     ///
     /// # Example
+    /// ```ignore
+    /// let owners = Product::aggregate(&conn)
+    ///     .group_by(&["owner"])
+    ///     .count("total")
+    ///     .having(kwargs!(total > 3))
+    ///     .run()
+    ///     .await;
     /// ```
-    /// let user = User::get(
-    ///     kwargs!(email = "24nomeniavo@gmail.com", password = "strongpassword"),
-    ///     &conn,
-    /// ).await;
-    /// println!("{:#?}", user);
+    fn aggregate(conn: &Connection) -> Aggregate<'_, Self>
+    where
+        Self: Sized,
+    {
+        Aggregate::new(conn)
+    }
+
+    /// Starts a lazy query over the model, run by `QuerySet::all`, `QuerySet::first` or
+    /// `QuerySet::count`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let adults = User::objects()
+    ///     .filter(kwargs!(age__gte = 18))
+    ///     .order_by(&["name"])
+    ///     .all(&conn)
+    ///     .await;
     /// ```
-    async fn get(kw: Vec<Condition>, conn: &Connection) -> Option<Self>
+    fn objects() -> QuerySet<Self>
     where
-        Self: Sized + Unpin + for<'r> FromRow<'r, AnyRow> + Clone,
+        Self: Sized,
     {
-        Self::filter(kw, conn).await.first().cloned()
+        QuerySet::new()
     }
 
-    /// Counts the number of instances of the model in the database.
+    /// Estimates the number of instances of the model from the statistics of the
+    /// database, for the tables where `count` takes seconds.
+    ///
+    /// Postgres uses `pg_class.reltuples` (falling back to `count` before the table is
+    /// first analyzed), MySQL `information_schema.tables.table_rows`, SQLite counts
+    /// exactly.
     ///
     /// # Arguments
     /// * `conn` - The database connection.
     ///
     /// # Returns
-    /// The count of instances.
+    /// The estimated count of instances.
     ///
     /// # Example
+    /// ```ignore
+    /// let total = Event::estimated_count(&conn).await;
+    /// println!("About {} events", total);
     /// ```
-    /// let count = User::count(&conn).await;
-    /// println!("User count: {}", count);
-    /// ```
-    async fn count(&self, conn: &Connection) -> i64
+    async fn estimated_count(conn: &Connection) -> i64
     where
         Self: Sized,
     {
-        let query = format!("select count(*) from {table_name}", table_name = Self::NAME);
-        sqlx::query(query.as_str())
-            .fetch_one(conn)
+        let placeholder = PLACEHOLDER.to_string();
+        let query = match *BACKEND {
+            Backend::Postgres => format!(
+                "select reltuples::bigint from pg_class where relname = lower({placeholder}1);"
+            ),
+            Backend::MySql => format!(
+                "select cast(table_rows as signed) from information_schema.tables \
+                 where table_schema = database() and table_name = {placeholder}1;"
+            ),
+            Backend::Sqlite => format!(
+                "select count(*) from {table_name};",
+                table_name = quote_ident(Self::NAME)
+            ),
+        };
+        let mut stream = sqlx::query_scalar::<_, i64>(&query);
+        if *BACKEND != Backend::Sqlite {
+            stream = stream.bind(Self::NAME);
+        }
+        let estimate = track_query(&query, on_conn!(conn, stream, fetch_one))
             .await
-            .map_or(0, |r| r.get(0))
+            .and_then(Result::ok);
+
+        match estimate {
+            // the table has not been analyzed yet
+            Some(estimate) if estimate < 0 => {
+                let query = format!(
+                    "select count(*) from {table_name}",
+                    table_name = quote_ident(Self::NAME)
+                );
+                track_query(
+                    &query,
+                    on_conn!(conn, sqlx::query_scalar::<_, i64>(&query), fetch_one),
+                )
+                .await
+                .and_then(Result::ok)
+                .unwrap_or(0)
+            }
+            Some(estimate) => estimate,
+            None => 0,
+        }
+    }
+}
+
+/// Builds the condition of a `field__lookup` on a range column, pushing its arguments.
+///
+/// * `__contains` - the range contains the value (an integer for `Int4Range`, a
+///   timestamp for `TsRange`).
+/// * `__overlaps` - the range shares at least one value with the given range.
+///
+/// Postgres compares the native range column, other backends its generated
+/// `{column}_lower` and `{column}_upper` bound columns (see `range_bounds`). Any other
+/// lookup is rejected with an `Error::Invalid`.
+fn range_lookup(column: &str, lookup: &str, value: &SqlValue, args: &mut Vec<SqlValue>) -> String {
+    // the bounds of an overlapping range are read from its `[lower,upper)` text
+    let bounds = match value {
+        SqlValue::Text(range) => range
+            .trim_start_matches('[')
+            .trim_end_matches(')')
+            .split_once(',')
+            .map(|(lower, upper)| (lower.trim().to_string(), upper.trim().to_string())),
+        _ => None,
+    };
+    let is_integer = match (lookup, &bounds) {
+        ("overlaps", Some((lower, upper))) => {
+            lower.parse::<i32>().is_ok() && upper.parse::<i32>().is_ok()
+        }
+        ("overlaps", None) => return unsupported_lookup(column, lookup, args),
+        _ => matches!(value, SqlValue::Int(_) | SqlValue::BigInt(_)),
+    };
+    let mut bind = |value: SqlValue| {
+        args.push(value);
+        "?"
+    };
+
+    if *BACKEND == Backend::Postgres {
+        let (range_type, bound_type) = if is_integer {
+            ("int4range", "integer")
+        } else {
+            ("tsrange", "timestamp")
+        };
+        return match lookup {
+            "contains" => format!(
+                "{column} @> cast({value} as {bound_type})",
+                value = bind(value.clone())
+            ),
+            "overlaps" => format!(
+                "{column} && cast({value} as {range_type})",
+                value = bind(value.clone())
+            ),
+            _ => unsupported_lookup(column, lookup, args),
+        };
+    }
+
+    let bound = |bound: String| match bound.parse::<i32>() {
+        Ok(bound) if is_integer => SqlValue::Int(bound),
+        _ => SqlValue::Text(bound),
+    };
+    match lookup {
+        "contains" => format!(
+            "({column}_lower<={} and {}<{column}_upper)",
+            bind(value.clone()),
+            bind(value.clone())
+        ),
+        "overlaps" => {
+            let (other_lower, other_upper) = bounds.unwrap_or_default();
+            format!(
+                "({column}_lower<{} and {}<{column}_upper)",
+                bind(bound(other_upper)),
+                bind(bound(other_lower))
+            )
+        }
+        _ => unsupported_lookup(column, lookup, args),
+    }
+}
+
+/// Renders an unsupported `column__lookup`, pushing an argument that fails to bind
+/// with an `Error::Invalid`, so the query is never executed.
+fn unsupported_lookup(column: &str, lookup: &str, args: &mut Vec<SqlValue>) -> String {
+    args.push(SqlValue::Unsupported(format!("{column}__{lookup}")));
+    format!("{column}=?")
+}
+
+/// Maps the backend specific column types of a column definition.
+///
+/// `CiText` columns are `citext` on Postgres and case-insensitively collated
+/// `varchar` columns elsewhere, so their unique constraints ignore the case.
+/// `auto` integer primary keys become `serial` on Postgres and `auto_increment` on
+/// MySQL.
+pub(crate) fn column_definition(column: &str, definition: &str) -> String {
+    let auto = format!("{column} integer primary key autoincrement");
+    if let Some(rest) = definition.strip_prefix(&auto) {
+        match *BACKEND {
+            Backend::Postgres => return format!("{column} serial primary key{rest}"),
+            Backend::MySql => return format!("{column} integer primary key auto_increment{rest}"),
+            Backend::Sqlite => {}
+        }
+    }
+
+    let citext = format!("{column} citext");
+    match definition.strip_prefix(&citext) {
+        Some(rest) if *BACKEND == Backend::Sqlite => {
+            format!("{column} varchar(255) collate nocase{rest}")
+        }
+        Some(rest) if *BACKEND == Backend::MySql => {
+            format!("{column} varchar(255) collate utf8mb4_unicode_ci{rest}")
+        }
+        _ => match range_type(column, definition) {
+            Some((range, _)) if *BACKEND != Backend::Postgres => {
+                definition.replacen(range, "varchar(100)", 1)
+            }
+            _ => definition.to_string(),
+        },
+    }
+}
+
+/// The columns of `T` selected to decode the instances, qualified by `table` (its name
+/// or an alias) if given: `*`, but on Postgres the range columns are read as their
+/// text, the Any driver decoding no native range type.
+pub(crate) fn selected_columns<T: Model>(table: Option<&str>) -> String {
+    let table = table.map(|table| format!("{table}.")).unwrap_or_default();
+    if *BACKEND != Backend::Postgres || T::RANGES.is_empty() {
+        return format!("{table}*");
+    }
+    T::COLUMNS
+        .iter()
+        .map(|(column, _)| {
+            if T::RANGES.contains(column) {
+                format!("cast({table}{column} as text) as {column}")
+            } else {
+                format!("{table}{column}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The (`int4range` or `tsrange`, bound type) of a range column definition, `None`
+/// for the other columns.
+fn range_type(column: &str, definition: &str) -> Option<(&'static str, &'static str)> {
+    let kind = definition.strip_prefix(column)?.split_whitespace().next()?;
+    match kind {
+        "int4range" => Some(("int4range", "integer")),
+        "tsrange" => Some(("tsrange", "varchar(40)")),
+        _ => None,
+    }
+}
+
+/// The (column, definition) pairs of the `{column}_lower` and `{column}_upper` columns
+/// of a range column on SQLite and MySQL, generated from its `[lower,upper)` text so
+/// the ranges are compared (and may be indexed) on typed bounds. Postgres stores the
+/// native range types, indexed with GiST, and other columns have no bound columns.
+pub(crate) fn range_bounds(column: &str, definition: &str) -> Vec<(String, String)> {
+    let Some((_, bound_type)) = range_type(column, definition) else {
+        return Vec::new();
+    };
+    let (lower, upper) = match *BACKEND {
+        Backend::Postgres => return Vec::new(),
+        Backend::MySql => (
+            format!("substring_index(substring({column}, 2), ',', 1)"),
+            format!("substring_index(substring_index({column}, ')', 1), ',', -1)"),
+        ),
+        Backend::Sqlite => (
+            format!("substr({column}, 2, instr({column}, ',') - 2)"),
+            format!(
+                "substr({column}, instr({column}, ',') + 1, length({column}) - instr({column}, ',') - 1)"
+            ),
+        ),
+    };
+    let integer = if *BACKEND == Backend::MySql {
+        "signed"
+    } else {
+        "integer"
+    };
+    [("lower", lower), ("upper", upper)]
+        .into_iter()
+        .map(|(bound, value)| {
+            let value = if bound_type == "integer" {
+                format!("cast({value} as {integer})")
+            } else {
+                value
+            };
+            let name = format!("{column}_{bound}");
+            let definition = format!("{name} {bound_type} generated always as ({value}) virtual");
+            (name, definition)
+        })
+        .collect()
+}
+
+/// Quotes `value` as a SQL string literal.
+pub(crate) fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Checks the bound of a partition, interpolated in its definition, holds no quote.
+fn check_bound(bound: &str) -> Result<(), Error> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || " -:.+_".contains(c);
+    if bound.is_empty() || !bound.chars().all(allowed) {
+        return Err(Error::Invalid(format!("Invalid partition bound `{bound}`")));
+    }
+    Ok(())
+}
+
+fn partition_name(table_name: &str, from: &str) -> String {
+    let suffix = from
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    format!("{table_name}_{suffix}")
+}
+
+/// The created or updated instances returned by `Model::poll_changes`.
+pub type Changes<'c, T> = Pin<Box<dyn Stream<Item = Result<T, Error>> + Send + 'c>>;
+
+/// The values of the `columns` of `instance`.
+fn keyset_values<T: Model>(instance: &T, columns: &[String]) -> Vec<serde_json::Value> {
+    let fields = instance.fields();
+    columns
+        .iter()
+        .map(|column| {
+            fields
+                .iter()
+                .find(|(field, _)| field == column)
+                .map_or(serde_json::Value::Null, |(_, value)| value.clone())
+        })
+        .collect()
+}
+
+/// Batches of model instances returned by `Model::iter_chunks`.
+pub struct Chunks<'c, T> {
+    conn: &'c Connection,
+    size: usize,
+    last: Option<Vec<serde_json::Value>>,
+    done: bool,
+    _model: PhantomData<T>,
+}
+
+impl<'c, T> Chunks<'c, T>
+where
+    T: Model + Unpin + for<'r> FromRow<'r, AnyRow>,
+{
+    /// Fetches the next batch, or `None` once every row has been returned.
+    ///
+    /// A failed query or a row that cannot be decoded is returned as an error and ends
+    /// the iteration.
+    pub async fn next(&mut self) -> Option<Result<Vec<T>, Error>> {
+        if self.done {
+            return None;
+        }
+        let columns = pk_columns::<T>();
+        let ordering = columns
+            .iter()
+            .map(|column| (column.to_string(), false))
+            .collect::<Vec<_>>();
+        let mut args = Vec::new();
+        let condition = match self.last.clone() {
+            Some(values) => format!(
+                "where {}",
+                select_conditions(&keyset_condition(&ordering, values).0, T::RANGES, &mut args)
+            ),
+            None => String::new(),
+        };
+        let table_name = quote_ident(T::NAME);
+        let query = format!(
+            "select {columns} from {table_name} {condition} order by {pk} limit {size};",
+            columns = selected_columns::<T>(None),
+            pk = columns.join(", "),
+            size = self.size,
+        );
+
+        let chunk = self.fetch(&query, args).await;
+        match &chunk {
+            Ok(chunk) => {
+                self.done = chunk.len() < self.size;
+                let keys = columns
+                    .iter()
+                    .map(|column| column.to_string())
+                    .collect::<Vec<_>>();
+                self.last = chunk.last().map(|last| keyset_values(last, &keys));
+            }
+            Err(_) => self.done = true,
+        }
+        match chunk {
+            Ok(chunk) if chunk.is_empty() => None,
+            chunk => Some(chunk),
+        }
+    }
+
+    /// Runs the `query` of a batch, decoding each of its rows.
+    async fn fetch(&self, query: &str, args: Vec<SqlValue>) -> Result<Vec<T>, Error> {
+        let mut stream = sqlx::query(query);
+        binds!(args, stream)?;
+        let rows = tracked(
+            query,
+            track_query(query, on_conn!(self.conn, stream, fetch_all)).await,
+        )?;
+        rows.iter()
+            .map(|row| T::from_row(row).map_err(Error::from))
+            .collect()
+    }
+}
+
+/// Batches of model instances returned by `Model::cursor`.
+pub enum Cursor<'c, T> {
+    Postgres {
+        conn: &'c Connection,
+        // the name of the cursor, unique among the cursors of the transaction
+        name: String,
+        fetch_size: usize,
+        // the transaction of the cursor, `None` within an `atomic` block
+        tx: Option<Transaction<'c, Any>>,
+        declared: bool,
+        done: bool,
+        _model: PhantomData<T>,
+    },
+    Chunks(Chunks<'c, T>),
+}
+
+impl<'c, T> Cursor<'c, T>
+where
+    T: Model + Send + Unpin + for<'r> FromRow<'r, AnyRow>,
+{
+    /// Fetches the next batch, or `None` once every row has been returned.
+    ///
+    /// A failed query or a row that cannot be decoded is returned as an error and ends
+    /// the iteration.
+    pub async fn next(&mut self) -> Option<Result<Vec<T>, Error>> {
+        match self {
+            Cursor::Chunks(chunks) => chunks.next().await,
+            Cursor::Postgres {
+                conn,
+                name,
+                fetch_size,
+                tx,
+                declared,
+                done,
+                ..
+            } => {
+                if *done {
+                    return None;
+                }
+                let chunk = Self::fetch_postgres(conn, name, *fetch_size, tx, declared).await;
+                match &chunk {
+                    Ok(chunk) if chunk.len() == *fetch_size => {}
+                    _ => *done = true,
+                }
+                let chunk = match chunk {
+                    Ok(chunk) if *done => Self::close_postgres(conn, name, tx).await.map(|_| chunk),
+                    chunk => chunk,
+                };
+                match chunk {
+                    Ok(chunk) if chunk.is_empty() => None,
+                    chunk => Some(chunk),
+                }
+            }
+        }
+    }
+
+    /// Fetches the next `fetch_size` rows of the Postgres cursor `cursor`, declaring it
+    /// first within the `atomic` block on `conn` or a transaction of its own.
+    async fn fetch_postgres(
+        conn: &'c Connection,
+        cursor: &str,
+        fetch_size: usize,
+        tx: &mut Option<Transaction<'c, Any>>,
+        declared: &mut bool,
+    ) -> Result<Vec<T>, Error> {
+        let fetch = format!("fetch forward {fetch_size} from {cursor};");
+        if let Some(transaction) = tx.as_mut() {
+            return Ok(sqlx::query_as::<Any, T>(&fetch)
+                .fetch_all(&mut **transaction)
+                .await?);
+        }
+
+        // the guard of an `atomic` block is released between the batches, so the
+        // statements run while iterating do not wait for it
+        let mut connection = transaction::acquire(conn).await?;
+        if !*declared {
+            let table_name = quote_ident(T::NAME);
+            let declare = format!(
+                "declare {cursor} no scroll cursor for select {columns} from {table_name};",
+                columns = selected_columns::<T>(None),
+            );
+            if let transaction::Connected::Pool(pool_connection) = connection {
+                let mut transaction = Transaction::begin(pool_connection).await?;
+                sqlx::query(&declare).execute(&mut *transaction).await?;
+                *declared = true;
+                let chunk = sqlx::query_as::<Any, T>(&fetch)
+                    .fetch_all(&mut *transaction)
+                    .await?;
+                *tx = Some(transaction);
+                return Ok(chunk);
+            }
+            sqlx::query(&declare).execute(&mut *connection).await?;
+            *declared = true;
+        }
+        Ok(sqlx::query_as::<Any, T>(&fetch)
+            .fetch_all(&mut *connection)
+            .await?)
+    }
+
+    /// Commits the transaction of the Postgres cursor `cursor`, or closes the cursor
+    /// within the `atomic` block on `conn`.
+    async fn close_postgres(
+        conn: &'c Connection,
+        cursor: &str,
+        tx: &mut Option<Transaction<'c, Any>>,
+    ) -> Result<(), Error> {
+        if let Some(transaction) = tx.take() {
+            return Ok(transaction.commit().await?);
+        }
+        let close = format!("close {cursor};");
+        let mut connection = transaction::acquire(conn).await?;
+        sqlx::query(&close).execute(&mut *connection).await?;
+        Ok(())
     }
 }
 
 #[async_trait::async_trait]
 pub trait Delete {
-    async fn delete(&self, conn: &Connection) -> bool;
+    async fn delete(&self, conn: &Connection) -> Result<u64, Error>;
 }
 
 #[async_trait::async_trait]
@@ -434,19 +4049,26 @@ where
     ///
     /// This method will delete all records from the table corresponding to the model `T`.
     /// Be cautious when using this method, as it will remove all entries without conditions.
+    /// It is rejected when the guardrail is enabled, unless run with `unguarded`.
     ///
     /// # Arguments
     /// * `conn` - The database connection.
     ///
     /// # Returns
-    /// `true` if deletion is successful, `false` otherwise.
+    /// The number of deleted instances, or the error.
     ///
     /// # Example
-    /// ```
+    /// ```no_run
     /// # use rusql_alchemy::prelude::*;
     /// # use sqlx::FromRow;
     /// #
     /// # #[derive(FromRow, Debug, Default, Model, Clone)]
+    /// # struct User {
+    /// #     #[model(primary_key = true, auto = true, null = false)]
+    /// #     id: Integer,
+    /// # }
+    /// #
+    /// # #[derive(FromRow, Debug, Default, Model, Clone)]
     /// # struct Product {
     /// #     #[model(primary_key = true, auto = true, null = false)]
     /// #     id: Integer,
@@ -467,8 +4089,8 @@ where
     ///     let conn = Database::new().await.conn;
     ///
     ///     let products = Product::all(&conn).await;
-    ///     let success = products.delete(&conn).await;
-    ///     println!("Products delete success: {}", success);
+    ///     let deleted = products.delete(&conn).await;
+    ///     println!("Products deleted: {:?}", deleted);
     ///
     ///     let products = Product::all(&conn).await;
     ///     println!("Remaining products: {:#?}", products);
@@ -476,8 +4098,18 @@ where
     /// ```
     ///
     /// In the above example, all records from the `Product` table will be deleted.
-    async fn delete(&self, conn: &Connection) -> bool {
-        let query = format!("delete from {table_name}", table_name = T::NAME);
-        sqlx::query(query.as_str()).execute(conn).await.is_ok()
+    async fn delete(&self, conn: &Connection) -> Result<u64, Error> {
+        let query = format!(
+            "delete from {table_name}",
+            table_name = quote_ident(T::NAME)
+        );
+        if !check_statement(&query) {
+            return Err(Error::Rejected { query });
+        }
+        let stream = sqlx::query(query.as_str());
+        let output =
+            track_operation(T::NAME, "delete", &query, on_conn!(conn, stream, execute)).await;
+        cache::invalidate_table(T::NAME, conn);
+        tracked(&query, output).map(|result| result.rows_affected())
     }
 }
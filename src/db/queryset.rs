@@ -0,0 +1,707 @@
+use std::marker::PhantomData;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sqlx::{any::AnyRow, FromRow, Row};
+
+use super::export::row_to_json;
+use super::expression::{Nulls, OrderBy, ToSql};
+use super::models::{
+    number_placeholders, pk_columns, related_ordering, select_conditions, selected_columns,
+    tracked, Column, Condition, Model, Q,
+};
+use crate::guardrail::{check_rows, row_limit};
+use crate::naming::quote_ident;
+use crate::telemetry::track_operation;
+use crate::types::SqlValue;
+use crate::{Backend, Connection, Error, BACKEND};
+
+/// A lazy query over a model, built by `Model::objects` and only run by `all`, `first`
+/// or `count`.
+///
+/// # Example
+/// ```ignore
+/// let users = User::objects()
+///     .filter(kwargs!(age__gte = 18))
+///     .exclude(kwargs!(role == "admin"))
+///     .order_by(&["-age", "name"])
+///     .limit(10)
+///     .all(&conn)
+///     .await;
+/// ```
+pub struct QuerySet<T> {
+    conditions: Vec<Condition>,
+    ordering: Vec<OrderField>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    distinct: bool,
+    // the query sets combined by `union`, with `union all` for the `true` ones
+    parts: Vec<(QuerySet<T>, bool)>,
+    // the query set holds no instance, it is `Send` and `Sync` for any model
+    _model: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for QuerySet<T> {
+    fn clone(&self) -> Self {
+        Self {
+            conditions: self.conditions.clone(),
+            ordering: self.ordering.clone(),
+            limit: self.limit,
+            offset: self.offset,
+            distinct: self.distinct,
+            parts: self.parts.clone(),
+            _model: PhantomData,
+        }
+    }
+}
+
+impl<T: Model> QuerySet<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            conditions: Vec::new(),
+            ordering: Vec::new(),
+            limit: None,
+            offset: None,
+            distinct: false,
+            parts: Vec::new(),
+            _model: PhantomData,
+        }
+    }
+
+    /// Keeps the instances matching `kw`, in addition to the previous filters.
+    pub fn filter(self, kw: Vec<Condition>) -> Self {
+        self.combine(Q(kw))
+    }
+
+    /// Leaves out the instances matching `kw`.
+    pub fn exclude(self, kw: Vec<Condition>) -> Self {
+        self.combine(!Q(kw))
+    }
+
+    /// Sorts the instances by `fields`, after the previous ordering: the columns of the
+    /// model (`T::Column`), the `asc`/`desc` expressions or the `field__column` paths,
+    /// see `OrderField`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let users = User::objects()
+    ///     .order_by(&[desc(UserColumn::Age).nulls_last()])
+    ///     .order_by(&[UserColumn::Name])
+    ///     .all(&conn)
+    ///     .await;
+    /// ```
+    pub fn order_by<F: Into<OrderField> + Clone>(mut self, fields: &[F]) -> Self {
+        self.ordering.extend(fields.iter().cloned().map(Into::into));
+        self
+    }
+
+    /// Returns at most `limit` instances.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skips the first `offset` instances.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Leaves out the duplicated rows, with `select distinct`.
+    pub fn distinct(mut self) -> Self {
+        self.distinct = true;
+        self
+    }
+
+    /// Combines the instances of this query and of `other` in one statement, leaving
+    /// out the duplicated rows. The filters, ordering and limit set afterwards apply to
+    /// the combined instances.
+    ///
+    /// # Example
+    /// ```ignore
+    /// // the documents of the user or shared with them
+    /// let documents = Document::objects()
+    ///     .filter(kwargs!(owner == user.id))
+    ///     .union(Document::objects().filter(kwargs!(shared_with == user.id)))
+    ///     .order_by(&["-updated_at"])
+    ///     .limit(20)
+    ///     .all(&conn)
+    ///     .await;
+    /// ```
+    pub fn union(self, other: Self) -> Self {
+        self.compound(other, false)
+    }
+
+    /// Combines the instances of this query and of `other` like `union`, keeping the
+    /// duplicated rows (which saves their removal by the database).
+    pub fn union_all(self, other: Self) -> Self {
+        self.compound(other, true)
+    }
+
+    fn compound(self, other: Self, all: bool) -> Self {
+        Self {
+            parts: vec![(self, false), (other, all)],
+            ..Self::new()
+        }
+    }
+
+    /// Runs the query, returning the instances.
+    pub async fn all(&self, conn: &Connection) -> Vec<T>
+    where
+        T: Send + Unpin + for<'r> FromRow<'r, AnyRow>,
+    {
+        let (query, args) = match self.select(self.guarded_limit()) {
+            Ok(select) => select,
+            Err(err) => return ordering_error(err, Vec::new()),
+        };
+
+        let mut stream = sqlx::query_as::<_, T>(&query);
+        binds!(args, stream, Vec::new());
+        let rows = track_operation(T::NAME, "select", &query, on_conn!(conn, stream, fetch_all))
+            .await
+            .and_then(Result::ok)
+            .unwrap_or_default();
+        if check_rows(&query, rows.len()) {
+            rows
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Runs the query, returning the instances, or the error of the ordering or of the
+    /// query (`Error::Rejected` beyond the guardrail).
+    ///
+    /// # Example
+    /// ```ignore
+    /// let users = User::objects().order_by(&["owner__email"]).try_all(&conn).await?;
+    /// ```
+    pub async fn try_all(&self, conn: &Connection) -> Result<Vec<T>, Error>
+    where
+        T: Send + Unpin + for<'r> FromRow<'r, AnyRow>,
+    {
+        let (query, args) = self.select(self.guarded_limit())?;
+
+        let mut stream = sqlx::query_as::<_, T>(&query);
+        binds!(args, stream)?;
+        let output =
+            track_operation(T::NAME, "select", &query, on_conn!(conn, stream, fetch_all)).await;
+        let rows = tracked(&query, output)?;
+        if check_rows(&query, rows.len()) {
+            Ok(rows)
+        } else {
+            Err(Error::Rejected { query })
+        }
+    }
+
+    /// Runs the query, returning its first instance, the one with the lowest primary
+    /// key when no ordering is set.
+    pub async fn first(&self, conn: &Connection) -> Option<T>
+    where
+        T: Send + Unpin + for<'r> FromRow<'r, AnyRow>,
+    {
+        let query = if self.ordering.is_empty() {
+            self.clone().order_by(pk_columns::<T>())
+        } else {
+            self.clone()
+        };
+        let (query, args) = match query.select(Some(1)) {
+            Ok(select) => select,
+            Err(err) => return ordering_error(err, None),
+        };
+
+        let mut stream = sqlx::query_as::<_, T>(&query);
+        binds!(args, stream, None);
+        track_operation(
+            T::NAME,
+            "select",
+            &query,
+            on_conn!(conn, stream, fetch_optional),
+        )
+        .await
+        .and_then(Result::ok)
+        .flatten()
+    }
+
+    /// Runs the query in the reverse order, returning its last instance, the one with
+    /// the highest primary key when no ordering is set.
+    pub async fn last(&self, conn: &Connection) -> Option<T>
+    where
+        T: Send + Unpin + for<'r> FromRow<'r, AnyRow>,
+    {
+        let ordering = if self.ordering.is_empty() {
+            pk_columns::<T>()
+                .iter()
+                .map(|column| OrderField::from(*column))
+                .collect()
+        } else {
+            self.ordering.clone()
+        };
+        let reversed = Self {
+            ordering: ordering.iter().map(OrderField::reversed).collect(),
+            ..self.clone()
+        };
+        reversed.first(conn).await
+    }
+
+    /// Counts the instances of the query.
+    pub async fn count(&self, conn: &Connection) -> i64 {
+        let (query, args) = match self.select(self.limit) {
+            Ok(select) => select,
+            Err(err) => return ordering_error(err, 0),
+        };
+        let query = format!(
+            "SELECT count(*) FROM ({}) AS {table_name};",
+            query.trim_end_matches(';'),
+            table_name = quote_ident(T::NAME)
+        );
+
+        let mut stream = sqlx::query(&query);
+        binds!(args, stream, 0);
+        match track_operation(T::NAME, "count", &query, on_conn!(conn, stream, fetch_one)).await {
+            Some(Ok(row)) => row.get(0),
+            _ => 0,
+        }
+    }
+
+    /// Runs the `EXPLAIN` of the query (`EXPLAIN QUERY PLAN` on SQLite) instead of the
+    /// query, returning the rows of the plan, to see how the database runs it.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let plan = User::objects()
+    ///     .filter(kwargs!(email == "joe@gmail.com"))
+    ///     .explain(&conn)
+    ///     .await?;
+    /// for row in plan {
+    ///     println!("{row:?}");
+    /// }
+    /// ```
+    pub async fn explain(&self, conn: &Connection) -> Result<Vec<Map<String, Value>>, Error> {
+        let (query, args) = self.select(self.guarded_limit())?;
+        let explain = match *BACKEND {
+            Backend::Sqlite => "EXPLAIN QUERY PLAN",
+            Backend::MySql | Backend::Postgres => "EXPLAIN",
+        };
+        let query = format!("{explain} {query}");
+
+        let mut stream = sqlx::query(&query);
+        binds!(args, stream)?;
+        let output = track_operation(
+            T::NAME,
+            "explain",
+            &query,
+            on_conn!(conn, stream, fetch_all),
+        )
+        .await;
+        let rows = tracked(&query, output)?;
+        Ok(rows.iter().map(row_to_json).collect())
+    }
+
+    /// Runs the query for the page `page` (from 1) of `per_page` instances, along with
+    /// the count of all the instances. The instances are sorted by primary key when no
+    /// ordering is set, so that the pages do not overlap.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let page = User::objects()
+    ///     .filter(kwargs!(age__gte = 18))
+    ///     .order_by(&["name"])
+    ///     .paginate(2, 20, &conn)
+    ///     .await;
+    /// ```
+    pub async fn paginate(&self, page: usize, per_page: usize, conn: &Connection) -> Page<T>
+    where
+        T: Send + Unpin + for<'r> FromRow<'r, AnyRow>,
+    {
+        let page = page.max(1);
+        let all = Self {
+            limit: None,
+            offset: None,
+            ..self.clone()
+        };
+        let mut items = all.clone().limit(per_page).offset((page - 1) * per_page);
+        if items.ordering.is_empty() {
+            items = items.order_by(pk_columns::<T>());
+        }
+
+        let (items, total) = tokio::join!(items.all(conn), all.count(conn));
+        Page::new(items, total, page, per_page)
+    }
+
+    /// Runs the query for the `per_page` instances following `cursor`, the `next` cursor
+    /// of the previous page (`None` for the first one).
+    ///
+    /// The instances are sorted by the ordering and then by primary key, and the page
+    /// starts after the values of the last instance of the previous one (keyset
+    /// pagination), so the deep pages cost as much as the first one and the writes
+    /// between two pages neither skip nor repeat instances. The ordering fields must be
+    /// columns of the model which are not `null`.
+    ///
+    /// # Errors
+    /// If `cursor` was not returned for this ordering, or the ordering follows a
+    /// foreign key.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let users = User::objects().order_by(&["-age"]);
+    /// let mut cursor = None;
+    /// loop {
+    ///     let page = users.page_after(cursor.as_deref(), 100, &conn).await?;
+    ///     println!("{:#?}", page.items);
+    ///     match page.next {
+    ///         Some(next) => cursor = Some(next),
+    ///         None => break,
+    ///     }
+    /// }
+    /// ```
+    pub async fn page_after(
+        &self,
+        cursor: Option<&str>,
+        per_page: usize,
+        conn: &Connection,
+    ) -> Result<CursorPage<T>, Error>
+    where
+        T: Send + Unpin + for<'r> FromRow<'r, AnyRow>,
+    {
+        let ordering = self.keyset_ordering()?;
+        let mut query = Self {
+            ordering: ordering
+                .iter()
+                .map(|(column, descending)| match descending {
+                    true => OrderField::Path(format!("-{column}")),
+                    false => OrderField::Path(column.clone()),
+                })
+                .collect(),
+            ..self.clone()
+        };
+        if let Some(cursor) = cursor {
+            let values = decode_cursor(cursor, ordering.len())?;
+            query = query.combine(keyset_condition(&ordering, values));
+        }
+
+        // one more instance tells whether a page follows
+        let mut items = query.limit(per_page + 1).try_all(conn).await?;
+        let next = if items.len() > per_page {
+            items.truncate(per_page);
+            items.last().map(|last| encode_cursor(last, &ordering))
+        } else {
+            None
+        };
+        Ok(CursorPage { items, next })
+    }
+
+    /// The (column, descending) pairs of the ordering followed by the primary key.
+    fn keyset_ordering(&self) -> Result<Vec<(String, bool)>, Error> {
+        let mut ordering = Vec::new();
+        for field in &self.ordering {
+            let OrderField::Path(field) = field else {
+                return Err(Error::Invalid(format!(
+                    "The keyset pagination cannot sort by the expression '{field}'"
+                )));
+            };
+            if Nulls::strip(field).1.is_some() {
+                return Err(Error::Invalid(format!(
+                    "The keyset pagination cannot place the null values of '{field}'"
+                )));
+            }
+            let (column, descending) = match field.strip_prefix('-') {
+                Some(column) => (column, true),
+                None => (field.as_str(), false),
+            };
+            if column.contains("__") {
+                return Err(Error::Invalid(format!(
+                    "The keyset pagination cannot sort by the related field '{column}'"
+                )));
+            }
+            ordering.push((column.to_string(), descending));
+        }
+        for column in pk_columns::<T>() {
+            if !ordering.iter().any(|(field, _)| field == column) {
+                ordering.push((column.to_string(), false));
+            }
+        }
+        Ok(ordering)
+    }
+
+    /// Adds `q` to the conditions with `and`.
+    fn combine(mut self, q: Q) -> Self {
+        self.conditions = if self.conditions.is_empty() {
+            q.0
+        } else {
+            (Q(self.conditions) & q).0
+        };
+        self
+    }
+
+    /// The limit of the query, the explicit limit staying within the guardrail.
+    fn guarded_limit(&self) -> Option<usize> {
+        match (self.limit, row_limit()) {
+            (Some(limit), Some(max_rows)) => Some(limit.min(max_rows)),
+            (limit, max_rows) => limit.or(max_rows),
+        }
+    }
+
+    /// Renders the select of the query returning at most `limit` rows, and its
+    /// arguments, or the error of an unknown ordering field.
+    fn select(&self, limit: Option<usize>) -> Result<(String, Vec<SqlValue>), Error> {
+        let mut args = Vec::new();
+        let query = self.render(limit, &mut args)?;
+        Ok((format!("{query};"), args))
+    }
+
+    /// Renders the select of `select`, without the final `;`, pushing its arguments
+    /// after `args`.
+    fn render(&self, limit: Option<usize>, args: &mut Vec<SqlValue>) -> Result<String, Error> {
+        let table_name = quote_ident(T::NAME);
+        // the rows the query filters: the table or the combination of the parts
+        let source = if self.parts.is_empty() {
+            table_name.clone()
+        } else {
+            let mut source = String::new();
+            for (index, (part, all)) in self.parts.iter().enumerate() {
+                if index > 0 {
+                    source.push_str(if *all { " UNION ALL " } else { " UNION " });
+                }
+                // wrapped, so the ordering and limit of the part apply to it alone
+                let part = part.render(part.limit, args)?;
+                source.push_str(&format!("SELECT * FROM ({part}) AS {table_name}"));
+            }
+            format!("({source}) AS {table_name}")
+        };
+        let fields = select_conditions(&self.conditions, T::RANGES, args);
+        let condition = if fields.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {fields}")
+        };
+        let paths = self
+            .ordering
+            .iter()
+            .filter_map(|field| match field {
+                OrderField::Path(path) => Some(path.as_str()),
+                OrderField::Expression(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let (joins, paths) = related_ordering::<T>(&paths)?;
+        let mut paths = paths.into_iter();
+        let order = self
+            .ordering
+            .iter()
+            .map(|field| match field {
+                OrderField::Path(_) => paths.next().unwrap_or_default(),
+                OrderField::Expression(order) => {
+                    number_placeholders(&order.to_sql(), &order.args(), args)
+                }
+            })
+            .collect::<Vec<_>>();
+        let order = if order.is_empty() {
+            String::new()
+        } else {
+            format!(" ORDER BY {}", order.join(", "))
+        };
+        let limit = match (limit, self.offset) {
+            (Some(limit), Some(offset)) => format!(" LIMIT {limit} OFFSET {offset}"),
+            (Some(limit), None) => format!(" LIMIT {limit}"),
+            // the offset needs a limit except on Postgres
+            (None, Some(offset)) => match *BACKEND {
+                Backend::Postgres => format!(" OFFSET {offset}"),
+                Backend::MySql => format!(" LIMIT {} OFFSET {offset}", u64::MAX),
+                Backend::Sqlite => format!(" LIMIT -1 OFFSET {offset}"),
+            },
+            (None, None) => String::new(),
+        };
+        let distinct = if self.distinct { "DISTINCT " } else { "" };
+        // the filter is applied before the joins, so its columns are not ambiguous
+        Ok(format!(
+            "SELECT {distinct}{columns} FROM (SELECT * FROM {source}{condition}) AS {table_name}{joins}{order}{limit}",
+            columns = selected_columns::<T>(Some(&table_name))
+        ))
+    }
+}
+
+/// A field sorting a `QuerySet`, see `QuerySet::order_by`.
+///
+/// It is built from:
+/// * a column of the model, e.g. `UserColumn::Name`;
+/// * an `asc`/`desc` expression of the columns of the model, a bare column becoming a
+///   path;
+/// * a `field__column` path to a column of the model or of a related model reached
+///   through its foreign key, descending when prefixed by `-` and placing the `null`
+///   values with a ` nulls first` or ` nulls last` suffix, e.g. `"-owner__email"`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderField {
+    Path(String),
+    Expression(OrderBy),
+}
+
+impl OrderField {
+    /// The field sorting in the reverse direction, see `QuerySet::last`.
+    fn reversed(&self) -> Self {
+        match self {
+            OrderField::Path(path) => {
+                let (path, nulls) = Nulls::strip(path);
+                let path = match path.strip_prefix('-') {
+                    Some(path) => path.to_string(),
+                    None => format!("-{path}"),
+                };
+                OrderField::Path(match nulls {
+                    Some(Nulls::First) => format!("{path} nulls last"),
+                    Some(Nulls::Last) => format!("{path} nulls first"),
+                    None => path,
+                })
+            }
+            OrderField::Expression(order) => OrderField::Expression(order.reversed()),
+        }
+    }
+}
+
+impl std::fmt::Display for OrderField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderField::Path(path) => write!(f, "{path}"),
+            OrderField::Expression(order) => write!(f, "{}", order.to_sql()),
+        }
+    }
+}
+
+impl From<&str> for OrderField {
+    fn from(path: &str) -> Self {
+        OrderField::Path(path.to_string())
+    }
+}
+
+impl From<String> for OrderField {
+    fn from(path: String) -> Self {
+        OrderField::Path(path)
+    }
+}
+
+impl From<OrderBy> for OrderField {
+    fn from(order: OrderBy) -> Self {
+        match order.path() {
+            Some(path) => OrderField::Path(path),
+            None => OrderField::Expression(order),
+        }
+    }
+}
+
+impl<C: Column> From<C> for OrderField {
+    fn from(column: C) -> Self {
+        OrderField::Path(column.name().to_string())
+    }
+}
+
+/// Reports the error of an unknown ordering field, returning `default` instead of
+/// running the query.
+fn ordering_error<D>(err: Error, default: D) -> D {
+    eprintln!("Error during the ordering\n->{err}");
+    default
+}
+
+/// A page of instances with the totals of the pages, see `QuerySet::paginate`.
+///
+/// # Example
+/// ```ignore
+/// // a web handler returning the page as JSON
+/// async fn users(page: usize) -> Json<Page<User>> {
+///     Json(User::paginate(vec![], page, 20, &conn).await)
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    /// The instances of the page.
+    pub items: Vec<T>,
+    /// The number of instances across all the pages.
+    pub total: i64,
+    /// The number of the page, from 1.
+    pub page: usize,
+    /// The maximum number of instances of a page.
+    pub per_page: usize,
+    /// The number of pages.
+    pub page_count: usize,
+    /// Whether a page follows this one.
+    pub has_next: bool,
+}
+
+impl<T> Page<T> {
+    fn new(items: Vec<T>, total: i64, page: usize, per_page: usize) -> Self {
+        let total_items = usize::try_from(total).unwrap_or_default();
+        let page_count = if per_page == 0 {
+            0
+        } else {
+            total_items.div_ceil(per_page)
+        };
+        Self {
+            items,
+            total,
+            page,
+            per_page,
+            page_count,
+            has_next: page < page_count,
+        }
+    }
+}
+
+/// A page of instances of the keyset pagination, see `QuerySet::page_after`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CursorPage<T> {
+    /// The instances of the page.
+    pub items: Vec<T>,
+    /// The cursor of the following page, `None` on the last one.
+    pub next: Option<String>,
+}
+
+/// Encodes the values of the `ordering` columns of `last` as an opaque cursor.
+fn encode_cursor<T: Model>(last: &T, ordering: &[(String, bool)]) -> String {
+    let fields = last.fields();
+    let values = ordering
+        .iter()
+        .map(|(column, _)| {
+            fields
+                .iter()
+                .find(|(field, _)| field == column)
+                .map_or(Value::Null, |(_, value)| value.clone())
+        })
+        .collect::<Vec<_>>();
+    URL_SAFE_NO_PAD.encode(Value::Array(values).to_string())
+}
+
+/// Decodes the `len` values of a cursor.
+fn decode_cursor(cursor: &str, len: usize) -> Result<Vec<Value>, Error> {
+    let invalid = || Error::Invalid(format!("Invalid cursor '{cursor}'"));
+    let json = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+    match serde_json::from_slice::<Vec<Value>>(&json) {
+        Ok(values) if values.len() == len => Ok(values),
+        _ => Err(invalid()),
+    }
+}
+
+/// The condition selecting the instances after `values` in the order of `ordering`:
+/// `c1 > v1 or (c1 = v1 and c2 > v2) or ...`, `<` for the descending columns.
+pub(super) fn keyset_condition(ordering: &[(String, bool)], values: Vec<Value>) -> Q {
+    let condition = |column: &str, operator: &str, value: &Value| Condition::FieldCondition {
+        field: column.to_string(),
+        value: value.into(),
+        comparaison_operator: operator.to_string(),
+    };
+    let mut after: Option<Q> = None;
+    for index in 0..ordering.len() {
+        let mut conditions = ordering[..index]
+            .iter()
+            .zip(&values)
+            .map(|((column, _), value)| condition(column, "=", value))
+            .collect::<Vec<_>>();
+        let (column, descending) = &ordering[index];
+        conditions.push(condition(
+            column,
+            if *descending { "<" } else { ">" },
+            &values[index],
+        ));
+        after = Some(match after {
+            Some(after) => after | Q(conditions),
+            None => Q(conditions),
+        });
+    }
+    after.unwrap_or(Q(Vec::new()))
+}
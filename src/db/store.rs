@@ -0,0 +1,424 @@
+use std::{cmp::Ordering, iter::Peekable, marker::PhantomData, slice::Iter, sync::Mutex};
+
+use serde_json::Value;
+use sqlx::{any::AnyRow, FromRow};
+
+use super::models::{comparison_lookup, is_true, pk_columns, Condition, Model, PrimaryKey};
+use crate::{types::SqlValue, Connection, Error};
+
+/// The CRUD operations of a model, so that the services depending on them take a
+/// `&dyn ModelStore<User>` and are tested with a `MemoryStore` instead of a database.
+///
+/// # Example
+/// ```ignore
+/// async fn promote(store: &dyn ModelStore<User>, email: &str) -> Result<(), Error> {
+///     let mut user = store
+///         .get(kwargs!(email == email))
+///         .await?
+///         .ok_or_else(|| Error::Invalid(format!("no user {email}")))?;
+///     user.role = "admin".to_string();
+///     store.update(&user).await
+/// }
+///
+/// // in the application
+/// promote(&DbStore::new(conn), "joe@gmail.com").await?;
+///
+/// // in the unit tests
+/// let store = MemoryStore::with_rows(vec![User { id: 1, email: "joe@gmail.com".into(), ..Default::default() }]);
+/// promote(&store, "joe@gmail.com").await?;
+/// assert_eq!(store.rows()[0].role, "admin");
+/// ```
+#[async_trait::async_trait]
+pub trait ModelStore<T>: Send + Sync {
+    /// Inserts `instance`, see `Model::save`.
+    async fn save(&self, instance: &T) -> Result<(), Error>;
+
+    /// Updates the instance with the primary key of `instance`, see `Model::update`.
+    async fn update(&self, instance: &T) -> Result<(), Error>;
+
+    /// Deletes the instance with the primary key of `instance`, see `Model::delete`.
+    async fn delete(&self, instance: &T) -> Result<(), Error>;
+
+    /// Returns all the instances, see `Model::all`.
+    async fn all(&self) -> Vec<T>;
+
+    /// Returns the instances matching `kw`, see `Model::filter`, or the error of the
+    /// query (`Error::Invalid` for a condition the store cannot evaluate).
+    async fn filter(&self, kw: Vec<Condition>) -> Result<Vec<T>, Error>;
+
+    /// Returns the first instance matching `kw`, see `Model::get`, or the error of the
+    /// query.
+    async fn get(&self, kw: Vec<Condition>) -> Result<Option<T>, Error> {
+        Ok(self.filter(kw).await?.into_iter().next())
+    }
+
+    /// Counts the instances.
+    async fn count(&self) -> i64;
+}
+
+/// The `ModelStore` of the model `T` in the database.
+pub struct DbStore<T> {
+    conn: Connection,
+    _model: PhantomData<fn() -> T>,
+}
+
+impl<T> DbStore<T> {
+    pub fn new(conn: Connection) -> Self {
+        Self {
+            conn,
+            _model: PhantomData,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> ModelStore<T> for DbStore<T>
+where
+    T: Model + Unpin + for<'r> FromRow<'r, AnyRow> + Clone + Send + Sync,
+{
+    async fn save(&self, instance: &T) -> Result<(), Error> {
+        instance.save(&self.conn).await
+    }
+
+    async fn update(&self, instance: &T) -> Result<(), Error> {
+        instance.update(&self.conn).await.map(|_| ())
+    }
+
+    async fn delete(&self, instance: &T) -> Result<(), Error> {
+        instance.delete(&self.conn).await
+    }
+
+    async fn all(&self) -> Vec<T> {
+        T::all(&self.conn).await
+    }
+
+    async fn filter(&self, kw: Vec<Condition>) -> Result<Vec<T>, Error> {
+        // through the query set, so that an empty filter returns every instance
+        T::objects().filter(kw).try_all(&self.conn).await
+    }
+
+    async fn get(&self, kw: Vec<Condition>) -> Result<Option<T>, Error> {
+        let first = T::objects()
+            .filter(kw)
+            .order_by(pk_columns::<T>())
+            .limit(1)
+            .try_all(&self.conn)
+            .await?;
+        Ok(first.into_iter().next())
+    }
+
+    async fn count(&self) -> i64 {
+        T::objects().count(&self.conn).await
+    }
+}
+
+/// A `ModelStore` keeping the instances in memory, for the unit tests.
+///
+/// The filters support the comparisons, the `&`, `|` and `!` combinators of `Q` and the
+/// `__eq`, `__ne`, `__gt`, `__gte`, `__lt`, `__lte`, `__in`, `__isnull`, `__contains`,
+/// `__startswith` and `__endswith` lookups, as well as the `__contains` and
+/// `__overlaps` lookups of the range fields. The instances are stored as they are, an
+/// `auto` primary key is not generated.
+///
+/// `filter` and `get` return an `Error::Invalid` for the conditions which need a
+/// database (`raw!` predicates and expressions) and the other lookups, which the
+/// database rejects as well.
+pub struct MemoryStore<T> {
+    rows: Mutex<Vec<T>>,
+}
+
+impl<T> Default for MemoryStore<T> {
+    fn default() -> Self {
+        Self {
+            rows: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<T: Model + Clone> MemoryStore<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A store holding `rows`, the fixtures of a test.
+    pub fn with_rows(rows: Vec<T>) -> Self {
+        Self {
+            rows: Mutex::new(rows),
+        }
+    }
+
+    /// Returns the instances in the store, in the order they were saved.
+    pub fn rows(&self) -> Vec<T> {
+        self.lock().clone()
+    }
+
+    /// Saves `instance`, replacing the instance with the same primary key.
+    pub fn upsert(&self, instance: T) {
+        let mut rows = self.lock();
+        match Self::position(&rows, &instance) {
+            Some(index) => rows[index] = instance,
+            None => rows.push(instance),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<T>> {
+        // a panicking test does not prevent the others from reading the rows
+        self.rows.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// The index of the instance with the primary key of `instance`.
+    fn position(rows: &[T], instance: &T) -> Option<usize> {
+        let pk = instance.pk().args();
+        rows.iter().position(|row| row.pk().args() == pk)
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> ModelStore<T> for MemoryStore<T>
+where
+    T: Model + Clone + Send + Sync,
+{
+    async fn save(&self, instance: &T) -> Result<(), Error> {
+        let mut rows = self.lock();
+        if Self::position(&rows, instance).is_some() {
+            return Err(Error::Invalid(format!(
+                "{} with the same primary key already exists",
+                T::NAME
+            )));
+        }
+        rows.push(instance.clone());
+        Ok(())
+    }
+
+    async fn update(&self, instance: &T) -> Result<(), Error> {
+        let mut rows = self.lock();
+        match Self::position(&rows, instance) {
+            Some(index) => {
+                rows[index] = instance.clone();
+                Ok(())
+            }
+            None => Err(Error::Invalid(format!("{} does not exist", T::NAME))),
+        }
+    }
+
+    async fn delete(&self, instance: &T) -> Result<(), Error> {
+        let mut rows = self.lock();
+        if let Some(index) = Self::position(&rows, instance) {
+            rows.remove(index);
+        }
+        Ok(())
+    }
+
+    async fn all(&self) -> Vec<T> {
+        self.rows()
+    }
+
+    async fn filter(&self, kw: Vec<Condition>) -> Result<Vec<T>, Error> {
+        let mut matched = Vec::new();
+        for row in self.lock().iter() {
+            let fields = row.fields();
+            let mut conditions = kw.iter().peekable();
+            // an empty filter keeps every instance, like `DbStore::filter`
+            if conditions.peek().is_none() || any(&mut conditions, &fields, T::RANGES)? {
+                matched.push(row.clone());
+            }
+        }
+        Ok(matched)
+    }
+
+    async fn count(&self) -> i64 {
+        self.lock().len() as i64
+    }
+}
+
+type Conditions<'a> = Peekable<Iter<'a, Condition>>;
+type Fields = [(&'static str, Value)];
+
+fn is_operator(condition: Option<&&Condition>, expected: &str) -> bool {
+    matches!(condition, Some(Condition::LogicalOperator { operator }) if operator == expected)
+}
+
+/// The error of a condition the store cannot evaluate.
+fn unsupported(condition: String) -> Error {
+    Error::Invalid(format!("MemoryStore cannot evaluate {condition}"))
+}
+
+/// Evaluates the conditions joined by `or`, `ranges` being the range fields of the
+/// model.
+fn any(conditions: &mut Conditions, fields: &Fields, ranges: &[&str]) -> Result<bool, Error> {
+    let mut matched = all(conditions, fields, ranges)?;
+    while is_operator(conditions.peek(), "or") {
+        conditions.next();
+        // both sides are evaluated to consume the conditions
+        matched |= all(conditions, fields, ranges)?;
+    }
+    Ok(matched)
+}
+
+/// Evaluates the conditions joined by `and`, explicitly or by being adjacent.
+fn all(conditions: &mut Conditions, fields: &Fields, ranges: &[&str]) -> Result<bool, Error> {
+    let mut matched = term(conditions, fields, ranges)?;
+    loop {
+        match conditions.peek() {
+            Some(Condition::LogicalOperator { operator }) if operator == "and" => {
+                conditions.next();
+            }
+            Some(Condition::LogicalOperator { operator })
+                if operator != "not" && operator != "(" =>
+            {
+                return Ok(matched)
+            }
+            None => return Ok(matched),
+            Some(_) => {}
+        }
+        matched &= term(conditions, fields, ranges)?;
+    }
+}
+
+/// Evaluates a condition, a group in parentheses or a negation.
+fn term(conditions: &mut Conditions, fields: &Fields, ranges: &[&str]) -> Result<bool, Error> {
+    match conditions.next() {
+        Some(Condition::LogicalOperator { operator }) if operator == "not" => {
+            Ok(!term(conditions, fields, ranges)?)
+        }
+        Some(Condition::LogicalOperator { operator }) if operator == "(" => {
+            let matched = any(conditions, fields, ranges)?;
+            if is_operator(conditions.peek(), ")") {
+                conditions.next();
+            }
+            Ok(matched)
+        }
+        Some(Condition::FieldCondition {
+            field,
+            value,
+            comparaison_operator,
+            ..
+        }) => matches(field, comparaison_operator, value, fields, ranges),
+        Some(Condition::Expression { field, .. }) => {
+            Err(unsupported(format!("the expression on `{field}`")))
+        }
+        Some(Condition::Raw { sql, .. }) => Err(unsupported(format!("`{sql}`"))),
+        Some(Condition::LogicalOperator { operator }) => Err(Error::Invalid(format!(
+            "MemoryStore found `{operator}` instead of a condition"
+        ))),
+        None => Err(Error::Invalid(
+            "MemoryStore found no condition after an operator".to_string(),
+        )),
+    }
+}
+
+/// Whether the value of `field` (which may carry a lookup) matches `value`, the way the
+/// database would compare them.
+fn matches(
+    field: &str,
+    operator: &str,
+    value: &SqlValue,
+    fields: &Fields,
+    ranges: &[&str],
+) -> Result<bool, Error> {
+    let (field, operator) = comparison_lookup(field).unwrap_or((field, operator));
+    let (column, lookup) = match field.split_once("__") {
+        Some((column, lookup)) => (column, Some(lookup)),
+        None => (field, None),
+    };
+    let Some((_, actual)) = fields.iter().find(|(name, _)| *name == column) else {
+        return Err(Error::Invalid(format!(
+            "MemoryStore found no field `{column}`"
+        )));
+    };
+    let actual = normalized(actual.clone());
+    let expected = value.to_json();
+
+    if ranges.contains(&column) && matches!(lookup, Some("contains" | "overlaps")) {
+        let (Value::String(range), Some(lookup)) = (&actual, lookup) else {
+            // a `null` range contains nothing
+            return Ok(false);
+        };
+        return range_matches(range, lookup, &expected)
+            .ok_or_else(|| unsupported(format!("the `{field}` lookup on `{expected}`")));
+    }
+    let matched = match (lookup, &actual, &expected) {
+        (Some("isnull"), _, _) => actual.is_null() == is_true(value),
+        (Some("in"), _, Value::Array(values)) => values
+            .iter()
+            .any(|value| compare(&actual, &normalized(value.clone())) == Some(Ordering::Equal)),
+        (Some("contains"), Value::String(actual), Value::String(text)) => {
+            actual.contains(text.as_str())
+        }
+        (Some("startswith"), Value::String(actual), Value::String(text)) => {
+            actual.starts_with(text.as_str())
+        }
+        (Some("endswith"), Value::String(actual), Value::String(text)) => {
+            actual.ends_with(text.as_str())
+        }
+        (Some(lookup), _, _) => return Err(unsupported(format!("the `__{lookup}` lookup"))),
+        // `null` is never equal to a value, `is null` is used instead
+        (None, _, Value::Null) if operator == "=" => actual.is_null(),
+        (None, _, Value::Null) if operator == "!=" => !actual.is_null(),
+        (None, _, _) => {
+            let ordering = compare(&actual, &expected);
+            match operator {
+                "=" => ordering == Some(Ordering::Equal),
+                "!=" => ordering.is_some_and(Ordering::is_ne),
+                ">" => ordering == Some(Ordering::Greater),
+                ">=" => ordering.is_some_and(Ordering::is_ge),
+                "<" => ordering == Some(Ordering::Less),
+                "<=" => ordering.is_some_and(Ordering::is_le),
+                _ => return Err(unsupported(format!("the `{operator}` comparison"))),
+            }
+        }
+    };
+    Ok(matched)
+}
+
+/// Whether the `[lower,upper)` `range` contains the bound or overlaps the range
+/// `expected`, `None` if `expected` is neither.
+fn range_matches(range: &str, lookup: &str, expected: &Value) -> Option<bool> {
+    let (lower, upper) = bounds(range)?;
+    let before = |a: &Value, b: &Value| compare(a, b) == Some(Ordering::Less);
+    match (lookup, expected) {
+        ("contains", Value::Number(_) | Value::String(_)) => {
+            Some(!before(expected, &lower) && before(expected, &upper))
+        }
+        ("overlaps", Value::String(other)) => {
+            let (other_lower, other_upper) = bounds(other)?;
+            Some(before(&lower, &other_upper) && before(&other_lower, &upper))
+        }
+        _ => None,
+    }
+}
+
+/// The bounds of the `[lower,upper)` text of a range, numbers for an `Int4Range`.
+fn bounds(range: &str) -> Option<(Value, Value)> {
+    let (lower, upper) = range
+        .trim_start_matches('[')
+        .trim_end_matches(')')
+        .split_once(',')?;
+    let bound = |bound: &str| {
+        let bound = bound.trim().trim_matches('"');
+        bound
+            .parse::<i64>()
+            .map(Value::from)
+            .unwrap_or_else(|_| Value::from(bound))
+    };
+    Some((bound(lower), bound(upper)))
+}
+
+/// The booleans as the integers they are stored as.
+fn normalized(value: Value) -> Value {
+    match value {
+        Value::Bool(value) => Value::from(value as i32),
+        value => value,
+    }
+}
+
+/// Compares two values, `None` when one is `null` or their types differ.
+fn compare(actual: &Value, expected: &Value) -> Option<Ordering> {
+    match (actual, expected) {
+        (Value::Number(actual), Value::Number(expected)) => {
+            actual.as_f64()?.partial_cmp(&expected.as_f64()?)
+        }
+        (Value::String(actual), Value::String(expected)) => Some(actual.cmp(expected)),
+        _ => None,
+    }
+}
@@ -0,0 +1,158 @@
+use std::fmt;
+
+use crate::{Backend, BACKEND};
+
+/// The errors of the database, classified so that transient failures can be retried,
+/// and of the operations of the models.
+#[derive(Debug)]
+pub enum Error {
+    /// The transaction was chosen as the victim of a deadlock.
+    Deadlock(sqlx::Error),
+    /// The transaction could not be serialized with concurrent transactions.
+    SerializationFailure(sqlx::Error),
+    /// A lock could not be acquired in time, or the SQLite database is busy.
+    LockTimeout(sqlx::Error),
+    /// Any other error of the database driver.
+    Sqlx(sqlx::Error),
+    /// The statement was rejected by the guardrail, see `enable_guardrail`.
+    Rejected { query: String },
+    /// The arguments of the operation are invalid.
+    Invalid(String),
+    /// The query returned more rows than the soft limit, see `set_max_rows`.
+    ResultTooLarge { query: String, max_rows: usize },
+    /// The query was refused by a strict query budget.
+    BudgetExceeded { query: String },
+    /// The generated primary key of the instance is not set, the instance was never
+    /// saved or read from the database.
+    MissingPrimaryKey { model: &'static str },
+    /// The state machine of the model does not allow the transition.
+    InvalidTransition {
+        model: &'static str,
+        field: &'static str,
+        from: String,
+        to: String,
+    },
+    /// The state of the instance was changed in the database since it was read.
+    StaleState {
+        model: &'static str,
+        field: &'static str,
+        state: String,
+    },
+    /// An error of the file system or of another storage, e.g. of a `BlobStore`.
+    Io(std::io::Error),
+}
+
+impl Error {
+    /// Returns `true` if running the same transaction again may succeed.
+    ///
+    /// # Example
+    /// ```ignore
+    /// if let Err(err) = sqlx::query(query).execute(&conn).await.map_err(Error::from) {
+    ///     if err.is_retryable() {
+    ///         // run the transaction again
+    ///     }
+    /// }
+    /// ```
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Error::Deadlock(_) | Error::SerializationFailure(_) | Error::LockTimeout(_)
+        )
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(err: sqlx::Error) -> Self {
+        let Some(database_error) = err.as_database_error() else {
+            return Error::Sqlx(err);
+        };
+        let code = database_error.code().unwrap_or_default().to_string();
+        let message = database_error.message().to_lowercase();
+
+        if code == "40P01" || message.contains("deadlock") {
+            Error::Deadlock(err)
+        } else if code == "40001" {
+            Error::SerializationFailure(err)
+        } else if code == "55P03"
+            || message.contains("lock wait timeout")
+            // SQLITE_BUSY and SQLITE_LOCKED, with their extended result codes (the
+            // numeric SQLSTATEs of Postgres would match as well)
+            || (*BACKEND == Backend::Sqlite
+                && code.parse::<i32>().is_ok_and(|code| matches!(code & 0xff, 5 | 6)))
+        {
+            Error::LockTimeout(err)
+        } else {
+            Error::Sqlx(err)
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Deadlock(err) => write!(f, "deadlock: {err}"),
+            Error::SerializationFailure(err) => write!(f, "serialization failure: {err}"),
+            Error::LockTimeout(err) => write!(f, "lock timeout: {err}"),
+            Error::Sqlx(err) => err.fmt(f),
+            Error::Rejected { query } => write!(f, "rejected by the guardrail: {query}"),
+            Error::Invalid(message) => write!(f, "invalid arguments: {message}"),
+            Error::ResultTooLarge { query, max_rows } => {
+                write!(f, "result too large: more than {max_rows} rows for {query}")
+            }
+            Error::BudgetExceeded { query } => write!(f, "query budget exceeded: {query}"),
+            Error::MissingPrimaryKey { model } => {
+                write!(
+                    f,
+                    "missing primary key: the instance of {model} is not saved"
+                )
+            }
+            Error::InvalidTransition {
+                model,
+                field,
+                from,
+                to,
+            } => {
+                write!(
+                    f,
+                    "invalid transition: the {field} of {model} can not go from {from} to {to}"
+                )
+            }
+            Error::StaleState {
+                model,
+                field,
+                state,
+            } => {
+                write!(
+                    f,
+                    "stale state: the {field} of the instance of {model} is no longer {state}"
+                )
+            }
+            Error::Io(err) => write!(f, "io error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Deadlock(err)
+            | Error::SerializationFailure(err)
+            | Error::LockTimeout(err)
+            | Error::Sqlx(err) => Some(err),
+            Error::Io(err) => Some(err),
+            Error::Rejected { .. }
+            | Error::Invalid(_)
+            | Error::ResultTooLarge { .. }
+            | Error::BudgetExceeded { .. }
+            | Error::MissingPrimaryKey { .. }
+            | Error::InvalidTransition { .. }
+            | Error::StaleState { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! ER diagrams of the models, for documentation and onboarding.
+
+use crate::prelude::*;
+
+/// A column as drawn in the diagram.
+struct GraphColumn {
+    name: &'static str,
+    kind: String,
+    primary_key: bool,
+    // the (table, column) referenced by a foreign key
+    references: Option<(String, String)>,
+}
+
+/// The ER diagram of the registered models and their foreign keys, see `schema_graph`.
+#[derive(Default)]
+pub struct SchemaGraph {
+    tables: Vec<(&'static str, Vec<GraphColumn>)>,
+}
+
+/// Starts the ER diagram of the models registered with `SchemaGraph::with_model`.
+///
+/// # Example
+/// ```ignore
+/// let graph = rusql_alchemy::schema_graph()
+///     .with_model::<User>()
+///     .with_model::<Product>();
+/// std::fs::write("docs/schema.mmd", graph.to_mermaid())?;
+/// std::fs::write("docs/schema.dot", graph.to_dot())?;
+/// ```
+pub fn schema_graph() -> SchemaGraph {
+    SchemaGraph::default()
+}
+
+impl SchemaGraph {
+    /// Registers a model drawn in the diagram.
+    pub fn with_model<M: Model>(mut self) -> Self {
+        let columns = M::COLUMNS
+            .iter()
+            .map(|(name, definition)| {
+                let definition = definition.strip_prefix(name).unwrap_or(definition).trim();
+                let references = M::FOREIGN_KEYS
+                    .iter()
+                    .find(|key| key.field == *name)
+                    .map(|key| (key.table.to_string(), key.column.to_string()));
+                GraphColumn {
+                    name,
+                    kind: definition
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    primary_key: definition.contains("primary key") || M::PK_COLUMNS.contains(name),
+                    references,
+                }
+            })
+            .collect();
+        self.tables.push((M::NAME, columns));
+        self
+    }
+
+    /// Renders the diagram in the GraphViz `dot` language.
+    pub fn to_dot(&self) -> String {
+        let mut dot = "digraph schema {\n    node [shape=record];\n".to_string();
+        for (table, columns) in &self.tables {
+            let fields = columns
+                .iter()
+                .map(|column| {
+                    let key = if column.primary_key { " (PK)" } else { "" };
+                    format!("{}: {}{key}\\l", column.name, column.kind)
+                })
+                .collect::<String>();
+            dot.push_str(&format!("    {table} [label=\"{{{table}|{fields}}}\"];\n"));
+        }
+        for (table, columns) in &self.tables {
+            for column in columns {
+                if let Some((target, _)) = &column.references {
+                    dot.push_str(&format!(
+                        "    {table} -> {target} [label=\"{}\"];\n",
+                        column.name
+                    ));
+                }
+            }
+        }
+        dot.push('}');
+        dot
+    }
+
+    /// Renders the diagram as a Mermaid `erDiagram`.
+    pub fn to_mermaid(&self) -> String {
+        let mut mermaid = "erDiagram\n".to_string();
+        for (table, columns) in &self.tables {
+            mermaid.push_str(&format!("    {table} {{\n"));
+            for column in columns {
+                // the types can not hold a size
+                let kind = column.kind.split('(').next().unwrap_or_default();
+                let key = match (column.primary_key, &column.references) {
+                    (true, _) => " PK",
+                    (false, Some(_)) => " FK",
+                    (false, None) => "",
+                };
+                mermaid.push_str(&format!("        {kind} {}{key}\n", column.name));
+            }
+            mermaid.push_str("    }\n");
+        }
+        for (table, columns) in &self.tables {
+            for column in columns {
+                if let Some((target, _)) = &column.references {
+                    mermaid.push_str(&format!("    {table} }}o--|| {target} : {}\n", column.name));
+                }
+            }
+        }
+        mermaid
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    future::Future,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static MAX_ROWS: AtomicUsize = AtomicUsize::new(0);
+static SOFT_MAX_ROWS: AtomicUsize = AtomicUsize::new(10_000);
+
+tokio::task_local! {
+    static UNGUARDED: bool;
+    static SOFT_MAX_ROWS_OVERRIDE: Option<usize>;
+}
+
+/// Enables the guardrail, which rejects the generated statements that would touch a
+/// whole table: `delete`/`update` without a `where` clause, and `all`/`filter`
+/// returning more than `max_rows` rows (when set).
+///
+/// # Example
+/// ```ignore
+/// rusql_alchemy::enable_guardrail(Some(10_000));
+///
+/// let products = Product::all(&conn).await;
+/// products.delete(&conn).await; // rejected, returns `false`
+///
+/// rusql_alchemy::unguarded(products.delete(&conn)).await; // deletes every product
+/// ```
+pub fn enable_guardrail(max_rows: Option<usize>) {
+    MAX_ROWS.store(max_rows.unwrap_or(0), Ordering::Relaxed);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Disables the guardrail enabled by `enable_guardrail`.
+pub fn disable_guardrail() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// Runs `future` with the guardrail disabled, to explicitly allow a full-table
+/// operation.
+pub async fn unguarded<F: Future>(future: F) -> F::Output {
+    UNGUARDED.scope(true, future).await
+}
+
+fn is_guarded() -> bool {
+    ENABLED.load(Ordering::Relaxed) && !UNGUARDED.try_with(|unguarded| *unguarded).unwrap_or(false)
+}
+
+/// Returns `false` (and reports it) if the guardrail rejects `query`.
+pub(crate) fn check_statement(query: &str) -> bool {
+    if !is_guarded() {
+        return true;
+    }
+    let statement = query.trim().to_lowercase();
+    let is_write = statement.starts_with("delete") || statement.starts_with("update");
+    if is_write && !statement.contains(" where ") {
+        eprintln!("Rejected by the guardrail\n->{query}\n-> wrap it with `unguarded` to run it");
+        return false;
+    }
+    true
+}
+
+/// Returns the number of rows a select may fetch to detect it exceeds the guardrail
+/// limit (one more than the limit), or `None` when unbounded.
+pub(crate) fn row_limit() -> Option<usize> {
+    let max_rows = MAX_ROWS.load(Ordering::Relaxed);
+    (is_guarded() && max_rows > 0).then_some(max_rows + 1)
+}
+
+/// Returns `false` (and reports it) if `rows` exceeds the guardrail limit.
+pub(crate) fn check_rows(query: &str, rows: usize) -> bool {
+    match row_limit() {
+        Some(limit) if rows >= limit => {
+            eprintln!(
+                "Rejected by the guardrail\n->{query}\n-> returns more than {} rows, wrap it with `unguarded` to run it",
+                limit - 1
+            );
+            false
+        }
+        _ => true,
+    }
+}
+
+/// Sets the default soft limit of `Model::try_all`/`Model::try_filter`, which return
+/// `Error::ResultTooLarge` instead of loading more rows (10 000 by default, `None` to
+/// disable it).
+///
+/// # Example
+/// ```ignore
+/// rusql_alchemy::set_max_rows(Some(50_000));
+///
+/// match Product::try_all(&conn).await {
+///     Ok(products) => println!("{products:#?}"),
+///     Err(Error::ResultTooLarge { .. }) => println!("paginate instead"),
+///     Err(err) => eprintln!("{err}"),
+/// }
+/// ```
+pub fn set_max_rows(max_rows: Option<usize>) {
+    SOFT_MAX_ROWS.store(max_rows.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Runs `future` with the soft limit `max_rows` instead of the default one, to allow a
+/// larger (or require a smaller) result for one call.
+///
+/// # Example
+/// ```ignore
+/// let products = rusql_alchemy::with_max_rows(None, Product::try_all(&conn)).await?;
+/// ```
+pub async fn with_max_rows<F: Future>(max_rows: Option<usize>, future: F) -> F::Output {
+    SOFT_MAX_ROWS_OVERRIDE.scope(max_rows, future).await
+}
+
+/// Returns the soft limit of the current call, `None` when unbounded.
+pub(crate) fn soft_max_rows() -> Option<usize> {
+    SOFT_MAX_ROWS_OVERRIDE
+        .try_with(|max_rows| *max_rows)
+        .unwrap_or_else(|_| {
+            let max_rows = SOFT_MAX_ROWS.load(Ordering::Relaxed);
+            (max_rows > 0).then_some(max_rows)
+        })
+}
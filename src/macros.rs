@@ -3,105 +3,134 @@ macro_rules! kwargs {
     // Support for direct field-value pairs with custom comparison operators
     ($($field:ident = $value:expr),* $(,)?) => {
         {
-            let mut args = Vec::new();
-            $(
-                args.push(Condition::FieldCondition {
-                    field: stringify!($field).to_string(),
-                    value: rusql_alchemy::to_string($value.clone()),
-                    value_type: rusql_alchemy::get_type_name($value.clone()).into(),
-                    comparaison_operator: "=".to_string(),
-                });
-            )*
-            args
+            vec![
+                $(
+                    rusql_alchemy::db::expression::Kwarg::condition($value.clone(), stringify!($field), "=")
+                ),*
+            ]
         }
     };
     ($field:ident == $value:expr) => {
         {
             vec![
-                Condition::FieldCondition {
-                    field: stringify!($field).to_string(),
-                    value: rusql_alchemy::to_string($value.clone()),
-                    value_type: rusql_alchemy::get_type_name($value.clone()).into(),
-                    comparaison_operator: "=".to_string(),
-                }
+                rusql_alchemy::db::expression::Kwarg::condition($value.clone(), stringify!($field), "=")
             ]
         }
     };
     ($field:ident != $value:expr) => {
         {
             vec![
-                Condition::FieldCondition {
-                    field: stringify!($field).to_string(),
-                    value: rusql_alchemy::to_string($value.clone()),
-                    value_type: rusql_alchemy::get_type_name($value.clone()).into(),
-                    comparaison_operator: "!=".to_string(),
-                }
+                rusql_alchemy::db::expression::Kwarg::condition($value.clone(), stringify!($field), "!=")
             ]
         }
     };
     ($field:ident < $value:expr) => {
         {
             vec![
-                Condition::FieldCondition {
-                    field: stringify!($field).to_string(),
-                    value: rusql_alchemy::to_string($value.clone()),
-                    value_type: rusql_alchemy::get_type_name($value.clone()).into(),
-                    comparaison_operator: "<".to_string(),
-                }
+                rusql_alchemy::db::expression::Kwarg::condition($value.clone(), stringify!($field), "<")
             ]
         }
     };
     ($field:ident <= $value:expr) => {
         {
             vec![
-                Condition::FieldCondition {
-                    field: stringify!($field).to_string(),
-                    value: rusql_alchemy::to_string($value.clone()),
-                    value_type: rusql_alchemy::get_type_name($value.clone()).into(),
-                    comparaison_operator: "<=".to_string(),
-                }
+                rusql_alchemy::db::expression::Kwarg::condition($value.clone(), stringify!($field), "<=")
             ]
         }
     };
     ($field:ident > $value:expr) => {
          {
              vec![
-                 Condition::FieldCondition {
-                    field: stringify!($field).to_string(),
-                    value: rusql_alchemy::to_string($value.clone()),
-                    value_type: rusql_alchemy::get_type_name($value.clone()).into(),
-                    comparaison_operator: ">".to_string(),
-                 }
+                 rusql_alchemy::db::expression::Kwarg::condition($value.clone(), stringify!($field), ">")
              ]
          }
      };
      ($field:ident >= $value:expr) => {
           {
               vec![
-                  Condition::FieldCondition {
-                    field: stringify!($field).to_string(),
-                    value: rusql_alchemy::to_string($value.clone()),
-                    value_type: rusql_alchemy::get_type_name($value.clone()).into(),
-                    comparaison_operator: ">=".to_string(),
-                  }
+                  rusql_alchemy::db::expression::Kwarg::condition($value.clone(), stringify!($field), ">=")
               ]
           }
       };
 }
+
+/// A group of `field: value` conditions (joined by `and`, the fields supporting the
+/// `kwargs!` lookups), combinable with `&`, `|` and `!`, see `Q`.
+///
+/// # Example
+/// ```ignore
+/// let users = User::filter(
+///     (Q!(user_id__eq: 5) & Q!(password__eq: "strongpassword")).into(),
+///     &conn,
+/// ).await;
+/// ```
+#[macro_export]
+macro_rules! Q {
+    ($($field:ident : $value:expr),* $(,)?) => {
+        Q(vec![
+            $(
+                rusql_alchemy::db::expression::Kwarg::condition($value.clone(), stringify!($field), "=")
+            ),*
+        ])
+    };
+}
+
+/// A raw SQL predicate, combinable with the `kwargs!` conditions, its `?` being bound
+/// to the following values.
+///
+/// # Example
+/// ```ignore
+/// let users = User::filter(
+///     kwargs!(role == "admin").and(raw!("length(name) > ? or age < ?", 10, 18)),
+///     &conn,
+/// ).await;
+/// ```
+#[macro_export]
+macro_rules! raw {
+    ($sql:expr $(, $value:expr)* $(,)?) => {
+        vec![
+            Condition::Raw {
+                sql: $sql.to_string(),
+                args: vec![$(rusql_alchemy::types::SqlValue::from($value.clone())),*],
+            }
+        ]
+    };
+}
+/// Binds the `SqlValue` `$args` to `$stream`, failing on the first argument which can
+/// not be bound (an unsupported lookup), or returning `$default` from the function after
+/// printing the error.
 macro_rules! binds {
-    ($args: expr, $stream:expr) => {
-        for (v, t) in $args {
-            let v = v.replace('"', "");
-            match t.as_str() {
-                "i32" | "bool" => {
-                    $stream = $stream.bind(v.parse::<i32>().unwrap());
-                }
-                "f64" => {
-                    $stream = $stream.bind(v.parse::<f64>().unwrap());
-                }
-                _ => {
-                    $stream = $stream.bind(v);
-                }
+    ($args: expr, $stream:expr) => {{
+        let mut bound = Ok(());
+        for value in $args {
+            let value: $crate::types::SqlValue = value;
+            if let Err(err) = value.check() {
+                bound = Err(err);
+                break;
+            }
+            $stream = $stream.bind(value);
+        }
+        bound
+    }};
+    ($args: expr, $stream:expr, $default:expr) => {
+        if let Err(err) = binds!($args, $stream) {
+            eprintln!("Error during the binding of the arguments\n->{err}");
+            return $default;
+        }
+    };
+}
+
+/// The future running `$stream.$method` on the transaction of the `atomic` block on
+/// `$conn`, or on `$conn` outside of it.
+macro_rules! on_conn {
+    ($conn:expr, $stream:expr, $method:ident) => {
+        async {
+            match $crate::transaction::current($conn) {
+                Some(transaction) => match transaction.lock().await.as_mut() {
+                    Some(transaction) => $stream.$method(&mut **transaction).await,
+                    None => Err($crate::transaction::finished()),
+                },
+                None => $stream.$method($conn.pool()).await,
             }
         }
     };
@@ -110,6 +139,10 @@ macro_rules! binds {
 #[macro_export]
 macro_rules! migrate {
     ([$($struct:ident),*], $conn:expr) => {
-        $( $struct::migrate($conn).await; )*
+        $(
+            if let Err(err) = $struct::migrate($conn).await {
+                eprintln!("Error during the migration of {}\n->{err}", stringify!($struct));
+            }
+        )*
     };
 }
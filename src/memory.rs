@@ -0,0 +1,107 @@
+//! An in-memory database for the tests and the examples which need neither a database
+//! server nor a SQLite file, enabled by the `memory` feature.
+//!
+//! Each model has its own table, a `MemoryStore` holding the instances in the order they
+//! were saved, so the results are the same from one run to the next.
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::db::models::{Condition, Model};
+use crate::db::store::{MemoryStore, ModelStore};
+use crate::Error;
+
+type Tables = HashMap<TypeId, Arc<dyn Any + Send + Sync>>;
+
+/// The tables of the models in memory, a `ModelStore` of every model.
+///
+/// The clones share the same tables, so the services built from one database see the
+/// writes of each other.
+///
+/// # Example
+/// ```ignore
+/// let db = MemoryDatabase::new().with_rows(vec![User { id: 1, ..Default::default() }]);
+/// let users: &dyn ModelStore<User> = &db;
+/// let products: &dyn ModelStore<Product> = &db;
+/// products.save(&Product { id: 1, owner: 1, ..Default::default() }).await?;
+/// assert_eq!(users.count().await, 1);
+/// ```
+#[derive(Clone, Default)]
+pub struct MemoryDatabase {
+    tables: Arc<Mutex<Tables>>,
+}
+
+impl MemoryDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `rows` to the table of `T`, the fixtures of a test, replacing the instances
+    /// with the same primary key.
+    pub fn with_rows<T>(self, rows: Vec<T>) -> Self
+    where
+        T: Model + Clone + Send + Sync + 'static,
+    {
+        let table = self.table::<T>();
+        for row in rows {
+            table.upsert(row);
+        }
+        self
+    }
+
+    /// Returns the table of `T`, created empty on its first use.
+    pub fn table<T>(&self) -> Arc<MemoryStore<T>>
+    where
+        T: Model + Clone + Send + Sync + 'static,
+    {
+        let mut tables = self.tables.lock().unwrap_or_else(|err| err.into_inner());
+        let table = tables
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(MemoryStore::<T>::new()))
+            .clone();
+        table
+            .downcast::<MemoryStore<T>>()
+            .unwrap_or_else(|_| unreachable!("the tables are keyed by the type of their model"))
+    }
+
+    /// Empties every table.
+    pub fn clear(&self) {
+        self.tables
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clear();
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> ModelStore<T> for MemoryDatabase
+where
+    T: Model + Clone + Send + Sync + 'static,
+{
+    async fn save(&self, instance: &T) -> Result<(), Error> {
+        self.table::<T>().save(instance).await
+    }
+
+    async fn update(&self, instance: &T) -> Result<(), Error> {
+        self.table::<T>().update(instance).await
+    }
+
+    async fn delete(&self, instance: &T) -> Result<(), Error> {
+        self.table::<T>().delete(instance).await
+    }
+
+    async fn all(&self) -> Vec<T> {
+        self.table::<T>().all().await
+    }
+
+    async fn filter(&self, kw: Vec<Condition>) -> Result<Vec<T>, Error> {
+        self.table::<T>().filter(kw).await
+    }
+
+    async fn count(&self) -> i64 {
+        ModelStore::<T>::count(&*self.table::<T>()).await
+    }
+}
@@ -0,0 +1,1381 @@
+//! Versioned migrations generated from the models.
+//!
+//! `makemigrations` diffs the registered models against the snapshot of the last
+//! migration (or the database) and writes the operations to a numbered SQL file, next
+//! to a JSON snapshot of the models schema. `migrate` runs the files not applied yet:
+//!
+//! ```sql
+//! -- migrate:up
+//! alter table "users" add column age integer;
+//! -- migrate:down
+//! alter table "users" drop column age;
+//! ```
+
+use std::{
+    fs,
+    future::Future,
+    io::Write,
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+
+use sqlx::Row;
+
+use crate::db::models::{column_definition, range_bounds};
+use crate::naming::{index_column, index_name, quote_ident, resolve_references};
+use crate::prelude::*;
+use crate::{Backend, BACKEND, PLACEHOLDER};
+
+const UP: &str = "-- migrate:up";
+const DOWN: &str = "-- migrate:down";
+const DESTRUCTIVE: &str = "-- migrate:destructive";
+const REPLACES: &str = "-- migrate:replaces";
+
+/// The policy applied to the operations which may lose data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SafeMode {
+    /// Every operation is generated and run.
+    #[default]
+    Unrestricted,
+    /// Dropped columns and narrowed column types are refused unless forced.
+    AdditiveOnly,
+}
+
+/// A schema change of a migration.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    CreateTable {
+        table: String,
+        schema: String,
+    },
+    AddColumn {
+        table: String,
+        column: String,
+        definition: String,
+    },
+    DropColumn {
+        table: String,
+        column: String,
+        definition: String,
+    },
+    AlterColumn {
+        table: String,
+        column: String,
+        from: String,
+        to: String,
+    },
+    RenameColumn {
+        table: String,
+        from: String,
+        to: String,
+    },
+    /// Recreates a SQLite table with the `to` columns and copies its rows, for the
+    /// changes SQLite can not alter (column types, constraints).
+    RebuildTable {
+        table: String,
+        from: Vec<(String, String)>,
+        to: Vec<(String, String)>,
+        renames: Vec<(String, String)>,
+    },
+    CreateIndex {
+        table: String,
+        column: String,
+    },
+    DropIndex {
+        table: String,
+        column: String,
+    },
+}
+
+impl Operation {
+    /// Returns `true` if the operation may lose data: dropping a column, or changing
+    /// its type to a narrower one.
+    pub fn is_destructive(&self) -> bool {
+        match self {
+            Operation::DropColumn { .. } => true,
+            Operation::AlterColumn { from, to, .. } => !is_widening(from, to),
+            Operation::RebuildTable {
+                from, to, renames, ..
+            } => from.iter().any(|(column, definition)| {
+                let target = renames
+                    .iter()
+                    .find(|(renamed, _)| renamed == column)
+                    .map_or(column, |(_, target)| target);
+                match to.iter().find(|(c, _)| c == target) {
+                    Some((_, new)) => !is_widening(
+                        &normalize_type(&column_type(definition)),
+                        &normalize_type(&column_type(new)),
+                    ),
+                    None => true,
+                }
+            }),
+            _ => false,
+        }
+    }
+
+    /// The statements applying the operation.
+    pub fn up(&self) -> Vec<String> {
+        match self {
+            Operation::CreateTable { schema, .. } => vec![schema.clone()],
+            Operation::AddColumn {
+                table, definition, ..
+            } => vec![format!(
+                "alter table {} add column {definition};",
+                quote_ident(table)
+            )],
+            Operation::DropColumn { table, column, .. } => {
+                vec![format!(
+                    "alter table {} drop column {column};",
+                    quote_ident(table)
+                )]
+            }
+            Operation::AlterColumn {
+                table, column, to, ..
+            } => vec![alter_column(table, column, to)],
+            Operation::RenameColumn { table, from, to } => {
+                vec![format!(
+                    "alter table {} rename column {from} to {to};",
+                    quote_ident(table)
+                )]
+            }
+            Operation::RebuildTable {
+                table,
+                from,
+                to,
+                renames,
+            } => rebuild_table(table, from, to, renames),
+            Operation::CreateIndex { table, column } => vec![create_index(table, column)],
+            Operation::DropIndex { table, column } => vec![drop_index(table, column)],
+        }
+    }
+
+    /// The statements reverting the operation.
+    pub fn down(&self) -> Vec<String> {
+        match self {
+            Operation::CreateTable { table, .. } => {
+                vec![format!("drop table {};", quote_ident(table))]
+            }
+            Operation::AddColumn { table, column, .. } => {
+                vec![format!(
+                    "alter table {} drop column {column};",
+                    quote_ident(table)
+                )]
+            }
+            Operation::DropColumn {
+                table, definition, ..
+            } => vec![format!(
+                "alter table {} add column {definition};",
+                quote_ident(table)
+            )],
+            Operation::AlterColumn {
+                table,
+                column,
+                from,
+                ..
+            } => vec![alter_column(table, column, from)],
+            Operation::RenameColumn { table, from, to } => {
+                vec![format!(
+                    "alter table {} rename column {to} to {from};",
+                    quote_ident(table)
+                )]
+            }
+            Operation::RebuildTable {
+                table,
+                from,
+                to,
+                renames,
+            } => {
+                let renames = renames
+                    .iter()
+                    .map(|(from, to)| (to.clone(), from.clone()))
+                    .collect::<Vec<_>>();
+                rebuild_table(table, to, from, &renames)
+            }
+            Operation::CreateIndex { table, column } => vec![drop_index(table, column)],
+            Operation::DropIndex { table, column } => vec![create_index(table, column)],
+        }
+    }
+}
+
+/// The record of an applied migration.
+#[derive(FromRow, Clone, Debug, Default, Model)]
+#[model(table = "AppliedMigration")]
+pub struct AppliedMigration {
+    #[model(primary_key = true)]
+    pub version: Integer,
+    #[model(size = 255, null = false)]
+    pub name: String,
+    #[model(default = "now")]
+    pub applied_at: DateTime,
+}
+
+/// A migration file of the migrations directory.
+#[derive(Clone, Debug)]
+pub struct MigrationFile {
+    pub version: i32,
+    pub name: String,
+    pub path: PathBuf,
+    pub destructive: bool,
+    /// The (first, last) versions of the migrations squashed into this one.
+    pub replaces: Option<(i32, i32)>,
+    pub up: Vec<String>,
+    pub down: Vec<String>,
+}
+
+impl MigrationFile {
+    /// Reads a migration file named `<version>_<name>.sql`.
+    pub fn read(path: &Path) -> Option<Self> {
+        let stem = path.file_stem()?.to_str()?;
+        let (version, name) = stem.split_once('_')?;
+        let content = fs::read_to_string(path).ok()?;
+        let (up, down) = content.split_once(DOWN).unwrap_or((&content, ""));
+        let replaces = content.lines().find_map(|line| {
+            let (first, last) = line.trim().strip_prefix(REPLACES)?.trim().split_once('-')?;
+            Some((first.parse().ok()?, last.parse().ok()?))
+        });
+        Some(Self {
+            version: version.parse().ok()?,
+            name: name.to_string(),
+            path: path.to_path_buf(),
+            destructive: content.lines().any(|line| line.trim() == DESTRUCTIVE),
+            replaces,
+            up: statements(up),
+            down: statements(down),
+        })
+    }
+
+    /// Returns `true` if the migration is marked destructive or drops data.
+    pub fn is_destructive(&self) -> bool {
+        self.destructive || drops_data(&self.up)
+    }
+
+    /// Writes the migration to its path.
+    pub fn write(&self) -> bool {
+        let mut content = String::new();
+        if self.destructive {
+            content.push_str(&format!("{DESTRUCTIVE}\n"));
+        }
+        if let Some((first, last)) = self.replaces {
+            content.push_str(&format!("{REPLACES} {first}-{last}\n"));
+        }
+        content.push_str(&format!("{UP}\n"));
+        for statement in &self.up {
+            content.push_str(&format!("{statement}\n"));
+        }
+        content.push_str(&format!("{DOWN}\n"));
+        for statement in &self.down {
+            content.push_str(&format!("{statement}\n"));
+        }
+
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        if let Err(err) = fs::create_dir_all(dir).and_then(|_| fs::write(&self.path, content)) {
+            eprintln!("Error during the writing of the migration\n->{err}");
+            return false;
+        }
+        true
+    }
+}
+
+/// The schema of a table, from a snapshot or the database.
+struct TableState {
+    table: String,
+    columns: Vec<(String, String)>,
+    indexes: Vec<String>,
+}
+
+struct ModelSchema {
+    table: String,
+    schema: String,
+    columns: Vec<(String, String)>,
+    indexes: Vec<String>,
+}
+
+/// A callback run around the migration batch.
+type Hook = Box<dyn Fn(Connection) -> Pin<Box<dyn Future<Output = bool> + Send>> + Send + Sync>;
+
+/// The migrations of a set of models, stored in a directory.
+///
+/// # Example
+/// ```ignore
+/// let migrations = Migrations::new("migrations")
+///     .with_model::<User>()
+///     .with_model::<Product>()
+///     .with_safe_mode(SafeMode::AdditiveOnly);
+///
+/// migrations.makemigrations("add_age", Some(&conn)).await;
+/// migrations.migrate(&conn).await;
+/// ```
+pub struct Migrations {
+    dir: PathBuf,
+    models: Vec<ModelSchema>,
+    safe_mode: SafeMode,
+    forced: bool,
+    interactive: bool,
+    // (table, from, to)
+    renames: Vec<(String, String, String)>,
+    before: Vec<Hook>,
+    after: Vec<Hook>,
+}
+
+impl Migrations {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            models: Vec::new(),
+            safe_mode: SafeMode::default(),
+            forced: false,
+            interactive: false,
+            renames: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+
+    /// Registers a model whose table is managed by the migrations.
+    pub fn with_model<M: Model>(mut self) -> Self {
+        let columns = M::COLUMNS
+            .iter()
+            .flat_map(|(column, definition)| {
+                let bounds = range_bounds(column, definition);
+                let definition = resolve_references::<M>(column, definition);
+                let definition = column_definition(column, &definition);
+                std::iter::once((column.to_string(), definition)).chain(bounds)
+            })
+            .collect();
+        self.models.push(ModelSchema {
+            table: M::NAME.to_string(),
+            schema: M::schema(),
+            columns,
+            indexes: M::INDEXES.iter().map(|column| column.to_string()).collect(),
+        });
+        self
+    }
+
+    /// Sets the policy applied to the destructive operations.
+    pub fn with_safe_mode(mut self, safe_mode: SafeMode) -> Self {
+        self.safe_mode = safe_mode;
+        self
+    }
+
+    /// Allows the destructive operations refused by `SafeMode::AdditiveOnly`.
+    pub fn forced(mut self) -> Self {
+        self.forced = true;
+        self
+    }
+
+    /// Asks on the terminal whether each column dropped while a column of the same type
+    /// is added was renamed. Otherwise only the renames declared with `with_rename` are
+    /// generated, the other columns being dropped and added.
+    pub fn interactive(mut self) -> Self {
+        self.interactive = true;
+        self
+    }
+
+    /// Declares that the column `from` of the model `M` was renamed to `to`, so its data
+    /// is kept instead of the column being dropped and added.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let migrations = Migrations::new("migrations")
+    ///     .with_model::<User>()
+    ///     .with_rename::<User>("nickname", "name");
+    /// ```
+    pub fn with_rename<M: Model>(mut self, from: &str, to: &str) -> Self {
+        self.renames
+            .push((M::NAME.to_string(), from.to_string(), to.to_string()));
+        self
+    }
+
+    /// Registers a callback run before the migration batch of `migrate` and
+    /// `migrate_to`, e.g. to pause the job workers. The batch is not run if it returns
+    /// `false`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let migrations = Migrations::new("migrations")
+    ///     .with_model::<User>()
+    ///     .before(|conn| async move { workers::pause(&conn).await })
+    ///     .after(|conn| async move { ProductStats::refresh(false, &conn).await });
+    /// ```
+    pub fn before<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn(Connection) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        self.before.push(Box::new(move |conn| Box::pin(hook(conn))));
+        self
+    }
+
+    /// Registers a callback run after the migration batch of `migrate` and
+    /// `migrate_to`, whether it succeeded or not (so the paused workers are resumed).
+    pub fn after<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn(Connection) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        self.after.push(Box::new(move |conn| Box::pin(hook(conn))));
+        self
+    }
+
+    fn refuses(&self, destructive: bool) -> bool {
+        destructive && self.safe_mode == SafeMode::AdditiveOnly && !self.forced
+    }
+
+    /// Returns the migration files of the directory, ordered by version.
+    pub fn files(&self) -> Vec<MigrationFile> {
+        let mut files = fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "sql"))
+                    .filter_map(|path| MigrationFile::read(&path))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        files.sort_by_key(|file| file.version);
+        files
+    }
+
+    /// Returns the operations bringing the database schema to the models.
+    ///
+    /// A column dropped while another one is added is renamed if declared with
+    /// `with_rename` (or confirmed in `interactive` mode), so its data is kept. On
+    /// SQLite, the changes it can not alter rebuild the table.
+    pub async fn diff(&self, conn: &Connection) -> Vec<Operation> {
+        self.diff_with(&self.live_state(conn).await)
+    }
+
+    /// Returns the operations bringing the `previous` schema to the models.
+    fn diff_with(&self, previous: &[TableState]) -> Vec<Operation> {
+        let mut operations = Vec::new();
+        for model in &self.models {
+            let start = operations.len();
+            let state = previous
+                .iter()
+                .find(|state| state.table.eq_ignore_ascii_case(&model.table));
+            let (live, live_indexes) = state
+                .map(|state| (state.columns.as_slice(), state.indexes.as_slice()))
+                .unwrap_or_default();
+            let create_index = |column: &String| Operation::CreateIndex {
+                table: model.table.clone(),
+                column: column.clone(),
+            };
+            if live.is_empty() {
+                operations.push(Operation::CreateTable {
+                    table: model.table.clone(),
+                    schema: model.schema.clone(),
+                });
+                operations.extend(model.indexes.iter().map(create_index));
+                continue;
+            }
+
+            for (column, definition) in &model.columns {
+                match live.iter().find(|(c, _)| c.eq_ignore_ascii_case(column)) {
+                    None => operations.push(Operation::AddColumn {
+                        table: model.table.clone(),
+                        column: column.clone(),
+                        definition: definition.clone(),
+                    }),
+                    Some((_, live_definition)) => {
+                        let model_type = normalize_type(&column_type(definition));
+                        let live_type = normalize_type(&column_type(live_definition));
+                        if model_type != live_type {
+                            operations.push(Operation::AlterColumn {
+                                table: model.table.clone(),
+                                column: column.clone(),
+                                from: live_type,
+                                to: model_type,
+                            });
+                        }
+                    }
+                }
+            }
+
+            for (column, live_definition) in live {
+                if !model
+                    .columns
+                    .iter()
+                    .any(|(c, _)| c.eq_ignore_ascii_case(column))
+                {
+                    operations.push(Operation::DropColumn {
+                        table: model.table.clone(),
+                        column: column.clone(),
+                        definition: live_definition.clone(),
+                    });
+                }
+            }
+            self.detect_renames(&mut operations, start);
+
+            if *BACKEND == Backend::Sqlite && operations[start..].iter().any(needs_rebuild) {
+                let renames = operations
+                    .drain(start..)
+                    .filter_map(|operation| match operation {
+                        Operation::RenameColumn { from, to, .. } => Some((from, to)),
+                        _ => None,
+                    })
+                    .collect();
+                operations.push(Operation::RebuildTable {
+                    table: model.table.clone(),
+                    from: live.to_vec(),
+                    to: model.columns.clone(),
+                    renames,
+                });
+                // the indexes are dropped with the rebuilt table
+                operations.extend(model.indexes.iter().map(create_index));
+                continue;
+            }
+
+            for column in &model.indexes {
+                if !live_indexes.iter().any(|c| c.eq_ignore_ascii_case(column)) {
+                    operations.push(create_index(column));
+                }
+            }
+            for column in live_indexes {
+                if !model.indexes.iter().any(|c| c.eq_ignore_ascii_case(column)) {
+                    operations.push(Operation::DropIndex {
+                        table: model.table.clone(),
+                        column: column.clone(),
+                    });
+                }
+            }
+        }
+        operations
+    }
+
+    /// Replaces the drop and add of columns, from `start`, with a rename when it is
+    /// declared, or confirmed in `interactive` mode for the columns of the same type.
+    fn detect_renames(&self, operations: &mut Vec<Operation>, start: usize) {
+        let mut index = start;
+        while index < operations.len() {
+            let Operation::DropColumn {
+                table,
+                column: from,
+                definition: dropped,
+            } = operations[index].clone()
+            else {
+                index += 1;
+                continue;
+            };
+            let dropped_type = normalize_type(&column_type(&dropped));
+            let declared = self.renames.iter().find(|(renamed, column, _)| {
+                renamed.eq_ignore_ascii_case(&table) && column.eq_ignore_ascii_case(&from)
+            });
+            let added = (start..operations.len()).find(|&added| match &operations[added] {
+                Operation::AddColumn {
+                    column, definition, ..
+                } => match declared {
+                    Some((_, _, to)) => column.eq_ignore_ascii_case(to),
+                    None => {
+                        self.interactive && normalize_type(&column_type(definition)) == dropped_type
+                    }
+                },
+                _ => false,
+            });
+            let Some((
+                added,
+                Operation::AddColumn {
+                    column: to,
+                    definition,
+                    ..
+                },
+            )) = added.map(|added| (added, operations[added].clone()))
+            else {
+                index += 1;
+                continue;
+            };
+            let question = format!("Did you rename {table}.{from} to {table}.{to}?");
+            if declared.is_none() && !confirm(&question) {
+                index += 1;
+                continue;
+            }
+            let added_type = normalize_type(&column_type(&definition));
+            operations[index] = Operation::RenameColumn {
+                table: table.clone(),
+                from,
+                to: to.clone(),
+            };
+            operations.remove(added);
+            if added < index {
+                index -= 1;
+            }
+            index += 1;
+            // a declared rename may change the type as well, once renamed
+            if added_type != dropped_type {
+                operations.insert(
+                    index,
+                    Operation::AlterColumn {
+                        table,
+                        column: to,
+                        from: dropped_type,
+                        to: added_type,
+                    },
+                );
+                index += 1;
+            }
+        }
+    }
+
+    /// Returns the schema of the registered models in the database, without the
+    /// missing tables.
+    async fn live_state(&self, conn: &Connection) -> Vec<TableState> {
+        let mut tables = Vec::new();
+        for model in &self.models {
+            let columns = live_columns(&model.table, conn)
+                .await
+                .into_iter()
+                .map(|(column, kind)| {
+                    let definition = format!("{column} {kind}");
+                    (column, definition)
+                })
+                .collect::<Vec<_>>();
+            if !columns.is_empty() {
+                tables.push(TableState {
+                    table: model.table.clone(),
+                    columns,
+                    indexes: live_indexes(&model.table, conn).await,
+                });
+            }
+        }
+        tables
+    }
+
+    /// Writes the schema of the models next to the migration file `path`.
+    fn write_snapshot(&self, path: &Path) -> bool {
+        let tables = self
+            .models
+            .iter()
+            .map(|model| {
+                let columns = model
+                    .columns
+                    .iter()
+                    .map(|(column, definition)| serde_json::json!([column, definition]))
+                    .collect::<Vec<_>>();
+                let table = serde_json::json!({ "columns": columns, "indexes": model.indexes });
+                (model.table.clone(), table)
+            })
+            .collect::<serde_json::Map<_, _>>();
+        let snapshot = serde_json::to_string_pretty(&tables).unwrap_or_default();
+        if let Err(err) = fs::write(path.with_extension("json"), snapshot) {
+            eprintln!("Error during the writing of the snapshot\n->{err}");
+            return false;
+        }
+        true
+    }
+
+    /// Reads the schema snapshot of the last migration, if any.
+    fn read_snapshot(&self) -> Option<Vec<TableState>> {
+        let path = self.files().last()?.path.with_extension("json");
+        let content = fs::read_to_string(path).ok()?;
+        let tables = serde_json::from_str::<serde_json::Map<_, _>>(&content).ok()?;
+        let tables = tables
+            .into_iter()
+            .map(|(table, state)| {
+                // the first snapshots only hold the columns
+                let columns = state.get("columns").unwrap_or(&state);
+                let columns = columns
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|column| {
+                        let column = column.as_array()?;
+                        let name = column.first()?.as_str()?.to_string();
+                        let definition = column.get(1)?.as_str()?.to_string();
+                        Some((name, definition))
+                    })
+                    .collect();
+                let indexes = state
+                    .get("indexes")
+                    .and_then(|indexes| indexes.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|column| Some(column.as_str()?.to_string()))
+                    .collect();
+                TableState {
+                    table,
+                    columns,
+                    indexes,
+                }
+            })
+            .collect();
+        Some(tables)
+    }
+
+    /// Writes the operations bringing the schema to the models in a new migration
+    /// file, along with a JSON snapshot of the models schema.
+    ///
+    /// The models are diffed against the snapshot of the last migration, so no
+    /// database is needed. Without snapshot the models are diffed against the
+    /// database `conn`, or against an empty database when it is `None`.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the migration.
+    /// * `conn` - The database connection, used when there is no snapshot.
+    ///
+    /// # Returns
+    /// The path of the migration file, or `None` if there is no change, if the safe
+    /// mode refuses a destructive operation or if the file can not be written.
+    ///
+    /// # Example
+    /// ```ignore
+    /// if let Some(path) = migrations.makemigrations("add_age", None).await {
+    ///     println!("Created {}", path.display());
+    /// }
+    /// ```
+    pub async fn makemigrations(&self, name: &str, conn: Option<&Connection>) -> Option<PathBuf> {
+        let previous = match (self.read_snapshot(), conn) {
+            (Some(snapshot), _) => snapshot,
+            (None, Some(conn)) => self.live_state(conn).await,
+            (None, None) => Vec::new(),
+        };
+        let operations = self.diff_with(&previous);
+        if operations.is_empty() {
+            println!("No changes detected");
+            return None;
+        }
+
+        let destructive = operations.iter().any(Operation::is_destructive);
+        if self.refuses(destructive) {
+            for operation in operations.iter().filter(|op| op.is_destructive()) {
+                eprintln!("Refused by the additive-only safe mode\n->{operation:?}");
+            }
+            return None;
+        }
+
+        let version = self.files().last().map_or(1, |file| file.version + 1);
+        let file = MigrationFile {
+            version,
+            name: name.to_string(),
+            path: self.dir.join(format!("{version:04}_{name}.sql")),
+            destructive,
+            replaces: None,
+            up: operations.iter().flat_map(Operation::up).collect(),
+            down: operations.iter().rev().flat_map(Operation::down).collect(),
+        };
+        (file.write() && self.write_snapshot(&file.path)).then_some(file.path)
+    }
+
+    /// Collapses the migrations from version `first` to `last` into one equivalent
+    /// migration, which takes the version of the last migration squashed.
+    ///
+    /// The databases where the squashed migrations are applied consider the new one
+    /// applied, so squash migrations which are applied everywhere (a database where
+    /// only some of them are applied can not run the new one).
+    ///
+    /// # Arguments
+    /// * `first` - The version of the first migration to squash.
+    /// * `last` - The version of the last migration to squash.
+    /// * `name` - The name of the new migration.
+    ///
+    /// # Returns
+    /// The path of the new migration file, or `None` if there are less than two
+    /// migrations in the range or if the file can not be written.
+    ///
+    /// # Example
+    /// ```ignore
+    /// migrations.squash(1, 42, "initial");
+    /// ```
+    pub fn squash(&self, first: i32, last: i32, name: &str) -> Option<PathBuf> {
+        let files = self
+            .files()
+            .into_iter()
+            .filter(|file| (first..=last).contains(&file.version))
+            .collect::<Vec<_>>();
+        if files.len() < 2 {
+            eprintln!("Nothing to squash between {first} and {last}");
+            return None;
+        }
+
+        let first = files[0]
+            .replaces
+            .map_or(first, |(replaced, _)| replaced.min(first));
+        // the databases where the squashed migrations are applied have applied this one
+        let last = files[files.len() - 1].version;
+        let squashed = MigrationFile {
+            version: last,
+            name: name.to_string(),
+            path: self.dir.join(format!("{last:04}_{name}.sql")),
+            destructive: files.iter().any(MigrationFile::is_destructive),
+            replaces: Some((first, last)),
+            up: files.iter().flat_map(|file| file.up.clone()).collect(),
+            down: files
+                .iter()
+                .rev()
+                .flat_map(|file| file.down.clone())
+                .collect(),
+        };
+        if !squashed.write() {
+            return None;
+        }
+        let snapshot = files[files.len() - 1].path.with_extension("json");
+        if snapshot.exists() {
+            if let Err(err) = fs::rename(&snapshot, squashed.path.with_extension("json")) {
+                eprintln!("Error during the squash\n->{err}");
+                return None;
+            }
+        }
+        for file in files.iter().filter(|file| file.path != squashed.path) {
+            let snapshot = file.path.with_extension("json");
+            let mut removed = fs::remove_file(&file.path);
+            if snapshot.exists() {
+                removed = removed.and_then(|_| fs::remove_file(&snapshot));
+            }
+            if let Err(err) = removed {
+                eprintln!("Error during the squash\n->{err}");
+                return None;
+            }
+        }
+        Some(squashed.path)
+    }
+
+    /// Returns the versions of the applied migrations.
+    pub async fn applied(&self, conn: &Connection) -> Vec<i32> {
+        if let Err(err) = AppliedMigration::migrate(conn).await {
+            eprintln!("Error during the migration\n->{err}");
+        }
+        AppliedMigration::all(conn)
+            .await
+            .into_iter()
+            .map(|migration| migration.version)
+            .collect()
+    }
+
+    /// Runs the migration files not applied yet, each in a transaction.
+    ///
+    /// # Arguments
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// `true` if every pending migration was applied, `false` otherwise (the following
+    /// migrations are not run).
+    pub async fn migrate(&self, conn: &Connection) -> bool {
+        self.migrate_to(i32::MAX, conn).await
+    }
+
+    /// Brings the database to the migration `version`: the pending migrations up to
+    /// `version` are applied, the applied migrations after it are reverted (newest
+    /// first) with their `down` statements. The `before` and `after` hooks run around
+    /// the batch, when there is a migration to run.
+    ///
+    /// # Arguments
+    /// * `version` - The version of the target migration, `0` to revert them all.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    /// `true` if the database reached `version`, `false` otherwise.
+    ///
+    /// # Example
+    /// ```ignore
+    /// migrations.migrate_to(3, &conn).await;
+    /// ```
+    pub async fn migrate_to(&self, version: i32, conn: &Connection) -> bool {
+        let applied = self.applied(conn).await;
+        let files = self.files();
+        let pending = files.iter().any(|file| {
+            let is_applied = applied.contains(&file.version);
+            (file.version <= version) != is_applied
+        });
+        if !pending {
+            return true;
+        }
+
+        for hook in &self.before {
+            if !hook(conn.clone()).await {
+                eprintln!("The migrations are canceled by a `before` hook");
+                return false;
+            }
+        }
+        let success = self.run_batch(version, &applied, &files, conn).await;
+        let mut hooks_success = true;
+        for hook in &self.after {
+            hooks_success &= hook(conn.clone()).await;
+        }
+        success && hooks_success
+    }
+
+    async fn run_batch(
+        &self,
+        version: i32,
+        applied: &[i32],
+        files: &[MigrationFile],
+        conn: &Connection,
+    ) -> bool {
+        for file in files.iter().rev() {
+            if file.version <= version || !applied.contains(&file.version) {
+                continue;
+            }
+            if self.refuses(drops_data(&file.down)) {
+                eprintln!(
+                    "Refused by the additive-only safe mode\n->{}",
+                    file.path.display()
+                );
+                return false;
+            }
+            println!("Reverting {:04}_{}", file.version, file.name);
+            if !run(file, &file.down, Record::Delete, conn).await {
+                return false;
+            }
+        }
+
+        for file in files {
+            if file.version > version || applied.contains(&file.version) {
+                continue;
+            }
+            if let Some((first, last)) = file.replaces {
+                if applied
+                    .iter()
+                    .any(|version| (first..=last).contains(version))
+                {
+                    eprintln!(
+                        "The migrations squashed into {} are partially applied, apply them before squashing",
+                        file.path.display()
+                    );
+                    return false;
+                }
+            }
+            if self.refuses(file.is_destructive()) {
+                eprintln!(
+                    "Refused by the additive-only safe mode\n->{}",
+                    file.path.display()
+                );
+                return false;
+            }
+            println!("Applying {:04}_{}", file.version, file.name);
+            if !run(file, &file.up, Record::Insert, conn).await {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Records the pending migrations up to `version` as applied without running
+    /// them, to adopt a database whose schema is already up to date.
+    ///
+    /// # Example
+    /// ```ignore
+    /// migrations.fake(1, &conn).await;
+    /// ```
+    pub async fn fake(&self, version: i32, conn: &Connection) -> bool {
+        let applied = self.applied(conn).await;
+        for file in self.files() {
+            if file.version > version || applied.contains(&file.version) {
+                continue;
+            }
+            println!("Faking {:04}_{}", file.version, file.name);
+            if !run(&file, &[], Record::Insert, conn).await {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Runs a migration command, to build a command line tool on top of the
+    /// migrations:
+    ///
+    /// * `makemigrations <name>`
+    /// * `migrate [--fake <version> | --to <version>]`
+    /// * `squash <first> <last> <name>`
+    /// * `new-model <Name> <field:type>...`, writing the model in `src/models`, see
+    ///   `scaffold::model_source`
+    ///
+    /// # Returns
+    /// `true` if the command succeeded, `false` otherwise.
+    ///
+    /// # Example
+    /// ```ignore
+    /// // src/bin/migrate.rs
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let conn = Database::new().await.conn;
+    ///     let args = std::env::args().skip(1).collect::<Vec<_>>();
+    ///     let migrations = Migrations::new("migrations").with_model::<User>();
+    ///     if !migrations.command(&args, &conn).await {
+    ///         std::process::exit(1);
+    ///     }
+    /// }
+    /// ```
+    pub async fn command(&self, args: &[String], conn: &Connection) -> bool {
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        let version = |version: &str| version.parse::<i32>().ok();
+        match args.as_slice() {
+            ["makemigrations", name] => {
+                return self.makemigrations(name, Some(conn)).await.is_some()
+            }
+            ["migrate"] => return self.migrate(conn).await,
+            ["migrate", "--fake", target] => {
+                if let Some(target) = version(target) {
+                    return self.fake(target, conn).await;
+                }
+            }
+            ["migrate", "--to", target] => {
+                if let Some(target) = version(target) {
+                    return self.migrate_to(target, conn).await;
+                }
+            }
+            ["squash", first, last, name] => {
+                if let (Some(first), Some(last)) = (version(first), version(last)) {
+                    return self.squash(first, last, name).is_some();
+                }
+            }
+            ["new-model", name, fields @ ..] => {
+                return crate::scaffold::new_model("src/models", name, fields)
+            }
+            _ => {}
+        }
+        eprintln!(
+            "Usage:\n  makemigrations <name>\n  migrate [--fake <version> | --to <version>]\n  squash <first> <last> <name>\n  new-model <Name> <field:type>..."
+        );
+        false
+    }
+}
+
+enum Record {
+    Insert,
+    Delete,
+}
+
+/// Runs `statements` of a migration and records it as applied (or reverted) in one
+/// transaction.
+///
+/// The Postgres `concurrently` index statements can not run in a transaction, so the
+/// migrations holding one run statement by statement, retrying those.
+async fn run(
+    file: &MigrationFile,
+    statements: &[String],
+    record: Record,
+    conn: &Connection,
+) -> bool {
+    let placeholder = PLACEHOLDER.to_string();
+    let table_name = quote_ident(AppliedMigration::NAME);
+    let online = statements.iter().any(|statement| is_concurrent(statement));
+
+    let result = async {
+        let mut tx = conn.begin().await?;
+        for statement in statements {
+            if online {
+                execute_with_retry(statement, conn).await?;
+            } else {
+                sqlx::query(statement).execute(&mut *tx).await?;
+            }
+        }
+        match record {
+            Record::Insert => {
+                let query = format!(
+                    "insert into {table_name} (version, name) values ({placeholder}1, {placeholder}2);"
+                );
+                sqlx::query(&query)
+                    .bind(file.version)
+                    .bind(file.name.clone())
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            Record::Delete => {
+                let query = format!("delete from {table_name} where version={placeholder}1;");
+                sqlx::query(&query)
+                    .bind(file.version)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+        tx.commit().await
+    }
+    .await;
+
+    if let Err(err) = result {
+        eprintln!("Error during the migration\n->{err}");
+        return false;
+    }
+    if *BACKEND == Backend::Sqlite {
+        reload_schema(conn).await;
+    }
+    true
+}
+
+fn is_concurrent(statement: &str) -> bool {
+    statement.to_lowercase().contains(" index concurrently ")
+}
+
+/// Executes `statement`, retrying a failed concurrent index build (which leaves an
+/// invalid index behind, dropped before the next attempt).
+async fn execute_with_retry(statement: &str, conn: &Connection) -> Result<(), sqlx::Error> {
+    const ATTEMPTS: usize = 3;
+
+    let mut attempt = 1;
+    loop {
+        let err = match sqlx::query(statement).execute(conn).await {
+            Ok(_) => return Ok(()),
+            Err(err) => err,
+        };
+        let lowered = statement.to_lowercase();
+        if attempt == ATTEMPTS || !lowered.starts_with("create index concurrently") {
+            return Err(err);
+        }
+        eprintln!("Error during the migration, retrying\n->{err}");
+        if let Some(name) = lowered.split_whitespace().nth(6) {
+            let drop = format!("drop index concurrently if exists {name};");
+            sqlx::query(&drop).execute(conn).await?;
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(attempt as u64)).await;
+        attempt += 1;
+    }
+}
+
+/// Makes the idle SQLite connections of the pool read the new schema, otherwise they
+/// report the columns of a rebuilt table as they were before the migration.
+async fn reload_schema(conn: &Connection) {
+    let mut connections = Vec::new();
+    while let Some(connection) = conn.try_acquire() {
+        connections.push(connection);
+    }
+    for connection in &mut connections {
+        let query = "select count(*) from sqlite_master;";
+        sqlx::query(query).execute(&mut **connection).await.ok();
+    }
+}
+
+/// Returns `true` if one of `statements` drops a table (which is not replaced by a
+/// rebuilt one) or a column.
+fn drops_data(statements: &[String]) -> bool {
+    let statements = statements
+        .iter()
+        .map(|statement| statement.to_lowercase())
+        .collect::<Vec<_>>();
+    statements.iter().any(|statement| {
+        let rebuilt = |table: &str| {
+            statements.iter().any(|s| {
+                s.starts_with("alter table ") && s.ends_with(&format!(" rename to {table};"))
+            })
+        };
+        match statement.strip_prefix("drop table ") {
+            Some(table) => !rebuilt(table.trim_end_matches(';')),
+            None => statement.contains(" drop column "),
+        }
+    })
+}
+
+/// Returns `true` if SQLite can not run the operation with an `alter table`.
+fn needs_rebuild(operation: &Operation) -> bool {
+    match operation {
+        Operation::AlterColumn { .. } => true,
+        Operation::AddColumn { definition, .. } => {
+            let definition = definition.to_lowercase();
+            definition.contains("primary key")
+                || definition.contains("unique")
+                || (definition.contains("not null") && !definition.contains(" default "))
+        }
+        Operation::DropColumn { definition, .. } => {
+            let definition = definition.to_lowercase();
+            definition.contains("primary key")
+                || definition.contains("unique")
+                || definition.contains("references")
+        }
+        _ => false,
+    }
+}
+
+/// Recreates `table` with the `to` columns, copying the rows of the columns kept (or
+/// renamed) from the `from` columns.
+fn rebuild_table(
+    table: &str,
+    from: &[(String, String)],
+    to: &[(String, String)],
+    renames: &[(String, String)],
+) -> Vec<String> {
+    let (targets, sources): (Vec<_>, Vec<_>) = to
+        .iter()
+        // the generated columns are computed by the new table
+        .filter(|(_, definition)| !definition.contains(" generated always as "))
+        .filter_map(|(column, _)| {
+            let source = renames
+                .iter()
+                .find(|(_, renamed)| renamed == column)
+                .map(|(source, _)| source)
+                .or_else(|| from.iter().map(|(c, _)| c).find(|c| *c == column))?;
+            Some((column.as_str(), source.as_str()))
+        })
+        .unzip();
+    let definitions = to
+        .iter()
+        .map(|(_, definition)| definition.as_str())
+        .collect::<Vec<_>>();
+    let new = quote_ident(&format!("{table}__new"));
+    let table = quote_ident(table);
+    vec![
+        "pragma defer_foreign_keys = on;".to_string(),
+        format!("create table {new} ({});", definitions.join(", ")),
+        format!(
+            "insert into {new} ({}) select {} from {table};",
+            targets.join(", "),
+            sources.join(", ")
+        ),
+        format!("drop table {table};"),
+        format!("alter table {new} rename to {table};"),
+    ]
+}
+
+/// Asks a yes/no question on the terminal.
+fn confirm(question: &str) -> bool {
+    print!("{question} [y/N] ");
+    std::io::stdout().flush().ok();
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).ok();
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// Splits the section of a migration file into its statements.
+fn statements(section: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut statement = String::new();
+    for line in section.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("--") {
+            continue;
+        }
+        if !statement.is_empty() {
+            statement.push(' ');
+        }
+        statement.push_str(line);
+        if line.ends_with(';') {
+            statements.push(std::mem::take(&mut statement));
+        }
+    }
+    if !statement.is_empty() {
+        statements.push(statement);
+    }
+    statements
+}
+
+/// Returns the (column, type) pairs of a table, empty if the table does not exist.
+pub(crate) async fn live_columns(table: &str, conn: &Connection) -> Vec<(String, String)> {
+    let placeholder = PLACEHOLDER.to_string();
+    let query = match *BACKEND {
+        Backend::Sqlite => {
+            // the `x` variant lists the generated columns as well
+            format!("select name, type from pragma_table_xinfo({placeholder}1);")
+        }
+        Backend::Postgres => format!(
+            "select column_name::text, case \
+             when data_type = 'character varying' then 'varchar(' || character_maximum_length || ')' \
+             when data_type = 'USER-DEFINED' then udt_name::text \
+             else data_type::text end \
+             from information_schema.columns where table_name = {placeholder}1 \
+             order by ordinal_position;"
+        ),
+        Backend::MySql => format!(
+            "select cast(column_name as char), cast(column_type as char) \
+             from information_schema.columns \
+             where table_schema = database() and table_name = {placeholder}1 \
+             order by ordinal_position;"
+        ),
+    };
+    match sqlx::query(&query).bind(table).fetch_all(conn).await {
+        Ok(rows) => rows
+            .iter()
+            .map(|row| (row.get::<String, _>(0), row.get::<String, _>(1)))
+            .collect(),
+        Err(err) => {
+            eprintln!("Error during the introspection\n->{err}");
+            Vec::new()
+        }
+    }
+}
+
+/// Returns the type of a column definition, e.g. `varchar(255)` for
+/// `name varchar(255) not null`.
+pub(crate) fn column_type(definition: &str) -> String {
+    definition
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Maps the spellings of a type across the backends to the one used by the models.
+fn normalize_type(kind: &str) -> String {
+    let kind = kind.to_lowercase().replace(' ', "");
+    match kind.as_str() {
+        "serial" | "int" | "int4" | "int(11)" => "integer".to_string(),
+        "int8" | "bigserial" => "bigint".to_string(),
+        "doubleprecision" | "double" | "float8" | "real" | "float4" => "float".to_string(),
+        _ => kind,
+    }
+}
+
+/// Returns `true` if every value of the `from` type fits in the `to` type.
+fn is_widening(from: &str, to: &str) -> bool {
+    let size = |kind: &str| {
+        kind.strip_prefix("varchar(")
+            .and_then(|size| size.strip_suffix(')'))
+            .and_then(|size| size.parse::<u32>().ok())
+    };
+    match (size(from), size(to)) {
+        (Some(from), Some(to)) => to >= from,
+        _ => matches!(
+            (from, to),
+            ("integer", "bigint") | ("integer", "float") | (_, "text")
+        ),
+    }
+}
+
+/// The statement creating the index of `column` without locking the writes to the
+/// table: concurrently on Postgres, with the online DDL on MySQL.
+fn create_index(table: &str, column: &str) -> String {
+    let name = index_name(table, column);
+    let table = quote_ident(table);
+    match *BACKEND {
+        Backend::Postgres => {
+            format!("create index concurrently if not exists {name} on {table} ({column});")
+        }
+        Backend::MySql => {
+            format!("create index {name} on {table} ({column}) algorithm=inplace lock=none;")
+        }
+        Backend::Sqlite => format!("create index if not exists {name} on {table} ({column});"),
+    }
+}
+
+fn drop_index(table: &str, column: &str) -> String {
+    let name = index_name(table, column);
+    let table = quote_ident(table);
+    match *BACKEND {
+        Backend::Postgres => format!("drop index concurrently if exists {name};"),
+        Backend::MySql => format!("drop index {name} on {table};"),
+        Backend::Sqlite => format!("drop index if exists {name};"),
+    }
+}
+
+/// Returns the indexed columns of a table, among the indexes named after
+/// `index_name`.
+async fn live_indexes(table: &str, conn: &Connection) -> Vec<String> {
+    let placeholder = PLACEHOLDER.to_string();
+    let query = match *BACKEND {
+        Backend::Sqlite => format!(
+            "select name from sqlite_master where type = 'index' and tbl_name = {placeholder}1;"
+        ),
+        Backend::Postgres => {
+            format!("select indexname::text from pg_indexes where tablename = {placeholder}1;")
+        }
+        Backend::MySql => format!(
+            "select distinct cast(index_name as char) from information_schema.statistics \
+             where table_schema = database() and table_name = {placeholder}1;"
+        ),
+    };
+    let names = sqlx::query_scalar::<_, String>(&query)
+        .bind(table)
+        .fetch_all(conn)
+        .await
+        .unwrap_or_default();
+    names
+        .into_iter()
+        .filter_map(|name| index_column(table, &name))
+        .collect()
+}
+
+fn alter_column(table: &str, column: &str, kind: &str) -> String {
+    let table = quote_ident(table);
+    match *BACKEND {
+        Backend::Postgres => {
+            format!("alter table {table} alter column {column} type {kind} using {column}::{kind};")
+        }
+        _ => format!("alter table {table} modify column {column} {kind};"),
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::RwLock;
+
+use crate::db::models::{Model, BACKEND};
+use crate::Backend;
+
+static STRATEGY: RwLock<NamingStrategy> = RwLock::new(NamingStrategy::DEFAULT);
+
+/// The templates of the names of the indexes and constraints created by the
+/// migrations, so that later migrations can reference and drop them by name.
+///
+/// The templates replace `{table}` by the table, `{column}` by the column and `{target}`
+/// by the referenced table, the names being lowercased.
+///
+/// # Example
+/// ```ignore
+/// rusql_alchemy::set_naming_strategy(NamingStrategy {
+///     index: "ix_{table}_{column}",
+///     ..Default::default()
+/// });
+/// // `create index if not exists ix_users_email on "users" (email);`
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NamingStrategy {
+    /// The name of the index of a column, `{table}_{column}_idx` by default.
+    pub index: &'static str,
+    /// The name of the unique constraint of a column, `ux_{table}_{column}` by default.
+    pub unique: &'static str,
+    /// The name of the foreign key of a column, `fk_{table}_{column}_{target}` by default.
+    pub foreign_key: &'static str,
+}
+
+impl NamingStrategy {
+    const DEFAULT: Self = Self {
+        index: "{table}_{column}_idx",
+        unique: "ux_{table}_{column}",
+        foreign_key: "fk_{table}_{column}_{target}",
+    };
+}
+
+impl Default for NamingStrategy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Sets the names of the indexes and constraints created from now on, see
+/// `NamingStrategy`.
+pub fn set_naming_strategy(strategy: NamingStrategy) {
+    *STRATEGY.write().unwrap_or_else(|err| err.into_inner()) = strategy;
+}
+
+fn strategy() -> NamingStrategy {
+    *STRATEGY.read().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Quotes the table `name`, so the tables named after a reserved word (`order`, `group`,
+/// `user`) can be queried.
+pub(crate) fn quote_ident(name: &str) -> String {
+    match *BACKEND {
+        Backend::MySql => format!("`{name}`"),
+        _ => format!("\"{name}\""),
+    }
+}
+
+/// The column `definition` of `T` referencing the table of its foreign key, the derive
+/// only knowing the name of the referenced model.
+pub(crate) fn resolve_references<T: Model>(column: &str, definition: &str) -> String {
+    let key = T::FOREIGN_KEYS.iter().find(|key| key.field == column);
+    match (definition.split_once(" references "), key) {
+        (Some((definition, references)), Some(key)) => {
+            let rest = references.split_once(')').map_or("", |(_, rest)| rest);
+            let table = quote_ident(key.table);
+            format!("{definition} references {table} ({}){rest}", key.column)
+        }
+        _ => definition.to_string(),
+    }
+}
+
+fn render(template: &str, table: &str, column: &str, target: &str) -> String {
+    template
+        .replace("{table}", table)
+        .replace("{column}", column)
+        .replace("{target}", target)
+        .to_lowercase()
+}
+
+/// The name of the index of `column`.
+pub(crate) fn index_name(table: &str, column: &str) -> String {
+    render(strategy().index, table, column, "")
+}
+
+/// The column of the index `name` of `table`, `None` if the name does not follow the
+/// naming strategy.
+pub(crate) fn index_column(table: &str, name: &str) -> Option<String> {
+    let template = render(strategy().index, table, "{column}", "");
+    let (prefix, suffix) = template.split_once("{column}")?;
+    let column = name.to_lowercase();
+    let column = column.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    Some(column.to_string())
+}
+
+/// Splits the column `definition` of `table` into the definition without its `unique`
+/// and `references` constraints, and these constraints as named table constraints.
+pub(crate) fn named_constraints(
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> (String, Vec<String>) {
+    let strategy = strategy();
+    let mut constraints = Vec::new();
+
+    let (definition, references) = match definition.split_once(" references ") {
+        Some((definition, references)) => (definition.to_string(), Some(references.trim())),
+        None => (definition.to_string(), None),
+    };
+    let mut words = definition.split(' ').collect::<Vec<_>>();
+    // the constraints precede the default value, which may contain the same words
+    let unique = words
+        .iter()
+        .take_while(|word| **word != "default")
+        .position(|word| *word == "unique");
+    if let Some(index) = unique {
+        words.remove(index);
+        let name = render(strategy.unique, table, column, "");
+        constraints.push(format!("constraint {name} unique ({column})"));
+    }
+    if let Some(references) = references {
+        let target = references.split([' ', '(']).next().unwrap_or_default();
+        let target = target.trim_matches(['"', '`']);
+        let name = render(strategy.foreign_key, table, column, target);
+        constraints.push(format!(
+            "constraint {name} foreign key ({column}) references {references}"
+        ));
+    }
+    (words.join(" "), constraints)
+}
@@ -0,0 +1,125 @@
+//! The transactional outbox: events are written in the same transaction as the model
+//! changes they describe, then published to the message broker by a relay task, so an
+//! event is never lost nor published for a rolled back change.
+
+use std::{future::Future, time::Duration};
+
+use crate::naming::quote_ident;
+use crate::prelude::*;
+use crate::{track_query, Error};
+
+/// An event waiting in (or delivered from) the outbox table.
+#[derive(FromRow, Clone, Debug, Default, Model)]
+#[model(table = "outbox_events")]
+pub struct OutboxEvent {
+    #[model(primary_key = true, auto = true, null = false)]
+    pub id: Integer,
+    #[model(size = 255, null = false)]
+    pub topic: String,
+    #[model(null = false)]
+    pub payload: Text,
+    #[model(default = false)]
+    pub delivered: Boolean,
+    #[model(default = "now")]
+    pub created_at: DateTime,
+}
+
+/// Enqueues an event in the outbox.
+///
+/// Within an `atomic` block on `conn`, the event is inserted in its transaction, so it
+/// is only enqueued if the model writes of the block are committed.
+///
+/// # Arguments
+/// * `topic` - The topic the event is published to.
+/// * `payload` - The content of the event.
+/// * `conn` - The database connection.
+///
+/// # Returns
+/// `Ok` if the event is enqueued, the error otherwise.
+///
+/// # Example
+/// ```ignore
+/// atomic(&conn, async {
+///     product.update_fields(kwargs!(price = 10.0), &conn).await?;
+///     outbox::enqueue("product.updated", &json!({ "id": product.id }), &conn).await?;
+///     Ok::<_, Error>(())
+/// })
+/// .await?;
+/// ```
+pub async fn enqueue(
+    topic: &str,
+    payload: &serde_json::Value,
+    conn: &Connection,
+) -> Result<(), Error> {
+    OutboxEvent::create(kwargs!(topic = topic, payload = payload.to_string()), conn).await
+}
+
+/// Publishes the pending events, oldest first, and marks them delivered.
+///
+/// An event is marked delivered only if `publish` returns `true`, so a failed event is
+/// published again by the next relay (the delivery is at least once). Run a single
+/// relay at a time.
+///
+/// # Arguments
+/// * `conn` - The database connection.
+/// * `batch_size` - The maximum number of events to publish.
+/// * `publish` - Publishes one event to the message broker.
+///
+/// # Returns
+/// The number of delivered events.
+///
+/// # Example
+/// ```ignore
+/// let delivered = outbox::relay(&conn, 100, |event| async move {
+///     broker.send(&event.topic, &event.payload).await.is_ok()
+/// })
+/// .await;
+/// ```
+pub async fn relay<F, Fut>(conn: &Connection, batch_size: usize, publish: F) -> usize
+where
+    F: Fn(OutboxEvent) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let query = format!(
+        "select * from {table_name} where delivered=0 order by id limit {batch_size};",
+        table_name = quote_ident(OutboxEvent::NAME)
+    );
+    let stream = sqlx::query_as::<_, OutboxEvent>(&query);
+    let events = track_query(&query, on_conn!(conn, stream, fetch_all))
+        .await
+        .and_then(Result::ok)
+        .unwrap_or_default();
+
+    let mut delivered = 0;
+    for event in events {
+        let id = event.id;
+        if !publish(event).await {
+            break;
+        }
+        if OutboxEvent::update_where(kwargs!(id == id), kwargs!(delivered = true), conn)
+            .await
+            .is_ok()
+        {
+            delivered += 1;
+        }
+    }
+    delivered
+}
+
+/// Runs `relay` forever, waiting `interval` when the outbox is empty.
+///
+/// # Example
+/// ```ignore
+/// tokio::spawn(outbox::run_relay(conn.clone(), Duration::from_secs(1), 100, publish));
+/// ```
+pub async fn run_relay<F, Fut>(conn: Connection, interval: Duration, batch_size: usize, publish: F)
+where
+    F: Fn(OutboxEvent) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    loop {
+        if relay(&conn, batch_size, &publish).await == 0 {
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
@@ -4,7 +4,11 @@ pub use super::types::Serial;
 pub use super::types::*;
 pub use super::Connection;
 pub use super::Database;
-pub use super::{db::models::*, kwargs, migrate};
+pub use super::Savepoint;
+pub use super::{
+    db::aggregate::*, db::expression::*, db::models::*, db::queryset::*, db::store::*, kwargs,
+    migrate, raw, Q,
+};
 pub use async_trait::async_trait;
 pub use rusql_alchemy_macro::Model;
 pub use sqlx::FromRow;
@@ -0,0 +1,193 @@
+use fastrand::Rng;
+use sqlx::{any::AnyRow, FromRow};
+
+use crate::db::models::{pk_columns, Model};
+use crate::Connection;
+
+/// A type whose values can be generated at random, the fields of the models checked by
+/// `assert_round_trip`.
+///
+/// # Example
+/// ```ignore
+/// impl Arbitrary for User {
+///     fn arbitrary(rng: &mut Rng) -> Self {
+///         Self {
+///             id: 0, // set by the database
+///             name: String::arbitrary(rng),
+///             age: Integer::arbitrary(rng),
+///             weight: Float::arbitrary(rng),
+///             // the other columns hold strings of a given format
+///             birthday: format!("{}-0{}-1{}", rng.u16(1000..3000), rng.u8(1..10), rng.u8(0..10)),
+///         }
+///     }
+/// }
+/// ```
+pub trait Arbitrary: Sized {
+    fn arbitrary(rng: &mut Rng) -> Self;
+}
+
+/// Picks one of the `edges` a time out of four, so the limits are checked.
+fn edge_or<T: Copy>(rng: &mut Rng, edges: &[T], random: impl FnOnce(&mut Rng) -> T) -> T {
+    if rng.u8(..4) == 0 {
+        edges[rng.usize(..edges.len())]
+    } else {
+        random(rng)
+    }
+}
+
+impl Arbitrary for i32 {
+    fn arbitrary(rng: &mut Rng) -> Self {
+        edge_or(rng, &[0, 1, -1, i32::MIN, i32::MAX], |rng| rng.i32(..))
+    }
+}
+
+impl Arbitrary for i64 {
+    fn arbitrary(rng: &mut Rng) -> Self {
+        edge_or(rng, &[0, 1, -1, i64::MIN, i64::MAX], |rng| rng.i64(..))
+    }
+}
+
+impl Arbitrary for f64 {
+    fn arbitrary(rng: &mut Rng) -> Self {
+        edge_or(rng, &[0.0, 1.5, -1.0, 1e-10, 1e15], |rng| {
+            (rng.f64() - 0.5) * 10f64.powi(rng.i32(-6..12))
+        })
+    }
+}
+
+impl Arbitrary for bool {
+    fn arbitrary(rng: &mut Rng) -> Self {
+        rng.bool()
+    }
+}
+
+impl Arbitrary for String {
+    fn arbitrary(rng: &mut Rng) -> Self {
+        // the strings a naive encoding would mistake for quoted strings, numbers,
+        // booleans, JSON or patterns
+        const EDGES: &[&str] = &[
+            "",
+            "'",
+            "\"quoted\"",
+            "\\",
+            "null",
+            "true",
+            "1",
+            "{\"key\": [1]}",
+            "100% _done_!",
+            "ünïcødé ✓",
+        ];
+        if rng.u8(..4) == 0 {
+            return EDGES[rng.usize(..EDGES.len())].to_string();
+        }
+        let len = rng.usize(..40);
+        (0..len)
+            .map(|_| match rng.u8(..8) {
+                0 => rng.choice(['\'', '"', '\\', '%', '_', ' ']).unwrap_or(' '),
+                1 => rng.char('\u{a0}'..'\u{2fff}'),
+                _ => rng.alphanumeric(),
+            })
+            .collect()
+    }
+}
+
+impl Arbitrary for Vec<u8> {
+    fn arbitrary(rng: &mut Rng) -> Self {
+        // the bytes a text encoding would alter
+        const EDGES: &[&[u8]] = &[b"", b"\0", b"\xff\xfe", b"'", b"null"];
+        if rng.u8(..4) == 0 {
+            return EDGES[rng.usize(..EDGES.len())].to_vec();
+        }
+        let len = rng.usize(..64);
+        (0..len).map(|_| rng.u8(..)).collect()
+    }
+}
+
+impl<T: Arbitrary> Arbitrary for Option<T> {
+    fn arbitrary(rng: &mut Rng) -> Self {
+        if rng.u8(..4) == 0 {
+            None
+        } else {
+            Some(T::arbitrary(rng))
+        }
+    }
+}
+
+/// Saves `cases` instances of `M` generated at random, reads each of them back and
+/// panics if a field changed on the way, e.g. a string losing its quotes or a float
+/// losing its precision on the active backend. The fields set by the database (auto
+/// primary keys and defaults) are not compared, and the instances are deleted once
+/// checked.
+///
+/// The instances are generated from the seed set by the `RUSQL_SEED` environment
+/// variable, a random one otherwise, printed on failure to reproduce it.
+///
+/// # Panics
+/// If an instance cannot be saved or read back, or a field differs.
+///
+/// # Example
+/// ```ignore
+/// #[tokio::test]
+/// async fn user_round_trip() {
+///     let conn = Database::new().await.conn;
+///     migrate!([User], &conn);
+///     assert_round_trip::<User>(100, &conn).await;
+/// }
+/// ```
+pub async fn assert_round_trip<M>(cases: usize, conn: &Connection)
+where
+    M: Model + Arbitrary + Unpin + for<'r> FromRow<'r, AnyRow> + Clone + Send + Sync,
+{
+    let seed = std::env::var("RUSQL_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or_else(|| fastrand::u64(..));
+    let mut rng = Rng::with_seed(seed);
+    let generated_pk = pk_columns::<M>()
+        .iter()
+        .any(|column| M::GENERATED.contains(column));
+    let latest = pk_columns::<M>()
+        .iter()
+        .map(|column| format!("-{column}"))
+        .collect::<Vec<_>>();
+    let latest = latest.iter().map(String::as_str).collect::<Vec<_>>();
+
+    for _ in 0..cases {
+        let instance = M::arbitrary(&mut rng);
+        if let Err(err) = instance.save(conn).await {
+            panic!(
+                "The {} instance could not be saved (RUSQL_SEED={seed})\n->{err}\n{}",
+                M::NAME,
+                instance.redacted()
+            );
+        }
+        // the instance saved with a generated key is the latest one
+        let saved = if generated_pk {
+            M::objects().order_by(&latest).first(conn).await
+        } else {
+            M::get_by_pk(instance.pk(), conn).await
+        };
+        let Some(saved) = saved else {
+            panic!(
+                "The saved {} instance could not be read back (RUSQL_SEED={seed})\n{}",
+                M::NAME,
+                instance.redacted()
+            );
+        };
+        let _ = saved.delete(conn).await;
+
+        let changes = instance
+            .diff(&saved)
+            .into_iter()
+            .filter(|change| !M::GENERATED.contains(&change.field))
+            .map(|change| format!("{}: {} -> {}", change.field, change.old, change.new))
+            .collect::<Vec<_>>();
+        if !changes.is_empty() {
+            panic!(
+                "The {} instance changed on the round trip (RUSQL_SEED={seed})\n{}",
+                M::NAME,
+                changes.join("\n")
+            );
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::{future::Future, pin::Pin};
+
+use sqlx::AnyConnection;
+
+use crate::transaction;
+
+/// The operation run within a savepoint, on the connection of the transaction.
+pub type SavepointOperation<'s, T> =
+    Pin<Box<dyn Future<Output = Result<T, sqlx::Error>> + Send + 's>>;
+
+/// Recovers from a failed sub-operation of a transaction.
+///
+/// On Postgres a failed statement aborts the whole transaction, so an acceptable error
+/// (e.g. a duplicate insert) must be rolled back to a savepoint for the transaction to
+/// continue.
+#[async_trait::async_trait]
+pub trait Savepoint {
+    /// Runs `operation` within a savepoint, released if it succeeds and rolled back if it
+    /// fails, the outer transaction continuing in both cases. The savepoint statements
+    /// are the ones of the nested `atomic` blocks.
+    ///
+    /// # Returns
+    /// The result of `operation`, its error once rolled back, or the error of the
+    /// savepoint statements.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut tx = conn.begin().await?;
+    /// // the tag may already exist
+    /// let inserted = tx
+    ///     .try_savepoint(|sp| {
+    ///         Box::pin(async move {
+    ///             sqlx::query("insert into tags (name) values (?1)")
+    ///                 .bind("rust")
+    ///                 .execute(sp)
+    ///                 .await
+    ///         })
+    ///     })
+    ///     .await;
+    /// if let Err(err) = inserted {
+    ///     println!("The tag was not inserted: {err}");
+    /// }
+    /// sqlx::query("insert into post_tags (post, tag) values (?1, ?2)")
+    ///     .bind(post_id)
+    ///     .bind("rust")
+    ///     .execute(&mut *tx)
+    ///     .await?;
+    /// tx.commit().await?;
+    /// ```
+    async fn try_savepoint<T, F>(&mut self, operation: F) -> Result<T, sqlx::Error>
+    where
+        T: Send,
+        F: for<'s> FnOnce(&'s mut AnyConnection) -> SavepointOperation<'s, T> + Send;
+}
+
+#[async_trait::async_trait]
+impl Savepoint for AnyConnection {
+    async fn try_savepoint<T, F>(&mut self, operation: F) -> Result<T, sqlx::Error>
+    where
+        T: Send,
+        F: for<'s> FnOnce(&'s mut AnyConnection) -> SavepointOperation<'s, T> + Send,
+    {
+        let savepoint = transaction::savepoint_name();
+        let create = format!("savepoint {savepoint};");
+        sqlx::query(&create).execute(&mut *self).await?;
+        let output = operation(self).await;
+        for statement in transaction::end_savepoint(&savepoint, output.is_ok()) {
+            sqlx::query(&statement).execute(&mut *self).await?;
+        }
+        output
+    }
+}
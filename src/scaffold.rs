@@ -0,0 +1,140 @@
+//! Generates the source of new models, e.g. from the migration command line:
+//!
+//! ```text
+//! migrate new-model Product name:string price:decimal owner:fk(User) sku:string:unique
+//! ```
+
+use std::{fs, path::Path};
+
+use crate::{Backend, BACKEND};
+
+/// Renders the source of the model `name` with its `fields`, written `name:type` with
+/// optional `:unique` and `:index` flags.
+///
+/// The types are `string`, `text`, `citext`, `integer`, `float`, `decimal` (a
+/// `Float`), `boolean`, `date`, `datetime`, `uuid` and `fk(Model)`, a reference to the
+/// `id` of `Model`. The model gets an auto incremented `id` primary key.
+///
+/// # Returns
+/// The source of the model, or the error describing an invalid field.
+///
+/// # Example
+/// ```ignore
+/// let source = scaffold::model_source("Product", &["name:string", "owner:fk(User)"])?;
+/// ```
+pub fn model_source(name: &str, fields: &[&str]) -> Result<String, String> {
+    if !name.starts_with(|c: char| c.is_ascii_uppercase()) {
+        return Err(format!("Invalid model name `{name}`, expected CamelCase"));
+    }
+
+    let mut source = format!(
+        "use rusql_alchemy::prelude::*;\n\n#[derive(FromRow, Clone, Debug, Default, Model)]\npub struct {name} {{\n"
+    );
+    if *BACKEND == Backend::Postgres {
+        source.push_str("    #[model(primary_key = true)]\n    pub id: Serial,\n");
+    } else {
+        source.push_str("    #[model(primary_key = true, auto = true)]\n    pub id: Integer,\n");
+    }
+
+    for field in fields {
+        let mut parts = field.split(':');
+        let (Some(field_name), Some(kind)) = (parts.next(), parts.next()) else {
+            return Err(format!("Invalid field `{field}`, expected name:type"));
+        };
+        if field_name.is_empty()
+            || !field_name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        {
+            return Err(format!(
+                "Invalid field name `{field_name}`, expected snake_case"
+            ));
+        }
+
+        let mut attributes = Vec::new();
+        let field_type = match kind {
+            "string" => {
+                attributes.push("size = 255".to_string());
+                "String"
+            }
+            "text" => "Text",
+            "citext" => "CiText",
+            "integer" | "int" => "Integer",
+            "float" | "decimal" => "Float",
+            "boolean" | "bool" => "Boolean",
+            "date" => "Date",
+            "datetime" => "DateTime",
+            "uuid" => "Uuid",
+            kind => match kind
+                .strip_prefix("fk(")
+                .and_then(|kind| kind.strip_suffix(')'))
+            {
+                Some(model) => {
+                    attributes.push(format!("foreign_key = \"{model}.id\""));
+                    "Integer"
+                }
+                None => return Err(format!("Unknown type `{kind}` of the field `{field_name}`")),
+            },
+        };
+        for flag in parts {
+            match flag {
+                "unique" | "index" => attributes.push(format!("{flag} = true")),
+                flag => return Err(format!("Unknown flag `{flag}` of the field `{field_name}`")),
+            }
+        }
+
+        if !attributes.is_empty() {
+            source.push_str(&format!("    #[model({})]\n", attributes.join(", ")));
+        }
+        source.push_str(&format!("    pub {field_name}: {field_type},\n"));
+    }
+    source.push_str("}\n");
+    Ok(source)
+}
+
+/// Writes the model `name` in `dir/<name in snake_case>.rs`, see `model_source`.
+///
+/// # Returns
+/// `true` if the model is written, `false` if it is invalid or the file already exists.
+///
+/// # Example
+/// ```ignore
+/// scaffold::new_model("src/models", "Product", &["name:string", "price:decimal"]);
+/// ```
+pub fn new_model(dir: impl AsRef<Path>, name: &str, fields: &[&str]) -> bool {
+    let source = match model_source(name, fields) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("Error during the model generation\n->{err}");
+            return false;
+        }
+    };
+
+    let mut file_name = String::new();
+    for (index, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && index > 0 {
+            file_name.push('_');
+        }
+        file_name.push(c.to_ascii_lowercase());
+    }
+    let path = dir.as_ref().join(format!("{file_name}.rs"));
+    if path.exists() {
+        eprintln!(
+            "Error during the model generation\n->{} already exists",
+            path.display()
+        );
+        return false;
+    }
+
+    let result = fs::create_dir_all(dir.as_ref()).and_then(|_| fs::write(&path, source));
+    match result {
+        Ok(()) => {
+            println!("Created {}", path.display());
+            true
+        }
+        Err(err) => {
+            eprintln!("Error during the model generation\n->{err}");
+            false
+        }
+    }
+}
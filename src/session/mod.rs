@@ -0,0 +1,200 @@
+//! Sessions stored in the database, for the web frameworks: the `SessionStore` loads and
+//! saves the values of a session by its id, the adapters of the frameworks implementing
+//! their own store traits on top of it: the `tower-sessions` store with the
+//! `tower-sessions` feature, and the Rocket request guard of the `rocket` module with the
+//! `rocket` feature.
+
+#[cfg(feature = "rocket")]
+pub mod rocket;
+#[cfg(feature = "tower-sessions")]
+mod tower;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde_json::{Map, Value};
+
+use crate::naming::quote_ident;
+use crate::prelude::*;
+use crate::{Error, PLACEHOLDER};
+
+/// A session, its values being stored as a JSON object.
+#[derive(FromRow, Clone, Debug, Default, Model)]
+#[model(table = "rusql_sessions")]
+pub struct Session {
+    #[model(primary_key = true, size = 64, null = false)]
+    pub id: String,
+    #[model(null = false)]
+    pub data: Text,
+    /// The UTC time the session expires at, as `YYYY-MM-DD HH:MM:SS`.
+    #[model(null = false, index = true)]
+    pub expires_at: DateTime,
+}
+
+impl Session {
+    /// The values of the session, empty if they are not a JSON object.
+    pub fn values(&self) -> Map<String, Value> {
+        serde_json::from_str(&self.data).unwrap_or_default()
+    }
+
+    /// The time the session expires at, the epoch if `expires_at` is invalid.
+    pub fn expiry(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(parse_timestamp(&self.expires_at).unwrap_or_default())
+    }
+}
+
+/// Loads and saves the sessions, each save pushing back their expiry by `ttl`.
+///
+/// # Example
+/// ```ignore
+/// let conn = Database::new().await.conn;
+/// Session::migrate(&conn).await?;
+/// let store = SessionStore::new(conn.clone(), Duration::from_secs(14 * 24 * 3600));
+///
+/// let mut values = Map::new();
+/// values.insert("user_id".to_string(), json!(1));
+/// let id = store.create(&values).await?;
+///
+/// if let Some(values) = store.load(&id).await? {
+///     println!("{:?}", values.get("user_id"));
+/// }
+/// store.delete(&id).await?;
+///
+/// tokio::spawn(store.clone().run_cleanup(Duration::from_secs(3600)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    conn: Connection,
+    ttl: Duration,
+}
+
+impl SessionStore {
+    pub fn new(conn: Connection, ttl: Duration) -> Self {
+        Self { conn, ttl }
+    }
+
+    /// Saves a new session, returning its id, 256 random bits encoded as base64.
+    pub async fn create(&self, values: &Map<String, Value>) -> Result<String, Error> {
+        let mut bytes = [0u8; 32];
+        getrandom::getrandom(&mut bytes)
+            .map_err(|err| Error::Invalid(format!("no session id generated: {err}")))?;
+        let id = URL_SAFE_NO_PAD.encode(bytes);
+        self.save(&id, values).await?;
+        Ok(id)
+    }
+
+    /// The session `id`, `None` if it does not exist or has expired.
+    pub async fn get(&self, id: &str) -> Result<Option<Session>, Error> {
+        let placeholder = PLACEHOLDER.to_string();
+        let query = format!(
+            "select * from {table_name} where id={placeholder}1 and expires_at>{placeholder}2;",
+            table_name = quote_ident(Session::NAME)
+        );
+        let stream = sqlx::query_as::<_, Session>(&query)
+            .bind(id.to_string())
+            .bind(timestamp(SystemTime::now()));
+        Ok(on_conn!(&self.conn, stream, fetch_optional).await?)
+    }
+
+    /// The values of the session `id`, `None` if it does not exist or has expired.
+    pub async fn load(&self, id: &str) -> Result<Option<Map<String, Value>>, Error> {
+        Ok(self.get(id).await?.map(|session| session.values()))
+    }
+
+    /// Saves the values of the session `id`, which expires after the `ttl`.
+    pub async fn save(&self, id: &str, values: &Map<String, Value>) -> Result<(), Error> {
+        self.save_until(id, values, SystemTime::now() + self.ttl)
+            .await
+    }
+
+    /// Saves the values of the session `id`, which expires at `expires_at`.
+    pub async fn save_until(
+        &self,
+        id: &str,
+        values: &Map<String, Value>,
+        expires_at: SystemTime,
+    ) -> Result<(), Error> {
+        let data = Value::Object(values.clone()).to_string();
+        Session::create_or_update(
+            kwargs!(id = id, data = data, expires_at = timestamp(expires_at)),
+            &self.conn,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Deletes the session `id`, e.g. on logout.
+    pub async fn delete(&self, id: &str) -> Result<(), Error> {
+        let session = Session {
+            id: id.to_string(),
+            ..Default::default()
+        };
+        session.delete(&self.conn).await
+    }
+
+    /// Deletes the expired sessions, returning their count.
+    pub async fn delete_expired(&self) -> Result<u64, Error> {
+        let placeholder = PLACEHOLDER.to_string();
+        let query = format!(
+            "delete from {table_name} where expires_at<={placeholder}1;",
+            table_name = quote_ident(Session::NAME)
+        );
+        let stream = sqlx::query(&query).bind(timestamp(SystemTime::now()));
+        let result = on_conn!(&self.conn, stream, execute).await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Runs `delete_expired` forever, every `interval`.
+    pub async fn run_cleanup(self, interval: Duration) {
+        loop {
+            if let Err(err) = self.delete_expired().await {
+                eprintln!("Error during the cleanup of the sessions\n->{err}");
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+/// Formats `time` as a UTC `YYYY-MM-DD HH:MM:SS` timestamp, ordered as the times.
+pub(crate) fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let (days, seconds) = (seconds / 86_400, seconds % 86_400);
+    // the civil date of a number of days since 1970-01-01, by Howard Hinnant
+    let days = days as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        seconds / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60
+    )
+}
+
+/// Parses a `YYYY-MM-DD HH:MM:SS` UTC timestamp into seconds since the epoch.
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let (date, time) = timestamp.split_once(' ')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let mut time = time.splitn(3, ':').map(str::parse::<i64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    // the number of days since 1970-01-01 of a civil date, the inverse of `timestamp`
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    u64::try_from(days * 86_400 + hour * 3_600 + minute * 60 + second).ok()
+}
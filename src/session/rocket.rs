@@ -0,0 +1,103 @@
+//! The Rocket request guard of the sessions, with the `rocket` feature: the session of
+//! the `session` cookie, read from the `SessionStore` managed by Rocket.
+//!
+//! # Example
+//! ```ignore
+//! use rusql_alchemy::session::rocket::CurrentSession;
+//!
+//! #[post("/login/<username>")]
+//! async fn login(
+//!     username: &str,
+//!     mut session: CurrentSession,
+//!     store: &State<SessionStore>,
+//!     cookies: &CookieJar<'_>,
+//! ) -> Value {
+//!     session.values.insert("username".to_string(), json!(username));
+//!     json!({ "saved": session.save(store, cookies).await.is_ok() })
+//! }
+//!
+//! rocket::build()
+//!     .manage(SessionStore::new(conn.clone(), Duration::from_secs(14 * 24 * 3600)))
+//!     .mount("/", routes![login]);
+//! ```
+
+use ::rocket::http::{Cookie, CookieJar, SameSite, Status};
+use ::rocket::request::{FromRequest, Outcome, Request};
+use serde_json::{Map, Value};
+
+use super::SessionStore;
+use crate::Error;
+
+/// The name of the cookie holding the session id.
+pub const COOKIE: &str = "session";
+
+/// The session of the request, empty if the request has none or it expired.
+pub struct CurrentSession {
+    pub id: Option<String>,
+    pub values: Map<String, Value>,
+}
+
+impl CurrentSession {
+    /// Saves the values, starting a new session (and setting its cookie) if needed.
+    pub async fn save(
+        &mut self,
+        store: &SessionStore,
+        cookies: &CookieJar<'_>,
+    ) -> Result<(), Error> {
+        match &self.id {
+            Some(id) => store.save(id, &self.values).await,
+            None => {
+                let id = store.create(&self.values).await?;
+                cookies.add(
+                    Cookie::build((COOKIE, id.clone()))
+                        .http_only(true)
+                        .same_site(SameSite::Lax),
+                );
+                self.id = Some(id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Deletes the session and its cookie, e.g. on logout.
+    pub async fn destroy(self, store: &SessionStore, cookies: &CookieJar<'_>) -> Result<(), Error> {
+        cookies.remove(COOKIE);
+        match self.id {
+            Some(id) => store.delete(&id).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[::rocket::async_trait]
+impl<'r> FromRequest<'r> for CurrentSession {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(store) = request.rocket().state::<SessionStore>() else {
+            let err = Error::Invalid("no SessionStore is managed by Rocket".to_string());
+            return Outcome::Error((Status::InternalServerError, err));
+        };
+        let id = request
+            .cookies()
+            .get(COOKIE)
+            .map(|cookie| cookie.value().to_string());
+        let session = match id {
+            Some(id) => match store.load(&id).await {
+                Ok(values) => values.map(|values| (id, values)),
+                Err(err) => return Outcome::Error((Status::InternalServerError, err)),
+            },
+            None => None,
+        };
+        Outcome::Success(match session {
+            Some((id, values)) => CurrentSession {
+                id: Some(id),
+                values,
+            },
+            None => CurrentSession {
+                id: None,
+                values: Map::new(),
+            },
+        })
+    }
+}
@@ -0,0 +1,74 @@
+//! The `tower-sessions` store of the sessions (e.g. for axum), with the `tower-sessions`
+//! feature.
+//!
+//! # Example
+//! ```ignore
+//! use tower_sessions::SessionManagerLayer;
+//!
+//! let store = SessionStore::new(conn.clone(), Duration::from_secs(14 * 24 * 3600));
+//! let app = Router::new()
+//!     .route("/", get(handler))
+//!     .layer(SessionManagerLayer::new(store));
+//! ```
+
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use tower_sessions_core::session::{Id, Record};
+use tower_sessions_core::session_store::{self, ExpiredDeletion};
+
+use super::SessionStore;
+use crate::Error;
+
+fn backend(err: Error) -> session_store::Error {
+    session_store::Error::Backend(err.to_string())
+}
+
+#[async_trait]
+impl tower_sessions_core::SessionStore for SessionStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        // a new id is drawn on the (unlikely) collision with an existing session
+        while self
+            .get(&record.id.to_string())
+            .await
+            .map_err(backend)?
+            .is_some()
+        {
+            record.id = Id::default();
+        }
+        tower_sessions_core::SessionStore::save(self, record).await
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        let values = record.data.clone().into_iter().collect();
+        let expires_at = SystemTime::from(record.expiry_date);
+        self.save_until(&record.id.to_string(), &values, expires_at)
+            .await
+            .map_err(backend)
+    }
+
+    async fn load(&self, id: &Id) -> session_store::Result<Option<Record>> {
+        let session = self.get(&id.to_string()).await.map_err(backend)?;
+        Ok(session.map(|session| Record {
+            id: *id,
+            data: session.values().into_iter().collect(),
+            expiry_date: session.expiry().into(),
+        }))
+    }
+
+    async fn delete(&self, id: &Id) -> session_store::Result<()> {
+        SessionStore::delete(self, &id.to_string())
+            .await
+            .map_err(backend)
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for SessionStore {
+    async fn delete_expired(&self) -> session_store::Result<()> {
+        SessionStore::delete_expired(self)
+            .await
+            .map(|_| ())
+            .map_err(backend)
+    }
+}
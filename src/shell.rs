@@ -0,0 +1,241 @@
+//! An interactive shell to inspect the database during development, running raw SQL
+//! statements and simple model queries:
+//!
+//! ```text
+//! rusql> User.filter(role="admin", age__gt=18)
+//! rusql> select count(*) from users;
+//! ```
+
+use std::{future::Future, io::Write, pin::Pin};
+
+use sqlx::{any::AnyRow, Column, Row};
+
+use crate::db::export::{render_table, row_to_json};
+use crate::prelude::*;
+
+/// Runs a model query: (method, conditions, connection) to the rendered result.
+type ModelQuery = Box<
+    dyn Fn(String, Vec<Condition>, Connection) -> Pin<Box<dyn Future<Output = String> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// An interactive prompt on a database connection.
+///
+/// The lines of the form `Model.all()`, `Model.filter(field=value, ...)` or
+/// `Model.get(field=value, ...)` query a registered model, the other lines are SQL
+/// statements run once terminated by a `;`. `.tables` lists the registered models and
+/// `.quit` exits.
+///
+/// # Example
+/// ```ignore
+/// // src/bin/shell.rs
+/// #[tokio::main]
+/// async fn main() {
+///     let conn = Database::new().await.conn;
+///     Shell::new(conn)
+///         .with_model::<User>()
+///         .with_model::<Product>()
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct Shell {
+    conn: Connection,
+    models: Vec<(&'static str, ModelQuery)>,
+}
+
+impl Shell {
+    pub fn new(conn: Connection) -> Self {
+        Self {
+            conn,
+            models: Vec::new(),
+        }
+    }
+
+    /// Registers a model queryable from the shell.
+    pub fn with_model<M>(mut self) -> Self
+    where
+        M: Model + Unpin + for<'r> FromRow<'r, AnyRow> + Clone + Send + Sync + 'static,
+    {
+        let query: ModelQuery = Box::new(|method, conditions, conn| {
+            Box::pin(async move {
+                let rows = match method.as_str() {
+                    "all" => M::all(&conn).await,
+                    "filter" => M::filter(conditions, &conn).await,
+                    "get" => M::get(conditions, &conn).await.into_iter().collect(),
+                    _ => return format!("Unknown method `{method}`, use all, filter or get"),
+                };
+                format!("{}({} rows)", M::to_table(&rows), rows.len())
+            })
+        });
+        self.models.push((M::MODEL_NAME, query));
+        self
+    }
+
+    /// Runs the prompt until `.quit` or the end of the input.
+    pub async fn run(&self) {
+        let mut statement = String::new();
+        loop {
+            let prompt = if statement.is_empty() {
+                "rusql> "
+            } else {
+                "  ...> "
+            };
+            let Some(line) = read_line(prompt).await else {
+                break;
+            };
+            let line = line.trim();
+
+            if statement.is_empty() {
+                match line {
+                    "" => continue,
+                    ".quit" | ".exit" => break,
+                    ".tables" => {
+                        for (name, _) in &self.models {
+                            println!("{name}");
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+                if let Some(output) = self.model_query(line).await {
+                    println!("{output}");
+                    continue;
+                }
+            }
+
+            if !statement.is_empty() {
+                statement.push(' ');
+            }
+            statement.push_str(line);
+            if statement.ends_with(';') {
+                println!("{}", self.sql(&std::mem::take(&mut statement)).await);
+            }
+        }
+    }
+
+    /// Runs `line` if it is a model query like `User.filter(role="admin")`, the model
+    /// being named by its Rust type.
+    ///
+    /// # Returns
+    /// The rendered result, or `None` if `line` is not a query of a registered model.
+    pub async fn model_query(&self, line: &str) -> Option<String> {
+        let (name, call) = line.split_once('.')?;
+        let (method, args) = call.strip_suffix(')')?.split_once('(')?;
+        let (_, query) = self.models.iter().find(|(model, _)| *model == name)?;
+        let conditions = match parse_conditions(args) {
+            Ok(conditions) => conditions,
+            Err(err) => return Some(err),
+        };
+        Some(query(method.to_string(), conditions, self.conn.clone()).await)
+    }
+
+    /// Runs a raw SQL statement and renders the rows it returns.
+    async fn sql(&self, statement: &str) -> String {
+        match sqlx::query(statement).fetch_all(&self.conn).await {
+            Ok(rows) => {
+                let Some(first) = rows.first() else {
+                    return "(0 rows)".to_string();
+                };
+                let header = first
+                    .columns()
+                    .iter()
+                    .map(|column| column.name())
+                    .collect::<Vec<_>>();
+                let cells = rows
+                    .iter()
+                    .map(|row| {
+                        let values = row_to_json(row);
+                        header
+                            .iter()
+                            .map(|column| match &values[*column] {
+                                serde_json::Value::String(value) => value.clone(),
+                                value => value.to_string(),
+                            })
+                            .collect()
+                    })
+                    .collect::<Vec<_>>();
+                format!("{}({} rows)", render_table(&header, &cells), rows.len())
+            }
+            Err(err) => format!("Error during the query\n->{err}"),
+        }
+    }
+}
+
+/// Reads a line from the terminal, `None` at the end of the input.
+async fn read_line(prompt: &str) -> Option<String> {
+    print!("{prompt}");
+    std::io::stdout().flush().ok();
+    tokio::task::spawn_blocking(|| {
+        let mut line = String::new();
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line),
+        }
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Parses the `field=value, ...` arguments of a model query, the values being quoted
+/// strings, numbers or booleans.
+fn parse_conditions(args: &str) -> Result<Vec<Condition>, String> {
+    let mut conditions = Vec::new();
+    for arg in split_args(args) {
+        let Some((field, value)) = arg.split_once('=') else {
+            return Err(format!("Invalid argument `{arg}`, expected field=value"));
+        };
+        let value = value.trim();
+        let value = if let Some(text) = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .or_else(|| {
+                value
+                    .strip_prefix('\'')
+                    .and_then(|value| value.strip_suffix('\''))
+            }) {
+            SqlValue::from(text)
+        } else if value == "true" || value == "false" {
+            SqlValue::from(value == "true")
+        } else if let Ok(number) = value.parse::<i32>() {
+            SqlValue::from(number)
+        } else if let Ok(number) = value.parse::<f64>() {
+            SqlValue::from(number)
+        } else {
+            return Err(format!("Invalid value `{value}`, quote the strings"));
+        };
+        conditions.push(Condition::FieldCondition {
+            field: field.trim().to_string(),
+            value,
+            comparaison_operator: "=".to_string(),
+        });
+    }
+    Ok(conditions)
+}
+
+/// Splits the arguments on the commas which are not quoted.
+fn split_args(args: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut quote = None;
+    for c in args.chars() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (',', None) => {
+                parts.push(std::mem::take(&mut part));
+                continue;
+            }
+            _ => {}
+        }
+        part.push(c);
+    }
+    parts.push(part);
+    parts
+        .into_iter()
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect()
+}
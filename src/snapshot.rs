@@ -0,0 +1,103 @@
+use std::{cell::RefCell, future::Future, path::PathBuf};
+
+use crate::{Backend, BACKEND};
+
+tokio::task_local! {
+    static CAPTURED: RefCell<Vec<String>>;
+}
+
+/// Runs `future` and returns the SQL of the queries the model methods ran within it,
+/// to be compared with `assert_query_snapshot`.
+///
+/// # Example
+/// ```ignore
+/// let (_, queries) = capture_queries(User::filter(kwargs!(age__gte = 18), &conn)).await;
+/// ```
+pub async fn capture_queries<F: Future>(future: F) -> (F::Output, Vec<String>) {
+    CAPTURED
+        .scope(RefCell::new(Vec::new()), async {
+            let output = future.await;
+            let queries = CAPTURED.with(|queries| queries.take());
+            (output, queries)
+        })
+        .await
+}
+
+/// Remembers that `query` was run, within `capture_queries`.
+pub(crate) fn record(query: &str) {
+    let _ = CAPTURED.try_with(|queries| queries.borrow_mut().push(query.to_string()));
+}
+
+/// Compares `queries` with the snapshot `name` of the current backend
+/// (`tests/snapshots/{backend}/{name}.sql` in the crate under test), so an upgrade
+/// changing the shape of the generated SQL fails the tests instead of going unnoticed.
+///
+/// The snapshot is only written when the `RUSQL_UPDATE_SNAPSHOTS` environment variable
+/// is set, to record a new snapshot or accept the changes, so a snapshot missing from
+/// the repository fails the tests instead of passing. As the SQL depends on the
+/// backend, each one has its own snapshots: running the same tests against SQLite,
+/// MySQL and Postgres checks the dialect of each.
+///
+/// # Panics
+/// If `queries` differ from the snapshot, showing both, or if the snapshot is missing.
+///
+/// # Example
+/// ```ignore
+/// #[tokio::test]
+/// async fn adults_query() {
+///     let conn = Database::new().await.conn;
+///     let (_, queries) = capture_queries(User::filter(kwargs!(age__gte = 18), &conn)).await;
+///     assert_query_snapshot("adults", &queries);
+/// }
+///
+/// #[tokio::test]
+/// async fn user_schema() {
+///     let conn = Database::new().await.conn;
+///     // the DDL run by the migration
+///     let (_, queries) = capture_queries(User::migrate(&conn)).await;
+///     assert_query_snapshot("user_schema", &queries);
+/// }
+/// ```
+pub fn assert_query_snapshot(name: &str, queries: &[String]) {
+    let dir = std::env::var("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join("tests")
+        .join("snapshots")
+        .join(match *BACKEND {
+            Backend::Sqlite => "sqlite",
+            Backend::MySql => "mysql",
+            Backend::Postgres => "postgres",
+        });
+    let path = dir.join(format!("{name}.sql"));
+    let actual = queries
+        .iter()
+        .map(|query| format!("{}\n", query.trim()))
+        .collect::<String>();
+
+    let update = std::env::var_os("RUSQL_UPDATE_SNAPSHOTS").is_some();
+    match std::fs::read_to_string(&path) {
+        Ok(expected) if expected == actual => {}
+        Ok(expected) if !update => panic!(
+            "The queries differ from the snapshot {}\
+             \n--- expected\n{expected}+++ actual\n{actual}\
+             (set RUSQL_UPDATE_SNAPSHOTS=1 to accept them)",
+            path.display()
+        ),
+        Err(_) if !update => panic!(
+            "The snapshot {} is missing\n+++ actual\n{actual}\
+             (set RUSQL_UPDATE_SNAPSHOTS=1 to record it)",
+            path.display()
+        ),
+        _ => {
+            if let Err(err) =
+                std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(&path, &actual))
+            {
+                panic!(
+                    "Error while writing the snapshot {}\n->{err}",
+                    path.display()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::future::Future;
+
+use crate::track_query;
+
+/// The number of rows returned or affected by a query, recorded on its span.
+#[cfg(feature = "tracing")]
+pub(crate) trait RowCount {
+    fn row_count(&self) -> Option<u64>;
+}
+
+#[cfg(feature = "tracing")]
+impl<T, E> RowCount for Result<Vec<T>, E> {
+    fn row_count(&self) -> Option<u64> {
+        self.as_ref().ok().map(|rows| rows.len() as u64)
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<T, E> RowCount for Result<Option<T>, E> {
+    fn row_count(&self) -> Option<u64> {
+        self.as_ref().ok().map(|row| row.is_some() as u64)
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<E> RowCount for Result<sqlx::any::AnyQueryResult, E> {
+    fn row_count(&self) -> Option<u64> {
+        self.as_ref().ok().map(|result| result.rows_affected())
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<E> RowCount for Result<sqlx::any::AnyRow, E> {
+    fn row_count(&self) -> Option<u64> {
+        self.as_ref().ok().map(|_| 1)
+    }
+}
+
+// the result computed from the streamed rows, with their count
+#[cfg(feature = "tracing")]
+impl<T, E> RowCount for Result<(T, u64), E> {
+    fn row_count(&self) -> Option<u64> {
+        self.as_ref().ok().map(|(_, rows)| *rows)
+    }
+}
+
+/// Runs the query `future` of a model `operation` like `track_query`, within a
+/// `rusql.query` span (with the `tracing` feature) carrying the `model`, `operation`,
+/// `rows`, `db.system` and `db.statement` attributes of the OpenTelemetry conventions.
+#[cfg(feature = "tracing")]
+pub(crate) async fn track_operation<F>(
+    model: &'static str,
+    operation: &'static str,
+    query: &str,
+    future: F,
+) -> Option<F::Output>
+where
+    F: Future,
+    F::Output: RowCount,
+{
+    use tracing::Instrument;
+
+    let system = match *crate::BACKEND {
+        crate::Backend::Sqlite => "sqlite",
+        crate::Backend::MySql => "mysql",
+        crate::Backend::Postgres => "postgresql",
+    };
+    let span = tracing::info_span!(
+        "rusql.query",
+        model,
+        operation,
+        rows = tracing::field::Empty,
+        "db.system" = system,
+        "db.statement" = query,
+    );
+    let output = track_query(query, future).instrument(span.clone()).await;
+    if let Some(rows) = output.as_ref().and_then(RowCount::row_count) {
+        span.record("rows", rows);
+    }
+    output
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) async fn track_operation<F: Future>(
+    _model: &'static str,
+    _operation: &'static str,
+    query: &str,
+    future: F,
+) -> Option<F::Output> {
+    track_query(query, future).await
+}
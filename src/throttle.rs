@@ -0,0 +1,89 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+tokio::task_local! {
+    static THROTTLE: Throttled;
+}
+
+/// A cap on the rate and the concurrency of the statements, so a bulk job does not
+/// starve the other clients of the database.
+///
+/// Every statement run by the model methods within `run` waits for its turn, at most
+/// `per_second` statements starting each second and at most `concurrency` of them
+/// running at once. The clones share the same limits, so the tasks spawned by a job
+/// each `run` within a clone.
+///
+/// # Example
+/// ```ignore
+/// let throttle = Throttled::new().per_second(50).concurrency(2);
+/// throttle
+///     .run(async {
+///         let mut chunks = User::iter_chunks(500, &conn);
+///         while let Some(users) = chunks.next().await {
+///             for user in users? {
+///                 user.update_fields(kwargs!(score = F("score") + 1), &conn).await?;
+///             }
+///         }
+///         Ok::<_, Error>(())
+///     })
+///     .await?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Throttled {
+    interval: Option<Duration>,
+    semaphore: Option<Arc<Semaphore>>,
+    next: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Throttled {
+    /// A throttle without any limit, see `per_second` and `concurrency`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts at most `statements` statements per second, evenly spaced.
+    pub fn per_second(mut self, statements: u32) -> Self {
+        self.interval = (statements > 0).then(|| Duration::from_secs(1) / statements);
+        self
+    }
+
+    /// Runs at most `statements` statements at once.
+    pub fn concurrency(mut self, statements: usize) -> Self {
+        self.semaphore = Some(Arc::new(Semaphore::new(statements.max(1))));
+        self
+    }
+
+    /// Runs `future`, throttling the statements it runs.
+    pub async fn run<F: Future>(&self, future: F) -> F::Output {
+        THROTTLE.scope(self.clone(), future).await
+    }
+
+    /// Waits for the turn of a statement, returning the permit held while it runs.
+    async fn wait(&self) -> Option<OwnedSemaphorePermit> {
+        let permit = match &self.semaphore {
+            Some(semaphore) => semaphore.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        if let Some(interval) = self.interval {
+            let start = {
+                let mut next = self.next.lock().unwrap();
+                let start = next.map_or(Instant::now(), |next| next.max(Instant::now()));
+                *next = Some(start + interval);
+                start
+            };
+            tokio::time::sleep_until(start.into()).await;
+        }
+        permit
+    }
+}
+
+/// Waits for the turn of a statement within `Throttled::run`, if any.
+pub(crate) async fn wait() -> Option<OwnedSemaphorePermit> {
+    let throttle = THROTTLE.try_with(Clone::clone).ok()?;
+    throttle.wait().await
+}
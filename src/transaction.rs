@@ -0,0 +1,328 @@
+use std::{
+    future::Future,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use sqlx::{any::AnyConnectOptions, pool::PoolConnection, Any, AnyConnection, Transaction};
+use tokio::sync::{Mutex, OwnedMappedMutexGuard, OwnedMutexGuard};
+
+use crate::{cache, Backend, Connection, BACKEND};
+
+tokio::task_local! {
+    static TRANSACTIONS: Vec<Scope>;
+}
+
+/// The savepoints of the nested `atomic` blocks and of `Savepoint::try_savepoint` are
+/// numbered, so their names are unique.
+static SAVEPOINTS: AtomicUsize = AtomicUsize::new(0);
+
+/// A transaction shared by the statements of an `atomic` block, taken out when it is
+/// committed or rolled back.
+pub(crate) type Shared = Arc<Mutex<Option<Transaction<'static, Any>>>>;
+
+/// The transaction of an `atomic` block, on the database of `options`.
+#[derive(Clone)]
+struct Scope {
+    options: Arc<AnyConnectOptions>,
+    transaction: Shared,
+}
+
+/// The error of a statement run on a transaction which was committed or rolled back.
+pub(crate) fn finished() -> sqlx::Error {
+    sqlx::Error::Protocol("the transaction is already committed or rolled back".to_string())
+}
+
+/// Runs `future` in a transaction on `conn`, committed if it returns `Ok` and rolled
+/// back if it returns `Err` (or panics).
+///
+/// Every statement run on `conn` by the model methods within `future` runs in the
+/// transaction, so a `create` and an `update` are committed together or not at all.
+/// The statements run on another connection (e.g. a replica) do not, nor the queries
+/// run directly with `sqlx` on the pool. A nested `atomic` block (or
+/// `Database::transaction`) on the same connection runs within a savepoint, rolled back
+/// alone if it fails, so the enclosing block can recover from the error and go on.
+///
+/// # Example
+/// ```ignore
+/// use rusql_alchemy::atomic;
+///
+/// atomic(&conn, async {
+///     Account::create(kwargs!(owner = "joe", balance = 100), &conn).await?;
+///     let account = Account::get(kwargs!(owner == "joe"), &conn).await.unwrap();
+///     account.update_fields(kwargs!(balance = F("balance") - 30), &conn).await?;
+///     Ok::<_, Error>(())
+/// })
+/// .await?;
+/// ```
+pub async fn atomic<F, T, E>(conn: &Connection, future: F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+    E: From<sqlx::Error>,
+{
+    transaction(conn, None, |_| future).await
+}
+
+/// The isolation level of a transaction started by `atomic_with`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    /// The `set transaction` statement of the level, `None` on SQLite whose
+    /// transactions are always serializable.
+    fn statement(self) -> Option<String> {
+        let level = match self {
+            IsolationLevel::ReadCommitted => "read committed",
+            IsolationLevel::RepeatableRead => "repeatable read",
+            IsolationLevel::Serializable => "serializable",
+        };
+        match *BACKEND {
+            Backend::Sqlite => None,
+            Backend::Postgres | Backend::MySql => {
+                Some(format!("set transaction isolation level {level};"))
+            }
+        }
+    }
+}
+
+/// Runs `future` in a transaction on `conn` with the isolation level `isolation`, as
+/// `atomic` does.
+///
+/// On Postgres the level is set at the start of the transaction, and on MySQL before
+/// it (for the next transaction of the connection). SQLite transactions are always
+/// serializable, which is stricter than any level. The level of a transaction can't
+/// change once it started, so `atomic_with` fails within an `atomic` block on `conn`.
+///
+/// # Example
+/// ```ignore
+/// use rusql_alchemy::{atomic_with, IsolationLevel};
+///
+/// // the check and the insert are not interleaved with another transaction
+/// atomic_with(&conn, IsolationLevel::Serializable, async {
+///     if User::count_where(kwargs!(role == "admin"), &conn).await == 0 {
+///         User::create(kwargs!(name = "joe", age = 20, role = "admin"), &conn).await?;
+///     }
+///     Ok::<_, Error>(())
+/// })
+/// .await?;
+/// ```
+pub async fn atomic_with<F, T, E>(
+    conn: &Connection,
+    isolation: IsolationLevel,
+    future: F,
+) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+    E: From<sqlx::Error>,
+{
+    transaction(conn, Some(isolation), |_| future).await
+}
+
+/// Runs the future of `operation` in a transaction on `conn` (with the isolation level
+/// `isolation`), as `atomic` does, `operation` being given the `Connection::Transaction`
+/// of the transaction, see `Database::transaction`.
+pub(crate) async fn transaction<F, Fut, T, E>(
+    conn: &Connection,
+    isolation: Option<IsolationLevel>,
+    operation: F,
+) -> Result<T, E>
+where
+    F: FnOnce(Connection) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: From<sqlx::Error>,
+{
+    if let Some(transaction) = current(conn) {
+        if isolation.is_some() {
+            return Err(sqlx::Error::Protocol(
+                "the isolation level of a nested transaction can't be set".to_string(),
+            )
+            .into());
+        }
+        let handle = Connection::Transaction(conn.pool().clone(), transaction.clone());
+        return savepoint(&transaction, operation(handle)).await;
+    }
+    let transaction = begin(conn, isolation).await?;
+    let handle = Connection::Transaction(conn.pool().clone(), transaction.clone());
+    run(transaction, conn, operation(handle)).await
+}
+
+/// Begins a transaction on the pool of `conn` with the isolation level `isolation`.
+async fn begin(
+    conn: &Connection,
+    isolation: Option<IsolationLevel>,
+) -> Result<Shared, sqlx::Error> {
+    let transaction = match (isolation.and_then(IsolationLevel::statement), &*BACKEND) {
+        (None, _) => conn.pool().begin().await?,
+        // the level of the next transaction of the connection
+        (Some(statement), Backend::MySql) => {
+            let mut connection = conn.pool().acquire().await?;
+            sqlx::query(&statement).execute(&mut *connection).await?;
+            Transaction::begin(connection).await?
+        }
+        // the first statement of the transaction
+        (Some(statement), _) => {
+            let mut transaction = conn.pool().begin().await?;
+            sqlx::query(&statement).execute(&mut *transaction).await?;
+            transaction
+        }
+    };
+    Ok(Arc::new(Mutex::new(Some(transaction))))
+}
+
+/// Runs `future` in `transaction` on `conn`, committed if it returns `Ok` and rolled
+/// back otherwise.
+async fn run<F, T, E>(shared: Shared, conn: &Connection, future: F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+    E: From<sqlx::Error>,
+{
+    let mut scopes = TRANSACTIONS.try_with(Clone::clone).unwrap_or_default();
+    scopes.push(Scope {
+        options: conn.connect_options(),
+        transaction: shared.clone(),
+    });
+    let output = TRANSACTIONS.scope(scopes, future).await;
+
+    // the clones of the `Connection::Transaction` kept after `future` fail from now on
+    let Some(transaction) = shared.lock().await.take() else {
+        cache::finish(&shared, false);
+        return Err(finished().into());
+    };
+    match output {
+        Ok(value) => {
+            let committed = transaction.commit().await;
+            cache::finish(&shared, committed.is_ok());
+            committed?;
+            Ok(value)
+        }
+        Err(err) => {
+            if let Err(err) = transaction.rollback().await {
+                eprintln!("Error during the rollback\n->{err}");
+            }
+            cache::finish(&shared, false);
+            // the cached instances may have been read within the transaction
+            cache::clear_object_cache();
+            Err(err)
+        }
+    }
+}
+
+/// Runs `future` within a savepoint of `transaction`, released if it returns `Ok` and
+/// rolled back then released if it returns `Err`, the transaction going on in both
+/// cases. The statements are the same on SQLite, Postgres and MySQL.
+async fn savepoint<F, T, E>(transaction: &Shared, future: F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+    E: From<sqlx::Error>,
+{
+    let savepoint = savepoint_name();
+    execute(transaction, &format!("savepoint {savepoint};")).await?;
+    let output = future.await;
+    for statement in end_savepoint(&savepoint, output.is_ok()) {
+        execute(transaction, &statement).await?;
+    }
+    if output.is_err() {
+        cache::clear_object_cache();
+    }
+    output
+}
+
+/// The name of a new savepoint.
+pub(crate) fn savepoint_name() -> String {
+    format!(
+        "rusql_savepoint_{}",
+        SAVEPOINTS.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// The statements ending the savepoint `savepoint`: released if its operation
+/// `succeeded`, rolled back then released otherwise.
+pub(crate) fn end_savepoint(savepoint: &str, succeeded: bool) -> Vec<String> {
+    if succeeded {
+        return vec![format!("release savepoint {savepoint};")];
+    }
+    // a savepoint rolled back to is kept until released
+    vec![
+        format!("rollback to savepoint {savepoint};"),
+        format!("release savepoint {savepoint};"),
+    ]
+}
+
+/// Executes `statement` on `transaction`.
+async fn execute(transaction: &Shared, statement: &str) -> Result<(), sqlx::Error> {
+    let mut transaction = transaction.lock().await;
+    let transaction = transaction.as_mut().ok_or_else(finished)?;
+    sqlx::query(statement).execute(&mut **transaction).await?;
+    Ok(())
+}
+
+/// The transaction of `conn` when it is a `Connection::Transaction`, or else the one of
+/// the innermost `atomic` block on its database, if any.
+pub(crate) fn current(conn: &Connection) -> Option<Shared> {
+    if let Some(transaction) = conn.transaction() {
+        return Some(transaction.clone());
+    }
+    TRANSACTIONS
+        .try_with(|scopes| {
+            if scopes.is_empty() {
+                return None;
+            }
+            let options = conn.connect_options();
+            scopes
+                .iter()
+                .rev()
+                .find(|scope| Arc::ptr_eq(&scope.options, &options))
+                .map(|scope| scope.transaction.clone())
+        })
+        .ok()
+        .flatten()
+}
+
+/// A connection running statements on `conn`: the transaction of the `atomic` block on
+/// `conn`, or a connection of the pool outside of it.
+pub(crate) enum Connected {
+    Pool(PoolConnection<Any>),
+    Transaction(
+        OwnedMappedMutexGuard<Option<Transaction<'static, Any>>, Transaction<'static, Any>>,
+    ),
+}
+
+/// Acquires the connection of `conn` for several statements, e.g. to `begin` a
+/// transaction (a savepoint within an `atomic` block).
+pub(crate) async fn acquire(conn: &Connection) -> Result<Connected, sqlx::Error> {
+    match current(conn) {
+        Some(transaction) => {
+            OwnedMutexGuard::try_map(transaction.lock_owned().await, Option::as_mut)
+                .map(Connected::Transaction)
+                .map_err(|_| finished())
+        }
+        None => Ok(Connected::Pool(conn.pool().acquire().await?)),
+    }
+}
+
+impl Deref for Connected {
+    type Target = AnyConnection;
+
+    fn deref(&self) -> &AnyConnection {
+        match self {
+            Connected::Pool(connection) => connection,
+            Connected::Transaction(transaction) => transaction,
+        }
+    }
+}
+
+impl DerefMut for Connected {
+    fn deref_mut(&mut self) -> &mut AnyConnection {
+        match self {
+            Connected::Pool(connection) => connection,
+            Connected::Transaction(transaction) => transaction,
+        }
+    }
+}
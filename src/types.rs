@@ -1,9 +1,435 @@
+use std::{fmt, str::FromStr};
+
+use serde_json::Value;
+
+use sqlx::{
+    any::{Any, AnyTypeInfo, AnyValueRef},
+    database::HasArguments,
+    encode::IsNull,
+    error::BoxDynError,
+    Decode, Encode, Type,
+};
+
+use crate::db::expression::Kwarg;
+use crate::db::models::{comparison_lookup, Condition};
+use crate::{Backend, BACKEND};
+
 #[cfg(feature = "postgres")]
 pub type Serial = i32;
 
 pub type Integer = i32;
 pub type Text = String;
+pub type CiText = String;
 pub type Float = f64;
 pub type Date = String;
 pub type DateTime = String;
 pub type Boolean = i32;
+pub type Uuid = String;
+
+/// A value bound to a query, built from the values of the conditions by `Into<SqlValue>`
+/// so each type is bound natively rather than through its text.
+///
+/// # Example
+/// ```
+/// use rusql_alchemy::types::SqlValue;
+///
+/// assert_eq!(SqlValue::from(42_i64), SqlValue::BigInt(42));
+/// assert_eq!(SqlValue::from(None::<i32>), SqlValue::Null);
+/// assert_eq!(
+///     SqlValue::from(vec![1_i32, 2]),
+///     SqlValue::List(vec![SqlValue::Int(1), SqlValue::Int(2)])
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue {
+    Null,
+    /// Bound as an integer, the `Boolean` columns being integers.
+    Bool(bool),
+    Int(i32),
+    BigInt(i64),
+    Float(f64),
+    Text(String),
+    Bytes(Vec<u8>),
+    /// The values of an `__in` lookup, each bound as its own argument.
+    List(Vec<SqlValue>),
+    /// The `field__lookup` of a lookup which is not supported, failing to bind with an
+    /// `Error::Invalid` so the query is never executed.
+    Unsupported(String),
+}
+
+impl SqlValue {
+    /// Checks the value can be bound, a list being only bound element by element by the
+    /// `__in` lookup.
+    pub(crate) fn check(&self) -> Result<(), crate::Error> {
+        match self {
+            Self::Unsupported(lookup) => Err(crate::Error::Invalid(format!(
+                "unsupported lookup '{lookup}'"
+            ))),
+            Self::List(_) => Err(crate::Error::Invalid(
+                "a list is only supported by the __in lookup".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// The value as JSON, the booleans being `1` or `0` as they are stored.
+    pub fn to_json(&self) -> Value {
+        match self {
+            Self::Null | Self::Unsupported(_) => Value::Null,
+            Self::Bool(value) => Value::from(*value as i32),
+            Self::Int(value) => Value::from(*value),
+            Self::BigInt(value) => Value::from(*value),
+            Self::Float(value) => Value::from(*value),
+            Self::Text(value) => Value::from(value.as_str()),
+            Self::Bytes(value) => Value::from(value.as_slice()),
+            Self::List(values) => Value::Array(values.iter().map(Self::to_json).collect()),
+        }
+    }
+}
+
+impl fmt::Display for SqlValue {
+    /// Renders the value as JSON, e.g. in the keys of the object cache.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_json())
+    }
+}
+
+/// Implements the conversion of rust values into a `SqlValue` variant.
+macro_rules! sql_value {
+    ($($variant:ident($($type:ty),*)),*) => {
+        $($(
+            impl From<$type> for SqlValue {
+                fn from(value: $type) -> Self {
+                    Self::$variant(value.into())
+                }
+            }
+        )*)*
+    };
+}
+
+sql_value!(
+    Bool(bool),
+    Int(i8, i16, i32, u8, u16),
+    BigInt(i64, u32),
+    Float(f32, f64),
+    Text(String, &str, &String),
+    Bytes(Vec<u8>, &[u8])
+);
+
+/// Implements the conversion of the lists of the `__in` lookups into a `SqlValue::List`.
+macro_rules! sql_list {
+    ($($type:ty),*) => {
+        $(
+            impl From<Vec<$type>> for SqlValue {
+                fn from(values: Vec<$type>) -> Self {
+                    Self::List(values.into_iter().map(Into::into).collect())
+                }
+            }
+
+            impl From<&[$type]> for SqlValue {
+                fn from(values: &[$type]) -> Self {
+                    Self::List(values.iter().cloned().map(Into::into).collect())
+                }
+            }
+        )*
+    };
+}
+
+sql_list!(i32, i64, u32, f64, String, &str);
+
+impl<T: Into<SqlValue>> From<Option<T>> for SqlValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+impl From<Value> for SqlValue {
+    /// Converts a JSON value, the arrays and objects being bound as their JSON text.
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => Self::Null,
+            Value::Bool(value) => Self::Bool(value),
+            Value::Number(number) => match number.as_i64() {
+                Some(number) => Self::BigInt(number),
+                None => Self::Float(number.as_f64().unwrap_or_default()),
+            },
+            Value::String(text) => Self::Text(text),
+            json => Self::Text(json.to_string()),
+        }
+    }
+}
+
+impl From<&Value> for SqlValue {
+    fn from(value: &Value) -> Self {
+        value.clone().into()
+    }
+}
+
+impl Type<Any> for SqlValue {
+    fn type_info() -> AnyTypeInfo {
+        <String as Type<Any>>::type_info()
+    }
+}
+
+impl<'q> Encode<'q, Any> for SqlValue {
+    fn encode_by_ref(&self, buf: &mut <Any as HasArguments<'q>>::ArgumentBuffer) -> IsNull {
+        match self {
+            Self::Null => <Option<String> as Encode<Any>>::encode(None, buf),
+            Self::Bool(value) => <i32 as Encode<Any>>::encode(*value as i32, buf),
+            Self::Int(value) => <i32 as Encode<Any>>::encode(*value, buf),
+            Self::BigInt(value) => <i64 as Encode<Any>>::encode(*value, buf),
+            Self::Float(value) => <f64 as Encode<Any>>::encode(*value, buf),
+            Self::Text(value) => <String as Encode<Any>>::encode(value.clone(), buf),
+            Self::Bytes(value) => <Vec<u8> as Encode<Any>>::encode(value.clone(), buf),
+            // rejected by `binds!` before being bound
+            Self::List(_) | Self::Unsupported(_) => {
+                <Option<String> as Encode<Any>>::encode(None, buf)
+            }
+        }
+    }
+}
+
+/// Implements the text representation `[lower,upper)` of a half-open range type.
+macro_rules! range {
+    ($name:ident, $bound:ty, $range_type:literal) => {
+        impl $name {
+            pub fn new(lower: impl Into<$bound>, upper: impl Into<$bound>) -> Self {
+                Self {
+                    lower: lower.into(),
+                    upper: upper.into(),
+                }
+            }
+
+            /// Returns `true` if `lower <= value < upper`.
+            pub fn contains(&self, value: &$bound) -> bool {
+                &self.lower <= value && value < &self.upper
+            }
+
+            /// Returns `true` if the two ranges share at least one value.
+            pub fn overlaps(&self, other: &Self) -> bool {
+                self.lower < other.upper && other.lower < self.upper
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "[{},{})", self.lower, self.upper)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = BoxDynError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                // Postgres reads an empty range, e.g. `[0,0)`, as `empty`
+                if s.trim() == "empty" {
+                    return Ok(Self::default());
+                }
+                let (lower, upper) = s
+                    .trim()
+                    .trim_start_matches(['[', '('])
+                    .trim_end_matches([')', ']'])
+                    .split_once(',')
+                    .ok_or_else(|| format!("invalid range '{s}'"))?;
+                Ok(Self {
+                    lower: lower.trim().trim_matches('"').parse()?,
+                    upper: upper.trim().trim_matches('"').parse()?,
+                })
+            }
+        }
+
+        impl From<$name> for serde_json::Value {
+            fn from(range: $name) -> Self {
+                serde_json::Value::String(range.to_string())
+            }
+        }
+
+        impl Kwarg for $name {
+            /// Binds the `[lower,upper)` text of the range, cast to the native range type
+            /// on Postgres when the range is compared or set as a whole.
+            fn condition(self, field: &str, operator: &str) -> Condition {
+                let value = SqlValue::Text(self.to_string());
+                let whole = !field.contains("__") || comparison_lookup(field).is_some();
+                if *BACKEND == Backend::Postgres && whole {
+                    return Condition::Expression {
+                        field: field.to_string(),
+                        sql: format!("cast(? as {})", $range_type),
+                        args: vec![value],
+                        comparaison_operator: operator.to_string(),
+                    };
+                }
+                value.condition(field, operator)
+            }
+        }
+
+        impl Type<Any> for $name {
+            fn type_info() -> AnyTypeInfo {
+                <String as Type<Any>>::type_info()
+            }
+        }
+
+        impl<'q> Encode<'q, Any> for $name {
+            fn encode_by_ref(&self, buf: &mut <Any as HasArguments<'q>>::ArgumentBuffer) -> IsNull {
+                <String as Encode<Any>>::encode(self.to_string(), buf)
+            }
+        }
+
+        impl<'r> Decode<'r, Any> for $name {
+            fn decode(value: AnyValueRef<'r>) -> Result<Self, BoxDynError> {
+                <String as Decode<Any>>::decode(value)?.parse()
+            }
+        }
+    };
+}
+
+/// A half-open range of integers, an `int4range` on Postgres and a `[lower,upper)` text
+/// (with its generated bound columns) elsewhere.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Int4Range {
+    pub lower: Integer,
+    pub upper: Integer,
+}
+
+/// A half-open range of timestamps, a `tsrange` on Postgres and a `[lower,upper)` text
+/// (with its generated bound columns) elsewhere.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TsRange {
+    pub lower: DateTime,
+    pub upper: DateTime,
+}
+
+range!(Int4Range, Integer, "int4range");
+range!(TsRange, DateTime, "tsrange");
+
+/// A value per locale, stored as a JSON object of the locales to their values, e.g.
+/// `{"en":"Hello","fr":"Bonjour"}`.
+///
+/// # Example
+/// ```ignore
+/// #[derive(Model, FromRow, Clone, Default)]
+/// struct Article {
+///     #[model(primary_key = true, auto = true)]
+///     id: Integer,
+///     title: Translated<Text>,
+/// }
+///
+/// let article = Article {
+///     title: Translated::new().with("en", "Hello").with("fr", "Bonjour"),
+///     ..Default::default()
+/// };
+/// assert_eq!(article.title.get_localized("fr-CA"), Some(&"Bonjour".to_string()));
+///
+/// // filter on the value of a locale
+/// let articles = Article::filter(localized(F("title"), "fr").compare("=", "Bonjour"), &conn).await;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct Translated<T = Text> {
+    values: std::collections::BTreeMap<String, T>,
+}
+
+impl<T> Default for Translated<T> {
+    fn default() -> Self {
+        Self {
+            values: Default::default(),
+        }
+    }
+}
+
+impl<T> Translated<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value of `locale`, returning the updated translations.
+    pub fn with(mut self, locale: impl Into<String>, value: impl Into<T>) -> Self {
+        self.set(locale, value);
+        self
+    }
+
+    /// Sets the value of `locale`, returning the previous one.
+    pub fn set(&mut self, locale: impl Into<String>, value: impl Into<T>) -> Option<T> {
+        self.values.insert(locale.into(), value.into())
+    }
+
+    /// Removes the value of `locale`, returning it.
+    pub fn remove(&mut self, locale: &str) -> Option<T> {
+        self.values.remove(locale)
+    }
+
+    /// The value of exactly `locale`.
+    pub fn get(&self, locale: &str) -> Option<&T> {
+        self.values.get(locale)
+    }
+
+    /// The value of `locale`, falling back on its language, `fr` for `fr-CA` or
+    /// `fr_CA`, when the region has no value of its own.
+    pub fn get_localized(&self, locale: &str) -> Option<&T> {
+        self.get(locale).or_else(|| {
+            let (language, _) = locale.split_once(['-', '_'])?;
+            self.get(language)
+        })
+    }
+
+    /// The value of `locale` as `get_localized`, falling back on `default` otherwise.
+    pub fn get_localized_or(&self, locale: &str, default: &str) -> Option<&T> {
+        self.get_localized(locale)
+            .or_else(|| self.get_localized(default))
+    }
+
+    /// The locales with a value, in alphabetical order.
+    pub fn locales(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl<T: serde::Serialize> fmt::Display for Translated<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_string(&self.values).map_err(|_| fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
+impl<T: serde::de::DeserializeOwned> FromStr for Translated<T> {
+    type Err = BoxDynError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            values: serde_json::from_str(s)?,
+        })
+    }
+}
+
+impl<T: serde::Serialize> From<Translated<T>> for serde_json::Value {
+    fn from(translated: Translated<T>) -> Self {
+        serde_json::Value::String(translated.to_string())
+    }
+}
+
+impl<T: serde::Serialize> From<Translated<T>> for SqlValue {
+    fn from(translated: Translated<T>) -> Self {
+        SqlValue::Text(translated.to_string())
+    }
+}
+
+impl<T> Type<Any> for Translated<T> {
+    fn type_info() -> AnyTypeInfo {
+        <String as Type<Any>>::type_info()
+    }
+}
+
+impl<'q, T: serde::Serialize> Encode<'q, Any> for Translated<T> {
+    fn encode_by_ref(&self, buf: &mut <Any as HasArguments<'q>>::ArgumentBuffer) -> IsNull {
+        <String as Encode<Any>>::encode(self.to_string(), buf)
+    }
+}
+
+impl<'r, T: serde::de::DeserializeOwned> Decode<'r, Any> for Translated<T> {
+    fn decode(value: AnyValueRef<'r>) -> Result<Self, BoxDynError> {
+        <String as Decode<Any>>::decode(value)?.parse()
+    }
+}
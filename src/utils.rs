@@ -4,14 +4,26 @@ pub fn get_type_name<T: Sized>(_: T) -> &'static str {
     type_name::<T>()
 }
 
-pub fn get_placeholder() -> std::io::Result<&'static str> {
-    let database_url = std::env::var("DATABASE_URL").map_err(|_| {
-        std::io::Error::new(std::io::ErrorKind::NotFound, "DATABASE_URL is not found")
-    })?;
-    if database_url.starts_with("sqlite") || database_url.starts_with("mysql") {
-        Ok("?")
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Sqlite,
+    MySql,
+    Postgres,
+}
+
+pub fn get_database_url() -> std::io::Result<String> {
+    std::env::var("DATABASE_URL")
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::NotFound, "DATABASE_URL is not found"))
+}
+
+pub fn get_backend() -> std::io::Result<Backend> {
+    let database_url = get_database_url()?;
+    if database_url.starts_with("sqlite") {
+        Ok(Backend::Sqlite)
+    } else if database_url.starts_with("mysql") {
+        Ok(Backend::MySql)
     } else if database_url.starts_with("postgres") {
-        Ok("$")
+        Ok(Backend::Postgres)
     } else {
         Err(Error::new(
             std::io::ErrorKind::InvalidData,
@@ -20,6 +32,13 @@ pub fn get_placeholder() -> std::io::Result<&'static str> {
     }
 }
 
+pub fn get_placeholder() -> std::io::Result<&'static str> {
+    match get_backend()? {
+        Backend::Sqlite | Backend::MySql => Ok("?"),
+        Backend::Postgres => Ok("$"),
+    }
+}
+
 pub fn to_string(value: impl Into<serde_json::Value>) -> String {
     let json_value = value.into();
     match json_value {
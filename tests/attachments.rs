@@ -0,0 +1,123 @@
+//! The `S3BlobStore` against a minimal in-process S3 endpoint, which keeps the objects of
+//! one bucket in memory.
+#![cfg(feature = "s3")]
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+use rusql_alchemy::attachment::{BlobStore, S3BlobStore};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+type Objects = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+/// Serves the `put`, `get`, `head` and `delete` object requests on a local port,
+/// returning its address.
+async fn serve(objects: Objects) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(handle(stream, objects.clone()));
+        }
+    });
+    format!("http://{address}")
+}
+
+/// Answers the requests of a (keep-alive) connection.
+async fn handle(stream: TcpStream, objects: Objects) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts.next().unwrap_or_default();
+        let key = path.split('?').next().unwrap_or_default().to_string();
+
+        let mut headers = HashMap::new();
+        loop {
+            let mut header = String::new();
+            stream.read_line(&mut header).await?;
+            match header.trim_end().split_once(':') {
+                Some((name, value)) => {
+                    headers.insert(name.to_lowercase(), value.trim().to_string());
+                }
+                None => break,
+            }
+        }
+        let length = headers
+            .get("content-length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).await?;
+
+        let (status, content) = {
+            let mut objects = objects.lock().unwrap();
+            match (method.as_str(), objects.get(&key)) {
+                ("PUT", _) => {
+                    objects.insert(key, body);
+                    ("200 OK", Vec::new())
+                }
+                ("GET", Some(object)) => ("200 OK", object.clone()),
+                ("GET", None) => {
+                    let error = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+                        <Error><Code>NoSuchKey</Code><Message>no such key</Message></Error>";
+                    ("404 Not Found", error.as_bytes().to_vec())
+                }
+                ("HEAD", Some(_)) => ("200 OK", Vec::new()),
+                ("HEAD", None) => ("404 Not Found", Vec::new()),
+                ("DELETE", _) => {
+                    objects.remove(&key);
+                    ("204 No Content", Vec::new())
+                }
+                _ => ("405 Method Not Allowed", Vec::new()),
+            }
+        };
+        let head = format!(
+            "HTTP/1.1 {status}\r\ncontent-length: {}\r\n\r\n",
+            content.len()
+        );
+        stream.get_mut().write_all(head.as_bytes()).await?;
+        stream.get_mut().write_all(&content).await?;
+    }
+}
+
+async fn connect(objects: Objects) -> S3BlobStore {
+    let config = aws_sdk_s3::Config::builder()
+        .behavior_version(BehaviorVersion::latest())
+        .endpoint_url(serve(objects).await)
+        .force_path_style(true)
+        .region(Region::new("us-east-1"))
+        .credentials_provider(Credentials::new("key", "secret", None, None, "tests"))
+        .build();
+    S3BlobStore::new(aws_sdk_s3::Client::from_conf(config), "uploads")
+}
+
+#[tokio::test]
+async fn s3_blob_store() {
+    let objects = Objects::default();
+    let blobs = connect(objects.clone()).await;
+
+    assert!(!blobs.exists("abcdef").await.unwrap());
+    let missing = blobs.get("abcdef").await.unwrap_err();
+    assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+
+    blobs.put("abcdef", b"content").await.unwrap();
+    assert_eq!(
+        objects.lock().unwrap().get("/uploads/abcdef").unwrap(),
+        b"content"
+    );
+    assert!(blobs.exists("abcdef").await.unwrap());
+    assert_eq!(blobs.get("abcdef").await.unwrap(), b"content");
+
+    blobs.delete("abcdef").await.unwrap();
+    assert!(!blobs.exists("abcdef").await.unwrap());
+    // deleting a missing blob succeeds
+    blobs.delete("abcdef").await.unwrap();
+}
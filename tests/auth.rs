@@ -0,0 +1,101 @@
+//! The passwords and tokens of the `auth` users, on a temporary SQLite database.
+#![cfg(all(feature = "auth", feature = "sqlite"))]
+
+use std::time::Duration;
+
+use rusql_alchemy::auth::{bearer_token, check_password, make_password, Token, User};
+use rusql_alchemy::prelude::*;
+use tokio::sync::{Mutex, MutexGuard};
+
+/// The tests share one database, each resetting it, so they run one at a time.
+static DATABASE: Mutex<()> = Mutex::const_new(());
+
+async fn connect() -> (Connection, MutexGuard<'static, ()>) {
+    let guard = DATABASE.lock().await;
+    let path = std::env::temp_dir().join("rusql-alchemy-auth.sqlite");
+    std::env::set_var(
+        "DATABASE_URL",
+        format!("sqlite://{}?mode=rwc", path.display()),
+    );
+    let conn = Database::new().await.conn;
+    for table in ["auth_tokens", "auth_users"] {
+        let drop = format!("drop table if exists {table};");
+        sqlx::query(&drop).execute(&conn).await.unwrap();
+    }
+    migrate!([User, Token], &conn);
+    (conn, guard)
+}
+
+#[test]
+fn password_round_trip() {
+    let hash = make_password("correct horse battery staple");
+    assert!(hash.starts_with("pbkdf2_sha256$600000$"));
+    assert!(check_password("correct horse battery staple", &hash));
+    assert!(!check_password("Correct horse battery staple", &hash));
+    // the salt is random
+    assert_ne!(hash, make_password("correct horse battery staple"));
+    assert!(!check_password("", "md5$1$salt$hash"));
+}
+
+#[test]
+fn bearer_tokens() {
+    assert_eq!(bearer_token("Bearer abc"), Some("abc"));
+    assert_eq!(bearer_token("bearer  abc "), Some("abc"));
+    assert_eq!(bearer_token("Basic abc"), None);
+    assert_eq!(bearer_token("Bearer "), None);
+}
+
+#[tokio::test]
+async fn authenticate() {
+    let (conn, _guard) = connect().await;
+    let user = User::create_user("joe", "secret", &conn).await.unwrap();
+    assert_eq!(user.is_active, 1);
+
+    assert_eq!(
+        User::authenticate("joe", "secret", &conn).await.unwrap().id,
+        user.id
+    );
+    assert!(User::authenticate("joe", "wrong", &conn).await.is_none());
+    assert!(User::authenticate("ann", "secret", &conn).await.is_none());
+
+    // the inactive users can neither log in nor use their tokens
+    let token = user
+        .issue_token(Duration::from_secs(3600), &conn)
+        .await
+        .unwrap();
+    assert_eq!(Token::verify(&token, &conn).await.unwrap().id, user.id);
+    user.update_fields(kwargs!(is_active = false), &conn)
+        .await
+        .unwrap();
+    assert!(User::authenticate("joe", "secret", &conn).await.is_none());
+    assert!(Token::verify(&token, &conn).await.is_none());
+}
+
+#[tokio::test]
+async fn tokens() {
+    let (conn, _guard) = connect().await;
+    let mut user = User::create_user("joe", "secret", &conn).await.unwrap();
+
+    let expired = user.issue_token(Duration::ZERO, &conn).await.unwrap();
+    assert!(Token::verify(&expired, &conn).await.is_none());
+    assert!(Token::verify("not a token", &conn).await.is_none());
+
+    let revoked = user
+        .issue_token(Duration::from_secs(3600), &conn)
+        .await
+        .unwrap();
+    Token::revoke(&revoked, &conn).await.unwrap();
+    assert!(Token::verify(&revoked, &conn).await.is_none());
+
+    // changing the password revokes the existing tokens
+    let token = user
+        .issue_token(Duration::from_secs(3600), &conn)
+        .await
+        .unwrap();
+    user.set_password("new secret", &conn).await.unwrap();
+    assert!(Token::verify(&token, &conn).await.is_none());
+    assert!(User::authenticate("joe", "secret", &conn).await.is_none());
+    assert!(User::authenticate("joe", "new secret", &conn)
+        .await
+        .is_some());
+}
@@ -0,0 +1,1019 @@
+//! Runs the model API against the backend of `DATABASE_URL` (a temporary SQLite
+//! database by default) and compares the SQL it generates with the snapshots of that
+//! backend under `tests/snapshots`, see "Testing against each backend" in the README.
+
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use rusql_alchemy::migration::Migrations;
+use rusql_alchemy::outbox::{self, OutboxEvent};
+use rusql_alchemy::prelude::*;
+use rusql_alchemy::{
+    assert_query_snapshot, atomic, atomic_with, capture_queries, with_query_budget, Backend, Error,
+    IsolationLevel, QueryBudget, SavepointOperation, BACKEND,
+};
+use sqlx::{AnyConnection, Row};
+use tokio::sync::{Mutex, MutexGuard};
+
+#[derive(FromRow, Clone, Debug, Default, Model)]
+struct Author {
+    #[model(primary_key = true, auto = true, null = false)]
+    id: Integer,
+    #[model(unique = true, null = false, size = 50)]
+    name: String,
+    #[model(default = "reader")]
+    role: String,
+    active: Boolean,
+}
+
+#[derive(FromRow, Clone, Debug, Default, Model)]
+#[model(index(Price))]
+struct Book {
+    #[model(primary_key = true, auto = true, null = false)]
+    id: Integer,
+    #[model(null = false, index = true)]
+    title: String,
+    price: Float,
+    #[model(foreign_key = "Author.id")]
+    author: Integer,
+}
+
+#[derive(FromRow, Clone, Debug, Default, Model)]
+struct Shelf {
+    #[model(primary_key = true, null = false)]
+    room: Integer,
+    #[model(primary_key = true, null = false)]
+    slot: Integer,
+    label: String,
+}
+
+#[derive(FromRow, Clone, Debug, Default, Model)]
+struct Booking {
+    #[model(primary_key = true, auto = true, null = false)]
+    id: Integer,
+    seats: Int4Range,
+}
+
+#[derive(FromRow, Clone, Debug, Default, Model)]
+struct Tag {
+    #[model(primary_key = true, null = false, size = 20)]
+    name: String,
+}
+
+#[derive(FromRow, Clone, Debug, Default, Model)]
+#[model(partition_by(range = "day"))]
+struct Visit {
+    #[model(primary_key = true, null = false)]
+    room: Integer,
+    #[model(primary_key = true, null = false, size = 10)]
+    day: String,
+}
+
+#[derive(FromRow, Clone, Debug, Default, Model)]
+#[model(materialized_view = "select id, name from authors where active = 1")]
+struct ActiveAuthor {
+    #[model(primary_key = true)]
+    id: Integer,
+    name: String,
+}
+
+#[derive(FromRow, Clone, Debug, Default, Model)]
+#[model(retention(days = 30, on = "at", archive = "events_archive"))]
+struct Event {
+    #[model(primary_key = true, auto = true, null = false)]
+    id: Integer,
+    at: DateTime,
+}
+
+#[derive(FromRow, Clone, Debug, Default, Model)]
+struct Diary {
+    #[model(primary_key = true, auto = true, null = false)]
+    id: Integer,
+    #[model(foreign_key = "Author.id")]
+    author: Integer,
+}
+
+/// The tests share one database, each resetting the tables it uses, so they run one
+/// at a time.
+static DATABASE: Mutex<()> = Mutex::const_new(());
+
+/// Connects to the database of the tests, dropping the `tables` first.
+async fn connect(tables: &[&str]) -> (Connection, MutexGuard<'static, ()>) {
+    let guard = DATABASE.lock().await;
+    if std::env::var_os("DATABASE_URL").is_none() {
+        let path = std::env::temp_dir().join("rusql-alchemy-tests.sqlite");
+        std::env::set_var(
+            "DATABASE_URL",
+            format!("sqlite://{}?mode=rwc", path.display()),
+        );
+    }
+    let conn = Database::new().await.conn;
+    for table in tables {
+        let drop = format!("drop table if exists {table};");
+        sqlx::query(&drop).execute(&conn).await.unwrap();
+    }
+    (conn, guard)
+}
+
+#[tokio::test]
+async fn schema() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    let (migrated, queries) = capture_queries(async {
+        Author::migrate(&conn).await?;
+        Book::migrate(&conn).await
+    })
+    .await;
+    migrated.unwrap();
+    assert_query_snapshot("schema", &queries);
+}
+
+#[tokio::test]
+async fn crud() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author, Book], &conn);
+
+    let (_, queries) = capture_queries(async {
+        Author::create(kwargs!(name = "Ursula", active = true), &conn)
+            .await
+            .unwrap();
+        Author::create(kwargs!(name = "Terry", active = false), &conn)
+            .await
+            .unwrap();
+        let ursula = Author::get(kwargs!(name == "Ursula"), &conn).await.unwrap();
+        assert_eq!(ursula.role, "reader");
+        assert_eq!(ursula.active, 1);
+
+        Book::create(
+            kwargs!(title = "Earthsea", price = 12.5, author = ursula.id),
+            &conn,
+        )
+        .await
+        .unwrap();
+        let terry = Author::get(kwargs!(active == false), &conn).await.unwrap();
+        Book::create(
+            kwargs!(title = "Mort", price = 8.0, author = terry.id),
+            &conn,
+        )
+        .await
+        .unwrap();
+
+        let cheap = Book::filter(kwargs!(price__lt = 10.0), &conn).await;
+        assert_eq!(cheap.len(), 1);
+        let titled = Book::filter(kwargs!(title__contains = "sea"), &conn).await;
+        assert_eq!(titled[0].title, "Earthsea");
+        let authored = Book::filter(kwargs!(author__isnull = false), &conn).await;
+        assert_eq!(authored.len(), 2);
+        let listed = Book::filter(kwargs!(title__in = vec!["Mort", "Dune"]), &conn).await;
+        assert_eq!(listed.len(), 1);
+
+        let mut book = listed[0].clone();
+        book.price = 9.5;
+        assert_eq!(book.update(&conn).await.unwrap(), 1);
+        assert_eq!(Book::get_by_pk(book.id, &conn).await.unwrap().price, 9.5);
+
+        book.delete(&conn).await.unwrap();
+        assert_eq!(Book::count(&conn).await, 1);
+        assert_eq!(Author::count_where(kwargs!(active == true), &conn).await, 1);
+    })
+    .await;
+    assert_query_snapshot("crud", &queries);
+}
+
+#[tokio::test]
+async fn typed_ordering() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author, Book], &conn);
+    Author::create(kwargs!(name = "Ursula", active = true), &conn)
+        .await
+        .unwrap();
+    let author = Author::get(kwargs!(name == "Ursula"), &conn).await.unwrap();
+    for (title, price) in [
+        ("Earthsea", 12.5),
+        ("Lavinia", 8.0),
+        ("Always Coming Home", 12.5),
+    ] {
+        Book::create(
+            kwargs!(title = title, price = price, author = author.id),
+            &conn,
+        )
+        .await
+        .unwrap();
+    }
+
+    let (_, queries) = capture_queries(async {
+        let books = Book::objects()
+            .order_by(&[desc(BookColumn::Price)])
+            .order_by(&[BookColumn::Title])
+            .all(&conn)
+            .await;
+        let titles = books
+            .iter()
+            .map(|book| book.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Always Coming Home", "Earthsea", "Lavinia"]);
+
+        let cheapest = Book::objects()
+            .order_by(&[desc(BookColumn::Price)])
+            .last(&conn)
+            .await;
+        assert_eq!(cheapest.unwrap().title, "Lavinia");
+
+        let books = Book::all_ordered(
+            &conn,
+            &[desc(coalesce(F("price"), 0.0)), asc(BookColumn::Title)],
+        )
+        .await
+        .unwrap();
+        assert_eq!(books[0].title, "Always Coming Home");
+
+        let page = Book::objects()
+            .order_by(&[desc(BookColumn::Price)])
+            .page_after(None, 2, &conn)
+            .await;
+        assert_eq!(page.unwrap().items.len(), 2);
+    })
+    .await;
+    assert_query_snapshot("typed_ordering", &queries);
+}
+
+#[tokio::test]
+async fn archive_gains_columns() {
+    let (conn, _guard) = connect(&["authors_archive", "books", "authors"]).await;
+    migrate!([Author], &conn);
+    // the archive of the model before it gained the `role` and `active` columns
+    sqlx::query("create table authors_archive (id integer, name varchar(50));")
+        .execute(&conn)
+        .await
+        .unwrap();
+    for name in ["Ursula", "Terry", "Iain"] {
+        Author::create(kwargs!(name = name, role = "author", active = true), &conn)
+            .await
+            .unwrap();
+    }
+
+    let archived = Author::archive_where(kwargs!(name != "Iain"), &conn)
+        .await
+        .unwrap();
+    assert_eq!(archived, 2);
+    assert_eq!(Author::count(&conn).await, 1);
+    let roles = sqlx::query_scalar::<_, String>("select role from authors_archive order by name;")
+        .fetch_all(&conn)
+        .await
+        .unwrap();
+    assert_eq!(roles, ["author", "author"]);
+}
+
+#[tokio::test]
+async fn unsupported_lookup() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author, Book], &conn);
+
+    let books = Book::try_filter(kwargs!(price__covers = 10.0), &conn).await;
+    assert!(matches!(books, Err(Error::Invalid(_))));
+}
+
+#[tokio::test]
+async fn composite_chunks() {
+    let (conn, _guard) = connect(&["shelves"]).await;
+    migrate!([Shelf], &conn);
+    for room in 1..=3 {
+        for slot in 1..=3 {
+            Shelf::create(kwargs!(room = room, slot = slot, label = "shelf"), &conn)
+                .await
+                .unwrap();
+        }
+    }
+
+    let (_, queries) = capture_queries(async {
+        let mut chunks = Shelf::iter_chunks(4, &conn);
+        let mut keys = Vec::new();
+        while let Some(chunk) = chunks.next().await {
+            keys.extend(chunk.unwrap().iter().map(|shelf| (shelf.room, shelf.slot)));
+        }
+        let expected = (1..=3)
+            .flat_map(|room| (1..=3).map(move |slot| (room, slot)))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, expected);
+    })
+    .await;
+    assert_query_snapshot("composite_chunks", &queries);
+}
+
+#[tokio::test]
+async fn range_lookups() {
+    let (conn, _guard) = connect(&["bookings"]).await;
+    let (_, queries) = capture_queries(async {
+        Booking::migrate(&conn).await.unwrap();
+        Booking::create(kwargs!(seats = Int4Range::new(1, 10)), &conn)
+            .await
+            .unwrap();
+        assert_eq!(
+            Booking::filter(kwargs!(seats__contains = 5), &conn)
+                .await
+                .len(),
+            1
+        );
+        assert_eq!(
+            Booking::filter(kwargs!(seats__contains = 10), &conn)
+                .await
+                .len(),
+            0
+        );
+        let overlapping = kwargs!(seats__overlaps = Int4Range::new(9, 20));
+        assert_eq!(Booking::filter(overlapping, &conn).await.len(), 1);
+        // the ranges are read back and compared as a whole
+        let bookings = Booking::filter(kwargs!(seats = Int4Range::new(1, 10)), &conn).await;
+        assert_eq!(bookings.len(), 1);
+        assert_eq!(bookings[0].seats, Int4Range::new(1, 10));
+    })
+    .await;
+    assert_query_snapshot("range_lookups", &queries);
+
+    // the generated bound columns are part of the schema of the model
+    let migrations = Migrations::new(std::env::temp_dir()).with_model::<Booking>();
+    assert!(migrations.diff(&conn).await.is_empty());
+}
+
+#[tokio::test]
+async fn database_transaction() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author], &conn);
+    let database = Database::new().await;
+
+    let rolled_back: Result<(), Error> = database
+        .transaction(|tx| async move {
+            Author::create(kwargs!(name = "Ursula", active = true), &tx).await?;
+            sqlx::query("update authors set role = 'author';")
+                .execute(&tx)
+                .await?;
+            assert_eq!(Author::count(&tx).await, 1);
+            Err(Error::Invalid("rolled back".to_string()))
+        })
+        .await;
+    assert!(matches!(rolled_back, Err(Error::Invalid(_))));
+    assert_eq!(Author::count(&conn).await, 0);
+
+    // the connection of the transaction outlives it, failing once it is committed
+    let tx = database
+        .transaction(|tx| async move {
+            Author::create(kwargs!(name = "Terry", active = true), &tx).await?;
+            Ok::<_, Error>(tx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(Author::count(&conn).await, 1);
+    assert!(sqlx::query("select 1;").execute(&tx).await.is_err());
+    assert!(Author::create(kwargs!(name = "Iain", active = true), &tx)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn atomic_reads() {
+    let (conn, _guard) = connect(&["outbox_events", "books", "authors"]).await;
+    migrate!([Author, OutboxEvent], &conn);
+
+    let atomic_reads: Result<(), Error> = atomic(&conn, async {
+        Author::create(kwargs!(name = "Ursula", active = true), &conn).await?;
+        outbox::enqueue(
+            "author.created",
+            &serde_json::json!({ "name": "Ursula" }),
+            &conn,
+        )
+        .await?;
+        // the uncommitted writes of the block are read within its transaction
+        let counted = Author::aggregate(&conn).count("total").run().await;
+        assert_eq!(counted[0]["total"], 1);
+        let delivered = outbox::relay(
+            &conn,
+            10,
+            |event| async move { event.topic == "author.created" },
+        )
+        .await;
+        assert_eq!(delivered, 1);
+        Err(Error::Invalid("rolled back".to_string()))
+    })
+    .await;
+    assert!(matches!(atomic_reads, Err(Error::Invalid(_))));
+    assert_eq!(Author::count(&conn).await, 0);
+    assert_eq!(OutboxEvent::count(&conn).await, 0);
+}
+
+#[tokio::test]
+async fn nested_cursors() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author], &conn);
+    for name in ["Ursula", "Terry", "Iain"] {
+        Author::create(kwargs!(name = name, active = true), &conn)
+            .await
+            .unwrap();
+    }
+
+    // two cursors over the same model are open at once in the block
+    let read: Result<(usize, usize), Error> = atomic(&conn, async {
+        let mut outer = Author::cursor(2, &conn);
+        let mut read = (outer.next().await.unwrap()?.len(), 0);
+        let mut inner = Author::cursor(2, &conn);
+        while let Some(authors) = inner.next().await {
+            read.1 += authors?.len();
+        }
+        while let Some(authors) = outer.next().await {
+            read.0 += authors?.len();
+        }
+        Ok(read)
+    })
+    .await;
+    assert_eq!(read.unwrap(), (3, 3));
+}
+
+#[tokio::test]
+async fn missing_pks() {
+    let (conn, _guard) = connect(&["tags", "books", "authors"]).await;
+    migrate!([Author], &conn);
+    for name in ["Ursula", "Terry", "Iain"] {
+        Author::create(kwargs!(name = name, active = true), &conn)
+            .await
+            .unwrap();
+    }
+
+    // the keys are checked 500 at a time, and returned in order
+    let ids = (1..=2000).rev().collect::<Vec<_>>();
+    let (missing, queries) = capture_queries(Author::missing_pks(&ids, &conn)).await;
+    assert_eq!(missing.unwrap(), (4..=2000).rev().collect::<Vec<_>>());
+    assert_eq!(queries.len(), 4);
+    assert!(Author::missing_pks(&[], &conn).await.unwrap().is_empty());
+
+    // the keys are compared by the database, under the collation of the column
+    if *BACKEND == Backend::Sqlite {
+        let create = "create table tags (name varchar(20) collate nocase primary key not null);";
+        sqlx::query(create).execute(&conn).await.unwrap();
+    } else {
+        migrate!([Tag], &conn);
+    }
+    Tag::create(kwargs!(name = "Joe"), &conn).await.unwrap();
+    let names = ["joe".to_string(), "Joe".to_string(), "Ann".to_string()];
+    let missing = Tag::missing_pks(&names, &conn).await.unwrap();
+    if *BACKEND == Backend::Postgres {
+        assert_eq!(missing, ["joe", "Ann"]);
+    } else {
+        assert_eq!(missing, ["Ann"]);
+    }
+}
+
+#[tokio::test]
+async fn filter_annotated() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author, Book], &conn);
+    for name in ["Ursula", "Terry"] {
+        Author::create(kwargs!(name = name, active = true), &conn)
+            .await
+            .unwrap();
+    }
+    for title in ["Earthsea", "Lathe of Heaven"] {
+        Book::create(kwargs!(title = title, price = 9.5, author = 1), &conn)
+            .await
+            .unwrap();
+    }
+
+    let authors = Author::filter_annotated(
+        count_related::<Book>("books"),
+        kwargs!(books__gte = 2),
+        &conn,
+    )
+    .await
+    .unwrap();
+    assert_eq!(authors.len(), 1);
+    assert_eq!(authors[0].name, "Ursula");
+
+    // no foreign key of the shelves references the authors
+    let unrelated =
+        Author::filter_annotated(count_related::<Shelf>("shelves"), vec![], &conn).await;
+    assert!(matches!(unrelated, Err(Error::Invalid(_))));
+}
+
+#[tokio::test]
+async fn create_or_update() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author], &conn);
+
+    let (created, queries) = capture_queries(Author::create_or_update(
+        kwargs!(id = 1, name = "Ursula", role = "author", active = true),
+        &conn,
+    ))
+    .await;
+    assert!(created.unwrap().is_created());
+    assert!(!queries.is_empty());
+
+    // an update leaving the row unchanged is still an update
+    for role in ["author", "editor"] {
+        let updated = Author::create_or_update(
+            kwargs!(id = 1, name = "Ursula", role = role, active = true),
+            &conn,
+        )
+        .await
+        .unwrap();
+        assert!(!updated.is_created());
+        assert_eq!(updated.into_inner().role, role);
+    }
+    assert_eq!(Author::count(&conn).await, 1);
+
+    let missing = Author::create_or_update(kwargs!(name = "Terry", active = true), &conn).await;
+    assert!(matches!(missing, Err(Error::MissingPrimaryKey { .. })));
+}
+
+#[tokio::test]
+async fn poll_changes() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author], &conn);
+    Author::create(kwargs!(name = "Ursula", active = true), &conn)
+        .await
+        .unwrap();
+
+    let mut unknown = Author::poll_changes("updated_at", Duration::from_millis(10), 100, &conn);
+    assert!(matches!(unknown.next().await, Some(Err(Error::Invalid(_)))));
+    assert!(unknown.next().await.is_none());
+
+    // the feed starts after the latest instance at its first poll
+    let mut changes = Author::poll_changes("id", Duration::from_millis(50), 2, &conn);
+    let (changed, _) = tokio::join!(changes.next(), async {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        for name in ["Terry", "Iain"] {
+            Author::create(kwargs!(name = name, active = true), &conn)
+                .await
+                .unwrap();
+        }
+    });
+    assert_eq!(changed.unwrap().unwrap().name, "Terry");
+    assert_eq!(changes.next().await.unwrap().unwrap().name, "Iain");
+
+    // a backlog larger than the guardrail limit is polled in batches
+    let names = ["Mary", "Anne", "Kim", "Ted", "Lois"];
+    for name in names {
+        Author::create(kwargs!(name = name, active = true), &conn)
+            .await
+            .unwrap();
+    }
+    rusql_alchemy::enable_guardrail(Some(2));
+    let mut changed = Vec::new();
+    for _ in names {
+        changed.push(changes.next().await.unwrap().map(|author| author.name));
+    }
+    rusql_alchemy::disable_guardrail();
+    assert_eq!(
+        changed.into_iter().collect::<Result<Vec<_>, _>>().unwrap(),
+        names
+    );
+}
+
+#[tokio::test]
+async fn partitions() {
+    let (conn, _guard) = connect(&["visits"]).await;
+    migrate!([Visit], &conn);
+    for (from, to) in [("2024-01-01", "2024-02-01"), ("2024-02-01", "2024-03-01")] {
+        Visit::create_partition(from, to, &conn).await.unwrap();
+    }
+    for day in ["2024-01-15", "2024-02-15"] {
+        Visit::create(kwargs!(room = 1, day = day), &conn)
+            .await
+            .unwrap();
+    }
+    let quoted = Visit::create_partition("2024-03-01') to ('2025", "2024-04-01", &conn).await;
+    assert!(matches!(quoted, Err(Error::Invalid(_))));
+
+    // the cached instances of the dropped range are invalidated
+    assert!(Visit::get_cached(
+        (1, "2024-01-15".to_string()),
+        Duration::from_secs(60),
+        &conn
+    )
+    .await
+    .is_some());
+    Visit::drop_partition("2024-01-01", "2024-02-01", &conn)
+        .await
+        .unwrap();
+    assert!(Visit::get_cached(
+        (1, "2024-01-15".to_string()),
+        Duration::from_secs(60),
+        &conn
+    )
+    .await
+    .is_none());
+    assert_eq!(Visit::count(&conn).await, 1);
+
+    let unpartitioned = Author::drop_partition("2024-01-01", "2024-02-01", &conn).await;
+    assert!(matches!(unpartitioned, Err(Error::Invalid(_))));
+    let unpartitioned = Author::create_partition("2024-01-01", "2024-02-01", &conn).await;
+    assert!(matches!(unpartitioned, Err(Error::Invalid(_))));
+}
+
+#[tokio::test]
+async fn refresh() {
+    let (conn, _guard) = connect(&[]).await;
+    // the view depends on the authors, so it is dropped first
+    let kind = if *BACKEND == Backend::Postgres {
+        "materialized view"
+    } else {
+        "table"
+    };
+    let drop_view = format!("drop {kind} if exists active_authors;");
+    sqlx::query(&drop_view).execute(&conn).await.unwrap();
+    for table in ["books", "authors"] {
+        sqlx::query(&format!("drop table if exists {table};"))
+            .execute(&conn)
+            .await
+            .unwrap();
+    }
+    migrate!([Author], &conn);
+    Author::create(kwargs!(name = "Ursula", active = true), &conn)
+        .await
+        .unwrap();
+    migrate!([ActiveAuthor], &conn);
+
+    let ttl = Duration::from_secs(60);
+    assert_eq!(
+        ActiveAuthor::get_cached(1, ttl, &conn).await.unwrap().name,
+        "Ursula"
+    );
+    sqlx::query("update authors set name = 'Le Guin';")
+        .execute(&conn)
+        .await
+        .unwrap();
+    ActiveAuthor::refresh(false, &conn).await.unwrap();
+    // the cached instances are invalidated by the refresh
+    assert_eq!(
+        ActiveAuthor::get_cached(1, ttl, &conn).await.unwrap().name,
+        "Le Guin"
+    );
+
+    let table = Author::refresh(false, &conn).await;
+    assert!(matches!(table, Err(Error::Invalid(_))));
+    sqlx::query(&drop_view).execute(&conn).await.unwrap();
+}
+
+#[tokio::test]
+async fn cached_in_atomic() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author], &conn);
+    Author::create(kwargs!(name = "Ursula", active = true), &conn)
+        .await
+        .unwrap();
+    rusql_alchemy::clear_object_cache();
+    let other = Database::new().await.conn;
+    let ttl = Duration::from_secs(60);
+
+    // the instances read in a transaction are not cached
+    let read: Result<(), Error> = atomic(&conn, async {
+        Author::get_cached(1, ttl, &conn).await.unwrap();
+        Ok(())
+    })
+    .await;
+    read.unwrap();
+    sqlx::query("update authors set name = 'Le Guin';")
+        .execute(&conn)
+        .await
+        .unwrap();
+    assert_eq!(
+        Author::get_cached(1, ttl, &conn).await.unwrap().name,
+        "Le Guin"
+    );
+
+    // the committed row cached by another connection before the commit is invalidated
+    let written: Result<(), Error> = atomic(&conn, async {
+        let author = Author::get_cached(1, ttl, &conn).await.unwrap();
+        author.update_fields(kwargs!(name = "Iain"), &conn).await?;
+        assert_eq!(
+            Author::get_cached(1, ttl, &other).await.unwrap().name,
+            "Le Guin"
+        );
+        Ok(())
+    })
+    .await;
+    written.unwrap();
+    assert_eq!(
+        Author::get_cached(1, ttl, &other).await.unwrap().name,
+        "Iain"
+    );
+}
+
+#[tokio::test]
+async fn copy_in() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author], &conn);
+
+    // the statements of the copy count against the budget and are captured
+    let records = ["Ursula", "Terry", "Iain"].map(|name| kwargs!(name = name, active = true));
+    let copy = Author::copy_in(futures_util::stream::iter(records), &conn);
+    let ((copied, queries), report) =
+        with_query_budget(QueryBudget::default(), capture_queries(copy)).await;
+    copied.unwrap();
+    assert_eq!(report.queries, 1);
+    let statement = if *BACKEND == Backend::Postgres {
+        "copy"
+    } else {
+        "insert"
+    };
+    assert!(queries[0].starts_with(statement));
+    assert_eq!(Author::count(&conn).await, 3);
+}
+
+#[tokio::test]
+async fn batch() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author], &conn);
+    let database = Database::new().await;
+    let conn = &database.conn;
+
+    let batched: Result<(), Error> = atomic(conn, async {
+        Author::create(kwargs!(name = "Ursula", active = true), conn).await?;
+        // the batch runs on the transaction of the block
+        let results = database
+            .batch(&["select count(*) from authors;", "select 1"])
+            .await?;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0][0].get::<i64, _>(0), 1);
+        Err(Error::Invalid("rolled back".to_string()))
+    })
+    .await;
+    assert!(matches!(batched, Err(Error::Invalid(_))));
+
+    let results = database
+        .batch(&["select count(*) from authors"])
+        .await
+        .unwrap();
+    assert_eq!(results[0][0].get::<i64, _>(0), 0);
+    assert!(database
+        .batch(&["select * from missing_table"])
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn upsert() {
+    let (conn, _guard) = connect(&["shelves"]).await;
+    migrate!([Shelf], &conn);
+
+    let mut shelf = Shelf {
+        room: 1,
+        slot: 2,
+        label: "poetry".to_string(),
+    };
+    assert!(shelf.upsert(&conn).await.unwrap().is_created());
+    assert!(!shelf.upsert(&conn).await.unwrap().is_created());
+    shelf.label = "novels".to_string();
+    let updated = shelf.upsert(&conn).await.unwrap();
+    assert!(!updated.is_created());
+    assert_eq!(updated.into_inner().label, "novels");
+    assert_eq!(Shelf::count(&conn).await, 1);
+}
+
+#[tokio::test]
+async fn isolation_levels() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author], &conn);
+
+    let levels = [
+        IsolationLevel::ReadCommitted,
+        IsolationLevel::RepeatableRead,
+        IsolationLevel::Serializable,
+    ];
+    for (level, name) in levels.into_iter().zip(["Ursula", "Terry", "Iain"]) {
+        atomic_with(&conn, level, async {
+            Author::create(kwargs!(name = name, active = true), &conn).await?;
+            Ok::<_, Error>(())
+        })
+        .await
+        .unwrap();
+    }
+    let rolled_back: Result<(), Error> = atomic_with(&conn, IsolationLevel::Serializable, async {
+        Author::create(kwargs!(name = "Philip", active = true), &conn).await?;
+        Err(Error::Invalid("rolled back".to_string()))
+    })
+    .await;
+    assert!(matches!(rolled_back, Err(Error::Invalid(_))));
+    assert_eq!(Author::count(&conn).await, 3);
+}
+
+#[tokio::test]
+async fn keyset_pagination() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author, Book], &conn);
+    Author::create(kwargs!(name = "Ursula", active = true), &conn)
+        .await
+        .unwrap();
+    let books = [
+        ("Earthsea", 12.5),
+        ("Lavinia", 8.0),
+        ("Always Coming Home", 12.5),
+        ("Voices", 8.0),
+        ("Powers", 9.0),
+    ];
+    for (title, price) in books {
+        Book::create(kwargs!(title = title, price = price, author = 1), &conn)
+            .await
+            .unwrap();
+    }
+
+    // the ties on the price are paginated by the title
+    let query = Book::objects()
+        .order_by(&[desc(BookColumn::Price)])
+        .order_by(&[BookColumn::Title]);
+    let mut titles = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = query.page_after(cursor.as_deref(), 2, &conn).await.unwrap();
+        assert!(page.items.len() <= 2);
+        titles.extend(page.items.into_iter().map(|book| book.title));
+        match page.next {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    assert_eq!(
+        titles,
+        [
+            "Always Coming Home",
+            "Earthsea",
+            "Powers",
+            "Lavinia",
+            "Voices"
+        ]
+    );
+    assert!(query
+        .page_after(Some("not a cursor"), 2, &conn)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn retention() {
+    let (conn, _guard) = connect(&["events_archive", "events"]).await;
+    migrate!([Event], &conn);
+    for at in [
+        "2000-01-01 00:00:00",
+        "2001-01-01 00:00:00",
+        "2999-01-01 00:00:00",
+    ] {
+        Event::create(kwargs!(at = at), &conn).await.unwrap();
+    }
+
+    // the expired events are moved to the archive
+    assert_eq!(Event::run_retention(&conn).await.unwrap(), 2);
+    assert_eq!(Event::count(&conn).await, 1);
+    let archived = sqlx::query("select id from events_archive order by id;")
+        .fetch_all(&conn)
+        .await
+        .unwrap();
+    assert_eq!(archived.len(), 2);
+    assert_eq!(Event::run_retention(&conn).await.unwrap(), 0);
+
+    let unretained = Author::run_retention(&conn).await;
+    assert!(matches!(unretained, Err(Error::Invalid(_))));
+}
+
+/// Inserts the author `name` within a savepoint.
+fn insert_author(
+    name: &'static str,
+) -> impl for<'s> FnOnce(&'s mut AnyConnection) -> SavepointOperation<'s, ()> + Send {
+    move |sp| {
+        Box::pin(async move {
+            let insert = format!("insert into authors (name, active) values ('{name}', 1);");
+            sqlx::query(&insert).execute(sp).await.map(|_| ())
+        })
+    }
+}
+
+#[tokio::test]
+async fn try_savepoint() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author], &conn);
+    Author::create(kwargs!(name = "Ursula", active = true), &conn)
+        .await
+        .unwrap();
+
+    let mut tx = conn.begin().await.unwrap();
+    // the duplicate is rolled back alone, with its error
+    let duplicate = tx.try_savepoint(insert_author("Ursula")).await;
+    assert!(matches!(duplicate, Err(sqlx::Error::Database(_))));
+    sqlx::query("insert into authors (name, active) values ('Terry', 1);")
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+    tx.try_savepoint(insert_author("Iain")).await.unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!(Author::count(&conn).await, 3);
+}
+
+#[tokio::test]
+async fn savepoints() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author], &conn);
+
+    let committed: Result<(), Error> = atomic(&conn, async {
+        Author::create(kwargs!(name = "Ursula", active = true), &conn).await?;
+        // a failing nested block is rolled back alone, the enclosing one goes on
+        let failed: Result<(), Error> = atomic(&conn, async {
+            Author::create(kwargs!(name = "Terry", active = true), &conn).await?;
+            Err(Error::Invalid("rolled back".to_string()))
+        })
+        .await;
+        assert!(matches!(failed, Err(Error::Invalid(_))));
+        assert_eq!(Author::count(&conn).await, 1);
+
+        // the savepoints nested in a released one are rolled back and released first
+        atomic(&conn, async {
+            Author::create(kwargs!(name = "Iain", active = true), &conn).await?;
+            let failed: Result<(), Error> = atomic(&conn, async {
+                Author::create(kwargs!(name = "Philip", active = true), &conn).await?;
+                Err(Error::Invalid("rolled back".to_string()))
+            })
+            .await;
+            assert!(failed.is_err());
+            atomic(&conn, async {
+                Author::create(kwargs!(name = "Octavia", active = true), &conn).await?;
+                Ok::<_, Error>(())
+            })
+            .await
+        })
+        .await?;
+
+        // the isolation level is set when the transaction starts
+        let nested = atomic_with(&conn, IsolationLevel::Serializable, async {
+            Ok::<_, Error>(())
+        })
+        .await;
+        assert!(matches!(nested, Err(Error::Sqlx(_))));
+        Author::create(kwargs!(name = "Ann", active = true), &conn).await
+    })
+    .await;
+    committed.unwrap();
+
+    let names = Author::objects()
+        .order_by(&[AuthorColumn::Id])
+        .all(&conn)
+        .await;
+    let names = names
+        .iter()
+        .map(|author| author.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["Ursula", "Iain", "Octavia", "Ann"]);
+}
+
+#[tokio::test]
+async fn orphans() {
+    let (conn, _guard) = connect(&["diaries", "books", "authors"]).await;
+    migrate!([Author, Diary], &conn);
+    Author::create(kwargs!(name = "Ursula", active = true), &conn)
+        .await
+        .unwrap();
+    let ursula = Author::get(kwargs!(name == "Ursula"), &conn).await.unwrap();
+    for _ in 0..2 {
+        Diary::create(kwargs!(author = ursula.id), &conn)
+            .await
+            .unwrap();
+    }
+
+    // the helper is named after the table of the children
+    assert_eq!(ursula.orphan_diaries(&conn).await.unwrap(), 2);
+    assert_eq!(
+        Diary::count_where(kwargs!(author__isnull = true), &conn).await,
+        2
+    );
+    // the diaries reference the authors the other tests drop
+    sqlx::query("drop table diaries;")
+        .execute(&conn)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn raw_placeholders() {
+    let (conn, _guard) = connect(&["books", "authors"]).await;
+    migrate!([Author], &conn);
+    for (name, role) in [("Ursula", "reader"), ("What?", "author")] {
+        Author::create(kwargs!(name = name, role = role, active = true), &conn)
+            .await
+            .unwrap();
+    }
+    let database = Database::new().await;
+
+    // the `?` of the quoted literals are not bound
+    let sql = "select name from authors where role = ? and name != 'Who?' order by name;";
+    let rows = database.raw(sql, &["author".into()]).await.unwrap();
+    assert_eq!(rows[0]["name"], "What?");
+    let authors = Author::raw(
+        "select * from authors where name = 'What?' or name = ?;",
+        &["Ursula".into()],
+        &conn,
+    )
+    .await
+    .unwrap();
+    assert_eq!(authors.len(), 2);
+
+    let missing = database
+        .raw(
+            "select * from authors where name = '?';",
+            &["Ursula".into()],
+        )
+        .await;
+    assert!(matches!(missing, Err(Error::Invalid(_))));
+}
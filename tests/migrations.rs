@@ -0,0 +1,138 @@
+//! The migration files applied, reverted, faked and squashed on a temporary SQLite
+//! database.
+#![cfg(feature = "sqlite")]
+
+use std::path::{Path, PathBuf};
+
+use rusql_alchemy::migration::{MigrationFile, Migrations};
+use rusql_alchemy::prelude::*;
+use tokio::sync::{Mutex, MutexGuard};
+
+/// The tests share one database, each resetting it, so they run one at a time.
+static DATABASE: Mutex<()> = Mutex::const_new(());
+
+const TABLES: [&str; 4] = ["\"AppliedMigration\"", "ones", "twos", "threes"];
+
+/// Connects to a reset database, with an empty migrations directory named `name`.
+async fn connect(name: &str) -> (Connection, PathBuf, MutexGuard<'static, ()>) {
+    let guard = DATABASE.lock().await;
+    let path = std::env::temp_dir().join("rusql-alchemy-migrations.sqlite");
+    std::env::set_var(
+        "DATABASE_URL",
+        format!("sqlite://{}?mode=rwc", path.display()),
+    );
+    let conn = Database::new().await.conn;
+    reset(&conn).await;
+    let dir = std::env::temp_dir().join(format!("rusql-alchemy-migrations-{name}"));
+    let _ = std::fs::remove_dir_all(&dir);
+    (conn, dir, guard)
+}
+
+async fn reset(conn: &Connection) {
+    for table in TABLES {
+        let drop = format!("drop table if exists {table};");
+        sqlx::query(&drop).execute(conn).await.unwrap();
+    }
+}
+
+/// Writes the migrations 1 to 3 in `dir`, each creating a table.
+fn write_files(dir: &Path) -> Migrations {
+    for (version, table) in [(1, "ones"), (2, "twos"), (3, "threes")] {
+        let file = MigrationFile {
+            version,
+            name: format!("create_{table}"),
+            path: dir.join(format!("{version:04}_create_{table}.sql")),
+            destructive: false,
+            replaces: None,
+            up: vec![format!("create table {table} (id integer);")],
+            down: vec![format!("drop table {table};")],
+        };
+        assert!(file.write());
+    }
+    Migrations::new(dir)
+}
+
+async fn table_exists(table: &str, conn: &Connection) -> bool {
+    let query = "select count(*) from sqlite_master where type = 'table' and name = ?1;";
+    sqlx::query_scalar::<_, i64>(query)
+        .bind(table)
+        .fetch_one(conn)
+        .await
+        .unwrap()
+        == 1
+}
+
+#[tokio::test]
+async fn migrate_to() {
+    let (conn, dir, _guard) = connect("migrate-to").await;
+    let migrations = write_files(&dir);
+
+    assert!(migrations.migrate_to(2, &conn).await);
+    assert_eq!(migrations.applied(&conn).await, [1, 2]);
+    assert!(table_exists("twos", &conn).await);
+    assert!(!table_exists("threes", &conn).await);
+
+    // the migrations after the target are reverted, newest first
+    assert!(migrations.migrate_to(0, &conn).await);
+    assert!(migrations.applied(&conn).await.is_empty());
+    assert!(!table_exists("ones", &conn).await);
+
+    assert!(migrations.migrate(&conn).await);
+    assert_eq!(migrations.applied(&conn).await, [1, 2, 3]);
+    assert!(table_exists("threes", &conn).await);
+}
+
+#[tokio::test]
+async fn fake() {
+    let (conn, dir, _guard) = connect("fake").await;
+    let migrations = write_files(&dir);
+
+    // the migration 1 is recorded without creating its table
+    assert!(migrations.fake(1, &conn).await);
+    assert_eq!(migrations.applied(&conn).await, [1]);
+    assert!(!table_exists("ones", &conn).await);
+
+    assert!(migrations.migrate(&conn).await);
+    assert_eq!(migrations.applied(&conn).await, [1, 2, 3]);
+    assert!(!table_exists("ones", &conn).await);
+    assert!(table_exists("twos", &conn).await);
+}
+
+#[tokio::test]
+async fn squash() {
+    let (conn, dir, _guard) = connect("squash").await;
+    let migrations = write_files(&dir);
+    assert!(migrations.migrate(&conn).await);
+
+    // the squashed migration takes the version of the last file, not the bound given
+    let path = migrations.squash(1, 42, "initial").unwrap();
+    assert_eq!(path, dir.join("0003_initial.sql"));
+    let files = migrations.files();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].version, 3);
+    assert_eq!(files[0].replaces, Some((1, 3)));
+
+    // a database where the squashed migrations are applied has nothing to run
+    assert!(migrations.migrate(&conn).await);
+    assert_eq!(migrations.applied(&conn).await, [1, 2, 3]);
+
+    // a new database runs the squashed migration alone
+    reset(&conn).await;
+    assert!(migrations.migrate(&conn).await);
+    assert_eq!(migrations.applied(&conn).await, [3]);
+    for table in ["ones", "twos", "threes"] {
+        assert!(table_exists(table, &conn).await);
+    }
+    assert!(migrations.squash(1, 3, "again").is_none());
+}
+
+#[tokio::test]
+async fn squash_partially_applied() {
+    let (conn, dir, _guard) = connect("squash-partial").await;
+    let migrations = write_files(&dir);
+    assert!(migrations.migrate_to(1, &conn).await);
+
+    assert!(migrations.squash(1, 3, "initial").is_some());
+    assert!(!migrations.migrate(&conn).await);
+    assert_eq!(migrations.applied(&conn).await, [1]);
+}
@@ -0,0 +1,146 @@
+//! Round trips of the built-in types through a temporary SQLite database: the fields of
+//! a model saved and read back, and the arguments of `kwargs!` bound and selected back.
+#![cfg(feature = "sqlite")]
+
+use std::fmt::Debug;
+
+use rusql_alchemy::prelude::*;
+use rusql_alchemy::types::SqlValue;
+use rusql_alchemy::{assert_round_trip, fastrand::Rng, Arbitrary};
+use sqlx::{Any, Decode, Type};
+
+#[derive(FromRow, Clone, Debug, Default, Model)]
+struct Sample {
+    #[model(primary_key = true, auto = true, null = false)]
+    id: Integer,
+    count: Integer,
+    amount: Float,
+    flag: Boolean,
+    name: String,
+    body: Text,
+}
+
+impl Arbitrary for Sample {
+    fn arbitrary(rng: &mut Rng) -> Self {
+        Self {
+            count: Integer::arbitrary(rng),
+            amount: Float::arbitrary(rng),
+            flag: Boolean::from(bool::arbitrary(rng)),
+            name: String::arbitrary(rng),
+            body: Text::arbitrary(rng),
+            ..Default::default()
+        }
+    }
+}
+
+const CASES: usize = 200;
+
+async fn connect() -> Connection {
+    let path = std::env::temp_dir().join("rusql-alchemy-roundtrip.sqlite");
+    std::env::set_var(
+        "DATABASE_URL",
+        format!("sqlite://{}?mode=rwc", path.display()),
+    );
+    Database::new().await.conn
+}
+
+/// Binds `CASES` values of `T` generated at random as the argument of a `kwargs!`
+/// and selects them back with `selected` (`?` being the argument) as a `D`, which must
+/// equal `expected` of the value.
+async fn assert_argument_round_trip<T, D>(
+    selected: &str,
+    conn: &Connection,
+    expected: impl Fn(&T) -> D,
+) where
+    T: Arbitrary + Clone + Debug + Into<SqlValue>,
+    D: for<'r> Decode<'r, Any> + Type<Any> + PartialEq + Debug + Send + Unpin,
+{
+    let mut rng = Rng::new();
+    let query = format!("select {};", selected.replace('?', "?1"));
+    for _ in 0..CASES {
+        let value = T::arbitrary(&mut rng);
+        let Some(Condition::FieldCondition {
+            value: argument, ..
+        }) = kwargs!(value = value).pop()
+        else {
+            unreachable!()
+        };
+        let selected = sqlx::query_scalar::<_, D>(&query)
+            .bind(argument.clone())
+            .fetch_one(conn)
+            .await
+            .unwrap_or_else(|err| panic!("{argument:?} could not be selected back\n->{err}"));
+        assert_eq!(
+            selected,
+            expected(&value),
+            "{value:?} changed on the round trip"
+        );
+    }
+}
+
+#[tokio::test]
+async fn model_round_trip() {
+    let conn = connect().await;
+    let _ = sqlx::query("drop table if exists samples;")
+        .execute(&conn)
+        .await;
+    migrate!([Sample], &conn);
+    assert_round_trip::<Sample>(CASES, &conn).await;
+}
+
+#[tokio::test]
+async fn i32_round_trip() {
+    let conn = connect().await;
+    assert_argument_round_trip::<i32, i32>("?", &conn, |value| *value).await;
+}
+
+#[tokio::test]
+async fn i64_round_trip() {
+    let conn = connect().await;
+    // sqlx decodes the SQLite integers as 32 bits ones through `Any`, so the bound
+    // value is selected back as text
+    assert_argument_round_trip::<i64, String>("cast(? as text)", &conn, i64::to_string).await;
+}
+
+#[tokio::test]
+async fn f64_round_trip() {
+    let conn = connect().await;
+    assert_argument_round_trip::<f64, f64>("?", &conn, |value| *value).await;
+}
+
+#[tokio::test]
+async fn bool_round_trip() {
+    let conn = connect().await;
+    // bound as an integer, as the `Boolean` columns
+    assert_argument_round_trip::<bool, i32>("?", &conn, |value| *value as i32).await;
+}
+
+#[tokio::test]
+async fn string_round_trip() {
+    let conn = connect().await;
+    assert_argument_round_trip::<String, String>("?", &conn, String::clone).await;
+}
+
+#[tokio::test]
+async fn bytes_round_trip() {
+    let conn = connect().await;
+    assert_argument_round_trip::<Vec<u8>, Vec<u8>>("?", &conn, Vec::clone).await;
+}
+
+#[tokio::test]
+async fn option_round_trip() {
+    let conn = connect().await;
+    // sqlx does not decode a `null` into an `Option` through `Any`, so the bound value
+    // is told apart from `null` in SQL
+    let selected = "case when ? is null then 'none' else 'some ' || ? end";
+    assert_argument_round_trip::<Option<i64>, String>(selected, &conn, |value| {
+        value.map_or("none".to_string(), |value| format!("some {value}"))
+    })
+    .await;
+    assert_argument_round_trip::<Option<String>, String>(selected, &conn, |value| {
+        value
+            .as_ref()
+            .map_or("none".to_string(), |value| format!("some {value}"))
+    })
+    .await;
+}
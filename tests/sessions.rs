@@ -0,0 +1,166 @@
+//! The `tower-sessions` store and the Rocket request guard of the sessions, on a
+//! temporary SQLite database.
+#![cfg(all(
+    feature = "sqlite",
+    any(feature = "tower-sessions", feature = "rocket")
+))]
+
+use std::time::Duration;
+
+use rusql_alchemy::prelude::*;
+use rusql_alchemy::session::{Session, SessionStore};
+use tokio::sync::{Mutex, MutexGuard};
+
+/// The tests share one database, each resetting it, so they run one at a time.
+static DATABASE: Mutex<()> = Mutex::const_new(());
+
+async fn connect() -> (SessionStore, MutexGuard<'static, ()>) {
+    let guard = DATABASE.lock().await;
+    let path = std::env::temp_dir().join("rusql-alchemy-sessions.sqlite");
+    std::env::set_var(
+        "DATABASE_URL",
+        format!("sqlite://{}?mode=rwc", path.display()),
+    );
+    let conn = Database::new().await.conn;
+    sqlx::query("drop table if exists rusql_sessions;")
+        .execute(&conn)
+        .await
+        .unwrap();
+    Session::migrate(&conn).await.unwrap();
+    (SessionStore::new(conn, Duration::from_secs(3600)), guard)
+}
+
+#[cfg(feature = "tower-sessions")]
+#[tokio::test]
+async fn tower_sessions() {
+    use std::collections::HashMap;
+    use std::time::SystemTime;
+
+    use rusql_alchemy::serde_json::json;
+    use tower_sessions_core::session::{Id, Record};
+    use tower_sessions_core::session_store::ExpiredDeletion;
+    use tower_sessions_core::SessionStore as TowerSessionStore;
+
+    let (store, _guard) = connect().await;
+    let mut record = Record {
+        id: Id::default(),
+        data: HashMap::from([("user_id".to_string(), json!(1))]),
+        expiry_date: (SystemTime::now() + Duration::from_secs(60)).into(),
+    };
+    TowerSessionStore::create(&store, &mut record)
+        .await
+        .unwrap();
+    let loaded = TowerSessionStore::load(&store, &record.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(loaded.data, record.data);
+    assert_eq!(
+        loaded.expiry_date.unix_timestamp(),
+        record.expiry_date.unix_timestamp()
+    );
+
+    // a new session colliding with an existing one gets another id
+    let mut colliding = record.clone();
+    TowerSessionStore::create(&store, &mut colliding)
+        .await
+        .unwrap();
+    assert_ne!(colliding.id, record.id);
+
+    let mut expired = Record {
+        id: Id::default(),
+        data: HashMap::new(),
+        expiry_date: (SystemTime::now() - Duration::from_secs(60)).into(),
+    };
+    TowerSessionStore::create(&store, &mut expired)
+        .await
+        .unwrap();
+    assert!(TowerSessionStore::load(&store, &expired.id)
+        .await
+        .unwrap()
+        .is_none());
+    ExpiredDeletion::delete_expired(&store).await.unwrap();
+    assert_eq!(store.delete_expired().await.unwrap(), 0);
+
+    TowerSessionStore::delete(&store, &record.id).await.unwrap();
+    assert!(TowerSessionStore::load(&store, &record.id)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[cfg(feature = "rocket")]
+mod rocket_guard {
+    use rocket::http::{CookieJar, Status};
+    use rocket::local::asynchronous::Client;
+    use rocket::State;
+    use rusql_alchemy::serde_json::json;
+    use rusql_alchemy::session::rocket::{CurrentSession, COOKIE};
+    use rusql_alchemy::session::SessionStore;
+
+    #[rocket::post("/login/<username>")]
+    async fn login(
+        username: &str,
+        mut session: CurrentSession,
+        store: &State<SessionStore>,
+        cookies: &CookieJar<'_>,
+    ) -> Status {
+        session
+            .values
+            .insert("username".to_string(), json!(username));
+        match session.save(store, cookies).await {
+            Ok(()) => Status::Ok,
+            Err(_) => Status::InternalServerError,
+        }
+    }
+
+    #[rocket::get("/me")]
+    fn me(session: CurrentSession) -> String {
+        session
+            .values
+            .get("username")
+            .and_then(|username| username.as_str())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    #[rocket::post("/logout")]
+    async fn logout(
+        session: CurrentSession,
+        store: &State<SessionStore>,
+        cookies: &CookieJar<'_>,
+    ) -> Status {
+        match session.destroy(store, cookies).await {
+            Ok(()) => Status::Ok,
+            Err(_) => Status::InternalServerError,
+        }
+    }
+
+    /// The username of the session of `client`.
+    async fn username(client: &Client) -> String {
+        let response = client.get("/me").dispatch().await;
+        response.into_string().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn current_session() {
+        let (store, _guard) = super::connect().await;
+        let rocket = rocket::build()
+            .manage(store.clone())
+            .mount("/", rocket::routes![login, me, logout]);
+        let client = Client::tracked(rocket).await.unwrap();
+
+        assert_eq!(username(&client).await, "");
+        let response = client.post("/login/joe").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let id = response.cookies().get(COOKIE).unwrap().value().to_string();
+        assert!(store.load(&id).await.unwrap().is_some());
+        // the cookie is sent back by the tracking client
+        assert_eq!(username(&client).await, "joe");
+
+        let response = client.post("/logout").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert!(store.load(&id).await.unwrap().is_none());
+        assert_eq!(username(&client).await, "");
+    }
+}
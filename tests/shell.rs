@@ -0,0 +1,74 @@
+//! The model queries of the shell, run on a temporary SQLite database.
+#![cfg(feature = "sqlite")]
+
+use rusql_alchemy::prelude::*;
+use rusql_alchemy::shell::Shell;
+
+#[derive(FromRow, Clone, Debug, Default, Model)]
+struct Category {
+    #[model(primary_key = true, auto = true, null = false)]
+    id: Integer,
+    #[model(null = false)]
+    name: String,
+    #[model(default = "visible")]
+    status: String,
+}
+
+async fn connect() -> Connection {
+    let path = std::env::temp_dir().join("rusql-alchemy-shell.sqlite");
+    std::env::set_var(
+        "DATABASE_URL",
+        format!("sqlite://{}?mode=rwc", path.display()),
+    );
+    let conn = Database::new().await.conn;
+    sqlx::query("drop table if exists categories;")
+        .execute(&conn)
+        .await
+        .unwrap();
+    Category::migrate(&conn).await.unwrap();
+    conn
+}
+
+#[tokio::test]
+async fn model_query() {
+    let conn = connect().await;
+    for (name, status) in [
+        ("books", "visible"),
+        ("games", "hidden"),
+        ("music", "visible"),
+    ] {
+        Category::create(kwargs!(name = name, status = status), &conn)
+            .await
+            .unwrap();
+    }
+    let shell = Shell::new(conn).with_model::<Category>();
+
+    // the models are named by their type, not by their table
+    let visible = shell
+        .model_query(r#"Category.filter(status="visible")"#)
+        .await
+        .unwrap();
+    assert!(visible.contains("books") && visible.contains("music"));
+    assert!(!visible.contains("games"));
+    assert!(visible.ends_with("(2 rows)"));
+    let all = shell.model_query("Category.all()").await.unwrap();
+    assert!(all.ends_with("(3 rows)"));
+    let games = shell
+        .model_query("Category.get(name='games')")
+        .await
+        .unwrap();
+    assert!(games.contains("hidden") && games.ends_with("(1 rows)"));
+
+    let unknown = shell.model_query("Category.count()").await.unwrap();
+    assert!(unknown.starts_with("Unknown method `count`"));
+    let invalid = shell
+        .model_query("Category.filter(name=games)")
+        .await
+        .unwrap();
+    assert!(invalid.starts_with("Invalid value `games`"));
+    assert!(shell.model_query("categories.all()").await.is_none());
+    assert!(shell
+        .model_query("select * from categories;")
+        .await
+        .is_none());
+}
@@ -0,0 +1,6 @@
+create table if not exists `bookings` (id integer primary key auto_increment not null, seats varchar(100), seats_lower integer generated always as (cast(substring_index(substring(seats, 2), ',', 1) as signed)) virtual, seats_upper integer generated always as (cast(substring_index(substring_index(seats, ')', 1), ',', -1) as signed)) virtual);
+insert into `bookings` (seats) values (?1);
+SELECT * FROM `bookings` WHERE (seats_lower<=?1 and ?2<seats_upper);
+SELECT * FROM `bookings` WHERE (seats_lower<=?1 and ?2<seats_upper);
+SELECT * FROM `bookings` WHERE (seats_lower<?1 and ?2<seats_upper);
+SELECT * FROM `bookings` WHERE seats=?1;
@@ -0,0 +1,3 @@
+select * from "shelves"  order by room, slot limit 4;
+select * from "shelves" where ( room>$1 ) or ( room=$2 and slot>$3 ) order by room, slot limit 4;
+select * from "shelves" where ( room>$1 ) or ( room=$2 and slot>$3 ) order by room, slot limit 4;
@@ -0,0 +1,15 @@
+insert into "authors" (name, active) values ($1, $2);
+insert into "authors" (name, active) values ($1, $2);
+SELECT "authors".* FROM (SELECT * FROM "authors" WHERE name=$1) AS "authors" ORDER BY "authors".id LIMIT 1;
+insert into "books" (title, price, author) values ($1, $2, $3);
+SELECT "authors".* FROM (SELECT * FROM "authors" WHERE active=$1) AS "authors" ORDER BY "authors".id LIMIT 1;
+insert into "books" (title, price, author) values ($1, $2, $3);
+SELECT * FROM "books" WHERE price<$1;
+SELECT * FROM "books" WHERE title like $1 escape '!';
+SELECT * FROM "books" WHERE author is not null;
+SELECT * FROM "books" WHERE title in ($1, $2);
+update "books" set title=$1, price=$2, author=$3 where id=$4;
+select * from "books" where id=$1;
+delete from "books" where id=$1;
+select count(*) from "books"
+select count(*) from "authors" where active=$1
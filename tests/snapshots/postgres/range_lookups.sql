@@ -0,0 +1,6 @@
+create table if not exists "bookings" (id serial primary key not null, seats int4range);
+insert into "bookings" (seats) values (cast($1 as int4range));
+SELECT id, cast(seats as text) as seats FROM "bookings" WHERE seats @> cast($1 as integer);
+SELECT id, cast(seats as text) as seats FROM "bookings" WHERE seats @> cast($1 as integer);
+SELECT id, cast(seats as text) as seats FROM "bookings" WHERE seats && cast($1 as int4range);
+SELECT id, cast(seats as text) as seats FROM "bookings" WHERE seats=cast($1 as int4range);
@@ -0,0 +1,4 @@
+SELECT "books".* FROM (SELECT * FROM "books") AS "books" ORDER BY "books".price desc, "books".title;
+SELECT "books".* FROM (SELECT * FROM "books") AS "books" ORDER BY "books".price LIMIT 1;
+SELECT "books".* FROM (SELECT * FROM "books") AS "books" ORDER BY coalesce(price, $1) desc, "books".title;
+SELECT "books".* FROM (SELECT * FROM "books") AS "books" ORDER BY "books".price desc, "books".id LIMIT 3;
@@ -0,0 +1,6 @@
+create table if not exists "bookings" (id integer primary key autoincrement not null, seats varchar(100), seats_lower integer generated always as (cast(substr(seats, 2, instr(seats, ',') - 2) as integer)) virtual, seats_upper integer generated always as (cast(substr(seats, instr(seats, ',') + 1, length(seats) - instr(seats, ',') - 1) as integer)) virtual);
+insert into "bookings" (seats) values (?1);
+SELECT * FROM "bookings" WHERE (seats_lower<=?1 and ?2<seats_upper);
+SELECT * FROM "bookings" WHERE (seats_lower<=?1 and ?2<seats_upper);
+SELECT * FROM "bookings" WHERE (seats_lower<?1 and ?2<seats_upper);
+SELECT * FROM "bookings" WHERE seats=?1;
@@ -0,0 +1,4 @@
+create table if not exists "authors" (id integer primary key autoincrement not null, name varchar(50) not null, role varchar(255) default 'reader', active integer, constraint ux_authors_name unique (name));
+create table if not exists "books" (id integer primary key autoincrement not null, title varchar(255) not null, price float, author integer, constraint fk_books_author_authors foreign key (author) references "authors" (id));
+create index if not exists books_title_idx on "books" (title);
+create index if not exists books_price_idx on "books" (price);
@@ -0,0 +1,63 @@
+//! The filters of the `MemoryStore`, evaluated without a database.
+
+use rusql_alchemy::prelude::*;
+use rusql_alchemy::Error;
+
+#[derive(FromRow, Clone, Debug, Default, Model)]
+struct Booking {
+    #[model(primary_key = true, auto = true, null = false)]
+    id: Integer,
+    #[model(size = 50)]
+    guest: String,
+    seats: Int4Range,
+}
+
+fn store() -> MemoryStore<Booking> {
+    MemoryStore::with_rows(vec![
+        Booking {
+            id: 1,
+            guest: "Ursula".to_string(),
+            seats: Int4Range::new(1, 10),
+        },
+        Booking {
+            id: 2,
+            guest: "Terry".to_string(),
+            seats: Int4Range::new(10, 20),
+        },
+    ])
+}
+
+/// The ids of the bookings matching `kw`.
+async fn ids(store: &MemoryStore<Booking>, kw: Vec<Condition>) -> Vec<Integer> {
+    let bookings = store.filter(kw).await.unwrap();
+    bookings.iter().map(|booking| booking.id).collect()
+}
+
+#[tokio::test]
+async fn range_lookups() {
+    let store = store();
+    assert_eq!(ids(&store, kwargs!(seats__contains = 9)).await, [1]);
+    assert_eq!(ids(&store, kwargs!(seats__contains = 10)).await, [2]);
+    let overlapping = kwargs!(seats__overlaps = Int4Range::new(9, 11));
+    assert_eq!(ids(&store, overlapping).await, [1, 2]);
+    // the text lookups still apply to the other fields
+    assert_eq!(ids(&store, kwargs!(guest__contains = "rr")).await, [2]);
+}
+
+#[tokio::test]
+async fn unsupported_conditions() {
+    let store = store();
+    let unsupported = [
+        kwargs!(guest__icontains = "ursula"),
+        raw!("guest = ?", "Ursula"),
+        kwargs!(id = F("id") + 1),
+        kwargs!(missing = 1),
+    ];
+    for kw in unsupported {
+        assert!(matches!(
+            store.filter(kw.clone()).await,
+            Err(Error::Invalid(_))
+        ));
+        assert!(matches!(store.get(kw).await, Err(Error::Invalid(_))));
+    }
+}